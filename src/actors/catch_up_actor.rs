@@ -0,0 +1,33 @@
+extern crate actix;
+use actix::{Actor, Context, AsyncContext};
+use std::time::Duration;
+use db::cachedb::CacheDB;
+
+/// Actor making writes of the primary cacherz process visible to a serve-only instance.
+/// Opening the database blocks, so it should be started in its own arbiter.
+pub struct CatchUpActor {
+  pub db: CacheDB,
+  pub interval_secs: u64
+}
+
+impl CatchUpActor {
+  pub fn create_new(db: CacheDB, interval_secs: u64) -> CatchUpActor {
+    CatchUpActor{db: db, interval_secs: interval_secs}
+  }
+
+  fn run_catch_up(&mut self) {
+    match self.db.storage.catch_up() {
+      Ok(()) => debug!("Database has caught up with the primary"),
+      Err(err) => error!("Cannot catch up with the primary. Error: {}", err)
+    };
+  }
+}
+
+impl Actor for CatchUpActor {
+  type Context = Context<CatchUpActor>;
+
+  fn started(&mut self, ctx: &mut Self::Context) {
+    info!("Database catches up with the primary every {} s", self.interval_secs);
+    ctx.run_interval(Duration::new(self.interval_secs, 0), |actor, _| actor.run_catch_up());
+  }
+}
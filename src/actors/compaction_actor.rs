@@ -0,0 +1,119 @@
+extern crate actix;
+use actix::{Actor, Context, Handler, Message, AsyncContext};
+use chrono::prelude::*;
+use std::time::Duration;
+use db::cachedb::CacheDB;
+use db::compaction::{compact, get_column_family_stats, CompactionStatus};
+use config::structs::storageconfig::StorageConfig;
+
+const CHECK_INTERVAL_SECS: u64 = 60;
+
+/// Triggers of scheduled compaction of a single column family
+#[derive(Debug, Clone)]
+pub struct CompactionSchedule {
+  pub column_family: String,
+  pub interval_secs: Option<u64>,       // Compaction at most once per interval
+  pub trigger_bytes: Option<u64>,       // Compaction when sst files grew by this size
+  pub last_compaction: i64,             // Timestamp in seconds
+  pub last_size: u64                    // Size of sst files after the last compaction
+}
+
+impl CompactionSchedule {
+  /// Returns reason of compaction when one of triggers is reached
+  fn get_trigger(&self, now: i64, size: u64) -> Option<&'static str> {
+    if self.trigger_bytes.map_or(false, |trigger_bytes| size >= self.last_size + trigger_bytes) {
+      return Some("size");
+    }
+    if self.interval_secs.map_or(false, |interval_secs| now - self.last_compaction >= interval_secs as i64) {
+      return Some("interval");
+    }
+    None
+  }
+}
+
+/// Message with manual compaction of a column family or its key range
+#[derive(Debug)]
+pub struct MsgCompact {
+  pub column_family: String,
+  pub from: Option<Vec<u8>>,
+  pub to: Option<Vec<u8>>
+}
+
+impl Message for MsgCompact {
+  type Result = Result<CompactionStatus, String>;
+}
+
+/// Actor compacting column families on schedule and on demand. Compaction blocks the actor,
+/// so it should be started in its own arbiter.
+pub struct CompactionActor {
+  pub db: CacheDB,
+  pub schedules: Vec<CompactionSchedule>
+}
+
+impl CompactionActor {
+  pub fn create_new(db: CacheDB, column_families: Vec<&str>, storage_config: &StorageConfig) -> CompactionActor {
+    let now = Utc::now().timestamp();
+    let schedules = column_families.into_iter()
+      .filter_map(|column_family| storage_config.get_compaction_triggers(column_family).map(|(interval_secs, trigger_mb)| {
+        CompactionSchedule {
+          column_family: column_family.to_string(),
+          interval_secs: interval_secs,
+          trigger_bytes: trigger_mb.map(|mb| mb * 1024 * 1024),
+          last_compaction: now,
+          last_size: get_column_family_stats(&db, column_family).map(|stats| stats.total_sst_files_size).unwrap_or(0)
+        }
+      }))
+      .collect();
+    CompactionActor{db: db, schedules: schedules}
+  }
+
+  /// Compacts every column family which reached one of its triggers
+  fn run_scheduled(&mut self) {
+    let db = self.db.clone();
+    for schedule in self.schedules.iter_mut() {
+      let size = match get_column_family_stats(&db, &schedule.column_family) {
+        Ok(stats) => stats.total_sst_files_size,
+        Err(err) => {
+          error!("Cannot get stats of column family {}. Error: {}", schedule.column_family, err);
+          continue;
+        }
+      };
+      if let Some(reason) = schedule.get_trigger(Utc::now().timestamp(), size) {
+        match compact(&db, &schedule.column_family, None, None, reason) {
+          Ok(compaction_status) => {
+            schedule.last_size = compaction_status.after.map_or(size, |stats| stats.total_sst_files_size);
+          },
+          Err(err) => error!("Scheduled compaction of column family {} has failed. Error: {}", schedule.column_family, err)
+        };
+        schedule.last_compaction = Utc::now().timestamp();
+      }
+    }
+  }
+}
+
+impl Handler<MsgCompact> for CompactionActor {
+  type Result = Result<CompactionStatus, String>;
+
+  fn handle(&mut self, msg: MsgCompact, _ctx: &mut Context<CompactionActor>) -> Result<CompactionStatus, String> {
+    let compaction_status = compact(&self.db, &msg.column_family, msg.from.as_ref().map(|from| from.as_slice()), msg.to.as_ref().map(|to| to.as_slice()), "manual")?;
+    // Full compaction resets schedule of the column family
+    if let (None, None) = (msg.from, msg.to) {
+      if let Some(schedule) = self.schedules.iter_mut().find(|schedule| schedule.column_family == msg.column_family) {
+        schedule.last_compaction = Utc::now().timestamp();
+        schedule.last_size = compaction_status.after.as_ref().map_or(schedule.last_size, |stats| stats.total_sst_files_size);
+      }
+    }
+    Ok(compaction_status)
+  }
+}
+
+impl Actor for CompactionActor {
+  type Context = Context<CompactionActor>;
+
+  fn started(&mut self, ctx: &mut Self::Context) {
+    info!("Compaction is scheduled for {:?}", self.schedules.iter().map(|schedule| schedule.column_family.clone()).collect::<Vec<String>>());
+    if !self.schedules.is_empty() {
+      ctx.run_interval(Duration::new(CHECK_INTERVAL_SECS, 0), |actor, _| actor.run_scheduled());
+    }
+  }
+}
@@ -1,22 +1,32 @@
 extern crate actix;
 // extern crate rustc_hex;
-use actix::{Actor, Addr, Context, Handler, Message, AsyncContext};
-use eth::eth_json_rpc;
-use std::time::Duration;
+use actix::{fut, Actor, ActorFuture, Addr, Context, Handler, Message, AsyncContext, Running, SpawnHandle, StreamHandler};
+use eth::eth_json_rpc::{self, TRANSACTION_SENDERS_BATCH_SIZE};
+use eth::endpoint::Endpoint;
+use eth::structs::eventresult::EventResult;
+use std::time::{Duration, Instant};
 use chrono::prelude::*;
 use ethabi::{Event, EventParam, Error};
 use std::collections::HashMap;
 use actors::structs::settings::Settings;
 use actors::traits::setupable::Setupable;
-use actors::rocks_write_actor::{RocksWriteActor, WriteMsg, MsgContentType, MsgType};
+use actors::lua_transform::{LuaTransform, TransformOutput, event_message, records_to_write_ops};
+use actix_lua::LuaMessage;
+use actors::rocks_write_actor::{RocksWriteActor, WriteMsg, WriteBatchMsg, WriteOp, MsgContentType, MsgType};
 use db::cachedb::CacheDB;
-use eth::structs::eventprefix::EventPrefix;
-use eth::structs::eventprefixparam::EventPrefixParam;
-use db::reader::{get_by_key_with_default, get_by_key};
-use std::str::from_utf8;
+use db::reader::get_by_key_bytes;
+use db::key_schema::{name_key, event_key_from_hex, decode_event_key, format_key, qualified_name};
+use db::stats::StatsDelta;
+use db::indexes::{EventIndexes, LogTransaction, TX_SENDER_PARAM};
 use std::time::{SystemTime, UNIX_EPOCH};
 use serde_json::{Value};
 
+/// Interval of polls. With a log subscription polls only catch what notifications have missed.
+const POLL_INTERVAL_SECS: u64 = 1;
+const SUBSCRIBED_POLL_INTERVAL_SECS: u64 = 15;
+/// Logs of a block come as many notifications, so they are fetched once after a short delay
+const NOTIFICATION_DELAY_MS: u64 = 100;
+
 #[derive(Debug, Message)]
 enum Ping {
   IsAlive(u64),
@@ -33,6 +43,24 @@ enum QueryType {
 #[derive(Message)]
 pub struct GetEvents;
 
+/// Decoded log of a poll. Removed log which cannot be decoded has no params, it is only deleted.
+struct PolledLog {
+  event_key: Vec<u8>,
+  removed: bool,
+  transaction: LogTransaction,
+  params: Option<HashMap<String, String>>
+}
+
+/// Logs of a poll with everything written together with them
+struct Poll {
+  logs: Vec<PolledLog>,
+  stats: StatsDelta,                  // Counters of the poll, rpc calls are in pending stats
+  last_block_log: Option<String>,     // Cursor after the poll
+  is_filter_failed: bool,             // Logs could not be fetched, cursor stays
+  is_finished: bool,                  // Event has reached its end block, stats are written even when idle
+  started: Instant
+}
+
 #[derive(Debug, Clone)]
 pub struct EthActor {
   pub id: i64, // Actor inner id
@@ -45,6 +73,11 @@ pub struct EthActor {
   pub db: Option<CacheDB>,
   pub last_block_log: Option<String>, // last block log for a given event filter.
   pub settings: Option<HashMap<String, Settings>>, // Actor settings
+  pub pending_stats: StatsDelta, // Counters not written yet. They are flushed with next batch of events.
+  pub transform: Option<LuaTransform>, // Lua script which transforms decoded logs before they are written
+  pub subscription: Option<SpawnHandle>, // Stream of log notifications of ipc endpoints
+  pub is_notified: bool, // Poll after a notification is scheduled
+  pub is_finished: bool, // Event has reached its end block
 }
 
 impl Setupable for EthActor {
@@ -62,7 +95,8 @@ impl EthActor {
     let generated_id: i64 = Utc::now().timestamp_nanos();
     return EthActor{event: event, last_event: None, filter_id: None, last_timestamp: None,
       id: generated_id, addr: None, settings: Some(settings), addr_writer: None, db: None, 
-      last_block_log: None};
+      last_block_log: None, pending_stats: StatsDelta::default(), transform: None, subscription: None,
+      is_notified: false, is_finished: false};
   }
 
   pub fn get_filter_id_default(&self, default: &str) -> String {
@@ -85,7 +119,11 @@ impl EthActor {
     self.event.inputs.clone()
   }
 
-  pub fn get_settings_host_and_port(&self) -> (String, String) {
+  /// Returns json-rpc endpoint. Falls back to "host" and "port" settings when there is no "endpoint".
+  pub fn get_endpoint(&self) -> Endpoint {
+    if let Some(Settings::Endpoint(endpoint)) = self.get_settings().get("endpoint") {
+      return endpoint.clone();
+    }
     let host_enum = self.get_settings_data_default("host", Settings::PureString("localhost".to_string()));
     let host = if let Settings::PureString(host_str) =  host_enum {
       host_str.clone()
@@ -98,7 +136,7 @@ impl EthActor {
       } else {
         "".to_string()
       };
-      (host, port)
+      Endpoint::from_host_and_port(host, port)
   }
 
   pub fn get_prefix(&self) -> usize {
@@ -109,12 +147,19 @@ impl EthActor {
     prefix
   }
 
+  /// Whether batches of the actor are written with fsync
+  fn get_sync_writes(&self) -> bool {
+    match self.get_settings_data_default("sync_writes", Settings::Bool(false)) {
+      Settings::Bool(sync_writes) => sync_writes,
+      _ => false
+    }
+  }
+
   pub fn send_to_write(&self, msg_content: (String, String), msg_type: MsgType) {
     match self.addr_writer.clone() {
       Some(w_addr) => {
         let _msg_content = msg_content.clone();
-        let prefix: String = generate_prefix_for_query(_msg_content.0, 30);
-        let writer_msg = WriteMsg{msg_content: (prefix, MsgContentType::PureString(_msg_content.1)), msg_type: msg_type};
+        let writer_msg = WriteMsg{msg_content: (_msg_content.0, MsgContentType::PureString(_msg_content.1)), msg_type: msg_type};
         match w_addr.try_send(writer_msg) {
           Ok(()) => {
             info!("Message has been send to RocksDBWriteAgent. Msg key: {}", msg_content.0);
@@ -130,37 +175,144 @@ impl EthActor {
     }
   }
 
-  fn get_new_filter(&mut self ,host: String, port: String, prefix: usize) -> Option<String> {
-    let event_name = self.clone().event.name;
-    let last_event_result = self.clone().db.clone()
+  /// Sends operations to RocksWriteActor. They are applied in a single batch.
+  pub fn send_batch_to_write(&self, ops: Vec<WriteOp>) {
+    let sync = self.get_sync_writes();
+    match self.addr_writer.clone() {
+      Some(w_addr) => {
+        let ops_count = ops.len();
+        match w_addr.try_send(WriteBatchMsg{ops: ops, sync: sync}) {
+          Ok(()) => {
+            info!("Batch of {} operations has been send to RocksDBWriteAgent. Event: {}", ops_count, self.event.name);
+          },
+          _ => {
+            error!("Cannot send batch to RocksDBWriteAgent. Event: {}", self.event.name);
+          }
+        }
+      },
+      None => {
+        error!("Cannot send batch to RocksDBWriteAgent. RocksDBAgent is not initialized");
+      }
+    }
+  }
+
+  /// Sends events of a poll to RocksWriteActor and waits for the result before handling next message.
+  /// Cursor of the actor moves only when the batch has been written. Otherwise filter is created again
+  /// from the stored cursor, so the same logs are fetched once more.
+  fn write_polled_batch(&self, ops: Vec<WriteOp>, last_block_log: Option<String>, ctx: &mut Context<EthActor>) {
+    let sync = self.get_sync_writes();
+    let w_addr = match self.addr_writer.clone() {
+      Some(w_addr) => w_addr,
+      None => {
+        error!("Cannot send batch to RocksDBWriteAgent. RocksDBAgent is not initialized");
+        return;
+      }
+    };
+    let ops_count = ops.len();
+    let write_request = fut::wrap_future::<_, EthActor>(w_addr.send(WriteBatchMsg{ops: ops, sync: sync}))
+      .then(move |write_result, actor, _| {
+        match write_result {
+          Ok(Ok(_)) => {
+            info!("Batch of {} operations has been written. Event: {}", ops_count, actor.event.name);
+            actor.last_block_log = last_block_log;
+          },
+          Ok(Err(err)) => {
+            error!("Batch of event {} has not been written, logs are fetched again. Error: {}", actor.event.name, err);
+            actor.filter_id = None;
+          },
+          Err(err) => {
+            error!("Cannot send batch to RocksDBWriteAgent, logs are fetched again. Event: {}. Error: {}", actor.event.name, err);
+            actor.filter_id = None;
+          }
+        };
+        fut::ok(())
+      });
+    ctx.wait(write_request);
+  }
+
+  /// Returns (start block, end block) configured for actor event
+  pub fn get_block_range(&self) -> (u64, Option<u64>) {
+    let start_block = match self.get_settings_data_default("start_block", Settings::U64(0)) {
+      Settings::U64(block) => block,
+      _ => 0
+    };
+    let end_block = match self.get_settings().get("end_block") {
+      Some(Settings::U64(block)) => Some(*block),
+      _ => None
+    };
+    (start_block, end_block)
+  }
+
+  /// Returns indexes maintained for actor event. Without settings only address typed arguments are indexed.
+  pub fn get_event_indexes(&self) -> EventIndexes {
+    match self.get_settings().get("event_indexes") {
+      Some(Settings::EventIndexes(event_indexes)) => event_indexes.clone(),
+      _ => {
+        let contract = match self.get_settings_data_default("contract", Settings::PureString(String::from("default"))) {
+          Settings::PureString(contract) => contract,
+          _ => String::from("default")
+        };
+        EventIndexes::for_event(contract, &self.event, Vec::new(), false)
+      }
+    }
+  }
+
+  pub fn get_contract_address(&self) -> Option<String> {
+    match self.get_settings().get("contract_address") {
+      Some(Settings::PureString(address)) => Some(address.clone()),
+      _ => None
+    }
+  }
+
+  /// Returns contract name of actor event, as used in index and stats entries
+  pub fn get_contract_name(&self) -> String {
+    self.get_event_indexes().contract
+  }
+
+  /// Returns "<contract>.<event>" name, which keys of actor event start with
+  pub fn get_qualified_name(&self) -> String {
+    qualified_name(&self.get_contract_name(), &self.event.name)
+  }
+
+  /// Returns last stored "block-log" cursor of actor event
+  fn get_cursor(&self) -> Result<String, String> {
+    let cursor_key = name_key(&self.get_qualified_name())?;
+    self.db.clone()
       .ok_or(String::from("There is no database atached to eth actor"))
-      .and_then(| db | get_by_key(db, String::from("aggregations"), event_name.clone()))
+      .and_then(| db | get_by_key_bytes(db, String::from("aggregations"), cursor_key))
       .and_then(| return_object | { serde_json::from_str(&return_object).map_err(| err | format!("Can not convert string into Value. Error: {}", err)) })
       .and_then(| json_map : Value | { json_map.as_object().cloned().ok_or(String::from("Can not cast json_value into object")) })
       .and_then(| json_object | { json_object.get("last_block").cloned().ok_or(String::from("There is no last_block in json_object")) })
       .and_then(| val | { val.as_str().and_then(| s | Some(s.to_string())).ok_or(String::from("Cannot cast json_object to string")) })
-      .map_err(| err | {
-        error!("There was an problem with getting new filter. {}", err);
-        String::from("0x0-0x0")
-      });
-    let last_event = match last_event_result {
-      Ok(l_e) => l_e,
+  }
+
+  fn get_new_filter(&mut self, endpoint: Endpoint) -> Option<String> {
+    let event_name = self.get_qualified_name();
+    let (start_block, end_block) = self.get_block_range();
+    // Cursor is stored as "block-log". Filter needs only its block part.
+    let from_block = match self.get_cursor() {
+      Ok(cursor) => cursor.split('-').next().unwrap_or("0x0").to_string(),
       Err(err) => {
-        err
+        warn!("There is no cursor for event {}. Filter starts from block {}. {}", event_name, start_block, err);
+        format!("0x{:x}", start_block)
       }
-     };
+    };
+    let to_block = end_block.map(| block | format!("0x{:x}", block));
 
-    match eth_json_rpc::create_new_filter(host, port, format!("{:x}", self.event.signature()), self.id, last_event) {
+    let new_filter = eth_json_rpc::create_new_filter(endpoint, format!("{:x}", self.event.signature()), self.id, from_block, to_block, self.get_contract_address());
+    self.pending_stats.add_rpc_call(&new_filter);
+    match new_filter {
       Ok(event_id) => {
-        let filter_prefix = EventPrefix{params: vec![(EventPrefixParam::PureString(event_name.clone()), prefix)]};
-        let prefix: String = generate_prefix_for_query(filter_prefix.generate_key(), 30);
-        self.send_to_write((prefix, event_id.clone()), MsgType::Filter);
+        match name_key(&event_name) {
+          Ok(filter_key) => self.send_batch_to_write(vec![WriteOp::Put(MsgType::Filter, filter_key, MsgContentType::PureString(event_id.clone()))]),
+          Err(err) => error!("Cannot store filter id of event {}. Error: {}", event_name, err)
+        };
         Some(event_id)
       },
       Err(error) => {
         match self.db.clone() {
           Some(db) => {
-            match get_by_key(db, String::from("filters"), event_name.clone()) {
+            match name_key(&event_name).and_then(|filter_key| get_by_key_bytes(db, String::from("filters"), filter_key)) {
               Ok(value) => {
                 Some(value)
               },
@@ -179,23 +331,285 @@ impl EthActor {
       }
     }
   }
+
+  /// Passes logs of a poll through the transform script in a single batch, then writes them.
+  /// Script runs in its own arbiter, so the actor waits for it without blocking others.
+  fn transform_and_write(&mut self, poll: Poll, ctx: &mut Context<EthActor>) {
+    let mut outputs: Vec<Option<Result<TransformOutput, String>>> = poll.logs.iter().map(|_| None).collect();
+    let request = match self.transform {
+      Some(ref transform) if poll.logs.iter().any(|log| log.params.is_some()) => {
+        let contract = self.get_contract_name();
+        let mut messages: Vec<LuaMessage> = Vec::new();
+        let mut sent: Vec<usize> = Vec::new();
+        for (position, log) in poll.logs.iter().enumerate() {
+          if let Some(ref params) = log.params {
+            match event_message(&contract, &log.event_key, &log.transaction, params, log.removed) {
+              Ok(message) => {
+                messages.push(message);
+                sent.push(position);
+              },
+              Err(err) => outputs[position] = Some(Err(err))
+            };
+          }
+        }
+        Some((transform.send_batch(messages), sent))
+      },
+      _ => None
+    };
+    let (request, sent) = match request {
+      Some(request) => request,
+      None => return self.write_poll(poll, outputs, ctx)
+    };
+    let transform_request = fut::wrap_future::<_, EthActor>(request)
+      .then(move |batch_outputs, actor, ctx| {
+        if let Some(ref mut transform) = actor.transform {
+          transform.restart_if_broken(&batch_outputs);
+        }
+        match batch_outputs {
+          Ok(batch_outputs) => for (position, output) in sent.into_iter().zip(batch_outputs.into_iter()) {
+            outputs[position] = Some(output);
+          },
+          Err(err) => for position in sent {
+            outputs[position] = Some(Err(err.clone()));
+          }
+        };
+        actor.write_poll(poll, outputs, ctx);
+        fut::ok(())
+      });
+    ctx.wait(transform_request);
+  }
+
+  /// Writes logs of a poll with their transform outputs, cursor and stats in a single batch.
+  /// Records emitted by the script are added to the batch. Log is written unchanged when its transform fails.
+  fn write_poll(&mut self, poll: Poll, outputs: Vec<Option<Result<TransformOutput, String>>>, ctx: &mut Context<EthActor>) {
+    let event_name = self.get_qualified_name();
+    let event_indexes = self.get_event_indexes();
+    let mut poll_stats = poll.stats;
+    let mut write_ops: Vec<WriteOp> = Vec::new();
+    for (log, output) in poll.logs.into_iter().zip(outputs.into_iter()) {
+      let params = match (log.params, output) {
+        (params, None) => params,
+        (params, Some(Ok(output))) => {
+          write_ops.extend(records_to_write_ops(&event_name, output.records));
+          // Transform sees removed logs, so it can revert its records. They are removed whatever it returns.
+          if log.removed { params } else { output.params }
+        },
+        (params, Some(Err(err))) => {
+          error!("Transform of log {} has failed, log is written unchanged. Error: {}", format_key(&log.event_key), err);
+          poll_stats.transform_failures = poll_stats.transform_failures + 1;
+          params
+        }
+      };
+      if log.removed {
+        // Log was removed by chain reorganization. Its index entries are removed too.
+        write_ops.push(WriteOp::DeleteEvent(log.event_key, event_indexes.clone()));
+        poll_stats.removed_events = poll_stats.removed_events + 1;
+        continue;
+      }
+      let params = match params {
+        Some(params) => params,
+        None => {
+          info!("Log {} has been dropped by transform script", format_key(&log.event_key));
+          continue;
+        }
+      };
+      if let Ok((_, block_number, _)) = decode_event_key(&log.event_key) {
+        poll_stats.blocks.push(block_number);
+      }
+      poll_stats.events = poll_stats.events + 1;
+      write_ops.push(WriteOp::PutEvent(log.event_key, params, log.transaction, event_indexes.clone()));
+    }
+    // Events and cursor are written together, so crash never leaves them inconsistent.
+    // Cursor is not touched when logs could not be fetched.
+    if let (false, Some(last_block_log_prefix)) = (poll.is_filter_failed, poll.last_block_log.clone()) {
+      let since_the_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+      let ts: u128 = since_the_epoch.as_secs() as u128 * 1000 + since_the_epoch.subsec_millis() as u128;
+      let msg: String = format!("{{\"last_block\": \"{}\", \"ts\": {}}}", last_block_log_prefix, ts);
+      match name_key(&event_name) {
+        Ok(cursor_key) => write_ops.push(WriteOp::Put(MsgType::Aggregation, cursor_key, MsgContentType::PureString(msg))),
+        Err(error_cursor_key) => error!("Cannot create cursor key for event {}. Error: {}", self.event.name, error_cursor_key)
+      };
+    }
+    // Stats are written in the same batch, so they always match stored events
+    let elapsed = poll.started.elapsed();
+    let mut stats = ::std::mem::replace(&mut self.pending_stats, StatsDelta::default());
+    stats.events = poll_stats.events;
+    stats.removed_events = poll_stats.removed_events;
+    stats.blocks = poll_stats.blocks;
+    stats.decode_failures = stats.decode_failures + poll_stats.decode_failures;
+    stats.transform_failures = stats.transform_failures + poll_stats.transform_failures;
+    stats.rpc_calls = stats.rpc_calls + poll_stats.rpc_calls;
+    stats.rpc_errors = stats.rpc_errors + poll_stats.rpc_errors;
+    stats.latency_ms = Some(elapsed.as_secs() * 1000 + elapsed.subsec_millis() as u64);
+    if stats.is_idle() && !poll.is_finished {
+      // Nothing to report, rpc calls of the poll are written with the next stats
+      self.pending_stats = stats;
+    } else {
+      write_ops.push(WriteOp::UpdateStats(self.get_contract_name(), self.event.name.clone(), stats));
+    }
+    self.write_polled_batch(write_ops, poll.last_block_log, ctx);
+  }
+
+  /// Checks if chain head has passed event end block, so there is nothing more to index
+  fn is_past_end_block(&mut self, endpoint: Endpoint) -> bool {
+    match self.get_block_range() {
+      (_, Some(end_block)) => {
+        let head_block = eth_json_rpc::get_block_number(endpoint, self.id);
+        self.pending_stats.add_rpc_call(&head_block);
+        match head_block {
+          Ok(head_block) => head_block > end_block,
+          Err(err) => {
+            error!("Cannot get block number for actor: {}. Error: {}", self.id, err);
+            false
+          }
+        }
+      },
+      (_, None) => false
+    }
+  }
+
+  /// Subscribes for logs of actor event when the endpoint supports it. Notifications come as a stream,
+  /// every one of them makes the actor poll its filter sooner, so logs are still taken in order from the filter.
+  fn subscribe_logs(&mut self, endpoint: Endpoint, ctx: &mut Context<EthActor>) {
+    if !endpoint.is_ipc() || self.subscription.is_some() {
+      return;
+    }
+    let subscription = eth_json_rpc::subscribe_logs(endpoint, format!("{:x}", self.event.signature()), self.id, self.get_contract_address());
+    self.pending_stats.add_rpc_call(&subscription);
+    match subscription {
+      Ok((subscription_id, logs)) => {
+        info!("Event {} is subscribed for logs. Subscription: {}", self.event.name, subscription_id);
+        self.subscription = Some(ctx.add_stream(logs));
+      },
+      Err(err) => warn!("Cannot subscribe for logs of event {}, it is only polled. Error: {}", self.event.name, err)
+    };
+  }
+
+  fn get_poll_interval(&self) -> Duration {
+    match self.subscription {
+      Some(_) => Duration::from_secs(SUBSCRIBED_POLL_INTERVAL_SECS),
+      None => Duration::from_secs(POLL_INTERVAL_SECS)
+    }
+  }
+
+  /// Polls the filter for new logs. Returns true when the event has reached its end block.
+  fn poll_logs(&mut self, _filter_id: String, ctx: &mut Context<EthActor>) -> bool {
+    let endpoint = self.get_endpoint();
+    let poll_started = Instant::now();
+    if let Some(ref mut transform) = self.transform {
+      transform.reload_if_changed();
+    }
+    let new_events = eth_json_rpc::get_new_events(endpoint.clone(), _filter_id, self.id);
+    self.pending_stats.add_rpc_call(&new_events);
+    let has_no_new_events = match new_events {
+      Ok(ref events) => events.result.is_empty(),
+      Err(_) => false
+    };
+    let is_filter_failed = new_events.is_err();
+    let event_name = self.get_qualified_name();
+    // Cursor moves only past logs which are still on chain, and only after they are written
+    let mut polled_block_log: Option<String> = None;
+    let _decode_result: Vec<(Result<Vec<u8>, String>, bool, LogTransaction, Result<HashMap<String, String>, Error>)> = match new_events {
+      Ok(events) => events.result.into_iter().map(|event| {
+        let _event = event.clone();
+        if !_event.removed {
+          polled_block_log = Some(format!("{}-{}", _event.blockNumber, _event.logIndex));
+        }
+        let event_key = event_key_from_hex(&event_name, &_event.blockNumber, &_event.logIndex);
+        let transaction = LogTransaction{hash: _event.transactionHash, index: _event.transactionIndex};
+        (event_key, _event.removed, transaction, event.decode_hashmap(self.get_event_inputs()))
+        }).collect::<Vec<(Result<Vec<u8>, String>, bool, LogTransaction, Result<HashMap<String, String>, Error>)>>(),
+      Err(err_get_new_events) => {
+        error!("Actor {:?} cannot get new events {:?}", self.id.to_string() ,err_get_new_events);
+        Vec::new()
+      },
+    };
+    if is_filter_failed {
+      // Filter could expire on the node side. New filter is created on next tick.
+      self.filter_id = None;
+    }
+    let event_indexes = self.get_event_indexes();
+    let mut poll_stats = StatsDelta::default();
+    // Many logs can come from one transaction, so its sender is fetched once. Senders of a poll come in batches.
+    let mut tx_senders: HashMap<String, String> = HashMap::new();
+    if event_indexes.index_tx_sender {
+      let mut transaction_hashes: Vec<String> = _decode_result.iter()
+        .filter(|(_, is_removed, _, decode_result)| !is_removed && decode_result.is_ok())
+        .map(|(_, _, transaction, _)| transaction.hash.clone())
+        .collect();
+      transaction_hashes.sort();
+      transaction_hashes.dedup();
+      for hashes_chunk in transaction_hashes.chunks(TRANSACTION_SENDERS_BATCH_SIZE) {
+        let senders = eth_json_rpc::get_transaction_senders(endpoint.clone(), hashes_chunk, self.id);
+        poll_stats.add_rpc_call(&senders);
+        match senders {
+          Ok(senders) => tx_senders.extend(senders),
+          Err(err) => error!("Cannot get senders of {} transactions. Error: {}", hashes_chunk.len(), err)
+        };
+      }
+    }
+    let mut logs: Vec<PolledLog> = Vec::new();
+    _decode_result.into_iter().for_each(|(event_key_result, is_removed, transaction, decode_result)| {
+      let event_key = match event_key_result {
+        Ok(key) => key,
+        Err(error_key) => {
+          error!("Cannot create key for event. Error: {}", error_key);
+          poll_stats.decode_failures = poll_stats.decode_failures + 1;
+          return;
+        }
+      };
+      match decode_result {
+        Ok(mut d_result) => {
+          // Event of failed lookup is written without sender index
+          if let (false, Some(sender)) = (is_removed, tx_senders.get(&transaction.hash)) {
+            d_result.insert(TX_SENDER_PARAM.to_string(), sender.clone());
+          }
+          logs.push(PolledLog{event_key: event_key, removed: is_removed, transaction: transaction, params: Some(d_result)});
+        },
+        // Removed log is deleted even when it cannot be decoded
+        Err(_) if is_removed => logs.push(PolledLog{event_key: event_key, removed: true, transaction: transaction, params: None}),
+        Err(error_msg) => {
+          error!("{}", error_msg);
+          poll_stats.decode_failures = poll_stats.decode_failures + 1;
+        }
+      }
+    });
+    let last_block_log = polled_block_log.or(self.last_block_log.clone());
+    // End block check is counted in stats of this poll
+    let is_finished = has_no_new_events && self.is_past_end_block(endpoint);
+    let poll = Poll{logs: logs, stats: poll_stats, last_block_log: last_block_log, is_filter_failed: is_filter_failed,
+      is_finished: is_finished, started: poll_started};
+    self.transform_and_write(poll, ctx);
+    if is_finished {
+      info!("Event {} has reached its end block. Actor {} stops polling", self.event.name, self.id);
+      self.is_finished = true;
+      // Dropping the stream ends the subscription
+      if let Some(subscription) = self.subscription.take() {
+        ctx.cancel_future(subscription);
+      }
+    }
+    is_finished
+  }
 }
 
 impl Actor for EthActor {
   type Context = Context<EthActor>;
 
   fn started(&mut self, ctx: &mut Self::Context) {
-    let (host, port) = self.get_settings_host_and_port();
-    let prefix: usize = self.get_prefix();
-    self.filter_id = self.get_new_filter(host, port, prefix);
+    let endpoint = self.get_endpoint();
+    self.filter_id = self.get_new_filter(endpoint.clone());
+    self.subscribe_logs(endpoint, ctx);
     self.addr = Some(ctx.address());
-    match self.db.clone() {
-      Some(db) => {
-        let last_block_log_from_db: String = get_by_key_with_default(db, String::from("aggregations"), self.event.name.clone(), String::from("0x0-0x0"));
+    if let Some(Settings::Transform(transform_config)) = self.get_settings().get("transform") {
+      self.transform = Some(LuaTransform::new(transform_config.clone()));
+    }
+    match self.get_cursor() {
+      Ok(last_block_log_from_db) => {
         self.last_block_log = Some(last_block_log_from_db);
-      }, 
-      None => {
-        error!("There is no db attached to Eth Actor: {}", self.event.name);
+      },
+      Err(_) => {
+        let (start_block, _) = self.get_block_range();
+        self.last_block_log = Some(format!("0x{:x}-0x0", start_block));
       }
     };
     info!("I am EthEventActor {} and I am alive! Context: {:?}", self.id.to_string(), ctx.address());
@@ -203,18 +617,6 @@ impl Actor for EthActor {
   }
 }
 
-fn generate_prefix_for_query(query: String, prefix_size: usize) -> String {
-  if query.clone().len() >= prefix_size {
-    query
-  } else {
-    let mut vec_query = Vec::from(query.clone());
-    let extend_query_size = prefix_size - query.clone().len();
-    vec_query.extend(&vec![b'-'; extend_query_size]);
-    from_utf8(&vec_query).unwrap().to_string()
-  }
-}
-
-
 impl Handler<Ping> for EthActor {
   type Result = ();
 
@@ -233,67 +635,56 @@ impl Handler<GetEvents> for EthActor{
 
   fn handle(&mut self, msg: GetEvents, ctx: &mut Context<EthActor>) {
     info!("Event {} hanlde GetEvents for event: {}", self.id, self.event.name);
-    let (host, port) = self.get_settings_host_and_port();
+    if self.is_finished {
+      return ();
+    }
+    let endpoint = self.get_endpoint();
     let actor_addr = match self.get_addr() {
       Some(addr) => addr,
       None => return (),
     };
     match self.filter_id.clone() {
       Some(_filter_id) => {
-        let new_events = eth_json_rpc::get_new_events(host, port, _filter_id, self.id);
-        let _decode_result: Vec<(EventPrefix, Result<HashMap<String, String>, Error>)> = match new_events {
-          Ok(events) => events.result.into_iter().map(|event| {
-            let mut event_params: Vec<(EventPrefixParam, usize)> = Vec::new();
-            let _event = event.clone();
-            let block_log = format!("{}-{}", _event.blockNumber, _event.logIndex);
-            self.last_block_log = Some(block_log);
-            event_params.push((EventPrefixParam::PureString(_event.blockNumber), 15));
-            event_params.push((EventPrefixParam::PureString(_event.logIndex), 10));
-            (EventPrefix{params: event_params}, event.decode_hashmap(self.get_event_inputs()))
-            }).collect::<Vec<(EventPrefix, Result<HashMap<String, String>, Error>)>>(),
-          Err(err_get_new_events) => {
-            error!("Actor {:?} cannot get new events {:?}", self.id.to_string() ,err_get_new_events);
-            Vec::new()
-          },
-        };
-        _decode_result.into_iter().for_each(|(event_prefix, decode_result)| {
-          match decode_result {
-            Ok(d_result) => {
-              let mut event_params: Vec<(EventPrefixParam, usize)> = Vec::new();
-              event_params.push((EventPrefixParam::PureString(self.event.clone().name), 30));
-              let mut _event_prefix = event_prefix.clone();
-              _event_prefix.append_at_beggining(event_params);
-              let json_value = serde_json::to_string(&d_result);
-              match json_value {
-                Ok(j_val) => {
-                  let msg_content = (_event_prefix.generate_key(), j_val);
-                  self.send_to_write(msg_content, MsgType::Event);
-                },
-                Err(error_convert_json_to_string) => {
-                  error!("Cannot convert json: {:?} to string. Error: {}", d_result, error_convert_json_to_string);
-                }
-              };
-            }, 
-            Err(error_msg) => {
-              error!("{}", error_msg);
-            }
-          }
-      });
-      let last_block_log_prefix = self.clone().last_block_log.unwrap();
-      let since_the_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-      let ts: u128 = since_the_epoch.as_secs() as u128 * 1000 + since_the_epoch.subsec_millis() as u128;
-      let msg: String = format!("{{\"last_block\": \"{}\", \"ts\": {}}}", last_block_log_prefix, ts);
-      self.send_to_write((self.clone().event.name, msg), MsgType::Aggregation);
+        if self.poll_logs(_filter_id, ctx) {
+          return ();
+        }
       },
       None => {
-        let prefix: usize = self.get_prefix();
-        self.filter_id = self.get_new_filter(host, port, prefix);
+        self.filter_id = self.get_new_filter(endpoint.clone());
+        // Subscription ends with its connection, ex. when the node restarts
+        self.subscribe_logs(endpoint, ctx);
         error!("There is no available filter id for actor: {}. Please check your connection with blockchain", self.id);
       }
     };
 
-    ctx.run_later(Duration::new(0, 1000000000), move |_, _| {
+    ctx.run_later(self.get_poll_interval(), move |_, _| {
       actor_addr.do_send(GetEvents{});
       });
   } 
-}
\ No newline at end of file
+}
+
+/// Notifications of the log subscription. Logs are not taken from them, they only make the actor poll its filter now.
+impl StreamHandler<EventResult, ()> for EthActor {
+  fn handle(&mut self, _log: EventResult, ctx: &mut Context<EthActor>) {
+    if self.is_notified || self.is_finished {
+      return;
+    }
+    self.is_notified = true;
+    ctx.run_later(Duration::from_millis(NOTIFICATION_DELAY_MS), |actor, ctx| {
+      actor.is_notified = false;
+      if let (false, Some(filter_id)) = (actor.is_finished, actor.filter_id.clone()) {
+        actor.poll_logs(filter_id, ctx);
+      }
+    });
+  }
+
+  fn error(&mut self, _err: (), _ctx: &mut Context<EthActor>) -> Running {
+    Running::Continue
+  }
+
+  /// Actor keeps polling when the subscription ends
+  fn finished(&mut self, _ctx: &mut Context<EthActor>) {
+    warn!("Log subscription of event {} has ended, it is only polled", self.event.name);
+    self.subscription = None;
+  }
+}
@@ -0,0 +1,345 @@
+extern crate actix;
+use actix::{Addr, Arbiter};
+use actix::msgs::{StartActor, StopArbiter};
+use actix_lua::{LuaActor, LuaActorBuilder, LuaMessage};
+use futures::{future, Future};
+use rlua::{Debug as LuaDebug, Error as LuaError, HookTriggers, Lua, Value as LuaValue};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use actors::rocks_write_actor::{MsgContentType, MsgType, WriteOp};
+use config::structs::contractconfig::TransformConfig;
+use db::indexes::LogTransaction;
+use db::key_schema::{decode_event_key, split_qualified_name};
+
+const TIME_LIMIT_MSG: &'static str = "Script has exceeded its time limit";
+const MEMORY_LIMIT_MSG: &'static str = "Script has exceeded its memory limit";
+const HOOK_INSTRUCTIONS: u32 = 1000;        // Time limit is checked every N lua instructions
+const RECORD_KEY_PREFIX: &'static str = "lua:";
+
+/// Globals removed before the script is loaded, so scripts cannot reach files, processes or load other code
+const SANDBOXED_GLOBALS: [&'static str; 7] = ["io", "os", "package", "require", "load", "loadfile", "dofile"];
+
+/// Functions available to transform scripts. Records are collected for a single log.
+const PRELUDE: &'static str = r#"
+__records = {}
+function emit(column_family, key, value)
+  table.insert(__records, {kind = "put", columnFamily = column_family, key = tostring(key), value = tostring(value)})
+end
+function increment(key, delta)
+  table.insert(__records, {kind = "increment", key = tostring(key), delta = math.tointeger(delta or 1)})
+end
+"#;
+
+/// Runs `transform` of the script for every log of a poll. Every log has its own time limit.
+/// Memory limit is kept by the allocator of the vm, which fails allocations above it with "not enough memory".
+const HANDLE_SCRIPT: &'static str = r#"
+local outputs = {}
+for position, event in pairs(ctx.msg) do
+  __records = {}
+  __start_timer()
+  local ok, result = pcall(transform, event)
+  __stop_timer()
+  if not ok then
+    if result == "not enough memory" then
+      return {error = __memory_limit_msg}
+    end
+    outputs[position] = {error = tostring(result)}
+  elseif result == nil or event.removed then
+    outputs[position] = {records = __records}
+  elseif type(result) ~= "table" or type(result.params) ~= "table" then
+    outputs[position] = {error = "Transform should return event with params or nil"}
+  else
+    outputs[position] = {params = result.params, records = __records}
+  end
+end
+return {outputs = outputs}
+"#;
+
+/// Record emitted by a script with `emit` or `increment`
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransformRecord {
+  Put(String, String, String),    // Column family, key, value
+  Increment(String, i64)          // Key of a counter in "aggregations", delta
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransformOutput {
+  pub params: Option<HashMap<String, String>>,    // Params to write. None drops the log
+  pub records: Vec<TransformRecord>
+}
+
+fn build_lua_actor(script: &str, config: &TransformConfig) -> Result<LuaActor, String> {
+  let timeout = Duration::from_millis(config.get_timeout_ms());
+  let memory_limit_kb = config.get_memory_limit_kb();
+  // Deadline of the current log. Hook is called during the whole life of the vm, so it is set only by transform calls.
+  let deadline: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+  LuaActorBuilder::new()
+    .on_started_with_lua(&format!("{}\n{}", PRELUDE, script))
+    .on_handle_with_lua(HANDLE_SCRIPT)
+    .with_vm(move |vm: &Lua| {
+      for name in SANDBOXED_GLOBALS.iter() {
+        vm.globals().set(*name, LuaValue::Nil)?;
+      }
+      vm.set_memory_limit(Some(memory_limit_kb as usize * 1024));
+      let start_deadline = deadline.clone();
+      let start_timer = vm.create_function(move |_, ()| {
+        *start_deadline.lock().unwrap() = Some(Instant::now() + timeout);
+        Ok(())
+      })?;
+      let stop_deadline = deadline.clone();
+      let stop_timer = vm.create_function(move |_, ()| {
+        *stop_deadline.lock().unwrap() = None;
+        Ok(())
+      })?;
+      vm.globals().set("__start_timer", start_timer)?;
+      vm.globals().set("__stop_timer", stop_timer)?;
+      vm.globals().set("__memory_limit_msg", MEMORY_LIMIT_MSG)?;
+      let hook_deadline = deadline.clone();
+      vm.set_hook(HookTriggers{every_nth_instruction: Some(HOOK_INSTRUCTIONS), ..HookTriggers::default()}, move |_: &LuaDebug| {
+        match *hook_deadline.lock().unwrap() {
+          Some(log_deadline) if Instant::now() > log_deadline => Err(LuaError::RuntimeError(TIME_LIMIT_MSG.to_string())),
+          _ => Ok(())
+        }
+      });
+      Ok(())
+    })
+    .build()
+    .map_err(|err| format!("Cannot load transform script {}. Error: {}", config.script_path, err))
+}
+
+fn read_script(config: &TransformConfig) -> Result<(String, Option<SystemTime>), String> {
+  let modified = fs::metadata(&config.script_path).and_then(|metadata| metadata.modified()).ok();
+  fs::read_to_string(&config.script_path)
+    .map(|script| (script, modified))
+    .map_err(|err| format!("Cannot read transform script {}. Error: {}", config.script_path, err))
+}
+
+/// Checks if transform script can be read and loaded
+pub fn check_script(config: &TransformConfig) -> Result<(), String> {
+  let (script, _) = read_script(config)?;
+  build_lua_actor(&script, config).map(|_| ())
+}
+
+/// Builds lua table of a log: event, contract, block, log, transactionHash, transactionIndex, removed and params
+pub fn event_message(contract: &str, event_key: &[u8], transaction: &LogTransaction, params: &HashMap<String, String>, removed: bool) -> Result<LuaMessage, String> {
+  let (name, block_number, log_index) = decode_event_key(event_key)?;
+  let (_, event_name) = split_qualified_name(&name);
+  let mut table: HashMap<String, LuaMessage> = HashMap::new();
+  table.insert(String::from("event"), LuaMessage::String(event_name.to_string()));
+  table.insert(String::from("contract"), LuaMessage::String(contract.to_string()));
+  table.insert(String::from("block"), LuaMessage::Integer(block_number as i64));
+  table.insert(String::from("log"), LuaMessage::Integer(log_index as i64));
+  table.insert(String::from("transactionHash"), LuaMessage::String(transaction.hash.clone()));
+  table.insert(String::from("transactionIndex"), LuaMessage::String(transaction.index.clone()));
+  table.insert(String::from("removed"), LuaMessage::Boolean(removed));
+  table.insert(String::from("params"), LuaMessage::Table(params.iter()
+    .map(|(name, value)| (name.clone(), LuaMessage::String(value.clone())))
+    .collect()));
+  Ok(LuaMessage::Table(table))
+}
+
+fn message_to_string(message: &LuaMessage) -> Option<String> {
+  match *message {
+    LuaMessage::String(ref value) => Some(value.clone()),
+    LuaMessage::Integer(value) => Some(value.to_string()),
+    LuaMessage::Number(value) => Some(value.to_string()),
+    LuaMessage::Boolean(value) => Some(value.to_string()),
+    _ => None
+  }
+}
+
+fn parse_record(record: &LuaMessage) -> Result<TransformRecord, String> {
+  let fields = match *record {
+    LuaMessage::Table(ref fields) => fields,
+    _ => return Err(String::from("Record should be a table"))
+  };
+  let get_field = |name: &str| fields.get(name).and_then(message_to_string).ok_or(format!("Record has no {}", name));
+  match get_field("kind")?.as_ref() {
+    "put" => Ok(TransformRecord::Put(get_field("columnFamily")?, get_field("key")?, get_field("value")?)),
+    "increment" => match fields.get("delta") {
+      Some(&LuaMessage::Integer(delta)) => Ok(TransformRecord::Increment(get_field("key")?, delta)),
+      _ => Err(String::from("Increment delta should be an integer"))
+    },
+    kind => Err(format!("Unknown record kind {}", kind))
+  }
+}
+
+/// Parses response of the handle script
+pub fn parse_output(response: LuaMessage) -> Result<TransformOutput, String> {
+  let mut fields = match response {
+    LuaMessage::Table(fields) => fields,
+    other => return Err(format!("Unexpected transform response {:?}", other))
+  };
+  if let Some(error_msg) = fields.get("error").and_then(message_to_string) {
+    return Err(error_msg);
+  }
+  let params = match fields.remove("params") {
+    Some(LuaMessage::Table(params)) => Some(params.iter()
+      .map(|(name, value)| message_to_string(value).map(|value| (name.clone(), value)).ok_or(format!("Param {} should be a string or a number", name)))
+      .collect::<Result<HashMap<String, String>, String>>()?),
+    _ => None
+  };
+  // Lua lists come as tables with "1", "2", ... keys
+  let mut records: Vec<(usize, TransformRecord)> = Vec::new();
+  if let Some(LuaMessage::Table(record_table)) = fields.remove("records") {
+    for (position, record) in record_table.iter() {
+      let position = position.parse::<usize>().map_err(|_| format!("Records should be a list, but has key {}", position))?;
+      records.push((position, parse_record(record)?));
+    }
+  }
+  records.sort_by_key(|&(position, _)| position);
+  Ok(TransformOutput{params: params, records: records.into_iter().map(|(_, record)| record).collect()})
+}
+
+/// Lua table of logs of a poll, keyed by their position from "1"
+pub fn batch_message(messages: Vec<LuaMessage>) -> LuaMessage {
+  LuaMessage::Table(messages.into_iter().enumerate()
+    .map(|(position, message)| ((position + 1).to_string(), message))
+    .collect())
+}
+
+/// Parses response of the handle script to a batch of `count` logs. Outputs are in order of the logs.
+/// Error of the whole batch (memory limit, stuck vm) is returned as Err.
+pub fn parse_batch_output(response: LuaMessage, count: usize) -> Result<Vec<Result<TransformOutput, String>>, String> {
+  let mut fields = match response {
+    LuaMessage::Table(fields) => fields,
+    other => return Err(format!("Unexpected transform response {:?}", other))
+  };
+  if let Some(error_msg) = fields.get("error").and_then(message_to_string) {
+    return Err(error_msg);
+  }
+  let mut outputs = match fields.remove("outputs") {
+    Some(LuaMessage::Table(outputs)) => outputs,
+    _ => HashMap::new()
+  };
+  Ok((1..count + 1).map(|position| match outputs.remove(&position.to_string()) {
+    Some(output) => parse_output(output),
+    None => Err(format!("Transform has not returned output of log {}", position))
+  }).collect())
+}
+
+/// Turns records of a script into write operations. Keys are stored as "lua:<contract>.<event>:<key>",
+/// so scripts never overwrite cursors, aggregations and stats of cacherz.
+pub fn records_to_write_ops(event_name: &str, records: Vec<TransformRecord>) -> Vec<WriteOp> {
+  records.into_iter().filter_map(|record| {
+    match record {
+      TransformRecord::Put(column_family, key, value) => {
+        let msg_type = match column_family.as_ref() {
+          "aggregations" => MsgType::Aggregation,
+          "stats" => MsgType::Stat,
+          _ => {
+            error!("Transform of {} cannot emit records into column family {}", event_name, column_family);
+            return None;
+          }
+        };
+        let record_key = format!("{}{}:{}", RECORD_KEY_PREFIX, event_name, key).into_bytes();
+        Some(WriteOp::Put(msg_type, record_key, MsgContentType::PureString(value)))
+      },
+      TransformRecord::Increment(key, delta) => {
+        Some(WriteOp::Increment(format!("{}{}:{}", RECORD_KEY_PREFIX, event_name, key).into_bytes(), delta))
+      }
+    }
+  }).collect()
+}
+
+/// Lua transform of a single event. Script runs in an actix-lua actor in its own arbiter
+/// and is reloaded when its file changes.
+#[derive(Clone)]
+pub struct LuaTransform {
+  pub config: TransformConfig,
+  arbiter: Option<Addr<Arbiter>>,
+  addr: Option<Addr<LuaActor>>,
+  modified: Option<SystemTime>      // Modification time of the loaded script
+}
+
+impl fmt::Debug for LuaTransform {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "LuaTransform {{ config: {:?}, is_loaded: {}, modified: {:?} }}", self.config, self.addr.is_some(), self.modified)
+  }
+}
+
+impl LuaTransform {
+  pub fn new(config: TransformConfig) -> LuaTransform {
+    LuaTransform{config: config, arbiter: None, addr: None, modified: None}
+  }
+
+  /// Stops arbiter of the loaded script, so its thread and vm do not outlive it
+  fn stop(&mut self) {
+    if let Some(arbiter) = self.arbiter.take() {
+      arbiter.do_send(StopArbiter(0));
+    }
+    self.addr = None;
+  }
+
+  /// Loads the script when it is not loaded yet or its file has changed. Broken script leaves the old one running.
+  pub fn reload_if_changed(&mut self) {
+    let modified = fs::metadata(&self.config.script_path).and_then(|metadata| metadata.modified()).ok();
+    if self.addr.is_some() && modified == self.modified {
+      return;
+    }
+    let loaded = read_script(&self.config)
+      .and_then(|(script, modified)| build_lua_actor(&script, &self.config).map(|_| (script, modified)));
+    match loaded {
+      Ok((script, modified)) => {
+        self.stop();
+        let config = self.config.clone();
+        let arbiter = Arbiter::new(format!("transform {}", self.config.script_path));
+        // Lua vm can not be moved between threads, so the actor is built again inside its arbiter
+        let started = arbiter.send(StartActor::new(move |_| {
+          build_lua_actor(&script, &config).expect("Transform script has been checked")
+        })).wait();
+        match started {
+          Ok(addr) => {
+            self.addr = Some(addr);
+            self.arbiter = Some(arbiter);
+            info!("Transform script {} has been loaded", self.config.script_path);
+          },
+          Err(err) => {
+            error!("Cannot start transform script {}. Error: {}", self.config.script_path, err);
+            arbiter.do_send(StopArbiter(0));
+          }
+        };
+        self.modified = modified;
+      },
+      Err(err) => {
+        error!("{}", err);
+        // Failed reload is not repeated until the file changes again
+        self.modified = modified;
+      }
+    }
+  }
+
+  /// Sends all logs of a poll to the script in a single message. Outputs come in order of the logs.
+  pub fn send_batch(&self, messages: Vec<LuaMessage>) -> Box<dyn Future<Item = Vec<Result<TransformOutput, String>>, Error = String>> {
+    let addr = match self.addr.clone() {
+      Some(addr) => addr,
+      None => return Box::new(future::err(format!("Transform script {} is not loaded", self.config.script_path)))
+    };
+    let count = messages.len();
+    let script_path = self.config.script_path.clone();
+    // Time limit is enforced inside the vm for every log. Timeout of the response only guards against a stuck actor.
+    let response_timeout = Duration::from_millis((self.config.get_timeout_ms() * 10 + 1000) * count.max(1) as u64);
+    Box::new(addr.send(batch_message(messages))
+      .timeout(response_timeout)
+      .map_err(move |err| format!("Transform script {} has not responded. Error: {}", script_path, err))
+      .and_then(move |response| parse_batch_output(response, count)))
+  }
+
+  /// Starts the vm again when a batch has exceeded its limits, so state left by the broken run is dropped
+  pub fn restart_if_broken(&mut self, outputs: &Result<Vec<Result<TransformOutput, String>>, String>) {
+    let is_broken = |err: &String| err.contains(TIME_LIMIT_MSG) || err.contains(MEMORY_LIMIT_MSG) || err.contains("has not responded");
+    let broken_error = match *outputs {
+      Err(ref err) if is_broken(err) => Some(err.clone()),
+      Ok(ref outputs) => outputs.iter().filter_map(|output| output.as_ref().err()).find(|err| is_broken(err)).cloned(),
+      _ => None
+    };
+    if let Some(err) = broken_error {
+      warn!("Transform script {} is started again. {}", self.config.script_path, err);
+      self.stop();
+      self.reload_if_changed();
+    }
+  }
+}
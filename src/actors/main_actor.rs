@@ -4,12 +4,22 @@ use actix::{Actor, Addr, Context, System, AsyncContext, Arbiter, Handler, Messag
 use actors::eth_actor::EthActor;
 use actors::rocks_write_actor::RocksWriteActor;
 use actors::rocks_read_actor::RocksReadActor;
+use actors::compaction_actor::CompactionActor;
+use actors::pruning_actor::PruningActor;
+use actors::catch_up_actor::CatchUpActor;
 use std::collections::HashMap;
 use db::cachedb::CacheDB;
+use db::schema_version::{check_schema_version, verify_schema_version};
+use db::value_codec::check_value_format;
+use db::backup::check_chain_id;
+use db::retention::RetentionPolicy;
+use db::aggregations::check_aggregations;
+use config::structs::storageconfig::StorageConfig;
 use actors::structs::settings::Settings;
 use actors::traits::setupable::Setupable;
 use futures::{future, Future};
 use web::web;
+use web::admin;
 
 /// Struct with description of Main actor state
 #[derive(Clone, Debug)]
@@ -60,11 +70,26 @@ impl<'a> MainActor {
     let main_addr = self.clone().start();
     let system_name = self.system_name.clone();
     let system = System::new(system_name.to_string());
+    if self.is_serve_only() {
+      // Serve-only instance reads database of another process, so it has no event, write or pruning actors
+      Arbiter::spawn(main_addr.send(MsgCreateWebService{}).then(|res| {
+        match res {
+          Ok(()) => info!("Serve-only web service has been created!"),
+          Err(error_web_service_creation) => error!("Cannot create web service. Reason: {}", error_web_service_creation)
+        }
+        future::result(Ok(()))
+      }));
+      system.run();
+      return;
+    }
     let create_read_write_actor = main_addr.send(MsgCreateReadWriteActor{});
     let create_web_service = main_addr.send(MsgCreateWebService{});
     Arbiter::spawn(create_read_write_actor.then(move |res| {
       match res {
         Ok(Some((read_actor_addr, write_actor_addr, db))) => {
+          if let Some(pruning_db) = db.clone() {
+            self.run_pruning_actor(pruning_db, write_actor_addr.clone());
+          }
           match self.run_event_actors(write_actor_addr.clone(), read_actor_addr.clone(), db.clone()) {
             Ok(run_event_msg) => info!("{}", run_event_msg),
             Err(err_event_msg) => error!("{}", err_event_msg)
@@ -85,6 +110,45 @@ impl<'a> MainActor {
     system.run();
   }
 
+  /// Serve-only instance opens database of another process read-only and serves only web api
+  pub fn is_serve_only(&self) -> bool {
+    match self.get_settings_data_default("serve_only", Settings::Bool(false)) {
+      Settings::Bool(serve_only) => serve_only,
+      _ => false
+    }
+  }
+
+  /// Function responsible for running actor which reopens read-only database of serve-only instance
+  pub fn run_catch_up_actor(&self, db: CacheDB) -> Addr<CatchUpActor> {
+    let interval_secs = match self.get_settings_data_default("catch_up_secs", Settings::U64(5)) {
+      Settings::U64(secs) => secs.max(1),
+      _ => 5
+    };
+    Arbiter::start(move |_| CatchUpActor::create_new(db, interval_secs))
+  }
+
+  /// Function responsible for running pruning actor when any event has retention policy
+  pub fn run_pruning_actor(&self, db: CacheDB, write_actor_addr: Addr<RocksWriteActor>) -> Option<Addr<PruningActor>> {
+    let policies: Vec<RetentionPolicy> = self.eth_actors.iter()
+      .filter_map(|actor| match actor.get_settings().get("retention") {
+        Some(Settings::Retention(retention)) => Some(RetentionPolicy{
+          event_name: actor.get_qualified_name(),
+          event_indexes: actor.get_event_indexes(),
+          retention: retention.clone()
+        }),
+        _ => None
+      })
+      .collect();
+    if policies.is_empty() {
+      return None;
+    }
+    let endpoint = match self.get_settings().get("endpoint") {
+      Some(Settings::Endpoint(endpoint)) => Some(endpoint.clone()),
+      _ => None
+    };
+    Some(Arbiter::start(move |_| PruningActor::create_new(db, write_actor_addr, policies, endpoint)))
+  }
+
   /// Function responsible for runing all MainActor event actors holded as a "eth_actor" field
   pub fn run_event_actors(self, write_actor_addr: Addr<RocksWriteActor>, read_actor_addr: Addr<RocksReadActor>, db: Option<CacheDB>) -> Result<String, String> {
     for mut actor in self.eth_actors {
@@ -126,10 +190,48 @@ impl<'a> MainActor {
           let empty_column_fam: Vec<&str> = Vec::new();
           empty_column_fam
         };
-        let db = CacheDB::create(db_path, column_families, prefix_length).map_err(|create_db_error| {
-          System::current().stop();
-          format!("Cannot create database. Reason: {}", create_db_error)
-        });
+        let storage_config = match self.get_settings_data_default("storage", Settings::Storage(StorageConfig::default())) {
+          Settings::Storage(storage) => storage,
+          _ => StorageConfig::default()
+        };
+        // Database with unknown layout version is never used
+        let chain_id = match self.get_settings_data_default("chain_id", Settings::PureString(String::new())) {
+          Settings::U64(node_chain_id) => Some(node_chain_id),
+          _ => None
+        };
+        if self.is_serve_only() {
+          let db = CacheDB::open_read_only(db_path, prefix_length, storage_config)
+            .and_then(|opened_database| verify_schema_version(&opened_database).map(|_| opened_database))
+            .map_err(|open_db_error| {
+              System::current().stop();
+              error!("Cannot open database read-only. Reason: {}", open_db_error);
+              format!("Cannot open database read-only. Reason: {}", open_db_error)
+            })?;
+          self.db = Some(db.clone());
+          return Ok(db);
+        }
+        let value_format = storage_config.get_value_format();
+        let event_aggregations: Vec<(String, Vec<_>)> = self.eth_actors.iter()
+          .map(|actor| (actor.get_qualified_name(), actor.get_event_indexes().aggregations))
+          .collect();
+        let db = CacheDB::create_with_config(db_path, column_families, prefix_length, storage_config)
+          .and_then(|created_database| check_schema_version(&created_database).map(|_| created_database))
+          .and_then(|created_database| check_value_format(&*created_database.storage, value_format).map(|_| created_database))
+          .and_then(|created_database| match chain_id {
+            Some(node_chain_id) => check_chain_id(&created_database, node_chain_id).map(|_| created_database),
+            None => Ok(created_database)
+          })
+          // Aggregations are rebuilt before event actors write anything
+          .and_then(|created_database| {
+            for (event_name, aggregations) in event_aggregations.iter() {
+              check_aggregations(&created_database, event_name, aggregations)?;
+            }
+            Ok(created_database)
+          })
+          .map_err(|create_db_error| {
+            System::current().stop();
+            format!("Cannot create database. Reason: {}", create_db_error)
+          });
         match db {
           Ok(created_database) => {
             self.db = Some(created_database.clone());
@@ -161,13 +263,6 @@ impl<'a> MainActor {
         self.create_db().unwrap()   
       }
     };
-    let prefix_length = match  self.get_settings_data_default("prefix", Settings::USize(30)) {
-          Settings::USize(prefix) => prefix,
-          _ => {
-            error!("Cannot get proper prefix value from Settings");
-            30
-          }
-    };
     let web_host: String = match self.get_settings_data_default("webHost", Settings::PureString(String::from("localhost"))) {
       Settings::PureString(webHost) => webHost,
       _ => String::from("")
@@ -176,7 +271,39 @@ impl<'a> MainActor {
       Settings::PureString(webHost) => webHost,
       _ => String::from("")
     };
-    web::run(web_host, web_port, db, prefix_length);
+    // Read-only database is never compacted, it follows compactions of the primary
+    let compaction_actor_addr = if self.is_serve_only() {
+      self.run_catch_up_actor(db.clone());
+      None
+    } else {
+      Some(self.create_compaction_actor(db.clone()))
+    };
+    let query_scripts = match self.get_settings().get("query_scripts") {
+      Some(Settings::QueryScripts(query_scripts)) => Some(query_scripts.clone()),
+      _ => None
+    };
+    let export_sources = match self.get_settings().get("export_sources") {
+      Some(Settings::ExportSources(export_sources)) => export_sources.clone(),
+      _ => Vec::new()
+    };
+    web::run(web_host, web_port, db.clone(), query_scripts, export_sources);
+    if let Some(Settings::Admin(admin_config)) = self.get_settings().get("admin") {
+      info!("Admin api listens on {}:{}", admin_config.host, admin_config.port);
+      admin::run(admin_config.clone(), db, compaction_actor_addr);
+    }
+  }
+
+  /// Function responsible for creating compaction actor. Compaction blocks, so the actor runs in its own arbiter.
+  pub fn create_compaction_actor(&mut self, db: CacheDB) -> Addr<CompactionActor> {
+    let column_families = match self.get_settings_data_default("column_families", Settings::VecStr(Vec::new())) {
+      Settings::VecStr(column_fam_vecstr) => column_fam_vecstr,
+      _ => Vec::new()
+    };
+    let storage_config = match self.get_settings_data_default("storage", Settings::Storage(StorageConfig::default())) {
+      Settings::Storage(storage) => storage,
+      _ => StorageConfig::default()
+    };
+    Arbiter::start(move |_| CompactionActor::create_new(db, column_families, &storage_config))
   }
 
   /// Function responsible for crating read actor
@@ -2,5 +2,9 @@
  pub mod eth_actor;
  pub mod rocks_write_actor;
  pub mod rocks_read_actor;
+ pub mod compaction_actor;
+ pub mod pruning_actor;
+ pub mod catch_up_actor;
+ pub mod lua_transform;
  pub mod traits;
  pub mod structs; 
\ No newline at end of file
@@ -0,0 +1,69 @@
+extern crate actix;
+use actix::{Actor, Addr, Context, AsyncContext};
+use futures::Future;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Duration;
+use actors::rocks_write_actor::{RocksWriteActor, WriteBatchMsg, WriteOp};
+use db::cachedb::CacheDB;
+use db::retention::{apply_retention, RetentionPolicy};
+use eth::endpoint::Endpoint;
+use eth::eth_json_rpc;
+
+const PRUNING_INTERVAL_SECS: u64 = 600;
+const MAX_CACHED_TIMESTAMPS: usize = 10000;
+
+/// Actor applying retention policies of events. Pruning blocks the actor, so it should be started in its own arbiter.
+/// Batches are written by RocksWriteActor, so they never interleave with ingestion.
+pub struct PruningActor {
+  pub db: CacheDB,
+  pub writer: Addr<RocksWriteActor>,
+  pub policies: Vec<RetentionPolicy>,
+  pub endpoint: Option<Endpoint>,     // Source of block timestamps for retention by age
+  block_timestamps: RefCell<HashMap<u64, u64>>  // Timestamps read from the node, blocks are searched again on every run
+}
+
+impl PruningActor {
+  pub fn create_new(db: CacheDB, writer: Addr<RocksWriteActor>, policies: Vec<RetentionPolicy>, endpoint: Option<Endpoint>) -> PruningActor {
+    PruningActor{db: db, writer: writer, policies: policies, endpoint: endpoint, block_timestamps: RefCell::new(HashMap::new())}
+  }
+
+  fn run_pruning(&mut self) {
+    if self.block_timestamps.borrow().len() > MAX_CACHED_TIMESTAMPS {
+      self.block_timestamps.borrow_mut().clear();
+    }
+    let block_timestamps = &self.block_timestamps;
+    let writer = &self.writer;
+    // Waits for every batch, so the next one is built from what the previous one has left
+    let write = |ops: Vec<WriteOp>| writer.send(WriteBatchMsg{ops: ops, sync: false}).wait()
+      .map_err(|err| format!("Cannot send pruning batch to RocksDBWriteAgent. Error: {}", err))
+      .and_then(|write_result| write_result);
+    for policy in self.policies.iter() {
+      let get_timestamp = self.endpoint.clone().map(|endpoint| {
+        move |block_number: u64| {
+          if let Some(timestamp) = block_timestamps.borrow().get(&block_number) {
+            return Ok(*timestamp);
+          }
+          let timestamp = eth_json_rpc::get_block_timestamp(endpoint.clone(), block_number, 0)?;
+          block_timestamps.borrow_mut().insert(block_number, timestamp);
+          Ok(timestamp)
+        }
+      });
+      match apply_retention(&self.db, policy, get_timestamp, &write) {
+        Ok(0) => (),
+        Ok(pruned) => info!("Retention of {} has removed {} events", policy.event_name, pruned),
+        Err(err) => error!("Cannot apply retention of {}. Error: {}", policy.event_name, err)
+      };
+    }
+  }
+}
+
+impl Actor for PruningActor {
+  type Context = Context<PruningActor>;
+
+  fn started(&mut self, ctx: &mut Self::Context) {
+    info!("Retention is applied to {:?}", self.policies.iter().map(|policy| policy.event_name.clone()).collect::<Vec<String>>());
+    self.run_pruning();
+    ctx.run_interval(Duration::new(PRUNING_INTERVAL_SECS, 0), |actor, _| actor.run_pruning());
+  }
+}
@@ -1,6 +1,5 @@
 extern crate actix;
 use actix::{Actor, Addr, Context, Handler, Message, AsyncContext};
-use rocksdb::{SeekKey, DBVector};
 use chrono::prelude::*;
 use db::cachedb::CacheDB;
 
@@ -72,34 +71,31 @@ impl Handler<MsgRead> for RocksReadActor {
             MsgType::Setting => "settings",
             MsgType::Stat => "stats"
         };
-        let result = match self.db.db.cf_handle(cf) {
-            Some(cf_handle) => {
+        let result = match self.db.storage.iter(cf) {
+            Ok(mut iter) => {
                 let get_result: Vec<(Vec<u8>, Vec<u8>)> = match msg.msg_content.clone() {
                     MsgContentType::Prefix(prefix, size) => {
                         let mut return_msg: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
-                        let mut iter = self.db.db.iter_cf(cf_handle);
-                        iter.seek(SeekKey::Key(prefix.as_bytes()));
+                        iter.seek(prefix.as_bytes());
                         let mut key_count = 0;
                         while iter.valid() && size > key_count {
                             key_count = key_count + 1;
-                            return_msg.push(iter.kv().unwrap());
+                            return_msg.push((iter.key().to_vec(), iter.value().to_vec()));
                             iter.next();
                         };
                         return_msg
                     },
                     MsgContentType::Key(key)=> {
-                        let get_result: Option<DBVector> =  self.db.db.get_cf(cf_handle, key.as_bytes())
+                        let get_result: Option<Vec<u8>> =  self.db.storage.get(cf, key.as_bytes())
                             .expect(&format!("Cannot get such key: {} from cf: {}", key, cf));
-                        let db_vector = get_result.expect(&format!("Cannot get such key: {} from cf: {}", key, cf));
-                        let db_vector_str = db_vector.to_utf8().expect(&format!("Cannot convert vector value to utf8"));
-                        let db_vector_as_vec_bytes = db_vector_str.as_bytes().to_vec();
+                        let db_vector_as_vec_bytes = get_result.expect(&format!("Cannot get such key: {} from cf: {}", key, cf));
                       vec!((key.as_bytes().to_vec(), db_vector_as_vec_bytes))
                     },
 
                 };
                 Ok(get_result)
             },
-            None => {
+            Err(_) => {
                 error!("There is no column family as: {} for message: {:?}", cf, msg);
                 Err("".to_string())
                 
@@ -1,10 +1,14 @@
 extern crate actix;
 use actix::{Actor, Addr, Context, Handler, Message, AsyncContext};
-use rocksdb::{DB, Writable};
 use chrono::prelude::*;
+use std::collections::{BTreeMap, HashMap};
 use db::cachedb::CacheDB;
+use db::indexes::{EventBatch, EventIndexes, LogTransaction};
+use db::key_schema::{qualified_name, split_qualified_name};
+use db::storage::Storage;
+use db::stats::{add_stats_delta, StatsDelta};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum MsgType {
   Event,
   Aggregation,
@@ -35,6 +39,51 @@ impl Message for WriteMsg {
   type Result = Result<String, String>;
 }
 
+/// Single operation of WriteBatchMsg
+#[derive(Debug, Clone)]
+pub enum WriteOp {
+  Put(MsgType, Vec<u8>, MsgContentType),
+  Delete(MsgType, Vec<u8>),
+  PutEvent(Vec<u8>, HashMap<String, String>, LogTransaction, EventIndexes),  // Event with decoded params and everything indexed for it
+  DeleteEvent(Vec<u8>, EventIndexes),                                         // Removes event together with its index entries
+  PruneEvents(Vec<u8>, Vec<u8>, EventIndexes),                                // Removes events of a key range with their entries, leaving aggregations
+  UpdateStats(String, String, StatsDelta),                                    // Adds poll counters into stats of (contract, event)
+  Increment(Vec<u8>, i64)                                                     // Adds delta to a counter in "aggregations"
+}
+
+/// Message with operations applied atomically in a single StorageBatch
+#[derive(Debug)]
+pub struct WriteBatchMsg {
+  pub ops: Vec<WriteOp>,
+  pub sync: bool            // Fsync write ahead log before returning
+}
+
+impl Message for WriteBatchMsg {
+  type Result = Result<String, String>;
+}
+
+/// Returns column family name for a given message type
+pub fn get_column_family(msg_type: &MsgType) -> &'static str {
+  match *msg_type {
+    MsgType::Event => "events",
+    MsgType::Aggregation => "aggregations",
+    MsgType::Log => "logs",
+    MsgType::Filter => "filters",
+    MsgType::Setting => "settings",
+    MsgType::Stat => "stats"
+  }
+}
+
+fn content_to_string(msg_content: MsgContentType) -> String {
+  match msg_content {
+    MsgContentType::PureString(msg_string) => msg_string,
+    MsgContentType::Int32(msg_int_i32) => msg_int_i32.to_string(),
+    MsgContentType::Int64(msg_int_i64) => msg_int_i64.to_string(),
+    MsgContentType::UInt32(msg_int_u32) => msg_int_u32.to_string(),
+    MsgContentType::UInt64(msg_int_u64) => msg_int_u64.to_string(),
+  }
+}
+
 /// WriteActor state declaration
 #[derive(Debug, Clone)]
 pub struct RocksWriteActor {
@@ -60,45 +109,90 @@ impl Handler<WriteMsg> for RocksWriteActor {
   type Result = Result<String, String>;
 
   fn handle(&mut self, msg: WriteMsg, ctx: &mut Context<RocksWriteActor>) -> Result<String, String> {
-    let cf = match msg.msg_type {
-      MsgType::Event => "events",
-      MsgType::Aggregation => "aggregations",
-      MsgType::Log => "logs",
-      MsgType::Filter => "filters",
-      MsgType::Setting => "settings",
-      MsgType::Stat => "stats"
-    };
-    match self.db.db.cf_handle(cf) {
-      Some(cf_handle) => {
-        let put_result = match msg.msg_content.clone() {
-          (key, MsgContentType::PureString(msg_string)) => {
-            self.db.db.put_cf(cf_handle, key.as_bytes(), msg_string.as_bytes())
-            },
-          (key, MsgContentType::Int32(msg_int_i32)) => self.db.db.put_cf(cf_handle, key.as_bytes(), msg_int_i32.to_string().as_bytes()),
-          (key, MsgContentType::Int64(msg_int_i64)) => self.db.db.put_cf(cf_handle, key.as_bytes(), msg_int_i64.to_string().as_bytes()),
-          (key, MsgContentType::UInt32(msg_int_u32)) => self.db.db.put_cf(cf_handle, key.as_bytes(), msg_int_u32.to_string().as_bytes()),
-          (key, MsgContentType::UInt64(msg_int_u64)) => self.db.db.put_cf(cf_handle, key.as_bytes(), msg_int_u64.to_string().as_bytes()),
-        };
-        match put_result {
-          Ok(()) => {
-            info!("Message for column '{}' has been writen. Content: {}: {:?}", cf, msg.msg_content.0, msg.msg_content.1);
-            Ok(format!("Message: {} {:?} has been written",  msg.msg_content.0, msg.msg_content.1))
-          },
-          Err(error_put_result) => {
-            error!("Can not save message for column {}. Message content:  {}: {:?}. Error: {}", cf, msg.msg_content.0, msg.msg_content.1, error_put_result);
-            Err(error_put_result)
-          }
-        }
+    let cf = get_column_family(&msg.msg_type);
+    if !self.db.storage.has_column_family(cf) {
+      let _err_msg = format!("There is no column family as: {} for message: {:?}", cf, msg);
+      error!("There is no column family as: {} for message: {:?}", cf, msg);
+      return Err(_err_msg);
+    }
+    let (key, msg_content) = msg.msg_content.clone();
+    let put_result = self.db.storage.put(cf, key.as_bytes(), content_to_string(msg_content).as_bytes());
+    match put_result {
+      Ok(()) => {
+        info!("Message for column '{}' has been writen. Content: {}: {:?}", cf, msg.msg_content.0, msg.msg_content.1);
+        Ok(format!("Message: {} {:?} has been written",  msg.msg_content.0, msg.msg_content.1))
       },
-      None => {
-        let _err_msg = format!("There is no column family as: {} for message: {:?}", cf, msg);
-        error!("There is no column family as: {} for message: {:?}", cf, msg);
-        Err(_err_msg)
+      Err(error_put_result) => {
+        error!("Can not save message for column {}. Message content:  {}: {:?}. Error: {}", cf, msg.msg_content.0, msg.msg_content.1, error_put_result);
+        Err(error_put_result)
       }
     }
   }
 }
 
+/// Writes operations in a single batch. Stats of the batch are summed per event and written after its events,
+/// together with aggregations skipped for its events.
+pub fn write_batch(storage: &dyn Storage, ops: Vec<WriteOp>, sync: bool) -> Result<String, String> {
+  let mut event_batch = EventBatch::new(storage);
+  let mut stats: BTreeMap<(String, String), StatsDelta> = BTreeMap::new();
+  let ops_count = ops.len();
+  for op in ops {
+    let (cf, op_result) = match op {
+      WriteOp::Put(msg_type, key, msg_content) => {
+        let cf = get_column_family(&msg_type);
+        event_batch.batch().put(cf, &key, content_to_string(msg_content).as_bytes());
+        (cf, Ok(()))
+      },
+      WriteOp::Delete(msg_type, key) => {
+        let cf = get_column_family(&msg_type);
+        event_batch.batch().delete(cf, &key);
+        (cf, Ok(()))
+      },
+      WriteOp::PutEvent(key, params, transaction, event_indexes) => ("events", event_batch.put_event(&key, params, &transaction, &event_indexes)),
+      WriteOp::DeleteEvent(key, event_indexes) => ("events", event_batch.delete_event(&key, &event_indexes)),
+      WriteOp::PruneEvents(from_key, to_key, event_indexes) => ("events", event_batch.delete_event_range(&from_key, &to_key, &event_indexes).map(|_| ())),
+      WriteOp::UpdateStats(contract, event_name, delta) => {
+        stats.entry((contract, event_name)).or_insert_with(StatsDelta::default).add(&delta);
+        ("stats", Ok(()))
+      },
+      WriteOp::Increment(key, delta) => ("aggregations", event_batch.add_to_counter(&key, delta))
+    };
+    if let Err(error_op) = op_result {
+      error!("Can not add operation for column {} into batch. Error: {}", cf, error_op);
+      return Err(error_op);
+    }
+  }
+  for (event_name, failures) in event_batch.aggregation_failures().clone() {
+    if let (Some(contract), event) = split_qualified_name(&event_name) {
+      stats.entry((contract.to_string(), event.to_string())).or_insert_with(StatsDelta::default).aggregation_failures += failures;
+    }
+  }
+  for ((contract, event_name), delta) in stats.iter() {
+    if let Err(error_stats) = add_stats_delta(storage, event_batch.batch(), contract, event_name, delta) {
+      error!("Can not add stats of {} into batch. Error: {}", qualified_name(contract, event_name), error_stats);
+      return Err(error_stats);
+    }
+  }
+  match storage.write(event_batch.into_batch(), sync) {
+    Ok(()) => {
+      info!("Batch of {} operations has been written", ops_count);
+      Ok(format!("Batch of {} operations has been written", ops_count))
+    },
+    Err(error_write) => {
+      error!("Can not write batch of {} operations. Error: {}", ops_count, error_write);
+      Err(error_write)
+    }
+  }
+}
+
+impl Handler<WriteBatchMsg> for RocksWriteActor {
+  type Result = Result<String, String>;
+
+  fn handle(&mut self, msg: WriteBatchMsg, ctx: &mut Context<RocksWriteActor>) -> Result<String, String> {
+    write_batch(&*self.db.storage, msg.ops, msg.sync)
+  }
+}
+
 impl Actor for RocksWriteActor {
   type Context = Context<RocksWriteActor>;
 
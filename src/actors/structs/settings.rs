@@ -1,3 +1,11 @@
+use eth::endpoint::Endpoint;
+use db::indexes::EventIndexes;
+use config::structs::storageconfig::StorageConfig;
+use config::structs::contractconfig::{RetentionConfig, TransformConfig};
+use web::lua_query::QueryScripts;
+use db::export::ExportSource;
+use web::admin::AdminConfig;
+
 #[derive(Clone, Debug)]
 pub enum Settings {
   PureString(String),
@@ -6,4 +14,14 @@ pub enum Settings {
   VecI64(Vec<i64>),
   VecI32(Vec<i32>),
   USize(usize),
+  U64(u64),
+  Bool(bool),
+  Endpoint(Endpoint),
+  EventIndexes(EventIndexes),
+  Storage(StorageConfig),
+  Retention(RetentionConfig),
+  Transform(TransformConfig),
+  QueryScripts(QueryScripts),
+  ExportSources(Vec<ExportSource>),
+  Admin(AdminConfig),
 }
\ No newline at end of file
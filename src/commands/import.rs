@@ -0,0 +1,323 @@
+/// # Module Import
+/// Loads events from NDJSON dumps without an ethereum node. Every line is either a row of `cacherz export`
+/// (metadata and typed arguments named with `arg.`) or an event of the web api (`key`, `fields` and `params`).
+/// Records are validated against configured abis and written through `EventBatch`, so indexes, timeline
+/// and aggregations are built as for ingested events. Cursors only move forward, so ingestion continues after imported events.
+use ethabi::{Event, ParamType, Uint};
+use serde_json::{self, Map, Value};
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::time::{SystemTime, UNIX_EPOCH};
+use db::aggregations::{parse_decimal, parse_uint};
+use db::cachedb::CacheDB;
+use db::export::ARGUMENT_PREFIX;
+use db::indexes::{EventBatch, EventIndexes, LogTransaction, TX_SENDER_PARAM};
+use db::key_schema::{event_key, name_key, qualified_name, split_qualified_name};
+use db::stats::{add_stats_delta, StatsDelta};
+use eth::eth_json_rpc::parse_hex_u64;
+
+/// Events written in a single batch together with their cursors
+pub const IMPORT_BATCH_SIZE: usize = 1000;
+
+const METADATA_FIELDS: [&'static str; 6] = ["contract", "event", "block", "log", "transactionHash", "transactionIndex"];
+
+/// Configured event which records are imported into
+#[derive(Debug, Clone)]
+pub struct ImportTarget {
+  pub contract: String,
+  pub event: Event,
+  pub event_indexes: EventIndexes
+}
+
+impl ImportTarget {
+  /// Name which keys of the target start with, ex. "Token.Transfer"
+  pub fn qualified_name(&self) -> String {
+    qualified_name(&self.contract, &self.event.name)
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportSummary {
+  pub events: u64,
+  pub cursors: HashMap<String, String>    // "block-log" cursor of every imported event by its qualified name
+}
+
+/// Validated record ready to be written
+struct ImportedEvent {
+  target_index: usize,
+  block_number: u64,
+  log_index: u32,
+  params: HashMap<String, String>,
+  transaction: LogTransaction
+}
+
+/// Block or log of a record. Numbers and decimal or hex ("0x1a") strings are accepted.
+fn parse_position(value: Option<&Value>, field: &str) -> Result<u64, String> {
+  match value {
+    Some(&Value::Number(ref number)) => number.as_u64().ok_or(format!("Field {} is not an unsigned number", field)),
+    Some(&Value::String(ref text)) if text.starts_with("0x") => parse_hex_u64(text),
+    Some(&Value::String(ref text)) => text.parse::<u64>().map_err(|err| format!("Can not parse {} into {}. Error: {}", text, field, err)),
+    _ => Err(format!("There is no field {}", field))
+  }
+}
+
+fn parse_hex_param(text: &str, bytes_count: Option<usize>) -> Result<String, String> {
+  let hex = text.trim_start_matches("0x").to_lowercase();
+  if hex.len() % 2 != 0 || !hex.chars().all(|c| c.is_digit(16)) {
+    return Err(format!("{} is not hex", text));
+  }
+  match bytes_count {
+    Some(bytes_count) if hex.len() != bytes_count * 2 => Err(format!("{} should have {} bytes", text, bytes_count)),
+    _ => Ok(hex)
+  }
+}
+
+/// Refuses numbers which do not fit into the abi type. Ints are two's complement of 256 bits,
+/// so a negative int fits when its complement does.
+fn check_width(number: Uint, size: usize, is_signed: bool) -> Result<Uint, String> {
+  if size >= 256 {
+    return Ok(number);
+  }
+  let (magnitude, value_bits) = match is_signed {
+    true if number.bit(255) => (!number, size - 1),
+    true => (number, size - 1),
+    false => (number, size)
+  };
+  if magnitude.bits() > value_bits {
+    return Err(format!("{} does not fit into {}{}", number, if is_signed { "int" } else { "uint" }, size));
+  }
+  Ok(number)
+}
+
+/// Converts imported argument into the param stored for ingested events (as formatted by ethabi).
+/// Numbers are json numbers, decimal strings (negative for int) or "0x" hex, and have to fit into their abi type.
+pub fn stored_param(param_type: &ParamType, value: &Value) -> Result<String, String> {
+  match (param_type, value) {
+    (&ParamType::Uint(size), &Value::Number(ref number)) => {
+      let number = number.as_u64().ok_or(format!("{} is not an unsigned integer", number))?;
+      Ok(format!("{:x}", check_width(Uint::from(number), size, false)?))
+    },
+    (&ParamType::Uint(size), &Value::String(ref text)) => {
+      let number = if text.starts_with("0x") { parse_uint(text)? } else { parse_decimal(text)? };
+      Ok(format!("{:x}", check_width(number, size, false)?))
+    },
+    // Ints are stored as two's complement of 256 bits
+    (&ParamType::Int(size), &Value::Number(ref number)) => {
+      let number = match number.as_i64() {
+        Some(number) if number < 0 => !Uint::from((-(number + 1)) as u64),
+        Some(number) => Uint::from(number as u64),
+        None => return Err(format!("{} is not an integer", number))
+      };
+      Ok(format!("{:x}", check_width(number, size, true)?))
+    },
+    (&ParamType::Int(size), &Value::String(ref text)) => {
+      let number = if text.starts_with("0x") {
+        parse_uint(text)?
+      } else if text.starts_with('-') {
+        let magnitude = parse_decimal(&text[1..])?;
+        if magnitude > Uint::from(1) << 255 {
+          return Err(format!("{} does not fit into int256", text));
+        }
+        (!magnitude).overflowing_add(Uint::from(1)).0
+      } else {
+        parse_decimal(text)?
+      };
+      Ok(format!("{:x}", check_width(number, size, true)?))
+    },
+    (&ParamType::Bool, &Value::Bool(boolean)) => Ok(boolean.to_string()),
+    (&ParamType::Bool, &Value::String(ref text)) if text == "true" || text == "false" => Ok(text.clone()),
+    (&ParamType::Address, &Value::String(ref text)) => parse_hex_param(text, Some(20)),
+    (&ParamType::FixedBytes(size), &Value::String(ref text)) => parse_hex_param(text, Some(size)),
+    (&ParamType::Bytes, &Value::String(ref text)) => parse_hex_param(text, None),
+    (_, &Value::String(ref text)) => Ok(text.clone()),
+    _ => Err(format!("{} is not a valid {:?} value", value, param_type))
+  }
+}
+
+/// Finds configured event of the record. Contract is needed only when more contracts have the event.
+fn find_target(targets: &[ImportTarget], contract: Option<&str>, event_name: &str) -> Result<usize, String> {
+  let matching: Vec<usize> = targets.iter().enumerate()
+    .filter(|&(_, target)| target.event.name == event_name && contract.map_or(true, |contract| contract == target.contract))
+    .map(|(target_index, _)| target_index)
+    .collect();
+  match matching.len() {
+    0 => Err(format!("There is no event {} of contract {} in config", event_name, contract.unwrap_or("*"))),
+    1 => Ok(matching[0]),
+    _ => Err(format!("Event {} is in more contracts. Add contract to the record", event_name))
+  }
+}
+
+/// Metadata is taken from `fields` of web events, falling back to their `key` ("Contract.Event-0x1a-0x3"), or from the record itself
+fn record_metadata(record: &Map<String, Value>) -> Result<(Map<String, Value>, bool), String> {
+  let is_web_event = record.get("params").map_or(false, |params| params.is_object());
+  if !is_web_event {
+    return Ok((record.clone(), false));
+  }
+  let mut metadata = match record.get("fields") {
+    Some(&Value::Object(ref fields)) => fields.clone(),
+    _ => Map::new()
+  };
+  if !metadata.contains_key("event") {
+    let key = record.get("key").and_then(|key| key.as_str()).ok_or(String::from("Web event has no fields and no key"))?;
+    let key_parts: Vec<&str> = key.rsplitn(3, '-').collect();
+    if key_parts.len() != 3 {
+      return Err(format!("Can not parse event key {}", key));
+    }
+    metadata.insert(String::from("event"), json!(key_parts[2]));
+    metadata.insert(String::from("block"), json!(key_parts[1]));
+    metadata.insert(String::from("log"), json!(key_parts[0]));
+  }
+  Ok((metadata, true))
+}
+
+fn parse_record(targets: &[ImportTarget], record: &Value) -> Result<ImportedEvent, String> {
+  let record = record.as_object().ok_or(String::from("Record is not a json object"))?;
+  let (metadata, is_web_event) = record_metadata(record)?;
+  let name = metadata.get("event").and_then(|event_name| event_name.as_str()).ok_or(String::from("There is no field event"))?;
+  // Web events name their event with its contract, ex. "Token.Transfer"
+  let (name_contract, event_name) = split_qualified_name(name);
+  let contract = metadata.get("contract").and_then(|contract| contract.as_str()).or(name_contract);
+  let target_index = find_target(targets, contract, event_name)?;
+  let target = &targets[target_index];
+  let block_number = parse_position(metadata.get("block"), "block")?;
+  let log_index = parse_position(metadata.get("log"), "log")?;
+  if log_index > u32::max_value() as u64 {
+    return Err(format!("Log index {} is too big", log_index));
+  }
+  let transaction = LogTransaction{
+    hash: metadata.get("transactionHash").and_then(|hash| hash.as_str()).unwrap_or("").to_string(),
+    index: metadata.get("transactionIndex").and_then(|index| index.as_str()).unwrap_or("").to_string()
+  };
+
+  // Web events hold stored params, which are hex without "0x" for numbers and bytes
+  let arguments: Map<String, Value> = if is_web_event {
+    let params = record.get("params").and_then(|params| params.as_object()).cloned().unwrap_or_default();
+    params.into_iter().map(|(name, value)| {
+      let is_hex = target.event.inputs.iter().any(|input| input.name == name && match input.kind {
+        ParamType::Uint(_) | ParamType::Int(_) | ParamType::Address | ParamType::Bytes | ParamType::FixedBytes(_) => true,
+        _ => false
+      });
+      match value {
+        Value::String(ref text) if is_hex => (name, json!(format!("0x{}", text))),
+        _ => (name, value)
+      }
+    }).collect()
+  } else {
+    // Arguments of export rows are named with "arg.", so they may have names of metadata
+    record.iter()
+      .filter(|&(name, _)| !METADATA_FIELDS.contains(&name.as_str()))
+      .map(|(name, value)| match name.starts_with(ARGUMENT_PREFIX) {
+        true => (name[ARGUMENT_PREFIX.len()..].to_string(), value.clone()),
+        false => (name.clone(), value.clone())
+      })
+      .collect()
+  };
+  let mut params: HashMap<String, String> = HashMap::new();
+  for input in target.event.inputs.iter() {
+    let value = arguments.get(&input.name).ok_or(format!("There is no argument {} of event {}", input.name, event_name))?;
+    let param = stored_param(&input.kind, value).map_err(|err| format!("Argument {} is not valid. Error: {}", input.name, err))?;
+    params.insert(input.name.clone(), param);
+  }
+  for (name, value) in arguments.iter() {
+    if params.contains_key(name) {
+      continue;
+    }
+    match (name.as_str(), value) {
+      (TX_SENDER_PARAM, &Value::String(ref sender)) if target.event_indexes.index_tx_sender => {
+        params.insert(name.clone(), parse_hex_param(sender, Some(20))?);
+      },
+      _ => return Err(format!("Event {} has no argument {}", event_name, name))
+    };
+  }
+  Ok(ImportedEvent{target_index: target_index, block_number: block_number, log_index: log_index as u32, params: params, transaction: transaction})
+}
+
+/// Block and log of the stored cursor of the event
+fn get_cursor_position(db: &CacheDB, event_name: &str) -> Result<Option<(u64, u64)>, String> {
+  let cursor = match db.storage.get("aggregations", &name_key(event_name)?)? {
+    Some(cursor) => cursor,
+    None => return Ok(None)
+  };
+  let cursor_json: Value = serde_json::from_slice(&cursor).map_err(|err| format!("Can not parse cursor of {}. Error: {}", event_name, err))?;
+  let last_block = cursor_json["last_block"].as_str().ok_or(format!("Cursor of {} has no last block", event_name))?;
+  let mut positions = last_block.split('-');
+  let block_number = parse_hex_u64(positions.next().unwrap_or("0x0"))?;
+  let log_index = parse_hex_u64(positions.next().unwrap_or("0x0"))?;
+  Ok(Some((block_number, log_index)))
+}
+
+/// Writes events with stats and cursors of events in the batch
+fn write_import_batch(db: &CacheDB, mut event_batch: EventBatch, targets: &[ImportTarget], stats: HashMap<usize, StatsDelta>, cursors: &HashMap<String, (u64, u64)>) -> Result<(), String> {
+  let since_the_epoch = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|err| err.to_string())?;
+  let ts: u128 = since_the_epoch.as_secs() as u128 * 1000 + since_the_epoch.subsec_millis() as u128;
+  for (target_index, delta) in stats.iter() {
+    let target = &targets[*target_index];
+    let mut delta = delta.clone();
+    delta.aggregation_failures = event_batch.aggregation_failures().get(&target.qualified_name()).cloned().unwrap_or(0);
+    add_stats_delta(&*db.storage, event_batch.batch(), &target.contract, &target.event.name, &delta)?;
+    if let Some(&(block_number, log_index)) = cursors.get(&target.qualified_name()) {
+      let msg: String = format!("{{\"last_block\": \"0x{:x}-0x{:x}\", \"ts\": {}}}", block_number, log_index, ts);
+      event_batch.batch().put("aggregations", &name_key(&target.qualified_name())?, msg.as_bytes());
+    }
+  }
+  db.storage.write(event_batch.into_batch(), false)
+}
+
+/// Imports NDJSON records. Records are written in batches of `batch_size` events, so an invalid record
+/// stops the import with previous batches written. Import is idempotent, so it can be run again after a fix.
+pub fn import<R: BufRead>(db: &CacheDB, targets: &[ImportTarget], input: R, batch_size: usize) -> Result<ImportSummary, String> {
+  let mut summary = ImportSummary::default();
+  let mut cursors: HashMap<String, (u64, u64)> = HashMap::new();
+  let mut event_batch = EventBatch::new(&*db.storage);
+  let mut stats: HashMap<usize, StatsDelta> = HashMap::new();
+  let mut batch_events: usize = 0;
+  for (line_index, line) in input.lines().enumerate() {
+    let line = line.map_err(|err| format!("Can not read line {}. Error: {}", line_index + 1, err))?;
+    if line.trim().is_empty() {
+      continue;
+    }
+    let imported = serde_json::from_str::<Value>(&line)
+      .map_err(|err| err.to_string())
+      .and_then(|record| parse_record(targets, &record))
+      .map_err(|err| format!("Can not import line {}. Error: {}", line_index + 1, err))?;
+    let target = &targets[imported.target_index];
+    let target_name = target.qualified_name();
+    let key = event_key(&target_name, imported.block_number, imported.log_index)?;
+    // Overwritten events are already counted, so imports can be run again without doubling stats
+    let is_new = !event_batch.is_stored(&key)?;
+    event_batch.put_event(&key, imported.params, &imported.transaction, &target.event_indexes)?;
+
+    let position = (imported.block_number, imported.log_index as u64);
+    let cursor = match cursors.get(&target_name).cloned() {
+      Some(cursor) => Some(cursor),
+      None => get_cursor_position(db, &target_name)?
+    };
+    let cursor = match cursor {
+      Some(cursor) if cursor >= position => cursor,
+      _ => position
+    };
+    cursors.insert(target_name, cursor);
+    let delta = stats.entry(imported.target_index).or_insert_with(StatsDelta::default);
+    if is_new {
+      delta.events = delta.events + 1;
+      delta.blocks.push(imported.block_number);
+    }
+    summary.events = summary.events + 1;
+    batch_events = batch_events + 1;
+
+    if batch_events >= batch_size {
+      write_import_batch(db, event_batch, targets, stats, &cursors)?;
+      info!("Import has written {} events", summary.events);
+      event_batch = EventBatch::new(&*db.storage);
+      stats = HashMap::new();
+      batch_events = 0;
+    }
+  }
+  if batch_events > 0 {
+    write_import_batch(db, event_batch, targets, stats, &cursors)?;
+  }
+  summary.cursors = cursors.into_iter()
+    .map(|(event_name, (block_number, log_index))| (event_name, format!("0x{:x}-0x{:x}", block_number, log_index)))
+    .collect();
+  Ok(summary)
+}
@@ -0,0 +1,99 @@
+/// # Module Migrate
+/// Runs registered migration steps until database reaches `SCHEMA_VERSION`. Every step moves database
+/// by one version. Steps save checkpoints in "settings" column family, so interrupted migration
+/// continues from the last checkpoint when `cacherz migrate` is run again.
+use std::collections::HashMap;
+use serde_json::Value;
+use db::cachedb::CacheDB;
+use db::storage::StorageBatch;
+use db::key_codec::{encode_hex, decode_hex};
+use db::schema_version::{detect_schema_version, put_schema_version, SCHEMA_VERSION};
+use commands::{migrate_keys, migrate_names};
+
+pub const MIGRATION_PROGRESS_KEY: &'static str = "migration_progress";
+
+/// Single migration step from `from_version` to `from_version + 1`
+pub struct MigrationStep {
+  pub from_version: u32,
+  pub description: &'static str,
+  pub run: fn(&mut MigrationContext) -> Result<usize, String>   // Returns number of migrated entries
+}
+
+/// Registered migration steps. New layout changes add a step here and bump `SCHEMA_VERSION`.
+pub fn get_migration_steps() -> Vec<MigrationStep> {
+  vec![
+    MigrationStep{from_version: 1, description: "Rewrite keys into binary order-preserving layout", run: migrate_keys::migrate_keys},
+    MigrationStep{from_version: 2, description: "Namespace event names in keys by contract", run: migrate_names::migrate_names}
+  ]
+}
+
+/// State of a running step. Progress json: {"fromVersion": 1, "columnFamilies": {"events": {"lastKey": "0x..", "done": false}}}
+pub struct MigrationContext {
+  pub db: CacheDB,
+  pub from_version: u32,
+  pub contracts: HashMap<String, String>,   // Contract of every configured event name
+  progress: Value
+}
+
+impl MigrationContext {
+  /// Loads progress of the step. Progress of other steps is ignored.
+  pub fn load(db: CacheDB, from_version: u32) -> Result<MigrationContext, String> {
+    let stored_progress: Option<Value> = match db.storage.get("settings", MIGRATION_PROGRESS_KEY.as_bytes())? {
+      Some(value) => serde_json::from_slice(&value).ok(),
+      None => None
+    };
+    let progress = match stored_progress {
+      Some(ref progress) if progress["fromVersion"] == json!(from_version) => progress.clone(),
+      _ => json!({"fromVersion": from_version, "columnFamilies": {}})
+    };
+    Ok(MigrationContext{db: db, from_version: from_version, contracts: HashMap::new(), progress: progress})
+  }
+
+  pub fn with_contracts(mut self, contracts: HashMap<String, String>) -> MigrationContext {
+    self.contracts = contracts;
+    self
+  }
+
+  /// Last key processed in column family before migration was interrupted
+  pub fn get_last_key(&self, column_family: &str) -> Option<Vec<u8>> {
+    self.progress["columnFamilies"][column_family]["lastKey"].as_str()
+      .and_then(|last_key| decode_hex(last_key).ok())
+  }
+
+  pub fn is_done(&self, column_family: &str) -> bool {
+    self.progress["columnFamilies"][column_family]["done"].as_bool().unwrap_or(false)
+  }
+
+  /// Adds checkpoint into the batch, so data and progress are written atomically
+  pub fn checkpoint(&mut self, batch: &mut StorageBatch, column_family: &str, last_key: Option<&[u8]>, done: bool) {
+    self.progress["columnFamilies"][column_family] = json!({
+      "lastKey": last_key.map(encode_hex),
+      "done": done
+    });
+    batch.put("settings", MIGRATION_PROGRESS_KEY.as_bytes(), self.progress.to_string().as_bytes());
+  }
+}
+
+/// Migrates database to `SCHEMA_VERSION`. `contracts` maps event names to their contracts. Returns final version.
+pub fn migrate(db: CacheDB, contracts: HashMap<String, String>) -> Result<u32, String> {
+  let mut version = detect_schema_version(&db)?;
+  if version > SCHEMA_VERSION {
+    return Err(format!("Database schema version {} is newer than version {} supported by this build", version, SCHEMA_VERSION));
+  }
+  let steps = get_migration_steps();
+  while version < SCHEMA_VERSION {
+    let step = steps.iter().find(|step| step.from_version == version)
+      .ok_or(format!("There is no migration step from schema version {}", version))?;
+    info!("Migrating database from schema version {} to {}: {}", version, version + 1, step.description);
+    let mut context = MigrationContext::load(db.clone(), version)?.with_contracts(contracts.clone());
+    let migrated = (step.run)(&mut context)?;
+    // New version is written together with removal of step progress
+    let mut batch = StorageBatch::new();
+    put_schema_version(&mut batch, version + 1);
+    batch.delete("settings", MIGRATION_PROGRESS_KEY.as_bytes());
+    db.storage.write(batch, true)?;
+    info!("Database has been migrated to schema version {}. {} entries have been rewritten", version + 1, migrated);
+    version = version + 1;
+  }
+  Ok(version)
+}
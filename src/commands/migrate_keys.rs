@@ -0,0 +1,129 @@
+/// # Module Migrate keys
+/// Rewrites keys stored as padded hex strings ("Transfer-------...0x1a-----------0x3-------")
+/// into binary keys produced by `db::key_codec`. Migration step from schema version 1 to 2.
+use std::str::from_utf8;
+use commands::migrate::MigrationContext;
+use db::key_codec::NAME_SIZE;
+use db::storage::StorageBatch;
+use db::key_schema::{name_key, event_key_from_hex};
+
+const LEGACY_NAME_SIZE: usize = 30;
+const LEGACY_BLOCK_NUMBER_SIZE: usize = 15;
+const LEGACY_LOG_INDEX_SIZE: usize = 10;
+const MIGRATION_BATCH_SIZE: usize = 1000;
+
+/// Value of a padded hex field. None when anything but padding follows the value.
+fn strip_hex_padding(field: &str) -> Option<&str> {
+  let value_size = field.find('-').unwrap_or(field.len());
+  if field[value_size..].bytes().all(|byte| byte == b'-') {
+    Some(&field[..value_size])
+  } else {
+    None
+  }
+}
+
+/// Name of a padded name field. Names may end with '-' themselves, so padding of a configured name is
+/// stripped by its length. Names which are not configured lose every trailing '-'.
+fn strip_name_padding<'a>(event_names: &[String], field: &'a str) -> &'a str {
+  let configured_size = event_names.iter()
+    .filter(|event_name| field.starts_with(event_name.as_str()) && field[event_name.len()..].bytes().all(|byte| byte == b'-'))
+    .map(|event_name| event_name.len())
+    .max();
+  match configured_size {
+    Some(name_size) => &field[..name_size],
+    None => field.trim_end_matches('-')
+  }
+}
+
+/// Converts legacy event key. Returns None when key is not in legacy layout.
+pub fn convert_legacy_event_key(event_names: &[String], key: &[u8]) -> Option<Result<Vec<u8>, String>> {
+  if key.len() != LEGACY_NAME_SIZE + LEGACY_BLOCK_NUMBER_SIZE + LEGACY_LOG_INDEX_SIZE {
+    return None;
+  }
+  let key_str = match from_utf8(key) {
+    Ok(k) => k,
+    Err(_) => return None
+  };
+  let name = strip_name_padding(event_names, &key_str[..LEGACY_NAME_SIZE]);
+  let block_number = strip_hex_padding(&key_str[LEGACY_NAME_SIZE..LEGACY_NAME_SIZE + LEGACY_BLOCK_NUMBER_SIZE])?;
+  let log_index = strip_hex_padding(&key_str[LEGACY_NAME_SIZE + LEGACY_BLOCK_NUMBER_SIZE..])?;
+  if !block_number.starts_with("0x") || !log_index.starts_with("0x") {
+    return None;
+  }
+  Some(event_key_from_hex(name, block_number, log_index))
+}
+
+/// Converts legacy name key (cursors and filters). Returns None when key is not in legacy layout.
+pub fn convert_legacy_name_key(event_names: &[String], key: &[u8]) -> Option<Result<Vec<u8>, String>> {
+  if key.len() > NAME_SIZE || key.contains(&0u8) {
+    return None;
+  }
+  let name = match from_utf8(key) {
+    Ok(k) => strip_name_padding(event_names, k),
+    Err(_) => return None
+  };
+  match name_key(name) {
+    Ok(ref new_key) if new_key.as_slice() == key => None,
+    new_key_result => Some(new_key_result)
+  }
+}
+
+/// Rewrites keys of a column family converted by `convert` with checkpoints. Keys converted into None are kept.
+/// A key which cannot be converted fails the step, so its schema version is never written over unconverted keys.
+pub fn migrate_column_family(context: &mut MigrationContext, column_family: &str, convert: &dyn Fn(&[u8]) -> Option<Result<Vec<u8>, String>>) -> Result<usize, String> {
+  if context.is_done(column_family) {
+    info!("Column family {} has been already migrated", column_family);
+    return Ok(0);
+  }
+  let db = context.db.clone();
+  let mut migrated: usize = 0;
+  let mut batch = StorageBatch::new();
+  let mut batch_size: usize = 0;
+  // Iterator works on implicit snapshot, so rewritten keys are not visited again
+  let mut iter = db.storage.iter(column_family)?;
+  match context.get_last_key(column_family) {
+    Some(last_key) => {
+      info!("Migration of column family {} continues from checkpoint", column_family);
+      iter.seek(&last_key);
+      if iter.valid() && iter.key() == last_key.as_slice() {
+        iter.next();
+      }
+    },
+    None => iter.seek_to_first()
+  };
+  while iter.valid() {
+    let key = iter.key().to_vec();
+    match convert(&key) {
+      Some(Ok(new_key)) => {
+        batch.put(column_family, &new_key, iter.value());
+        batch.delete(column_family, &key);
+        batch_size = batch_size + 1;
+      },
+      Some(Err(err)) => return Err(format!("Cannot migrate key {:?} in column family {}. Error: {}", key, column_family, err)),
+      None => ()
+    };
+    if batch_size >= MIGRATION_BATCH_SIZE {
+      context.checkpoint(&mut batch, column_family, Some(&key), false);
+      db.storage.write(batch, false)?;
+      migrated = migrated + batch_size;
+      info!("Migrated {} keys in column family {}", migrated, column_family);
+      batch = StorageBatch::new();
+      batch_size = 0;
+    }
+    iter.next();
+  }
+  context.checkpoint(&mut batch, column_family, None, true);
+  db.storage.write(batch, false)?;
+  migrated = migrated + batch_size;
+  Ok(migrated)
+}
+
+/// Rewrites events, cursors and filters keys into binary layout. Can be run many times.
+pub fn migrate_keys(context: &mut MigrationContext) -> Result<usize, String> {
+  let event_names: Vec<String> = context.contracts.keys().cloned().collect();
+  let events = migrate_column_family(context, "events", &|key: &[u8]| convert_legacy_event_key(&event_names, key))?;
+  let cursors = migrate_column_family(context, "aggregations", &|key: &[u8]| convert_legacy_name_key(&event_names, key))?;
+  let filters = migrate_column_family(context, "filters", &|key: &[u8]| convert_legacy_name_key(&event_names, key))?;
+  info!("Keys migration has finished. Events: {}, cursors: {}, filters: {}", events, cursors, filters);
+  Ok(events + cursors + filters)
+}
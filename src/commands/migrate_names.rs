@@ -0,0 +1,83 @@
+/// # Module Migrate names
+/// Rewrites event names in keys into "<contract>.<event>" qualified names, so events with the same name
+/// in different contracts stop sharing keys. Migration step from schema version 2 to 3.
+/// Contract of every stored event is taken from configuration given to `cacherz migrate`.
+use std::collections::HashMap;
+use commands::migrate::MigrationContext;
+use commands::migrate_keys::migrate_column_family;
+use db::key_schema::{qualified_name, split_qualified_name, KeySchema, KeyValue};
+
+/// Prefixes of string keys followed by an event name and optionally ":<rest>"
+const STRING_KEY_PREFIXES: [&str; 4] = ["ingest:", "pruned:", "lua:", "aggregation:"];
+
+/// Qualified name of a stored event name. None for qualified names and events without configured contract.
+fn rename(contracts: &HashMap<String, String>, name: &str) -> Option<String> {
+  match split_qualified_name(name) {
+    (None, event_name) => contracts.get(event_name).map(|contract| qualified_name(contract, event_name)),
+    (Some(_), _) => None
+  }
+}
+
+/// Converts binary key whose event name is component `name_position` of the schema. Keys of other layouts are kept.
+fn convert_binary_key(contracts: &HashMap<String, String>, schema: &KeySchema, name_position: usize, key: &[u8]) -> Option<Result<Vec<u8>, String>> {
+  let mut values = schema.decode(key).ok()?;
+  let new_name = match values.get(name_position) {
+    Some(&KeyValue::Name(ref name)) => rename(contracts, name)?,
+    _ => return None
+  };
+  values[name_position] = KeyValue::Name(new_name);
+  Some(schema.encode(&values))
+}
+
+/// Converts string key like "ingest:Transfer" or "aggregation:Transfer:volume"
+fn convert_string_key(contracts: &HashMap<String, String>, key: &[u8]) -> Option<Result<Vec<u8>, String>> {
+  let key_str = ::std::str::from_utf8(key).ok()?;
+  let prefix = STRING_KEY_PREFIXES.iter().find(|prefix| key_str.starts_with(*prefix))?;
+  let rest = &key_str[prefix.len()..];
+  let (name, suffix) = match rest.find(':') {
+    Some(position) => (&rest[..position], &rest[position..]),
+    None => (rest, "")
+  };
+  rename(contracts, name).map(|new_name| Ok(format!("{}{}{}", prefix, new_name, suffix).into_bytes()))
+}
+
+/// "aggregations" holds cursors, aggregate groups and their values, which differ by key size, and lua records
+fn convert_aggregations_key(contracts: &HashMap<String, String>, key: &[u8]) -> Option<Result<Vec<u8>, String>> {
+  if STRING_KEY_PREFIXES.iter().any(|prefix| key.starts_with(prefix.as_bytes())) {
+    return convert_string_key(contracts, key);
+  }
+  let schema = [KeySchema::cursors(), KeySchema::aggregates(), KeySchema::aggregate_values()].iter()
+    .find(|schema| schema.key_size() == key.len())
+    .cloned()?;
+  convert_binary_key(contracts, &schema, 0, key)
+}
+
+/// Rewrites event names in keys of every column family. Can be run many times.
+pub fn migrate_names(context: &mut MigrationContext) -> Result<usize, String> {
+  let contracts = context.contracts.clone();
+  let has_events = {
+    let mut iter = context.db.storage.iter("events")?;
+    iter.seek_to_first();
+    iter.valid()
+  };
+  if has_events && contracts.is_empty() {
+    return Err(String::from("Stored events have to be assigned to contracts. Run `cacherz migrate` with --configFilePath or --abiFilePath"));
+  }
+  let binary_column_families: [(&str, KeySchema, usize); 5] = [
+    ("events", KeySchema::events(), 0),
+    ("filters", KeySchema::filters(), 0),
+    ("indexes", KeySchema::indexes(), 0),
+    ("addresses", KeySchema::addresses(), 3),
+    ("timeline", KeySchema::timeline(), 3)
+  ];
+  let mut migrated: usize = 0;
+  for &(column_family, ref schema, name_position) in binary_column_families.iter() {
+    migrated = migrated + migrate_column_family(context, column_family, &|key: &[u8]| convert_binary_key(&contracts, schema, name_position, key))?;
+  }
+  migrated = migrated + migrate_column_family(context, "aggregations", &|key: &[u8]| convert_aggregations_key(&contracts, key))?;
+  for column_family in ["stats", "settings"].iter() {
+    migrated = migrated + migrate_column_family(context, column_family, &|key: &[u8]| convert_string_key(&contracts, key))?;
+  }
+  info!("Names migration has finished. {} keys have been rewritten", migrated);
+  Ok(migrated)
+}
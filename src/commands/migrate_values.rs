@@ -0,0 +1,41 @@
+/// # Module Migrate values
+/// `convert_values` rewrites stored events into another format. Every value carries its own format,
+/// so interrupted conversion skips already converted values when it is run again.
+/// Binary values of an older schema are rewritten into the current one.
+use config::structs::storageconfig::ValueFormat;
+use db::cachedb::CacheDB;
+use db::storage::StorageBatch;
+use db::value_codec::{decode_params, encode_params, format_name, is_encoded_in, put_value_format};
+
+const CONVERSION_BATCH_SIZE: usize = 1000;
+
+/// Rewrites events into a given format and makes it the format of new events. Returns number of rewritten values.
+pub fn convert_values(db: &CacheDB, format: ValueFormat) -> Result<usize, String> {
+  // Format is switched first, so events written after conversion are not left behind
+  let mut batch = StorageBatch::new();
+  put_value_format(&mut batch, format);
+  db.storage.write(batch, true)?;
+  let mut converted: usize = 0;
+  let mut batch = StorageBatch::new();
+  let mut iter = db.storage.iter("events")?;
+  iter.seek_to_first();
+  while iter.valid() {
+    if !is_encoded_in(iter.value(), format) {
+      match decode_params(iter.value()).and_then(|params| encode_params(&params, format)) {
+        Ok(value) => {
+          batch.put("events", iter.key(), &value);
+          converted = converted + 1;
+        },
+        Err(err) => warn!("Cannot convert value of {:?}. Error: {}", iter.key(), err)
+      };
+      if batch.len() >= CONVERSION_BATCH_SIZE {
+        db.storage.write(batch, false)?;
+        info!("Converted {} values into {}", converted, format_name(format));
+        batch = StorageBatch::new();
+      }
+    }
+    iter.next();
+  }
+  db.storage.write(batch, true)?;
+  Ok(converted)
+}
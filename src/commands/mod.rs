@@ -0,0 +1,6 @@
+pub mod reindex;
+pub mod migrate_keys;
+pub mod migrate_values;
+pub mod migrate_names;
+pub mod migrate;
+pub mod import;
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use ethabi::Event;
+use db::cachedb::CacheDB;
+use db::indexes::{EventBatch, EventIndexes, LogTransaction, TX_SENDER_PARAM};
+use db::key_schema::{event_key, event_key_from_hex, format_key, qualified_name};
+use eth::eth_json_rpc::{self, TRANSACTION_SENDERS_BATCH_SIZE};
+use eth::endpoint::Endpoint;
+
+const REINDEX_WINDOW_BLOCKS: u64 = 10000;
+
+/// Rebuilds events of a given block range. The range is walked in windows of `REINDEX_WINDOW_BLOCKS` blocks,
+/// so a long range is neither one eth_getLogs request nor one batch held in memory.
+/// Cursor in "aggregations" column family is not modified, so ingestion continues from where it was.
+pub fn reindex(db: CacheDB, event: Event, endpoint: Endpoint, address: Option<String>, event_indexes: EventIndexes, from_block: u64, to_block: u64) -> Result<usize, String> {
+  if from_block > to_block {
+    return Err(format!("From block {} is greater than to block {}", from_block, to_block));
+  }
+  let mut reindexed: usize = 0;
+  let mut window_start = from_block;
+  loop {
+    let window_end = window_start.saturating_add(REINDEX_WINDOW_BLOCKS - 1).min(to_block);
+    reindexed += reindex_window(&db, &event, endpoint.clone(), address.clone(), &event_indexes, window_start, window_end)?;
+    if window_end == to_block {
+      return Ok(reindexed);
+    }
+    window_start = window_end + 1;
+  }
+}
+
+/// Refetches logs of one window with eth_getLogs, deletes stored events of the window and writes decoded logs back in one batch.
+/// Secondary index entries of deleted events are removed and rebuilt for written ones.
+/// Windows written before a failure stay reindexed.
+fn reindex_window(db: &CacheDB, event: &Event, endpoint: Endpoint, address: Option<String>, event_indexes: &EventIndexes, from_block: u64, to_block: u64) -> Result<usize, String> {
+  // Logs are fetched before anything is deleted, so failed request leaves the window untouched
+  let logs = eth_json_rpc::get_logs(endpoint.clone(), format!("{:x}", event.signature()), 0,
+    format!("0x{:x}", from_block), format!("0x{:x}", to_block), address)?;
+  let mut event_batch = EventBatch::new(&*db.storage);
+  let event_name = qualified_name(&event_indexes.contract, &event.name);
+
+  // Keys are ordered by block number, so the whole window is a single scan.
+  // Events are deleted one by one, because their index entries have to be found from stored params.
+  let range_start = event_key(&event_name, from_block, 0)?;
+  let range_end = event_key(&event_name, to_block + 1, 0)?;
+  info!("Reindex of {} removes stored events from blocks {} - {}", event_name, from_block, to_block);
+  let mut stored_keys: Vec<Vec<u8>> = Vec::new();
+  let mut iter = db.storage.iter("events")?;
+  iter.seek(&range_start);
+  while iter.valid() && iter.key() < range_end.as_slice() {
+    stored_keys.push(iter.key().to_vec());
+    iter.next();
+  }
+  for stored_key in stored_keys {
+    event_batch.delete_event(&stored_key, event_indexes)?;
+  }
+
+  // Senders are fetched in batches, once per transaction. Events of failed lookups are written without sender index.
+  let mut tx_senders: HashMap<String, String> = HashMap::new();
+  if event_indexes.index_tx_sender {
+    let mut transaction_hashes: Vec<String> = logs.result.iter().map(|log| log.transactionHash.clone()).collect();
+    transaction_hashes.sort();
+    transaction_hashes.dedup();
+    for hashes_chunk in transaction_hashes.chunks(TRANSACTION_SENDERS_BATCH_SIZE) {
+      match eth_json_rpc::get_transaction_senders(endpoint.clone(), hashes_chunk, 0) {
+        Ok(senders) => tx_senders.extend(senders),
+        Err(err) => error!("Cannot get senders of {} transactions. Error: {}", hashes_chunk.len(), err)
+      };
+    }
+  }
+
+  let mut reindexed: usize = 0;
+  for log in logs.result {
+    let event_key = event_key_from_hex(&event_name, &log.blockNumber, &log.logIndex)?;
+    let transaction = LogTransaction{hash: log.transactionHash.clone(), index: log.transactionIndex.clone()};
+    match log.decode_hashmap(event.inputs.clone()) {
+      Ok(mut d_result) => {
+        if let Some(sender) = tx_senders.get(&transaction.hash) {
+          d_result.insert(TX_SENDER_PARAM.to_string(), sender.clone());
+        }
+        event_batch.put_event(&event_key, d_result, &transaction, event_indexes)?;
+        reindexed = reindexed + 1;
+      },
+      Err(error_msg) => {
+        error!("Cannot decode event {} during reindex. Error: {}", format_key(&event_key), error_msg);
+      }
+    }
+  }
+  db.storage.write(event_batch.into_batch(), false)?;
+  info!("Reindex of {} wrote {} events from blocks {} - {}", event_name, reindexed, from_block, to_block);
+  Ok(reindexed)
+}
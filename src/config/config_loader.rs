@@ -0,0 +1,27 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use serde_json;
+use config::structs::cacherzconfig::CacherzConfig;
+
+pub fn get_config(file_path: String) -> Result<CacherzConfig, String> {
+  read_config_file(file_path)
+    .and_then(|config_file_content| convert_to_config(config_file_content))
+}
+
+fn read_config_file(file_path: String) -> Result<String, String> {
+  let path = Path::new(&file_path);
+  match File::open(path) {
+    Ok(mut file_handler) => {
+      let mut contents = String::new();
+      file_handler.read_to_string(&mut contents).map_err(|err| err.to_string())?;
+      Ok(contents)
+    },
+    Err(msg) => Err(msg.to_string()),
+  }
+}
+
+fn convert_to_config(config_content: String) -> Result<CacherzConfig, String> {
+  serde_json::from_str::<CacherzConfig>(&config_content)
+    .map_err(|err| format!("Can not parse config file. Error: {}", err))
+}
@@ -0,0 +1,14 @@
+/// # Module Cacherzconfig
+use config::structs::contractconfig::ContractConfig;
+use config::structs::storageconfig::StorageConfig;
+
+/// Root of the json configuration file passed by `--configFilePath`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CacherzConfig {
+  pub contracts: Vec<ContractConfig>,
+  #[serde(default)]
+  pub global_timeline: bool,        // Keeps timeline of events across all contracts
+  #[serde(default)]
+  pub storage: StorageConfig        // RocksDB tuning of column families
+}
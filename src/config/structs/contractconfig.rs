@@ -0,0 +1,204 @@
+/// # Module Contractconfig
+use std::collections::HashMap;
+use ethabi::{Event, ParamType};
+use db::key_codec::NAME_SIZE;
+use db::key_schema::qualified_name;
+
+/// Secondary index over one or more decoded event arguments
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexConfig {
+  pub name: String,       // Index name used in queries
+  pub args: Vec<String>   // Names of indexed event arguments
+}
+
+/// Function of an aggregation
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AggregationKind {
+  Count,      // Number of events
+  Sum,        // Sum of a numeric argument
+  Min,        // Minimum of a numeric argument
+  Max,        // Maximum of a numeric argument
+  Distinct    // Number of distinct values of an argument
+}
+
+/// Aggregation of an event maintained on every write, ex. total `value` transferred per `to` address
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregationConfig {
+  pub name: String,             // Aggregation name used in queries
+  pub kind: AggregationKind,
+  pub arg: Option<String>,      // Aggregated event argument. Not used by count
+  #[serde(default)]
+  pub group_by: Vec<String>     // Names of grouping event arguments. Without them there is a single group
+}
+
+/// Lua script transforming logs of an event before they are written
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransformConfig {
+  pub script_path: String,            // Full path to lua script. It is reloaded when the file changes
+  pub timeout_ms: Option<u64>,        // Time limit of a single log. 100 ms by default
+  pub memory_limit_kb: Option<u64>    // Memory limit of the script state. 16 MB by default
+}
+
+impl TransformConfig {
+  pub fn get_timeout_ms(&self) -> u64 {
+    self.timeout_ms.unwrap_or(100)
+  }
+
+  pub fn get_memory_limit_kb(&self) -> u64 {
+    self.memory_limit_kb.unwrap_or(16384)
+  }
+}
+
+/// Retention policy of an event. Events breaking any of the limits are pruned.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionConfig {
+  pub max_blocks: Option<u64>,    // Keeps events of the last N blocks before the event cursor
+  pub max_age_secs: Option<u64>,  // Keeps events of blocks mined in the last N seconds
+  pub max_count: Option<u64>      // Keeps the last N events
+}
+
+/// Settings of a single event. Every field overrides the contract level value.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EventConfig {
+  pub start_block: Option<u64>,
+  pub end_block: Option<u64>,
+  #[serde(default)]
+  pub indexes: Vec<IndexConfig>,
+  pub retention: Option<RetentionConfig>,
+  #[serde(default)]
+  pub aggregations: Vec<AggregationConfig>,
+  pub transform: Option<TransformConfig>
+}
+
+/// Settings of a single contract and its events
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractConfig {
+  pub name: String,                         // Contract name, used to group its event actors
+  pub abi_file_path: String,                // Full path to abi file
+  pub address: Option<String>,              // Contract address. Without it events are taken from every contract
+  pub start_block: Option<u64>,             // Deployment block. First filter starts from it
+  pub end_block: Option<u64>,               // Retirement block. Indexing stops after it
+  #[serde(default)]
+  pub index_tx_sender: bool,                // Adds transaction sender of every log into address index
+  pub retention: Option<RetentionConfig>,   // Retention of all events of the contract
+  #[serde(default)]
+  pub events: HashMap<String, EventConfig>  // Per event overrides
+}
+
+impl ContractConfig {
+  /// Returns (start block, end block) for a given event
+  pub fn get_block_range(&self, event_name: &str) -> (u64, Option<u64>) {
+    let event_config = self.events.get(event_name).cloned().unwrap_or_default();
+    let start_block = event_config.start_block.or(self.start_block).unwrap_or(0);
+    let end_block = event_config.end_block.or(self.end_block);
+    (start_block, end_block)
+  }
+
+  /// Returns secondary indexes configured for a given event
+  pub fn get_indexes(&self, event_name: &str) -> Vec<IndexConfig> {
+    self.events.get(event_name).map(|event_config| event_config.indexes.clone()).unwrap_or_default()
+  }
+
+  /// Returns retention policy of a given event. Event policy replaces the contract one.
+  pub fn get_retention(&self, event_name: &str) -> Option<RetentionConfig> {
+    self.events.get(event_name)
+      .and_then(|event_config| event_config.retention.clone())
+      .or(self.retention.clone())
+  }
+
+  /// Returns aggregations configured for a given event
+  pub fn get_aggregations(&self, event_name: &str) -> Vec<AggregationConfig> {
+    self.events.get(event_name).map(|event_config| event_config.aggregations.clone()).unwrap_or_default()
+  }
+
+  /// Returns lua transform of a given event
+  pub fn get_transform(&self, event_name: &str) -> Option<TransformConfig> {
+    self.events.get(event_name).and_then(|event_config| event_config.transform.clone())
+  }
+
+  /// Checks if "<contract>.<event>" name of every event fits into keys
+  pub fn validate_names(&self, events: &[Event]) -> Result<(), String> {
+    for event in events {
+      let name = qualified_name(&self.name, &event.name);
+      if name.len() > NAME_SIZE {
+        return Err(format!("Name {} of event {}::{} is longer than {} bytes. Please use a shorter contract name", name, self.name, event.name, NAME_SIZE));
+      }
+    }
+    Ok(())
+  }
+
+  /// Checks if every index name fits into keys and every indexed argument exists in the event
+  pub fn validate_indexes(&self, events: &[Event]) -> Result<(), String> {
+    for event in events {
+      for index in self.get_indexes(&event.name) {
+        if index.name.len() > NAME_SIZE {
+          return Err(format!("Name of index {} of event {}::{} is longer than {} bytes", index.name, self.name, event.name, NAME_SIZE));
+        }
+        if index.args.is_empty() {
+          return Err(format!("Index {} of event {}::{} has no arguments", index.name, self.name, event.name));
+        }
+        if let Some(arg) = index.args.iter().find(|arg| !event.inputs.iter().any(|input| &input.name == *arg)) {
+          return Err(format!("Index {} of event {}::{} uses unknown argument {}", index.name, self.name, event.name, arg));
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Checks if aggregation names fit into keys, aggregated arguments exist in the event and numeric functions use unsigned integers
+  pub fn validate_aggregations(&self, events: &[Event]) -> Result<(), String> {
+    for event in events {
+      let aggregations = self.get_aggregations(&event.name);
+      for (position, aggregation) in aggregations.iter().enumerate() {
+        let full_name = format!("{} of event {}::{}", aggregation.name, self.name, event.name);
+        if aggregation.name.len() > NAME_SIZE {
+          return Err(format!("Name of aggregation {} is longer than {} bytes", full_name, NAME_SIZE));
+        }
+        if aggregations.iter().skip(position + 1).any(|other| other.name == aggregation.name) {
+          return Err(format!("Aggregation {} is defined more than once", full_name));
+        }
+        if let Some(arg) = aggregation.group_by.iter().find(|arg| !event.inputs.iter().any(|input| &input.name == *arg)) {
+          return Err(format!("Aggregation {} is grouped by unknown argument {}", full_name, arg));
+        }
+        match (aggregation.kind, aggregation.arg.as_ref()) {
+          (AggregationKind::Count, _) => (),
+          (_, None) => return Err(format!("Aggregation {} has no argument", full_name)),
+          (kind, Some(arg)) => {
+            let input = event.inputs.iter().find(|input| &input.name == arg)
+              .ok_or(format!("Aggregation {} uses unknown argument {}", full_name, arg))?;
+            let is_uint = match input.kind {
+              ParamType::Uint(_) => true,
+              _ => false
+            };
+            if kind != AggregationKind::Distinct && !is_uint {
+              return Err(format!("Aggregation {} needs unsigned integer argument, but {} is {:?}", full_name, arg, input.kind));
+            }
+          }
+        };
+      }
+    }
+    Ok(())
+  }
+
+  /// Checks block range of every event against the current chain head
+  pub fn validate_block_ranges(&self, event_names: Vec<String>, head_block: u64) -> Result<(), String> {
+    for event_name in event_names {
+      let (start_block, end_block) = self.get_block_range(&event_name);
+      if start_block > head_block {
+        return Err(format!("Start block {} of event {}::{} is above the chain head {}", start_block, self.name, event_name, head_block));
+      }
+      if let Some(end) = end_block {
+        if end < start_block {
+          return Err(format!("End block {} of event {}::{} is lower than its start block {}", end, self.name, event_name, start_block));
+        }
+      }
+    }
+    Ok(())
+  }
+}
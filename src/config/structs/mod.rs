@@ -0,0 +1,3 @@
+pub mod cacherzconfig;
+pub mod contractconfig;
+pub mod storageconfig;
@@ -0,0 +1,153 @@
+/// # Module Storageconfig
+/// RocksDB tuning of column families. Every column family starts from defaults of its kind
+/// (see `ColumnFamilyConfig::defaults_for`) and fields set in config override them.
+/// Defaults keep RocksDB level compaction with auto compactions, so scheduled compaction
+/// (`StorageConfig::get_compaction_triggers`) runs only for column families configured for it.
+use std::collections::HashMap;
+
+/// Column families written on every indexed log. Everything else holds a few small entries.
+pub const HOT_COLUMN_FAMILIES: [&'static str; 4] = ["events", "indexes", "addresses", "timeline"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Compression {
+  None,
+  Snappy,
+  Lz4,
+  Zstd
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum CompactionStyle {
+  Level,
+  Universal,
+  Fifo
+}
+
+/// Encoding of event values. Json is kept by databases created before binary values.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ValueFormat {
+  Json,
+  Binary
+}
+
+/// Options of a single column family. Unset fields are taken from defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnFamilyConfig {
+  pub compression: Option<Compression>,             // Compression of all levels but the last one
+  pub bottommost_compression: Option<Compression>,  // Compression of the last level, where most of data lives
+  pub compression_level: Option<i32>,
+  pub max_dict_bytes: Option<i32>,                  // Size of compression dictionary, 0 disables dictionary compression
+  pub block_size: Option<usize>,                    // Size of data block in bytes
+  pub block_cache_size_mb: Option<usize>,           // Size of own block cache. Ignored when shared cache is used
+  pub shared_cache: Option<bool>,                   // Uses block cache shared by all column families
+  pub write_buffer_size_mb: Option<u64>,
+  pub max_write_buffer_number: Option<i32>,
+  pub compaction_style: Option<CompactionStyle>,
+  pub disable_auto_compactions: Option<bool>,
+  pub compaction_interval_secs: Option<u64>,        // Manual compaction at most once per interval
+  pub compaction_trigger_mb: Option<u64>            // Manual compaction when files grew by this size since the last one
+}
+
+impl ColumnFamilyConfig {
+  /// Defaults of a column family. Hot ones get large buffers, own cache and strong compression
+  /// of the last level. Small ones share a cache and stay uncompressed.
+  pub fn defaults_for(column_family: &str) -> ColumnFamilyConfig {
+    if HOT_COLUMN_FAMILIES.contains(&column_family) {
+      ColumnFamilyConfig {
+        compression: Some(Compression::Lz4),
+        bottommost_compression: Some(Compression::Zstd),
+        compression_level: Some(3),
+        max_dict_bytes: Some(16384),
+        block_size: Some(16384),
+        block_cache_size_mb: Some(512),
+        shared_cache: Some(false),
+        write_buffer_size_mb: Some(128),
+        max_write_buffer_number: Some(4),
+        compaction_style: Some(CompactionStyle::Level),
+        disable_auto_compactions: Some(false),
+        compaction_interval_secs: None,
+        compaction_trigger_mb: None
+      }
+    } else {
+      ColumnFamilyConfig {
+        compression: Some(Compression::None),
+        bottommost_compression: Some(Compression::None),
+        compression_level: None,
+        max_dict_bytes: Some(0),
+        block_size: Some(4096),
+        block_cache_size_mb: None,
+        shared_cache: Some(true),
+        write_buffer_size_mb: Some(4),
+        max_write_buffer_number: Some(2),
+        compaction_style: Some(CompactionStyle::Level),
+        disable_auto_compactions: Some(false),
+        compaction_interval_secs: None,
+        compaction_trigger_mb: None
+      }
+    }
+  }
+
+  /// Returns config where fields set in `overrides` replace own ones
+  pub fn merge(&self, overrides: &ColumnFamilyConfig) -> ColumnFamilyConfig {
+    ColumnFamilyConfig {
+      compression: overrides.compression.or(self.compression),
+      bottommost_compression: overrides.bottommost_compression.or(self.bottommost_compression),
+      compression_level: overrides.compression_level.or(self.compression_level),
+      max_dict_bytes: overrides.max_dict_bytes.or(self.max_dict_bytes),
+      block_size: overrides.block_size.or(self.block_size),
+      block_cache_size_mb: overrides.block_cache_size_mb.or(self.block_cache_size_mb),
+      shared_cache: overrides.shared_cache.or(self.shared_cache),
+      write_buffer_size_mb: overrides.write_buffer_size_mb.or(self.write_buffer_size_mb),
+      max_write_buffer_number: overrides.max_write_buffer_number.or(self.max_write_buffer_number),
+      compaction_style: overrides.compaction_style.or(self.compaction_style),
+      disable_auto_compactions: overrides.disable_auto_compactions.or(self.disable_auto_compactions),
+      compaction_interval_secs: overrides.compaction_interval_secs.or(self.compaction_interval_secs),
+      compaction_trigger_mb: overrides.compaction_trigger_mb.or(self.compaction_trigger_mb)
+    }
+  }
+}
+
+/// `storage` section of the config file
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageConfig {
+  pub shared_cache_size_mb: Option<usize>,                      // Size of block cache shared by column families, 256 MiB by default
+  pub value_format: Option<ValueFormat>,                        // Format of event values in new databases, binary by default
+  #[serde(default)]
+  pub column_families: HashMap<String, ColumnFamilyConfig>      // Per column family overrides
+}
+
+impl StorageConfig {
+  /// Returns options of a column family with overrides applied
+  pub fn get_column_family_config(&self, column_family: &str) -> ColumnFamilyConfig {
+    let defaults = ColumnFamilyConfig::defaults_for(column_family);
+    match self.column_families.get(column_family) {
+      Some(overrides) => defaults.merge(overrides),
+      None => defaults
+    }
+  }
+
+  /// Returns (interval in seconds, growth in MiB) triggers of scheduled compaction. Column families with
+  /// auto compactions disabled are compacted hourly or after 1 GiB of new files unless set otherwise.
+  /// None with defaults, which leave compaction to RocksDB.
+  pub fn get_compaction_triggers(&self, column_family: &str) -> Option<(Option<u64>, Option<u64>)> {
+    let config = self.get_column_family_config(column_family);
+    match (config.compaction_interval_secs, config.compaction_trigger_mb, config.disable_auto_compactions) {
+      (None, None, Some(true)) => Some((Some(3600), Some(1024))),
+      (None, None, _) => None,
+      (interval, trigger_mb, _) => Some((interval, trigger_mb))
+    }
+  }
+
+  pub fn get_shared_cache_size_mb(&self) -> usize {
+    self.shared_cache_size_mb.unwrap_or(256)
+  }
+
+  pub fn get_value_format(&self) -> ValueFormat {
+    self.value_format.unwrap_or(ValueFormat::Binary)
+  }
+}
@@ -0,0 +1,330 @@
+/// # Module Aggregations
+/// Aggregations of events declared in config (count, sum, min, max, distinct), grouped by event arguments.
+/// `EventBatch` updates them in the same batch as events: written events are added, and overwritten or removed
+/// events (reorg rollback, reindex) are subtracted using their stored params. Events pruned by retention stay counted.
+///
+/// Every group has an entry in "aggregations" column family with its count and sum. Min, max and distinct keep
+/// a counted entry for every value right after the group entry, ordered by value, so min and max are the first
+/// and the last of them. Numbers are 256 bit unsigned integers, returned as decimal strings.
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use ethabi::Uint;
+use tiny_keccak::keccak256;
+use serde_json;
+use config::structs::contractconfig::{AggregationConfig, AggregationKind};
+use db::cachedb::CacheDB;
+use db::key_codec::{decode_hex, encode_hex};
+use db::key_schema::{decode_event_key, name_key, KeySchema, KeyValue};
+use db::indexes::{hash_index_values, normalize_index_value};
+use db::storage::{Storage, StorageBatch};
+use db::value_codec::decode_params;
+
+const REBUILD_BATCH_SIZE: usize = 1000;
+const DEFINITION_PREFIX: &'static str = "aggregation:";
+
+/// Stored state of a single group
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregateGroup {
+  pub kind: AggregationKind,
+  pub group: BTreeMap<String, String>,   // Values of grouping arguments
+  pub count: u64,                        // Number of aggregated events
+  pub sum: Option<String>,               // Decimal sum of sum aggregation
+  pub distinct: Option<u64>              // Number of distinct values of distinct aggregation
+}
+
+/// Parses decoded uint param or query value. Accepts hex with or without "0x".
+pub fn parse_uint(value: &str) -> Result<Uint, String> {
+  Uint::from_str(value.trim_start_matches("0x"))
+    .map_err(|err| format!("Can not parse {} into unsigned integer. Error: {:?}", value, err))
+}
+
+/// Parses decimal value, ex. sum of an aggregation or imported argument
+pub fn parse_decimal(value: &str) -> Result<Uint, String> {
+  Uint::from_dec_str(value).map_err(|err| format!("Can not parse {} into unsigned integer. Error: {:?}", value, err))
+}
+
+fn uint_bytes(value: Uint) -> Vec<u8> {
+  let mut bytes = [0u8; 32];
+  value.to_big_endian(&mut bytes);
+  bytes.to_vec()
+}
+
+/// Prefix of all groups of an aggregation
+pub fn aggregation_query(event_name: &str, aggregation_name: &str) -> Result<Vec<u8>, String> {
+  KeySchema::aggregates().encode(&[KeyValue::Name(event_name.to_string()), KeyValue::Name(aggregation_name.to_string())])
+}
+
+/// Key of a group. Values are hashed like values of secondary indexes, so "0xABC" matches "abc".
+pub fn group_key(event_name: &str, aggregation_name: &str, group_values: &[String]) -> Result<Vec<u8>, String> {
+  KeySchema::aggregates().encode(&[
+    KeyValue::Name(event_name.to_string()),
+    KeyValue::Name(aggregation_name.to_string()),
+    KeyValue::Bytes(hash_index_values(group_values))
+  ])
+}
+
+/// Key of a value entry. Min and max values are big-endian numbers, distinct values are hashed.
+fn value_key(group_key: &[u8], kind: AggregationKind, value: &str) -> Result<Vec<u8>, String> {
+  let value_bytes = match kind {
+    AggregationKind::Distinct => keccak256(normalize_index_value(value).as_bytes()).to_vec(),
+    _ => uint_bytes(parse_uint(value)?)
+  };
+  let mut key_values = KeySchema::aggregates().decode(group_key)?;
+  key_values.push(KeyValue::Bytes(value_bytes));
+  KeySchema::aggregate_values().encode(&key_values)
+}
+
+fn decode_count(value: &[u8]) -> Result<u64, String> {
+  String::from_utf8_lossy(value).parse::<u64>().map_err(|err| format!("Can not decode aggregated value count. Error: {}", err))
+}
+
+/// Aggregation entries changed by a batch. Later events of the batch see changes of the earlier ones.
+#[derive(Debug, Default)]
+pub struct AggregateChanges {
+  pending: HashMap<Vec<u8>, Option<Vec<u8>>>
+}
+
+impl AggregateChanges {
+  pub fn new() -> AggregateChanges {
+    AggregateChanges::default()
+  }
+
+  fn get(&self, db: &dyn Storage, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    if let Some(value) = self.pending.get(key) {
+      return Ok(value.clone());
+    }
+    db.get("aggregations", key)
+  }
+
+  fn set(&mut self, batch: &mut StorageBatch, key: Vec<u8>, value: Option<Vec<u8>>) {
+    match value {
+      Some(ref entry) => batch.put("aggregations", &key, entry),
+      None => batch.delete("aggregations", &key)
+    };
+    self.pending.insert(key, value);
+  }
+
+  /// Adds delta to a plain decimal counter, ex. counters of transform scripts. Counter at zero is removed.
+  pub fn add_to_counter(&mut self, db: &dyn Storage, batch: &mut StorageBatch, key: &[u8], delta: i64) -> Result<(), String> {
+    let counter = match self.get(db, key)? {
+      Some(stored) => String::from_utf8_lossy(&stored).parse::<i64>()
+        .map_err(|err| format!("Can not decode counter {}. Error: {}", String::from_utf8_lossy(key), err))?,
+      None => 0
+    };
+    let new_counter = counter.checked_add(delta).ok_or(format!("Counter {} is out of range", String::from_utf8_lossy(key)))?;
+    self.set(batch, key.to_vec(), if new_counter == 0 { None } else { Some(new_counter.to_string().into_bytes()) });
+    Ok(())
+  }
+
+  /// Adds event into its groups of every aggregation, or subtracts it when `added` is false.
+  /// Aggregation which fails for the event (missing argument, value which is not a number, overflow) is skipped,
+  /// so a single bad event does not stop writing of its batch. Returns number of skipped aggregations.
+  pub fn apply(&mut self, db: &dyn Storage, batch: &mut StorageBatch, event_key: &[u8], params: &HashMap<String, String>, aggregations: &[AggregationConfig], added: bool) -> Result<u64, String> {
+    let (event_name, block_number, log_index) = decode_event_key(event_key)?;
+    let mut failures = 0;
+    for aggregation in aggregations {
+      if let Err(err) = self.apply_aggregation(db, batch, &event_name, params, aggregation, added) {
+        warn!("Aggregation {} of event {} at block {} log {} is skipped. Error: {}", aggregation.name, event_name, block_number, log_index, err);
+        failures = failures + 1;
+      }
+    }
+    Ok(failures)
+  }
+
+  fn apply_aggregation(&mut self, db: &dyn Storage, batch: &mut StorageBatch, event_name: &str, params: &HashMap<String, String>, aggregation: &AggregationConfig, added: bool) -> Result<(), String> {
+    let group_values = aggregation.group_by.iter()
+      .map(|arg| params.get(arg).cloned().ok_or(format!("There is no argument {} for aggregation {}", arg, aggregation.name)))
+      .collect::<Result<Vec<String>, String>>()?;
+    let value = match (aggregation.kind, aggregation.arg.as_ref()) {
+      (AggregationKind::Count, _) | (_, None) => None,
+      (_, Some(arg)) => Some(params.get(arg).cloned().ok_or(format!("There is no argument {} for aggregation {}", arg, aggregation.name))?)
+    };
+    let group_key = group_key(event_name, &aggregation.name, &group_values)?;
+    let mut group = match self.get(db, &group_key)? {
+      Some(stored) => serde_json::from_slice::<AggregateGroup>(&stored)
+        .map_err(|err| format!("Can not decode group of aggregation {}. Error: {}", aggregation.name, err))?,
+      None => AggregateGroup{
+        kind: aggregation.kind,
+        group: aggregation.group_by.iter().cloned().zip(group_values.into_iter()).collect(),
+        count: 0,
+        sum: if aggregation.kind == AggregationKind::Sum { Some(String::from("0")) } else { None },
+        distinct: if aggregation.kind == AggregationKind::Distinct { Some(0) } else { None }
+      }
+    };
+    group.count = if added {
+      group.count + 1
+    } else {
+      group.count.checked_sub(1).ok_or(format!("Removed event is not counted in aggregation {}", aggregation.name))?
+    };
+    if let Some(value) = value {
+      if aggregation.kind == AggregationKind::Sum {
+        let sum = parse_decimal(group.sum.as_ref().map(|sum| sum.as_str()).unwrap_or("0"))?;
+        let (new_sum, is_overflow) = if added { sum.overflowing_add(parse_uint(&value)?) } else { sum.overflowing_sub(parse_uint(&value)?) };
+        if is_overflow {
+          return Err(format!("Sum of aggregation {} is out of range", aggregation.name));
+        }
+        group.sum = Some(new_sum.to_string());
+      } else {
+        let value_key = value_key(&group_key, aggregation.kind, &value)?;
+        let stored_count = match self.get(db, &value_key)? {
+          Some(stored) => decode_count(&stored)?,
+          None => 0
+        };
+        let value_count = if added {
+          stored_count + 1
+        } else {
+          stored_count.checked_sub(1).ok_or(format!("Removed value is not counted in aggregation {}", aggregation.name))?
+        };
+        // Distinct values are counted when their first event comes and when their last event goes
+        if let Some(distinct) = group.distinct {
+          if added && value_count == 1 {
+            group.distinct = Some(distinct + 1);
+          } else if !added && value_count == 0 {
+            group.distinct = Some(distinct.saturating_sub(1));
+          }
+        }
+        self.set(batch, value_key, if value_count == 0 { None } else { Some(value_count.to_string().into_bytes()) });
+      }
+    }
+    let group_entry = if group.count == 0 {
+      None
+    } else {
+      Some(serde_json::to_vec(&group).map_err(|err| err.to_string())?)
+    };
+    self.set(batch, group_key, group_entry);
+    Ok(())
+  }
+}
+
+/// Returns first or last value entry of a group
+fn get_extreme_value(db: &CacheDB, group_key: &[u8], is_max: bool) -> Result<Option<String>, String> {
+  let mut iter = db.storage.iter("aggregations")?;
+  if is_max {
+    let mut upper_bound = group_key.to_vec();
+    upper_bound.extend(vec![0xffu8; 32]);
+    iter.seek_for_prev(&upper_bound);
+  } else {
+    iter.seek(group_key);
+    if iter.valid() && iter.key() == group_key {
+      iter.next();
+    }
+  }
+  if iter.valid() && iter.key().starts_with(group_key) && iter.key().len() == KeySchema::aggregate_values().key_size() {
+    let value_bytes = &iter.key()[group_key.len()..];
+    return Ok(Some(Uint::from(value_bytes).to_string()));
+  }
+  Ok(None)
+}
+
+/// Json of a group with min and max read from its values
+fn group_to_json(db: &CacheDB, group_key: &[u8], group: AggregateGroup) -> Result<serde_json::Value, String> {
+  let mut group_json = json!({
+    "groupId": encode_hex(&group_key[KeySchema::aggregates().prefix_size(2)..]),
+    "group": group.group,
+    "count": group.count
+  });
+  match group.kind {
+    AggregationKind::Sum => group_json["sum"] = json!(group.sum),
+    AggregationKind::Distinct => group_json["distinct"] = json!(group.distinct),
+    AggregationKind::Min => group_json["min"] = json!(get_extreme_value(db, group_key, false)?),
+    AggregationKind::Max => group_json["max"] = json!(get_extreme_value(db, group_key, true)?),
+    AggregationKind::Count => ()
+  };
+  Ok(group_json)
+}
+
+/// Returns a group of given values (in group by order)
+pub fn get_aggregate(db: &CacheDB, event_name: &str, aggregation_name: &str, group_values: &[String]) -> Result<Option<serde_json::Value>, String> {
+  let group_key = group_key(event_name, aggregation_name, group_values)?;
+  match db.storage.get("aggregations", &group_key)? {
+    Some(stored) => {
+      let group = serde_json::from_slice::<AggregateGroup>(&stored).map_err(|err| err.to_string())?;
+      group_to_json(db, &group_key, group).map(Some)
+    },
+    None => Ok(None)
+  }
+}
+
+/// Returns page of groups ordered by group id, starting after a given one, and id of the last returned group
+/// when there can be more of them
+pub fn get_aggregates(db: &CacheDB, event_name: &str, aggregation_name: &str, after: Option<&str>, size: usize) -> Result<(Vec<serde_json::Value>, Option<String>), String> {
+  let prefix = aggregation_query(event_name, aggregation_name)?;
+  let group_key_size = KeySchema::aggregates().key_size();
+  let mut iter = db.storage.iter("aggregations")?;
+  match after {
+    Some(group_id) => {
+      let mut after_key = prefix.clone();
+      after_key.extend(decode_hex(group_id)?);
+      after_key.extend(vec![0xffu8; 32]);   // Skips values of the group
+      iter.seek(&after_key);
+    },
+    None => iter.seek(&prefix)
+  };
+  let mut groups: Vec<serde_json::Value> = Vec::new();
+  let mut last_group_id: Option<String> = None;
+  while iter.valid() && iter.key().starts_with(&prefix) && groups.len() < size {
+    if iter.key().len() == group_key_size {
+      let group_key = iter.key().to_vec();
+      let group = serde_json::from_slice::<AggregateGroup>(iter.value()).map_err(|err| err.to_string())?;
+      last_group_id = Some(encode_hex(&group_key[prefix.len()..]));
+      groups.push(group_to_json(db, &group_key, group)?);
+    }
+    iter.next();
+  }
+  let next = if groups.len() < size { None } else { last_group_id };
+  Ok((groups, next))
+}
+
+fn definition_key(event_name: &str, aggregation_name: &str) -> String {
+  format!("{}{}:{}", DEFINITION_PREFIX, event_name, aggregation_name)
+}
+
+/// Rebuilds an aggregation from stored events. Should be run when nothing else writes events.
+/// Returns number of aggregated events, events which fail the aggregation are skipped.
+pub fn rebuild_aggregation(db: &CacheDB, event_name: &str, aggregation: &AggregationConfig) -> Result<usize, String> {
+  let (range_start, range_end) = KeySchema::aggregates().prefix_range(&[KeyValue::Name(event_name.to_string()), KeyValue::Name(aggregation.name.clone())])?;
+  let mut batch = StorageBatch::new();
+  batch.delete_range("aggregations", &range_start, &range_end.ok_or(String::from("Aggregation range has no upper bound"))?);
+  db.storage.write(batch, false)?;
+
+  let aggregations = vec![aggregation.clone()];
+  let event_prefix = name_key(event_name)?;
+  let mut changes = AggregateChanges::new();
+  let mut batch = StorageBatch::new();
+  let mut aggregated: usize = 0;
+  let mut scanned: usize = 0;
+  let mut iter = db.storage.iter("events")?;
+  iter.seek(&event_prefix);
+  while iter.valid() && iter.key().starts_with(&event_prefix) {
+    let params = decode_params(iter.value())?;
+    if changes.apply(&*db.storage, &mut batch, iter.key(), &params, &aggregations, true)? == 0 {
+      aggregated = aggregated + 1;
+    }
+    scanned = scanned + 1;
+    if scanned % REBUILD_BATCH_SIZE == 0 {
+      db.storage.write(batch, false)?;
+      batch = StorageBatch::new();
+      changes = AggregateChanges::new();
+    }
+    iter.next();
+  }
+  let definition = serde_json::to_string(aggregation).map_err(|err| err.to_string())?;
+  batch.put("settings", definition_key(event_name, &aggregation.name).as_bytes(), definition.as_bytes());
+  db.storage.write(batch, true)?;
+  Ok(aggregated)
+}
+
+/// Rebuilds aggregations which are new or were changed in config since they were built
+pub fn check_aggregations(db: &CacheDB, event_name: &str, aggregations: &[AggregationConfig]) -> Result<(), String> {
+  for aggregation in aggregations {
+    let stored_definition = db.storage.get("settings", definition_key(event_name, &aggregation.name).as_bytes())?
+      .and_then(|definition| serde_json::from_slice::<AggregationConfig>(&definition).ok());
+    if stored_definition.as_ref() != Some(aggregation) {
+      info!("Aggregation {} of event {} is built from stored events", aggregation.name, event_name);
+      let aggregated = rebuild_aggregation(db, event_name, aggregation)?;
+      info!("Aggregation {} of event {} has been built from {} events", aggregation.name, event_name, aggregated);
+    }
+  }
+  Ok(())
+}
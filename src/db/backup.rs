@@ -0,0 +1,164 @@
+/// # Module Backup
+/// Consistent copies of the database made with RocksDB checkpoints. Checkpoint directory gets
+/// a manifest describing its content, which is validated before the copy is restored.
+use chrono::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use config::structs::storageconfig::StorageConfig;
+use db::cachedb::CacheDB;
+use db::key_codec::NAME_SIZE;
+use db::key_schema::{KeySchema, KeyValue};
+use db::schema_version::{get_schema_version, SCHEMA_VERSION};
+
+pub const CHAIN_ID_KEY: &'static str = "chain_id";
+pub const MANIFEST_FILE: &'static str = "cacherz_manifest.json";
+
+/// Description of a backup stored next to checkpoint files
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+  pub version: String,                    // Version of cacherz which made the backup
+  pub schema_version: u32,
+  pub chain_id: Option<u64>,
+  pub created_at: i64,                    // Timestamp in milliseconds
+  pub column_families: Vec<String>,
+  pub cursors: HashMap<String, String>    // Last "block-log" of every event
+}
+
+/// Returns chain id of the node which filled the database. None for databases filled before it was stored.
+pub fn get_chain_id(db: &CacheDB) -> Result<Option<u64>, String> {
+  match db.storage.get("settings", CHAIN_ID_KEY.as_bytes())? {
+    Some(value) => {
+      let chain_id_str = String::from_utf8(value).map_err(|_| String::from("Cannot convert chain id to utf8"))?;
+      chain_id_str.parse::<u64>()
+        .map(Some)
+        .map_err(|err| format!("Cannot parse chain id {}. Error: {}", chain_id_str, err))
+    },
+    None => Ok(None)
+  }
+}
+
+/// Checks if database was filled from the same chain as node. Stores chain id on first run.
+pub fn check_chain_id(db: &CacheDB, chain_id: u64) -> Result<(), String> {
+  match get_chain_id(db)? {
+    Some(stored_chain_id) if stored_chain_id != chain_id => {
+      Err(format!("Database holds events of chain {}, but node is connected to chain {}", stored_chain_id, chain_id))
+    },
+    Some(_) => Ok(()),
+    None => db.storage.put("settings", CHAIN_ID_KEY.as_bytes(), chain_id.to_string().as_bytes())
+  }
+}
+
+/// Returns "block-log" cursors of all events
+pub fn get_cursors(db: &CacheDB) -> Result<HashMap<String, String>, String> {
+  let schema = KeySchema::cursors();
+  let mut cursors: HashMap<String, String> = HashMap::new();
+  let mut iter = db.storage.iter("aggregations")?;
+  iter.seek_to_first();
+  while iter.valid() {
+    // Aggregation entries share the column family with cursors
+    if iter.key().len() != schema.key_size() {
+      iter.next();
+      continue;
+    }
+    let cursor: Option<serde_json::Value> = serde_json::from_slice(iter.value()).ok();
+    match (schema.decode(iter.key()), cursor) {
+      (Ok(ref values), Some(ref cursor_json)) if cursor_json["last_block"].is_string() => {
+        if let Some(KeyValue::Name(event_name)) = values.first() {
+          cursors.insert(event_name.clone(), cursor_json["last_block"].as_str().unwrap_or("").to_string());
+        }
+      },
+      _ => warn!("Entry {:?} of aggregations is not a cursor", iter.key())
+    };
+    iter.next();
+  }
+  Ok(cursors)
+}
+
+/// Builds manifest from the content of a database
+pub fn build_manifest(db: &CacheDB, column_families: Vec<&str>) -> Result<BackupManifest, String> {
+  Ok(BackupManifest {
+    version: env!("CARGO_PKG_VERSION").to_string(),
+    schema_version: get_schema_version(db)?.unwrap_or(SCHEMA_VERSION),
+    chain_id: get_chain_id(db)?,
+    created_at: Utc::now().timestamp_millis(),
+    column_families: column_families.into_iter().map(|cf| cf.to_string()).collect(),
+    cursors: get_cursors(db)?
+  })
+}
+
+pub fn read_manifest(backup_path: &str) -> Result<BackupManifest, String> {
+  let manifest_path = Path::new(backup_path).join(MANIFEST_FILE);
+  let manifest_content = fs::read_to_string(&manifest_path)
+    .map_err(|err| format!("Cannot read manifest {}. Error: {}", manifest_path.display(), err))?;
+  serde_json::from_str(&manifest_content)
+    .map_err(|err| format!("Cannot parse manifest {}. Error: {}", manifest_path.display(), err))
+}
+
+fn write_manifest(backup_path: &str, manifest: &BackupManifest) -> Result<(), String> {
+  let manifest_path = Path::new(backup_path).join(MANIFEST_FILE);
+  let manifest_content = serde_json::to_string_pretty(manifest).map_err(|err| err.to_string())?;
+  fs::write(&manifest_path, manifest_content)
+    .map_err(|err| format!("Cannot write manifest {}. Error: {}", manifest_path.display(), err))
+}
+
+/// Creates checkpoint of a running database in `backup_path`, which must not exist yet.
+/// Files are hard linked when backup is on the same filesystem as database.
+pub fn create_backup(db: &CacheDB, backup_path: &str) -> Result<BackupManifest, String> {
+  if Path::new(backup_path).exists() {
+    return Err(format!("Backup path {} already exists", backup_path));
+  }
+  let column_families: Vec<String> = db.storage.column_families();
+  let column_families: Vec<&str> = column_families.iter().map(|cf| cf.as_str()).collect();
+  db.storage.create_checkpoint(Path::new(backup_path))?;
+  // Manifest is built from the checkpoint, so it describes exactly what was copied. Checkpoint is opened
+  // read-only, so it is never modified. It is only scanned, so storage options of the source do not matter.
+  let manifest = {
+    let checkpoint_db = CacheDB::open_read_only(backup_path.to_string(), NAME_SIZE, StorageConfig::default())?;
+    build_manifest(&checkpoint_db, column_families)?
+  };
+  write_manifest(backup_path, &manifest)?;
+  info!("Backup has been created in {}. Schema version: {}, cursors: {:?}", backup_path, manifest.schema_version, manifest.cursors);
+  Ok(manifest)
+}
+
+/// Manifest, lock and info logs are not part of the database
+fn is_restored_file(file_name: &str) -> bool {
+  file_name != MANIFEST_FILE && file_name != "LOCK" && !file_name.starts_with("LOG")
+}
+
+/// Validates backup and copies it into `db_path`, which must be empty. Restored database is opened with `storage_config`
+/// of the service which will run it and is checked against manifest.
+pub fn restore_backup(backup_path: &str, db_path: &str, column_families: Vec<&str>, prefix_length: usize, storage_config: StorageConfig) -> Result<BackupManifest, String> {
+  let manifest = read_manifest(backup_path)?;
+  if manifest.schema_version > SCHEMA_VERSION {
+    return Err(format!("Backup schema version {} is newer than version {} supported by this build", manifest.schema_version, SCHEMA_VERSION));
+  }
+  if let Some(missing_cf) = column_families.iter().find(|cf| !manifest.column_families.iter().any(|backup_cf| backup_cf.as_str() == **cf)) {
+    warn!("Backup has no column family {}. It will be created empty", missing_cf);
+  }
+  let target = Path::new(db_path);
+  let is_empty_target = !target.exists() || fs::read_dir(target).map(|mut entries| entries.next().is_none()).unwrap_or(false);
+  if !is_empty_target {
+    return Err(format!("Database path {} is not empty", db_path));
+  }
+  fs::create_dir_all(target).map_err(|err| format!("Cannot create {}. Error: {}", db_path, err))?;
+  let entries = fs::read_dir(backup_path).map_err(|err| format!("Cannot read {}. Error: {}", backup_path, err))?;
+  for entry in entries {
+    let entry = entry.map_err(|err| err.to_string())?;
+    if !entry.path().is_file() || !is_restored_file(&entry.file_name().to_string_lossy()) {
+      continue;
+    }
+    fs::copy(entry.path(), target.join(entry.file_name()))
+      .map_err(|err| format!("Cannot copy {}. Error: {}", entry.path().display(), err))?;
+  }
+  let restored_db = CacheDB::create_with_config(db_path.to_string(), column_families, prefix_length, storage_config)?;
+  let restored_schema_version = get_schema_version(&restored_db)?.unwrap_or(SCHEMA_VERSION);
+  let restored_cursors = get_cursors(&restored_db)?;
+  if restored_schema_version != manifest.schema_version || get_chain_id(&restored_db)? != manifest.chain_id || restored_cursors != manifest.cursors {
+    return Err(format!("Restored database in {} does not match manifest of backup {}", db_path, backup_path));
+  }
+  info!("Backup {} has been restored into {}. Cursors: {:?}", backup_path, db_path, restored_cursors);
+  Ok(manifest)
+}
@@ -1,18 +1,37 @@
-use rocksdb::{ColumnFamilyOptions, DBOptions, DB, SliceTransform, BlockBasedOptions, DBCompactionStyle};
+#[cfg(feature = "rocksdb")]
+use rocksdb::{ColumnFamilyOptions, DBOptions, DB, SliceTransform, BlockBasedOptions, DBCompactionStyle, DBCompressionType, Cache, LRUCacheOptions};
+use config::structs::storageconfig::StorageConfig;
+#[cfg(feature = "rocksdb")]
+use config::structs::storageconfig::{ColumnFamilyConfig, Compression, CompactionStyle};
 
+use db::storage::Storage;
+#[cfg(feature = "rocksdb")]
+use db::rocks_storage::{RocksStorage, ReadOnlyRocksStorage};
+use db::memory_storage::MemoryStorage;
+
+use std::fmt;
 use std::sync::Arc;
 use std::str::from_utf8;
 
 
-#[derive(Debug, Clone)]
+/// Handle of the storage shared by actors. RocksDB is used by the service, memory storage by library users and tests.
+#[derive(Clone)]
 pub struct CacheDB{
-  pub db: Arc<rocksdb::DB>
+  pub storage: Arc<dyn Storage>
+}
+
+impl fmt::Debug for CacheDB {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "CacheDB {{ column_families: {:?} }}", self.storage.column_families())
+  }
 }
 
+#[cfg(feature = "rocksdb")]
 struct FixedPrefixTransform {
     pub prefix_len: usize,
 }
 
+#[cfg(feature = "rocksdb")]
 impl SliceTransform for FixedPrefixTransform {
     fn transform<'a>(&mut self, key: &'a [u8]) -> &'a [u8] {
         // println!("{:?}", from_utf8(key).unwrap());
@@ -28,36 +47,95 @@ impl SliceTransform for FixedPrefixTransform {
     }
 }
 
+/// Column families whose keys start with a padded event name. Keys of others start with an address
+/// or contract name, so prefix of the name size means nothing there.
+#[cfg(feature = "rocksdb")]
+const NAME_PREFIXED_COLUMN_FAMILIES: [&'static str; 4] = ["events", "indexes", "aggregations", "filters"];
+
+/// Length of the prefix extractor of a column family. None when its keys do not start with event name.
+#[cfg(feature = "rocksdb")]
+fn name_prefix_length(column_family: &str, prefix_length: usize) -> Option<usize> {
+  if NAME_PREFIXED_COLUMN_FAMILIES.contains(&column_family) {
+    Some(prefix_length)
+  } else {
+    None
+  }
+}
+
+#[cfg(feature = "rocksdb")]
+fn to_compression_type(compression: Compression) -> DBCompressionType {
+  match compression {
+    Compression::None => DBCompressionType::No,
+    Compression::Snappy => DBCompressionType::Snappy,
+    Compression::Lz4 => DBCompressionType::Lz4,
+    Compression::Zstd => DBCompressionType::Zstd
+  }
+}
+
+#[cfg(feature = "rocksdb")]
+fn to_compaction_style(compaction_style: CompactionStyle) -> DBCompactionStyle {
+  match compaction_style {
+    CompactionStyle::Level => DBCompactionStyle::Level,
+    CompactionStyle::Universal => DBCompactionStyle::Universal,
+    CompactionStyle::Fifo => DBCompactionStyle::Fifo
+  }
+}
+
+#[cfg(feature = "rocksdb")]
+fn new_lru_cache(size_mb: usize) -> Cache {
+  let mut cache_opts = LRUCacheOptions::new();
+  cache_opts.set_capacity(size_mb * 1024 * 1024);
+  Cache::new_lru_cache(cache_opts)
+}
+
+/// Column families written for every event, whatever indexes are configured
+pub const INDEX_COLUMN_FAMILIES: [&'static str; 3] = ["indexes", "addresses", "timeline"];
+
+/// Adds index column families missing in the given list, so databases created with older lists keep working
+fn with_index_column_families<'a>(mut column_families: Vec<&'a str>) -> Vec<&'a str> {
+  for cf in INDEX_COLUMN_FAMILIES.iter() {
+    if !column_families.contains(cf) {
+      column_families.push(cf);
+    }
+  }
+  column_families
+}
+
+impl CacheDB{
+  pub fn from_storage<S: Storage + 'static>(storage: S) -> CacheDB {
+    CacheDB{storage: Arc::new(storage)}
+  }
+  /// Creates empty database kept in memory, meant for tests (see `MemoryStorage`)
+  /// Creates empty database kept in memory
+  pub fn in_memory(column_families: Vec<&str>) -> CacheDB {
+    Self::from_storage(MemoryStorage::new(with_index_column_families(column_families)))
+  }
+}
+
+/// RocksDB databases on disk. Without "rocksdb" feature only in-memory databases are available.
+#[cfg(feature = "rocksdb")]
 impl CacheDB{
   pub fn create(db_path: String, column_families: Vec<&str>, prefix_length: usize) -> Result<CacheDB, String> {
-    let mut bbto = BlockBasedOptions::new();
-    bbto.set_bloom_filter(10, false);
-    bbto.set_whole_key_filtering(false);
-    
+    Self::create_with_config(db_path, column_families, prefix_length, StorageConfig::default())
+  }
+
+  /// Opens database with column families tuned by storage config
+  pub fn create_with_config(db_path: String, column_families: Vec<&str>, prefix_length: usize, storage_config: StorageConfig) -> Result<CacheDB, String> {
+    let column_families = with_index_column_families(column_families);
     let mut opts = DBOptions::new();
     opts.create_if_missing(true);
     opts.set_use_fsync(false);
     opts.set_bytes_per_sync(8388608);
     opts.set_max_background_jobs(4);
 
-    let mut cf_opts = ColumnFamilyOptions::new();
-    cf_opts.set_block_based_table_factory(&bbto);
-    cf_opts.set_block_cache_size_mb(1024);
-    cf_opts.set_compaction_style(DBCompactionStyle::Universal);
-    cf_opts.set_level_zero_stop_writes_trigger(2000);
-    cf_opts.set_level_zero_slowdown_writes_trigger(0);
-    cf_opts.set_disable_auto_compactions(true);
-    cf_opts.set_min_write_buffer_number_to_merge(4);
-    cf_opts.set_write_buffer_size(536870912);
-    cf_opts.set_prefix_extractor(
-      "FixedPrefixTransform",
-      Box::new(FixedPrefixTransform { prefix_len: prefix_length }),
-      ).expect("Cannot set prefix extractor for database");
-    cf_opts.set_memtable_prefix_bloom_size_ratio(0.1 as f64);
-    match DB::open_cf(opts.clone(), &db_path, vec![("default", cf_opts.clone())]) {
+    let shared_cache = new_lru_cache(storage_config.get_shared_cache_size_mb());
+    let cf_opts_for = |cf: &str| -> ColumnFamilyOptions {
+      Self::column_family_options(&storage_config.get_column_family_config(cf), &shared_cache, name_prefix_length(cf, prefix_length))
+    };
+    match DB::open_cf(opts.clone(), &db_path, vec![("default", cf_opts_for("default"))]) {
       Ok(mut db_handler) => {
         for cf in  column_families {
-          match db_handler.create_cf(cf.clone()) {
+          match db_handler.create_cf((cf, cf_opts_for(cf))) {
             Ok(_) => info!("Column family: {} was created", cf),
             Err(error_cf) => {
               error!("Cannot create column family: {}. Reason: {}", cf, error_cf);
@@ -65,14 +143,25 @@ impl CacheDB{
               },
           };
         };
-        Ok(CacheDB{db: Arc::new(db_handler)})},
+        Ok(Self::from_storage(RocksStorage::new(db_handler)))},
       Err(error) => {
         error!("There was an error on opening default database in RocksWriteAcotor. Error: {:?}", error);
-        let _generated_column_families_tuples = Self::generate_column_families_tuples(column_families.clone(), cf_opts.clone());
+        // Database could be created by older version with fewer column families.
+        // Existing column families are opened and missing ones are created.
+        let existing_column_families: Vec<String> = DB::list_column_families(&opts, &db_path)?;
+        let _generated_column_families_tuples = existing_column_families.iter()
+          .map(|cf| (cf.as_str(), cf_opts_for(cf.as_str())))
+          .collect::<Vec<(&str, ColumnFamilyOptions)>>();
         match DB::open_cf(opts.clone(), &db_path, _generated_column_families_tuples) {
-          Ok(db_handler_with_cf_group) => return Ok(CacheDB{db: Arc::new(db_handler_with_cf_group)}),
+          Ok(mut db_handler_with_cf_group) => {
+            for cf in column_families.into_iter().filter(|cf| !existing_column_families.iter().any(|existing| existing.as_str() == *cf)) {
+              db_handler_with_cf_group.create_cf((cf, cf_opts_for(cf)))?;
+              info!("Missing column family: {} was created", cf);
+            }
+            return Ok(Self::from_storage(RocksStorage::new(db_handler_with_cf_group)));
+          },
           Err(error_db_handler_with_cf_group) => {
-            error!("Cannot open db with column families {:?}", column_families);
+            error!("Cannot open db with column families {:?}", existing_column_families);
             return Err(error_db_handler_with_cf_group);
           }
         };
@@ -80,11 +169,85 @@ impl CacheDB{
     }
   }
 
-  fn generate_column_families_tuples(column_families: Vec<&str>, cf_opts: ColumnFamilyOptions) -> Vec<(&str, ColumnFamilyOptions)> {
-    column_families
-      .into_iter()
-      .map(|cf| {
-        (cf, cf_opts.clone())
-      }).collect::<Vec<(&str, ColumnFamilyOptions)>>()
+  /// Opens database written by another process as read-only. `catch_up` of its storage opens it again,
+  /// so writes made since are visible. Column families are the ones the database already has.
+  pub fn open_read_only(db_path: String, prefix_length: usize, storage_config: StorageConfig) -> Result<CacheDB, String> {
+    let open = move || -> Result<DB, String> {
+      let opts = DBOptions::new();
+      let shared_cache = new_lru_cache(storage_config.get_shared_cache_size_mb());
+      let existing_column_families: Vec<String> = DB::list_column_families(&opts, &db_path)?;
+      let column_families_tuples = existing_column_families.iter()
+        .map(|cf| (cf.as_str(), Self::column_family_options(&storage_config.get_column_family_config(cf), &shared_cache, name_prefix_length(cf, prefix_length))))
+        .collect::<Vec<(&str, ColumnFamilyOptions)>>();
+      DB::open_cf_for_read_only(opts, &db_path, column_families_tuples, false)
+    };
+    ReadOnlyRocksStorage::new(open).map(Self::from_storage)
+  }
+
+  /// Builds RocksDB options of a single column family. Prefix extractor is set only with `prefix_length`.
+  /// Compaction style and auto compactions come from storage config defaults.
+  fn column_family_options(config: &ColumnFamilyConfig, shared_cache: &Cache, prefix_length: Option<usize>) -> ColumnFamilyOptions {
+    let mut bbto = BlockBasedOptions::new();
+    bbto.set_bloom_filter(10, false);
+    bbto.set_whole_key_filtering(false);
+    if let Some(block_size) = config.block_size {
+      bbto.set_block_size(block_size);
+    }
+    match (config.shared_cache, config.block_cache_size_mb) {
+      (Some(true), _) => bbto.set_block_cache(shared_cache),
+      (_, Some(block_cache_size_mb)) => bbto.set_block_cache(&new_lru_cache(block_cache_size_mb)),
+      _ => ()
+    };
+
+    let mut cf_opts = ColumnFamilyOptions::new();
+    cf_opts.set_block_based_table_factory(&bbto);
+    if let Some(compression) = config.compression {
+      cf_opts.compression(to_compression_type(compression));
+    }
+    if let Some(bottommost_compression) = config.bottommost_compression {
+      cf_opts.bottommost_compression(to_compression_type(bottommost_compression));
+    }
+    if config.compression_level.is_some() || config.max_dict_bytes.is_some() {
+      // -14 and 0 are RocksDB defaults of window bits and strategy
+      cf_opts.compression_options(-14, config.compression_level.unwrap_or(-1), 0, config.max_dict_bytes.unwrap_or(0));
+    }
+    if let Some(compaction_style) = config.compaction_style {
+      cf_opts.set_compaction_style(to_compaction_style(compaction_style));
+    }
+    if let Some(write_buffer_size_mb) = config.write_buffer_size_mb {
+      cf_opts.set_write_buffer_size(write_buffer_size_mb * 1024 * 1024);
+    }
+    if let Some(max_write_buffer_number) = config.max_write_buffer_number {
+      cf_opts.set_max_write_buffer_number(max_write_buffer_number);
+    }
+    if config.disable_auto_compactions == Some(true) {
+      // Level 0 files pile up until manual compaction, writes should not stall on them
+      cf_opts.set_disable_auto_compactions(true);
+      cf_opts.set_level_zero_slowdown_writes_trigger(2000);
+      cf_opts.set_level_zero_stop_writes_trigger(2000);
+    }
+    if let Some(prefix_length) = prefix_length {
+      cf_opts.set_prefix_extractor(
+        "FixedPrefixTransform",
+        Box::new(FixedPrefixTransform { prefix_len: prefix_length }),
+        ).expect("Cannot set prefix extractor for database");
+      cf_opts.set_memtable_prefix_bloom_size_ratio(0.1 as f64);
+    }
+    cf_opts
+  }
+}
+
+#[cfg(not(feature = "rocksdb"))]
+impl CacheDB{
+  pub fn create(db_path: String, column_families: Vec<&str>, prefix_length: usize) -> Result<CacheDB, String> {
+    Self::create_with_config(db_path, column_families, prefix_length, StorageConfig::default())
+  }
+
+  pub fn create_with_config(db_path: String, _column_families: Vec<&str>, _prefix_length: usize, _storage_config: StorageConfig) -> Result<CacheDB, String> {
+    Err(format!("Cannot open database {}. Cacherz is built without rocksdb feature", db_path))
   }
-}
\ No newline at end of file
+
+  pub fn open_read_only(db_path: String, _prefix_length: usize, _storage_config: StorageConfig) -> Result<CacheDB, String> {
+    Err(format!("Cannot open database {}. Cacherz is built without rocksdb feature", db_path))
+  }
+}
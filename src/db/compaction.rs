@@ -0,0 +1,112 @@
+/// # Module Compaction
+/// Manual compaction of column families. Status of the last compaction of every column family is kept
+/// in "stats" column family under "compaction:<column family>" key, so it can be read while compaction runs.
+use chrono::prelude::*;
+use serde_json::Value;
+use db::cachedb::CacheDB;
+use db::key_codec::{encode_hex, decode_hex};
+
+pub const COMPACTION_STATUS_PREFIX: &'static str = "compaction:";
+
+/// Size and shape of a column family taken from RocksDB properties. Storages without them report zeros.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnFamilyStats {
+  pub total_sst_files_size: u64,
+  pub live_sst_files_size: u64,
+  pub level0_files: u64,
+  pub estimated_keys: u64,
+  pub pending_compaction_bytes: u64,
+  pub running_compactions: u64
+}
+
+/// Status of manual compaction of a column family
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactionStatus {
+  pub column_family: String,
+  pub status: String,                     // "running" or "done"
+  pub reason: String,                     // What triggered compaction: "manual", "interval" or "size"
+  pub from: Option<String>,               // Key range as hex, None is an open end
+  pub to: Option<String>,
+  pub started_at: i64,                    // Timestamps in milliseconds
+  pub finished_at: Option<i64>,
+  pub before: ColumnFamilyStats,
+  pub after: Option<ColumnFamilyStats>
+}
+
+pub fn get_column_family_stats(db: &CacheDB, column_family: &str) -> Result<ColumnFamilyStats, String> {
+  if !db.storage.has_column_family(column_family) {
+    return Err(format!("There is no column family as: {}", column_family));
+  }
+  let property = |name: &str| db.storage.get_property_int(column_family, name).unwrap_or(0);
+  Ok(ColumnFamilyStats {
+    total_sst_files_size: property("rocksdb.total-sst-files-size"),
+    live_sst_files_size: property("rocksdb.live-sst-files-size"),
+    level0_files: property("rocksdb.num-files-at-level0"),
+    estimated_keys: property("rocksdb.estimate-num-keys"),
+    pending_compaction_bytes: property("rocksdb.estimate-pending-compaction-bytes"),
+    running_compactions: property("rocksdb.num-running-compactions")
+  })
+}
+
+fn put_compaction_status(db: &CacheDB, compaction_status: &CompactionStatus) -> Result<(), String> {
+  let key = format!("{}{}", COMPACTION_STATUS_PREFIX, compaction_status.column_family);
+  let value = serde_json::to_string(compaction_status).map_err(|err| err.to_string())?;
+  db.storage.put("stats", key.as_bytes(), value.as_bytes())
+}
+
+/// Returns status of the last compaction of a column family. None when it was never compacted manually.
+pub fn get_compaction_status(db: &CacheDB, column_family: &str) -> Result<Option<CompactionStatus>, String> {
+  let key = format!("{}{}", COMPACTION_STATUS_PREFIX, column_family);
+  match db.storage.get("stats", key.as_bytes())? {
+    Some(value) => {
+      serde_json::from_slice(&value)
+        .map(Some)
+        .map_err(|err| format!("Cannot decode compaction status of {}. Error: {}", column_family, err))
+    },
+    None => Ok(None)
+  }
+}
+
+/// Returns live stats and the last compaction status of a column family as json
+pub fn get_compaction_report(db: &CacheDB, column_family: &str) -> Result<Value, String> {
+  Ok(json!({
+    "columnFamily": column_family,
+    "stats": get_column_family_stats(db, column_family)?,
+    "lastCompaction": get_compaction_status(db, column_family)?
+  }))
+}
+
+/// Parses optional key range bound given as hex
+pub fn parse_key_bound(bound: Option<&String>) -> Result<Option<Vec<u8>>, String> {
+  match bound {
+    Some(hex_key) => decode_hex(hex_key).map(Some),
+    None => Ok(None)
+  }
+}
+
+/// Compacts column family or its key range. Blocks until compaction is finished.
+pub fn compact(db: &CacheDB, column_family: &str, from: Option<&[u8]>, to: Option<&[u8]>, reason: &str) -> Result<CompactionStatus, String> {
+  let mut compaction_status = CompactionStatus {
+    column_family: column_family.to_string(),
+    status: String::from("running"),
+    reason: reason.to_string(),
+    from: from.map(encode_hex),
+    to: to.map(encode_hex),
+    started_at: Utc::now().timestamp_millis(),
+    finished_at: None,
+    before: get_column_family_stats(db, column_family)?,
+    after: None
+  };
+  put_compaction_status(db, &compaction_status)?;
+  info!("Compaction of column family {} has started. Reason: {}", column_family, reason);
+  db.storage.compact_range(column_family, from, to)?;
+  compaction_status.status = String::from("done");
+  compaction_status.finished_at = Some(Utc::now().timestamp_millis());
+  compaction_status.after = Some(get_column_family_stats(db, column_family)?);
+  put_compaction_status(db, &compaction_status)?;
+  info!("Compaction of column family {} has finished in {} ms", column_family,
+    compaction_status.finished_at.unwrap_or(0) - compaction_status.started_at);
+  Ok(compaction_status)
+}
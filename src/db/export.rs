@@ -0,0 +1,429 @@
+/// # Module Export
+/// Exports stored events of an event or of a whole contract, optionally limited to a block range,
+/// as CSV, NDJSON or Parquet. Columns are `contract`, `event`, `block`, `log`, `transactionHash`, `transactionIndex`,
+/// `txSender` of contracts which index it, and event arguments typed from the abi, named with `arg.` so they never collide with metadata:
+/// uint and int up to 64 bits are numbers, bool is boolean, everything else (ex. uint256, address) is text.
+/// Events are read with an iterator and written row by row, so exports never load the whole table into memory.
+use ethabi::{Event, ParamType, Uint};
+use serde_json::{self, Map, Value};
+use parquet::basic::{ConvertedType, Repetition, Type as PhysicalType};
+use parquet::column::writer::ColumnWriter;
+use parquet::data_type::ByteArray;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+use db::aggregations::parse_uint;
+use db::cachedb::CacheDB;
+use db::indexes::TX_SENDER_PARAM;
+use db::key_schema::{decode_event_key, qualified_name, KeySchema, KeyValue};
+use db::value_codec::decode_params;
+
+const PARQUET_ROW_GROUP_SIZE: usize = 10000;
+
+/// Prefix of argument columns, ex. "arg.value"
+pub const ARGUMENT_PREFIX: &'static str = "arg.";
+
+pub fn argument_column(name: &str) -> String {
+  format!("{}{}", ARGUMENT_PREFIX, name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+  Csv,
+  Ndjson,
+  Parquet
+}
+
+pub fn parse_export_format(name: &str) -> Result<ExportFormat, String> {
+  match name {
+    "csv" => Ok(ExportFormat::Csv),
+    "ndjson" => Ok(ExportFormat::Ndjson),
+    "parquet" => Ok(ExportFormat::Parquet),
+    _ => Err(format!("Unknown export format {}. Use csv, ndjson or parquet", name))
+  }
+}
+
+/// Content type of http export
+pub fn content_type(format: ExportFormat) -> &'static str {
+  match format {
+    ExportFormat::Csv => "text/csv",
+    ExportFormat::Ndjson => "application/x-ndjson",
+    ExportFormat::Parquet => "application/octet-stream"
+  }
+}
+
+/// Event of a contract which can be exported
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportSource {
+  pub contract: String,
+  pub event: Event,
+  pub index_tx_sender: bool     // Events have transaction sender in their params
+}
+
+/// Picks the event, or every event of the contract sorted by name
+pub fn select_sources(sources: &[ExportSource], contract: Option<&str>, event_name: Option<&str>) -> Result<Vec<ExportSource>, String> {
+  if contract.is_none() && event_name.is_none() {
+    return Err(String::from("There is no event or contract to export"));
+  }
+  let mut selected: Vec<ExportSource> = sources.iter()
+    .filter(|source| contract.map_or(true, |contract| contract == source.contract) && event_name.map_or(true, |event_name| event_name == source.event.name))
+    .cloned()
+    .collect();
+  if selected.is_empty() {
+    return Err(format!("There is no event {} of contract {}", event_name.unwrap_or("*"), contract.unwrap_or("*")));
+  }
+  selected.sort_by(|a, b| (&a.contract, &a.event.name).cmp(&(&b.contract, &b.event.name)));
+  Ok(selected)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnType {
+  UInt64,
+  Int64,
+  Bool,
+  Text
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnValue {
+  Null,
+  UInt64(u64),
+  Int64(i64),
+  Bool(bool),
+  Text(String)
+}
+
+impl ColumnValue {
+  fn to_text(&self) -> ColumnValue {
+    match *self {
+      ColumnValue::Null => ColumnValue::Null,
+      ColumnValue::UInt64(number) => ColumnValue::Text(number.to_string()),
+      ColumnValue::Int64(number) => ColumnValue::Text(number.to_string()),
+      ColumnValue::Bool(boolean) => ColumnValue::Text(boolean.to_string()),
+      ColumnValue::Text(ref text) => ColumnValue::Text(text.clone())
+    }
+  }
+
+  fn to_json(&self) -> Value {
+    match *self {
+      ColumnValue::Null => Value::Null,
+      ColumnValue::UInt64(number) => json!(number),
+      ColumnValue::Int64(number) => json!(number),
+      ColumnValue::Bool(boolean) => json!(boolean),
+      ColumnValue::Text(ref text) => json!(text)
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportColumn {
+  pub name: String,
+  pub column_type: ColumnType
+}
+
+fn column_type(param_type: &ParamType) -> ColumnType {
+  match *param_type {
+    ParamType::Uint(size) if size <= 64 => ColumnType::UInt64,
+    ParamType::Int(size) if size <= 64 => ColumnType::Int64,
+    ParamType::Bool => ColumnType::Bool,
+    _ => ColumnType::Text
+  }
+}
+
+/// Converts decoded param (as formatted by ethabi) into a typed value
+pub fn column_value(param_type: &ParamType, raw_value: &str) -> Result<ColumnValue, String> {
+  Ok(match *param_type {
+    ParamType::Uint(size) => {
+      let number = parse_uint(raw_value)?;
+      if size <= 64 { ColumnValue::UInt64(number.low_u64()) } else { ColumnValue::Text(number.to_string()) }
+    },
+    ParamType::Int(size) => {
+      // Ints are two's complement of 256 bits
+      let number = parse_uint(raw_value)?;
+      if size <= 64 {
+        ColumnValue::Int64(number.low_u64() as i64)
+      } else if number.bit(255) {
+        ColumnValue::Text(format!("-{}", (!number).overflowing_add(Uint::from(1)).0))
+      } else {
+        ColumnValue::Text(number.to_string())
+      }
+    },
+    ParamType::Bool => match raw_value {
+      "true" => ColumnValue::Bool(true),
+      "false" => ColumnValue::Bool(false),
+      _ => return Err(format!("Can not parse {} into bool", raw_value))
+    },
+    ParamType::Address | ParamType::Bytes | ParamType::FixedBytes(_) => ColumnValue::Text(format!("0x{}", raw_value.trim_start_matches("0x"))),
+    _ => ColumnValue::Text(raw_value.to_string())
+  })
+}
+
+/// Metadata columns followed by arguments of every source. Argument of the same name and different types is text.
+/// Transaction sender has a column only when a source indexes it.
+pub fn export_columns(sources: &[ExportSource]) -> Vec<ExportColumn> {
+  let mut columns = vec![
+    ExportColumn{name: String::from("contract"), column_type: ColumnType::Text},
+    ExportColumn{name: String::from("event"), column_type: ColumnType::Text},
+    ExportColumn{name: String::from("block"), column_type: ColumnType::UInt64},
+    ExportColumn{name: String::from("log"), column_type: ColumnType::UInt64},
+    ExportColumn{name: String::from("transactionHash"), column_type: ColumnType::Text},
+    ExportColumn{name: String::from("transactionIndex"), column_type: ColumnType::Text}
+  ];
+  if sources.iter().any(|source| source.index_tx_sender) {
+    columns.push(ExportColumn{name: String::from(TX_SENDER_PARAM), column_type: ColumnType::Text});
+  }
+  for source in sources {
+    for input in source.event.inputs.iter() {
+      let input_type = column_type(&input.kind);
+      let name = argument_column(&input.name);
+      match columns.iter().position(|column| column.name == name) {
+        Some(position) => if columns[position].column_type != input_type {
+          columns[position].column_type = ColumnType::Text;
+        },
+        None => columns.push(ExportColumn{name: name, column_type: input_type})
+      };
+    }
+  }
+  columns
+}
+
+/// Writes rows in one of export formats
+pub trait TableWriter {
+  fn write_row(&mut self, row: &[ColumnValue]) -> Result<(), String>;
+  /// Writes what is buffered, ex. parquet footer
+  fn finish(self: Box<Self>) -> Result<(), String>;
+}
+
+/// Quotes fields with separators, quotes or new lines (RFC 4180)
+fn csv_field(value: &ColumnValue) -> String {
+  match value.to_text() {
+    ColumnValue::Text(text) => {
+      if text.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", text.replace("\"", "\"\""))
+      } else {
+        text
+      }
+    },
+    _ => String::new()
+  }
+}
+
+struct CsvWriter<W: Write> {
+  out: W
+}
+
+impl<W: Write> TableWriter for CsvWriter<W> {
+  fn write_row(&mut self, row: &[ColumnValue]) -> Result<(), String> {
+    let line = row.iter().map(csv_field).collect::<Vec<String>>().join(",");
+    writeln!(self.out, "{}", line).map_err(|err| err.to_string())
+  }
+
+  fn finish(mut self: Box<Self>) -> Result<(), String> {
+    self.out.flush().map_err(|err| err.to_string())
+  }
+}
+
+struct NdjsonWriter<W: Write> {
+  out: W,
+  columns: Vec<ExportColumn>
+}
+
+impl<W: Write> TableWriter for NdjsonWriter<W> {
+  /// Arguments which the event does not have are left out
+  fn write_row(&mut self, row: &[ColumnValue]) -> Result<(), String> {
+    let mut fields = Map::new();
+    for (column, value) in self.columns.iter().zip(row.iter()) {
+      if *value != ColumnValue::Null {
+        fields.insert(column.name.clone(), value.to_json());
+      }
+    }
+    let line = serde_json::to_string(&Value::Object(fields)).map_err(|err| err.to_string())?;
+    writeln!(self.out, "{}", line).map_err(|err| err.to_string())
+  }
+
+  fn finish(mut self: Box<Self>) -> Result<(), String> {
+    self.out.flush().map_err(|err| err.to_string())
+  }
+}
+
+/// Rows are buffered into row groups, so memory is bounded by the row group size and not by the file size
+struct ParquetTableWriter<W: Write> {
+  writer: SerializedFileWriter<W>,
+  rows: Vec<Vec<ColumnValue>>
+}
+
+impl<W: Write> ParquetTableWriter<W> {
+  /// Every column is optional, so nulls are only definition levels of 0
+  fn flush_row_group(&mut self) -> Result<(), String> {
+    let mut row_group = self.writer.next_row_group().map_err(|err| err.to_string())?;
+    let mut position = 0;
+    while let Some(mut column) = row_group.next_column().map_err(|err| err.to_string())? {
+      let def_levels: Vec<i16> = self.rows.iter().map(|row| if row[position] == ColumnValue::Null { 0 } else { 1 }).collect();
+      let result = match *column.untyped() {
+        ColumnWriter::Int64ColumnWriter(ref mut typed) => {
+          let values: Vec<i64> = self.rows.iter().filter_map(|row| match row[position] {
+            ColumnValue::Int64(number) => Some(number),
+            ColumnValue::UInt64(number) => Some(number as i64),
+            _ => None
+          }).collect();
+          typed.write_batch(&values, Some(&def_levels), None)
+        },
+        ColumnWriter::BoolColumnWriter(ref mut typed) => {
+          let values: Vec<bool> = self.rows.iter().filter_map(|row| match row[position] {
+            ColumnValue::Bool(boolean) => Some(boolean),
+            _ => None
+          }).collect();
+          typed.write_batch(&values, Some(&def_levels), None)
+        },
+        ColumnWriter::ByteArrayColumnWriter(ref mut typed) => {
+          let values: Vec<ByteArray> = self.rows.iter().filter_map(|row| match row[position] {
+            ColumnValue::Text(ref text) => Some(ByteArray::from(text.as_str())),
+            _ => None
+          }).collect();
+          typed.write_batch(&values, Some(&def_levels), None)
+        },
+        _ => return Err(format!("Column {} has unexpected parquet type", position))
+      };
+      result.map_err(|err| err.to_string())?;
+      column.close().map_err(|err| err.to_string())?;
+      position = position + 1;
+    }
+    row_group.close().map_err(|err| err.to_string())?;
+    self.rows.clear();
+    Ok(())
+  }
+}
+
+impl<W: Write> TableWriter for ParquetTableWriter<W> {
+  fn write_row(&mut self, row: &[ColumnValue]) -> Result<(), String> {
+    self.rows.push(row.to_vec());
+    if self.rows.len() >= PARQUET_ROW_GROUP_SIZE {
+      self.flush_row_group()?;
+    }
+    Ok(())
+  }
+
+  fn finish(mut self: Box<Self>) -> Result<(), String> {
+    if !self.rows.is_empty() {
+      self.flush_row_group()?;
+    }
+    let mut out = self.writer.into_inner().map_err(|err| err.to_string())?;
+    out.flush().map_err(|err| err.to_string())
+  }
+}
+
+/// Flat schema of optional columns. Unsigned numbers are INT64 annotated as UINT_64 and text is UTF8 BYTE_ARRAY.
+fn parquet_schema(columns: &[ExportColumn]) -> Result<Type, String> {
+  let mut fields = columns.iter().map(|column| {
+    let (physical_type, converted_type) = match column.column_type {
+      ColumnType::UInt64 => (PhysicalType::INT64, ConvertedType::UINT_64),
+      ColumnType::Int64 => (PhysicalType::INT64, ConvertedType::NONE),
+      ColumnType::Bool => (PhysicalType::BOOLEAN, ConvertedType::NONE),
+      ColumnType::Text => (PhysicalType::BYTE_ARRAY, ConvertedType::UTF8)
+    };
+    Type::primitive_type_builder(&column.name, physical_type)
+      .with_repetition(Repetition::OPTIONAL)
+      .with_converted_type(converted_type)
+      .build()
+      .map(Arc::new)
+      .map_err(|err| format!("Can not build parquet column {}. Error: {}", column.name, err))
+  }).collect::<Result<Vec<_>, String>>()?;
+  Type::group_type_builder("schema").with_fields(&mut fields).build().map_err(|err| err.to_string())
+}
+
+/// Creates writer of the format. CSV header is written right away.
+pub fn new_table_writer<'a, W: Write + 'a>(format: ExportFormat, columns: &[ExportColumn], mut out: W) -> Result<Box<dyn TableWriter + 'a>, String> {
+  match format {
+    ExportFormat::Csv => {
+      let header = columns.iter().map(|column| csv_field(&ColumnValue::Text(column.name.clone()))).collect::<Vec<String>>().join(",");
+      writeln!(out, "{}", header).map_err(|err| err.to_string())?;
+      Ok(Box::new(CsvWriter{out: out}))
+    },
+    ExportFormat::Ndjson => Ok(Box::new(NdjsonWriter{out: out, columns: columns.to_vec()})),
+    ExportFormat::Parquet => {
+      let properties = WriterProperties::builder()
+        .set_created_by(String::from("cacherz"))
+        .build();
+      let writer = SerializedFileWriter::new(out, Arc::new(parquet_schema(columns)?), Arc::new(properties)).map_err(|err| err.to_string())?;
+      Ok(Box::new(ParquetTableWriter{writer: writer, rows: Vec::with_capacity(PARQUET_ROW_GROUP_SIZE)}))
+    }
+  }
+}
+
+/// Transaction hash and index of the event, which are kept in its timeline entry
+fn get_transaction(db: &CacheDB, contract: &str, event_name: &str, block_number: u64, log_index: u32) -> Result<(ColumnValue, ColumnValue), String> {
+  let timeline_key = KeySchema::timeline().encode(&[
+    KeyValue::Name(contract.to_string()),
+    KeyValue::U64(block_number),
+    KeyValue::U32(log_index),
+    KeyValue::Name(event_name.to_string())
+  ])?;
+  let entry: Value = match db.storage.get("timeline", &timeline_key)? {
+    Some(entry) => serde_json::from_slice(&entry).map_err(|err| format!("Can not parse timeline entry of {}. Error: {}", event_name, err))?,
+    None => return Ok((ColumnValue::Null, ColumnValue::Null))
+  };
+  let text_value = |field: &str| match entry[field].as_str() {
+    Some(text) if !text.is_empty() => ColumnValue::Text(text.to_string()),
+    _ => ColumnValue::Null
+  };
+  Ok((text_value("transactionHash"), text_value("transactionIndex")))
+}
+
+/// Builds row of a stored event. Arguments of other events stay null.
+fn event_row(columns: &[ExportColumn], source: &ExportSource, block_number: u64, log_index: u32, transaction: (ColumnValue, ColumnValue), params: &HashMap<String, String>) -> Result<Vec<ColumnValue>, String> {
+  let mut row = vec![ColumnValue::Null; columns.len()];
+  row[0] = ColumnValue::Text(source.contract.clone());
+  row[1] = ColumnValue::Text(source.event.name.clone());
+  row[2] = ColumnValue::UInt64(block_number);
+  row[3] = ColumnValue::UInt64(log_index as u64);
+  row[4] = transaction.0;
+  row[5] = transaction.1;
+  if let (true, Some(sender)) = (source.index_tx_sender, params.get(TX_SENDER_PARAM)) {
+    row[6] = ColumnValue::Text(format!("0x{}", sender.trim_start_matches("0x")));
+  }
+  for input in source.event.inputs.iter() {
+    let raw_value = match params.get(&input.name) {
+      Some(raw_value) => raw_value,
+      None => continue
+    };
+    let name = argument_column(&input.name);
+    if let Some(position) = columns.iter().position(|column| column.name == name) {
+      let value = column_value(&input.kind, raw_value)
+        .map_err(|err| format!("Can not export argument {} of {}. Error: {}", input.name, source.event.name, err))?;
+      row[position] = if columns[position].column_type == ColumnType::Text { value.to_text() } else { value };
+    }
+  }
+  Ok(row)
+}
+
+/// Writes events of sources from `from_block` to `to_block` (both inclusive). Events are written source by source,
+/// each of them in chain order. Returns number of written rows.
+pub fn export_events<W: Write>(db: &CacheDB, sources: &[ExportSource], from_block: Option<u64>, to_block: Option<u64>, format: ExportFormat, out: W) -> Result<u64, String> {
+  let columns = export_columns(sources);
+  let mut writer = new_table_writer(format, &columns, out)?;
+  let schema = KeySchema::events();
+  let mut rows: u64 = 0;
+  for source in sources {
+    let event_name = qualified_name(&source.contract, &source.event.name);
+    let event_prefix = schema.encode(&[KeyValue::Name(event_name.clone())])?;
+    let start_key = schema.encode(&[KeyValue::Name(event_name.clone()), KeyValue::U64(from_block.unwrap_or(0))])?;
+    let mut iter = db.storage.iter("events")?;
+    iter.seek(&start_key);
+    while iter.valid() && iter.key().starts_with(&event_prefix) {
+      let (_, block_number, log_index) = decode_event_key(iter.key())?;
+      if to_block.map_or(false, |to_block| block_number > to_block) {
+        break;
+      }
+      let params = decode_params(iter.value())?;
+      let transaction = get_transaction(db, &source.contract, &event_name, block_number, log_index)?;
+      writer.write_row(&event_row(&columns, source, block_number, log_index, transaction, &params)?)?;
+      rows = rows + 1;
+      iter.next();
+    }
+  }
+  writer.finish()?;
+  Ok(rows)
+}
@@ -0,0 +1,358 @@
+/// # Module Indexes
+/// Secondary indexes over decoded event arguments. Index entries live in "indexes" column family,
+/// are ordered by block and log like events, and point at the primary key in "events" column family.
+/// Address activity entries live in "addresses" column family and cover every address typed argument
+/// of every event, so all activity of an address is a single prefix scan.
+/// Timeline entries live in "timeline" column family and order all events of a contract as they happened on chain.
+use std::collections::{HashMap, HashSet};
+use tiny_keccak::keccak256;
+use ethabi::{Event, ParamType};
+use config::structs::contractconfig::{AggregationConfig, IndexConfig};
+use db::aggregations::AggregateChanges;
+use db::key_codec::decode_hex;
+use db::key_schema::{decode_event_key, KeySchema, KeyValue};
+use db::storage::{Storage, StorageBatch};
+use db::value_codec::{decode_params, encode_params, get_value_format};
+use config::structs::storageconfig::ValueFormat;
+
+/// Param under which transaction sender is stored when contract has `indexTxSender` enabled
+pub const TX_SENDER_PARAM: &'static str = "txSender";
+
+/// Contract name of the timeline across all contracts
+pub const GLOBAL_TIMELINE: &'static str = "*";
+
+/// Everything that has to be indexed for events of a single actor
+#[derive(Debug, Clone, Default)]
+pub struct EventIndexes {
+  pub contract: String,             // Contract name stored in address and timeline entries
+  pub indexes: Vec<IndexConfig>,    // Secondary indexes
+  pub address_args: Vec<String>,    // Names of address typed arguments
+  pub index_tx_sender: bool,        // Transaction sender is added to address entries
+  pub global_timeline: bool,        // Events are added also to the timeline across all contracts
+  pub aggregations: Vec<AggregationConfig>  // Aggregations updated with every written and removed event
+}
+
+/// Transaction of a log, stored in timeline entries
+#[derive(Debug, Clone, Default)]
+pub struct LogTransaction {
+  pub hash: String,
+  pub index: String
+}
+
+impl EventIndexes {
+  /// Indexes of a contract event. Address typed arguments are taken from the event abi.
+  pub fn for_event(contract: String, event: &Event, indexes: Vec<IndexConfig>, index_tx_sender: bool) -> EventIndexes {
+    let address_args = event.inputs.iter()
+      .filter(|input| input.kind == ParamType::Address)
+      .map(|input| input.name.clone())
+      .collect();
+    EventIndexes{contract: contract, indexes: indexes, address_args: address_args, index_tx_sender: index_tx_sender, global_timeline: false,
+      aggregations: Vec::new()}
+  }
+
+  pub fn with_global_timeline(mut self, global_timeline: bool) -> EventIndexes {
+    self.global_timeline = global_timeline;
+    self
+  }
+
+  pub fn with_aggregations(mut self, aggregations: Vec<AggregationConfig>) -> EventIndexes {
+    self.aggregations = aggregations;
+    self
+  }
+
+  fn is_empty(&self) -> bool {
+    self.indexes.is_empty() && self.address_args.is_empty() && !self.index_tx_sender
+  }
+}
+
+/// Normalizes indexed value, so "0xABC" from query matches "abc" decoded from log
+pub fn normalize_index_value(value: &str) -> String {
+  value.trim_start_matches("0x").to_lowercase()
+}
+
+/// Hash of indexed values. Values of multi argument index are hashed together in config order.
+pub fn hash_index_values(values: &[String]) -> Vec<u8> {
+  let normalized: Vec<String> = values.iter().map(|value| normalize_index_value(value)).collect();
+  keccak256(normalized.join(",").as_bytes()).to_vec()
+}
+
+/// Prefix of all entries of an index for given values, optionally starting from block and log
+pub fn index_query(event_name: &str, index_name: &str, values: &[String], from: Option<(u64, u32)>) -> Result<Vec<u8>, String> {
+  let mut key_values = vec![
+    KeyValue::Name(event_name.to_string()),
+    KeyValue::Name(index_name.to_string()),
+    KeyValue::Bytes(hash_index_values(values))
+  ];
+  if let Some((block_number, log_index)) = from {
+    key_values.push(KeyValue::U64(block_number));
+    key_values.push(KeyValue::U32(log_index));
+  }
+  KeySchema::indexes().encode(&key_values)
+}
+
+/// Parses address from decoded param or query. Accepts hex with or without "0x".
+pub fn parse_address(address: &str) -> Result<Vec<u8>, String> {
+  let address_bytes = decode_hex(address)?;
+  if address_bytes.len() != 20 {
+    return Err(format!("Address {} should have 20 bytes", address));
+  }
+  Ok(address_bytes)
+}
+
+/// Prefix of all entries of an address, optionally starting from block and log
+pub fn address_query(address: &str, from: Option<(u64, u32)>) -> Result<Vec<u8>, String> {
+  let mut key_values = vec![KeyValue::Bytes(parse_address(address)?)];
+  if let Some((block_number, log_index)) = from {
+    key_values.push(KeyValue::U64(block_number));
+    key_values.push(KeyValue::U32(log_index));
+  }
+  KeySchema::addresses().encode(&key_values)
+}
+
+/// Returns keys of address entries of a single event. Address used by many arguments has a single entry.
+pub fn address_keys(event_key: &[u8], params: &HashMap<String, String>, event_indexes: &EventIndexes) -> Result<Vec<Vec<u8>>, String> {
+  let (event_name, block_number, log_index) = decode_event_key(event_key)?;
+  let mut address_params: Vec<&String> = event_indexes.address_args.iter().collect();
+  let tx_sender_param = String::from(TX_SENDER_PARAM);
+  if event_indexes.index_tx_sender {
+    address_params.push(&tx_sender_param);
+  }
+  let mut addresses: HashSet<Vec<u8>> = HashSet::new();
+  for param in address_params {
+    // Sender could not be fetched, so it is not in params
+    if let Some(value) = params.get(param) {
+      match parse_address(value) {
+        Ok(address) => { addresses.insert(address); },
+        Err(err) => warn!("Param {} of event {} is not indexed. Error: {}", param, event_name, err)
+      };
+    }
+  }
+  addresses.into_iter()
+    .map(|address| KeySchema::addresses().encode(&[
+      KeyValue::Bytes(address),
+      KeyValue::U64(block_number),
+      KeyValue::U32(log_index),
+      KeyValue::Name(event_name.clone())
+    ]))
+    .collect()
+}
+
+/// Prefix of timeline entries of a contract (or GLOBAL_TIMELINE), optionally starting from block and log
+pub fn timeline_query(contract: &str, from: Option<(u64, u32)>) -> Result<Vec<u8>, String> {
+  let mut key_values = vec![KeyValue::Name(contract.to_string())];
+  if let Some((block_number, log_index)) = from {
+    key_values.push(KeyValue::U64(block_number));
+    key_values.push(KeyValue::U32(log_index));
+  }
+  KeySchema::timeline().encode(&key_values)
+}
+
+/// Returns keys of timeline entries of a single event. Log index is unique in a block and grows
+/// with transaction index, so block and log give the chain order of events.
+pub fn timeline_keys(event_key: &[u8], event_indexes: &EventIndexes) -> Result<Vec<Vec<u8>>, String> {
+  let (event_name, block_number, log_index) = decode_event_key(event_key)?;
+  let mut contracts = vec![event_indexes.contract.clone()];
+  if event_indexes.global_timeline {
+    contracts.push(GLOBAL_TIMELINE.to_string());
+  }
+  contracts.into_iter()
+    .map(|contract| KeySchema::timeline().encode(&[
+      KeyValue::Name(contract),
+      KeyValue::U64(block_number),
+      KeyValue::U32(log_index),
+      KeyValue::Name(event_name.clone())
+    ]))
+    .collect()
+}
+
+/// Returns keys of all index entries of a single event
+pub fn index_keys(event_key: &[u8], params: &HashMap<String, String>, indexes: &[IndexConfig]) -> Result<Vec<Vec<u8>>, String> {
+  let (event_name, block_number, log_index) = decode_event_key(event_key)?;
+  indexes.iter()
+    .map(|index| {
+      let values = index.args.iter()
+        .map(|arg| params.get(arg).cloned().ok_or(format!("There is no argument {} for index {}", arg, index.name)))
+        .collect::<Result<Vec<String>, String>>()?;
+      KeySchema::indexes().encode(&[
+        KeyValue::Name(event_name.clone()),
+        KeyValue::Name(index.name.clone()),
+        KeyValue::Bytes(hash_index_values(&values)),
+        KeyValue::U64(block_number),
+        KeyValue::U32(log_index)
+      ])
+    })
+    .collect()
+}
+
+/// StorageBatch which keeps secondary indexes consistent with events.
+/// Index entries of overwritten or removed events are found by reading their stored params,
+/// so reorg rollback removes exactly the entries which were written before.
+/// Events are encoded in the value format of the database.
+/// Aggregations are updated the same way: old params are subtracted and new ones are added.
+pub struct EventBatch<'a> {
+  db: &'a dyn Storage,
+  batch: StorageBatch,
+  value_format: ValueFormat,
+  aggregates: AggregateChanges,
+  aggregation_failures: HashMap<String, u64>,                 // Skipped aggregations per event name
+  pending: HashMap<Vec<u8>, Option<HashMap<String, String>>>  // Events already changed in this batch
+}
+
+impl<'a> EventBatch<'a> {
+  pub fn new(db: &'a dyn Storage) -> EventBatch<'a> {
+    let value_format = get_value_format(db).unwrap_or_else(|err| {
+      warn!("Cannot read value format, events are written as json. Error: {}", err);
+      ValueFormat::Json
+    });
+    EventBatch{db: db, batch: StorageBatch::new(), value_format: value_format, aggregates: AggregateChanges::new(),
+      aggregation_failures: HashMap::new(), pending: HashMap::new()}
+  }
+
+  /// Underlying batch for writes which are not events
+  pub fn batch(&mut self) -> &mut StorageBatch {
+    &mut self.batch
+  }
+
+  pub fn into_batch(self) -> StorageBatch {
+    self.batch
+  }
+
+  /// Aggregations skipped for events of the batch, per event name
+  pub fn aggregation_failures(&self) -> &HashMap<String, u64> {
+    &self.aggregation_failures
+  }
+
+  fn apply_aggregations(&mut self, event_key: &[u8], params: &HashMap<String, String>, event_indexes: &EventIndexes, added: bool) -> Result<(), String> {
+    let failures = self.aggregates.apply(self.db, &mut self.batch, event_key, params, &event_indexes.aggregations, added)?;
+    if failures > 0 {
+      let (event_name, _, _) = decode_event_key(event_key)?;
+      *self.aggregation_failures.entry(event_name).or_insert(0) += failures;
+    }
+    Ok(())
+  }
+
+  /// Params of the event as they will be after pending operations
+  fn get_current_params(&self, event_key: &[u8]) -> Result<Option<HashMap<String, String>>, String> {
+    if let Some(params) = self.pending.get(event_key) {
+      return Ok(params.clone());
+    }
+    match self.db.get("events", event_key)? {
+      Some(value) => decode_params(&value).map(Some),
+      None => Ok(None)
+    }
+  }
+
+  /// Whether the event is stored or put earlier in this batch
+  pub fn is_stored(&self, event_key: &[u8]) -> Result<bool, String> {
+    self.get_current_params(event_key).map(|params| params.is_some())
+  }
+
+  fn delete_index_entries(&mut self, event_key: &[u8], event_indexes: &EventIndexes) -> Result<(), String> {
+    if event_indexes.is_empty() {
+      return Ok(());
+    }
+    if let Some(old_params) = self.get_current_params(event_key)? {
+      self.delete_index_entries_of(event_key, &old_params, event_indexes)?;
+    }
+    Ok(())
+  }
+
+  fn delete_index_entries_of(&mut self, event_key: &[u8], params: &HashMap<String, String>, event_indexes: &EventIndexes) -> Result<(), String> {
+    for index_key in index_keys(event_key, params, &event_indexes.indexes)? {
+      self.batch.delete("indexes", &index_key);
+    }
+    for address_key in address_keys(event_key, params, event_indexes)? {
+      self.batch.delete("addresses", &address_key);
+    }
+    Ok(())
+  }
+
+  /// Subtracts the event as it is now from aggregations
+  fn remove_from_aggregations(&mut self, event_key: &[u8], event_indexes: &EventIndexes) -> Result<(), String> {
+    if event_indexes.aggregations.is_empty() {
+      return Ok(());
+    }
+    if let Some(old_params) = self.get_current_params(event_key)? {
+      self.apply_aggregations(event_key, &old_params, event_indexes, false)?;
+    }
+    Ok(())
+  }
+
+  pub fn put_event(&mut self, event_key: &[u8], params: HashMap<String, String>, transaction: &LogTransaction, event_indexes: &EventIndexes) -> Result<(), String> {
+    self.remove_from_aggregations(event_key, event_indexes)?;
+    self.delete_index_entries(event_key, event_indexes)?;
+    let value = encode_params(&params, self.value_format)?;
+    self.batch.put("events", event_key, &value);
+    for index_key in index_keys(event_key, &params, &event_indexes.indexes)? {
+      self.batch.put("indexes", &index_key, event_key);
+    }
+    let address_entry = json!({"contract": event_indexes.contract}).to_string();
+    for address_key in address_keys(event_key, &params, event_indexes)? {
+      self.batch.put("addresses", &address_key, address_entry.as_bytes());
+    }
+    let timeline_entry = json!({
+      "contract": event_indexes.contract,
+      "transactionIndex": transaction.index,
+      "transactionHash": transaction.hash
+    }).to_string();
+    for timeline_key in timeline_keys(event_key, event_indexes)? {
+      self.batch.put("timeline", &timeline_key, timeline_entry.as_bytes());
+    }
+    self.apply_aggregations(event_key, &params, event_indexes, true)?;
+    self.pending.insert(event_key.to_vec(), Some(params));
+    Ok(())
+  }
+
+  /// Removes index, address and timeline entries of the event, leaving the event itself and its aggregations.
+  /// Used when events are removed by range.
+  pub fn delete_event_entries(&mut self, event_key: &[u8], event_indexes: &EventIndexes) -> Result<(), String> {
+    self.delete_index_entries(event_key, event_indexes)?;
+    for timeline_key in timeline_keys(event_key, event_indexes)? {
+      self.batch.delete("timeline", &timeline_key);
+    }
+    Ok(())
+  }
+
+  /// Same as `delete_event_entries` for an event read by the caller, so its params are not read again.
+  /// Used when many events are removed by scanning "events" column family.
+  pub fn delete_stored_event_entries(&mut self, event_key: &[u8], stored_value: &[u8], event_indexes: &EventIndexes) -> Result<(), String> {
+    if !event_indexes.is_empty() {
+      let params = decode_params(stored_value)?;
+      self.delete_index_entries_of(event_key, &params, event_indexes)?;
+    }
+    for timeline_key in timeline_keys(event_key, event_indexes)? {
+      self.batch.delete("timeline", &timeline_key);
+    }
+    Ok(())
+  }
+
+  /// Removes events from `from_key` up to `to_key` (exclusive) with their index, address and timeline entries,
+  /// leaving their aggregations. Events are removed with a single range delete. Used by retention.
+  pub fn delete_event_range(&mut self, from_key: &[u8], to_key: &[u8], event_indexes: &EventIndexes) -> Result<usize, String> {
+    let db = self.db;
+    let mut deleted: usize = 0;
+    let mut iter = db.iter("events")?;
+    iter.seek(from_key);
+    while iter.valid() && iter.key() < to_key {
+      self.delete_stored_event_entries(iter.key(), iter.value(), event_indexes)?;
+      self.pending.insert(iter.key().to_vec(), None);
+      deleted = deleted + 1;
+      iter.next();
+    }
+    self.batch.delete_range("events", from_key, to_key);
+    Ok(deleted)
+  }
+
+  /// Adds delta to a counter in "aggregations" column family
+  pub fn add_to_counter(&mut self, key: &[u8], delta: i64) -> Result<(), String> {
+    self.aggregates.add_to_counter(self.db, &mut self.batch, key, delta)
+  }
+
+  pub fn delete_event(&mut self, event_key: &[u8], event_indexes: &EventIndexes) -> Result<(), String> {
+    self.remove_from_aggregations(event_key, event_indexes)?;
+    self.delete_event_entries(event_key, event_indexes)?;
+    self.batch.delete("events", event_key);
+    self.pending.insert(event_key.to_vec(), None);
+    Ok(())
+  }
+}
@@ -0,0 +1,65 @@
+/// # Module Key codec
+/// Order-preserving binary key components. Names are padded with zero bytes to a fixed width
+/// and numbers are stored big-endian, so lexicographic RocksDB order is the chain order.
+/// Keys are composed from components by `db::key_schema`.
+use std::str::from_utf8;
+
+pub const NAME_SIZE: usize = 30;        // Event name. Equal to default prefix extractor length
+
+pub fn encode_name(name: &str) -> Result<Vec<u8>, String> {
+  let name_bytes = name.as_bytes();
+  if name_bytes.len() > NAME_SIZE {
+    return Err(format!("Name {} is longer than {} bytes", name, NAME_SIZE));
+  }
+  if name_bytes.contains(&0u8) {
+    return Err(format!("Name {:?} can not contain zero bytes", name));
+  }
+  let mut encoded_name = name_bytes.to_vec();
+  encoded_name.resize(NAME_SIZE, 0u8);
+  Ok(encoded_name)
+}
+
+pub fn decode_name(name_bytes: &[u8]) -> Result<String, String> {
+  let trimmed_len = name_bytes.iter().position(|byte| *byte == 0u8).unwrap_or(name_bytes.len());
+  from_utf8(&name_bytes[..trimmed_len])
+    .map(|name| name.to_string())
+    .map_err(|err| format!("Can not decode name {:?}. Error: {}", name_bytes, err))
+}
+
+pub fn encode_u64(value: u64) -> Vec<u8> {
+  (0..8).map(|byte_index| (value >> (56 - byte_index * 8)) as u8).collect()
+}
+
+pub fn decode_u64(bytes: &[u8]) -> Result<u64, String> {
+  if bytes.len() != 8 {
+    return Err(format!("Can not decode u64 from {} bytes", bytes.len()));
+  }
+  Ok(bytes.iter().fold(0u64, |acc, byte| (acc << 8) | (*byte as u64)))
+}
+
+pub fn encode_u32(value: u32) -> Vec<u8> {
+  (0..4).map(|byte_index| (value >> (24 - byte_index * 8)) as u8).collect()
+}
+
+pub fn decode_u32(bytes: &[u8]) -> Result<u32, String> {
+  if bytes.len() != 4 {
+    return Err(format!("Can not decode u32 from {} bytes", bytes.len()));
+  }
+  Ok(bytes.iter().fold(0u32, |acc, byte| (acc << 8) | (*byte as u32)))
+}
+
+/// Encodes bytes as "0x" prefixed hex
+pub fn encode_hex(bytes: &[u8]) -> String {
+  format!("0x{}", bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
+
+/// Decodes hex with or without "0x" prefix
+pub fn decode_hex(raw_value: &str) -> Result<Vec<u8>, String> {
+  let hex = raw_value.trim_start_matches("0x");
+  if hex.len() % 2 != 0 {
+    return Err(format!("Hex value {} has odd length", raw_value));
+  }
+  (0..hex.len()).step_by(2)
+    .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).map_err(|err| format!("Can not parse {}. Error: {}", raw_value, err)))
+    .collect()
+}
@@ -0,0 +1,284 @@
+/// # Module Key schema
+/// Describes ordered components of keys in every column family.
+/// Writer, reader and web layer build and parse keys only through these schemas.
+use std::collections::HashMap;
+use serde_json::{Map, Value};
+use db::key_codec::{encode_name, decode_name, encode_u64, decode_u64, encode_u32, decode_u32, encode_hex, decode_hex, NAME_SIZE};
+use eth::eth_json_rpc::parse_hex_u64;
+
+/// Single component of a key. Holds name of the field used in api responses.
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyComponent {
+  Name(&'static str),           // Zero padded utf8 name
+  U64(&'static str),            // Big-endian u64
+  U32(&'static str),            // Big-endian u32
+  Bytes(&'static str, usize)    // Raw bytes of fixed width
+}
+
+impl KeyComponent {
+  pub fn field(&self) -> &'static str {
+    match *self {
+      KeyComponent::Name(field) | KeyComponent::U64(field) | KeyComponent::U32(field) | KeyComponent::Bytes(field, _) => field
+    }
+  }
+
+  pub fn width(&self) -> usize {
+    match *self {
+      KeyComponent::Name(_) => NAME_SIZE,
+      KeyComponent::U64(_) => 8,
+      KeyComponent::U32(_) => 4,
+      KeyComponent::Bytes(_, width) => width
+    }
+  }
+}
+
+/// Value of a single key component
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyValue {
+  Name(String),
+  U64(u64),
+  U32(u32),
+  Bytes(Vec<u8>)
+}
+
+#[derive(Clone, Debug)]
+pub struct KeySchema {
+  pub components: Vec<KeyComponent>
+}
+
+impl KeySchema {
+  pub fn new(components: Vec<KeyComponent>) -> KeySchema {
+    KeySchema{components: components}
+  }
+
+  /// "events": event name, block number, log index
+  pub fn events() -> KeySchema {
+    KeySchema::new(vec![KeyComponent::Name("event"), KeyComponent::U64("block"), KeyComponent::U32("log")])
+  }
+
+  /// "aggregations" cursors: event name
+  pub fn cursors() -> KeySchema {
+    KeySchema::new(vec![KeyComponent::Name("event")])
+  }
+
+  /// "aggregations" groups: event name, aggregation name, hash of group values
+  pub fn aggregates() -> KeySchema {
+    KeySchema::new(vec![KeyComponent::Name("event"), KeyComponent::Name("aggregation"), KeyComponent::Bytes("group", 32)])
+  }
+
+  /// "aggregations" values of min, max and distinct: group key followed by the value
+  pub fn aggregate_values() -> KeySchema {
+    KeySchema::new(vec![KeyComponent::Name("event"), KeyComponent::Name("aggregation"), KeyComponent::Bytes("group", 32),
+      KeyComponent::Bytes("value", 32)])
+  }
+
+  /// "filters": event name
+  pub fn filters() -> KeySchema {
+    KeySchema::new(vec![KeyComponent::Name("event")])
+  }
+
+  /// "indexes": event name, index name, hash of indexed values, block number, log index
+  pub fn indexes() -> KeySchema {
+    KeySchema::new(vec![KeyComponent::Name("event"), KeyComponent::Name("index"), KeyComponent::Bytes("value", 32),
+      KeyComponent::U64("block"), KeyComponent::U32("log")])
+  }
+
+  /// "addresses": address, block number, log index, event name
+  pub fn addresses() -> KeySchema {
+    KeySchema::new(vec![KeyComponent::Bytes("address", 20), KeyComponent::U64("block"), KeyComponent::U32("log"),
+      KeyComponent::Name("event")])
+  }
+
+  /// "timeline": contract name, block number, log index, event name
+  pub fn timeline() -> KeySchema {
+    KeySchema::new(vec![KeyComponent::Name("contract"), KeyComponent::U64("block"), KeyComponent::U32("log"),
+      KeyComponent::Name("event")])
+  }
+
+  pub fn for_column_family(column_family: &str) -> Result<KeySchema, String> {
+    match column_family {
+      "timeline" => Ok(KeySchema::timeline()),
+      "addresses" => Ok(KeySchema::addresses()),
+      "events" => Ok(KeySchema::events()),
+      "indexes" => Ok(KeySchema::indexes()),
+      "aggregations" => Ok(KeySchema::cursors()),
+      "filters" => Ok(KeySchema::filters()),
+      _ => Err(format!("There is no key schema for column family: {}", column_family))
+    }
+  }
+
+  pub fn key_size(&self) -> usize {
+    self.prefix_size(self.components.len())
+  }
+
+  /// Size in bytes of first `components_count` components
+  pub fn prefix_size(&self, components_count: usize) -> usize {
+    self.components.iter().take(components_count).map(|component| component.width()).sum()
+  }
+
+  /// Encodes values into a key. Fewer values than components gives a key prefix.
+  pub fn encode(&self, values: &[KeyValue]) -> Result<Vec<u8>, String> {
+    if values.len() > self.components.len() {
+      return Err(format!("Key has {} components, but {} values were given", self.components.len(), values.len()));
+    }
+    let mut key: Vec<u8> = Vec::with_capacity(self.key_size());
+    for (component, value) in self.components.iter().zip(values.iter()) {
+      match (component, value) {
+        (&KeyComponent::Name(_), &KeyValue::Name(ref name)) => key.extend(encode_name(name)?),
+        (&KeyComponent::U64(_), &KeyValue::U64(number)) => key.extend(encode_u64(number)),
+        (&KeyComponent::U32(_), &KeyValue::U32(number)) => key.extend(encode_u32(number)),
+        (&KeyComponent::Bytes(_, width), &KeyValue::Bytes(ref bytes)) => {
+          if bytes.len() != width {
+            return Err(format!("Component {} should have {} bytes, but has {}", component.field(), width, bytes.len()));
+          }
+          key.extend(bytes.iter().cloned());
+        },
+        _ => return Err(format!("Value {:?} does not match key component {:?}", value, component))
+      };
+    }
+    Ok(key)
+  }
+
+  pub fn decode(&self, key: &[u8]) -> Result<Vec<KeyValue>, String> {
+    if key.len() != self.key_size() {
+      return Err(format!("Key should have {} bytes, but has {}", self.key_size(), key.len()));
+    }
+    let mut offset: usize = 0;
+    let mut values: Vec<KeyValue> = Vec::with_capacity(self.components.len());
+    for component in self.components.iter() {
+      let component_bytes = &key[offset..offset + component.width()];
+      offset = offset + component.width();
+      values.push(match *component {
+        KeyComponent::Name(_) => KeyValue::Name(decode_name(component_bytes)?),
+        KeyComponent::U64(_) => KeyValue::U64(decode_u64(component_bytes)?),
+        KeyComponent::U32(_) => KeyValue::U32(decode_u32(component_bytes)?),
+        KeyComponent::Bytes(_, _) => KeyValue::Bytes(component_bytes.to_vec())
+      });
+    }
+    Ok(values)
+  }
+
+  /// Decodes key into values by component field
+  pub fn decode_to_map(&self, key: &[u8]) -> Result<HashMap<&'static str, KeyValue>, String> {
+    let values = self.decode(key)?;
+    Ok(self.components.iter().map(|component| component.field()).zip(values.into_iter()).collect())
+  }
+
+  /// Decodes key into json object with component fields, ex. {"event": "Transfer", "block": "0x1a", "log": "0x3"}
+  pub fn decode_to_json(&self, key: &[u8]) -> Result<Value, String> {
+    let values = self.decode(key)?;
+    let mut fields = Map::new();
+    for (component, value) in self.components.iter().zip(values.into_iter()) {
+      let json_value = match value {
+        KeyValue::Name(name) => json!(name),
+        KeyValue::U64(number) => json!(format!("0x{:x}", number)),
+        KeyValue::U32(number) => json!(format!("0x{:x}", number)),
+        KeyValue::Bytes(bytes) => json!(encode_hex(&bytes))
+      };
+      fields.insert(component.field().to_string(), json_value);
+    }
+    Ok(Value::Object(fields))
+  }
+
+  /// Parses raw values (ex. web query params) into key values. Numbers can be hex ("0x1a") or decimal.
+  pub fn parse_values(&self, raw_values: Vec<String>) -> Result<Vec<KeyValue>, String> {
+    if raw_values.len() > self.components.len() {
+      return Err(format!("Key has {} components, but {} values were given", self.components.len(), raw_values.len()));
+    }
+    self.components.iter().zip(raw_values.into_iter())
+      .map(|(component, raw_value)| {
+        match *component {
+          KeyComponent::Name(_) => Ok(KeyValue::Name(raw_value)),
+          KeyComponent::U64(_) => parse_number(&raw_value).map(KeyValue::U64),
+          KeyComponent::U32(_) => parse_number(&raw_value)
+            .and_then(|number| if number > u32::max_value() as u64 { Err(format!("Value {} is too big", raw_value)) } else { Ok(number as u32) })
+            .map(KeyValue::U32),
+          KeyComponent::Bytes(_, _) => decode_hex(&raw_value).map(KeyValue::Bytes)
+        }
+      })
+      .collect()
+  }
+
+  /// Returns [start, end) range of keys starting with encoded values. End is None when there is no upper bound.
+  pub fn prefix_range(&self, values: &[KeyValue]) -> Result<(Vec<u8>, Option<Vec<u8>>), String> {
+    let prefix = self.encode(values)?;
+    let upper_bound = prefix_upper_bound(&prefix);
+    Ok((prefix, upper_bound))
+  }
+}
+
+/// Smallest key greater than every key starting with the prefix
+pub fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+  let mut upper_bound = prefix.to_vec();
+  while let Some(last_byte) = upper_bound.pop() {
+    if last_byte < 0xff {
+      upper_bound.push(last_byte + 1);
+      return Some(upper_bound);
+    }
+  }
+  None
+}
+
+fn parse_number(raw_value: &str) -> Result<u64, String> {
+  if raw_value.starts_with("0x") {
+    parse_hex_u64(raw_value)
+  } else {
+    raw_value.parse::<u64>().map_err(|err| format!("Can not parse {} into number. Error: {}", raw_value, err))
+  }
+}
+
+/// Separates contract and event in qualified names. Solidity identifiers never contain it.
+pub const CONTRACT_SEPARATOR: char = '.';
+
+/// Name of the event within its contract, ex. "Token.Transfer". Keys of every column family use it,
+/// so events with the same name in different contracts never share keys.
+pub fn qualified_name(contract: &str, event_name: &str) -> String {
+  format!("{}{}{}", contract, CONTRACT_SEPARATOR, event_name)
+}
+
+/// Splits qualified name into contract and event. Names stored before contract namespaces have no contract.
+pub fn split_qualified_name(name: &str) -> (Option<&str>, &str) {
+  match name.find(CONTRACT_SEPARATOR) {
+    Some(position) => (Some(&name[..position]), &name[position + 1..]),
+    None => (None, name)
+  }
+}
+
+/// Key of the event in "events" column family
+pub fn event_key(event_name: &str, block_number: u64, log_index: u32) -> Result<Vec<u8>, String> {
+  KeySchema::events().encode(&[KeyValue::Name(event_name.to_string()), KeyValue::U64(block_number), KeyValue::U32(log_index)])
+}
+
+/// Same as event_key, but block number and log index are ethereum quantities (ex. "0x1a")
+pub fn event_key_from_hex(event_name: &str, block_number: &str, log_index: &str) -> Result<Vec<u8>, String> {
+  let schema = KeySchema::events();
+  let values = schema.parse_values(vec![event_name.to_string(), block_number.to_string(), log_index.to_string()])?;
+  schema.encode(&values)
+}
+
+pub fn decode_event_key(key: &[u8]) -> Result<(String, u64, u32), String> {
+  match KeySchema::events().decode(key)?.as_slice() {
+    [KeyValue::Name(name), KeyValue::U64(block_number), KeyValue::U32(log_index)] => Ok((name.clone(), *block_number, *log_index)),
+    _ => Err(format!("Can not decode event key {:?}", key))
+  }
+}
+
+/// Key of the event name in "aggregations" (cursor) and "filters" column families
+pub fn name_key(event_name: &str) -> Result<Vec<u8>, String> {
+  KeySchema::cursors().encode(&[KeyValue::Name(event_name.to_string())])
+}
+
+/// Human readable form of a key used in web responses, ex. "Transfer-0x1a-0x3"
+pub fn format_key(key: &[u8]) -> String {
+  match decode_event_key(key) {
+    Ok((name, block_number, log_index)) => format!("{}-0x{:x}-0x{:x}", name, block_number, log_index),
+    Err(_) => {
+      if key.len() == NAME_SIZE {
+        if let Ok(name) = decode_name(key) {
+          return name;
+        }
+      }
+      String::from_utf8_lossy(key).to_string()
+    }
+  }
+}
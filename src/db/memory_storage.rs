@@ -0,0 +1,176 @@
+/// # Module MemoryStorage
+/// Storage keeping column families as sorted maps in memory. Maps are shared with iterators and snapshots
+/// and copied on the next write, so readers always see data as it was when they were created.
+/// A write made while any reader is open copies the whole column family, which is fine for tests
+/// and small data sets only. Services run on `RocksStorage`.
+use std::collections::{BTreeMap, HashMap};
+use std::collections::Bound::{Excluded, Included, Unbounded};
+use std::sync::{Arc, RwLock};
+use db::storage::{BatchOp, Storage, StorageBatch, StorageIterator, StorageSnapshot};
+
+type ColumnFamily = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Test storage. Writes copy column families shared with open iterators or snapshots.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+  column_families: RwLock<HashMap<String, Arc<ColumnFamily>>>
+}
+
+pub struct MemoryIterator {
+  column_family: Arc<ColumnFamily>,
+  current: Option<(Vec<u8>, Vec<u8>)>
+}
+
+impl MemoryIterator {
+  fn set_current(&mut self, entry: Option<(&Vec<u8>, &Vec<u8>)>) {
+    self.current = entry.map(|(key, value)| (key.clone(), value.clone()));
+  }
+}
+
+impl StorageIterator for MemoryIterator {
+  fn seek(&mut self, key: &[u8]) {
+    let entry = self.column_family.clone();
+    self.set_current(entry.range::<[u8], _>((Included(key), Unbounded)).next());
+  }
+
+  fn seek_for_prev(&mut self, key: &[u8]) {
+    let entry = self.column_family.clone();
+    self.set_current(entry.range::<[u8], _>((Unbounded, Included(key))).next_back());
+  }
+
+  fn seek_to_first(&mut self) {
+    let entry = self.column_family.clone();
+    self.set_current(entry.iter().next());
+  }
+
+  fn seek_to_last(&mut self) {
+    let entry = self.column_family.clone();
+    self.set_current(entry.iter().next_back());
+  }
+
+  fn valid(&self) -> bool {
+    self.current.is_some()
+  }
+
+  fn next(&mut self) {
+    if let Some((key, _)) = self.current.take() {
+      let entry = self.column_family.clone();
+      self.set_current(entry.range::<[u8], _>((Excluded(key.as_slice()), Unbounded)).next());
+    }
+  }
+
+  fn prev(&mut self) {
+    if let Some((key, _)) = self.current.take() {
+      let entry = self.column_family.clone();
+      self.set_current(entry.range::<[u8], _>((Unbounded, Excluded(key.as_slice()))).next_back());
+    }
+  }
+
+  fn key(&self) -> &[u8] {
+    self.current.as_ref().map(|(key, _)| key.as_slice()).expect("Iterator is not valid")
+  }
+
+  fn value(&self) -> &[u8] {
+    self.current.as_ref().map(|(_, value)| value.as_slice()).expect("Iterator is not valid")
+  }
+}
+
+pub struct MemorySnapshot {
+  column_families: HashMap<String, Arc<ColumnFamily>>
+}
+
+impl MemorySnapshot {
+  fn column_family(&self, column_family: &str) -> Result<&Arc<ColumnFamily>, String> {
+    self.column_families.get(column_family).ok_or(format!("There is no column family as: {}", column_family))
+  }
+}
+
+impl StorageSnapshot for MemorySnapshot {
+  fn get(&self, column_family: &str, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    Ok(self.column_family(column_family)?.get(key).cloned())
+  }
+
+  fn iter<'a>(&'a self, column_family: &str) -> Result<Box<dyn StorageIterator + 'a>, String> {
+    Ok(Box::new(MemoryIterator{column_family: self.column_family(column_family)?.clone(), current: None}))
+  }
+}
+
+impl MemoryStorage {
+  pub fn new(column_families: Vec<&str>) -> MemoryStorage {
+    let column_families = column_families.into_iter()
+      .map(|cf| (cf.to_string(), Arc::new(ColumnFamily::new())))
+      .collect();
+    MemoryStorage{column_families: RwLock::new(column_families)}
+  }
+
+  fn read_column_family(&self, column_family: &str) -> Result<Arc<ColumnFamily>, String> {
+    let column_families = self.column_families.read().map_err(|err| err.to_string())?;
+    column_families.get(column_family).cloned().ok_or(format!("There is no column family as: {}", column_family))
+  }
+}
+
+impl Storage for MemoryStorage {
+  fn column_families(&self) -> Vec<String> {
+    let mut names: Vec<String> = self.column_families.read()
+      .map(|column_families| column_families.keys().cloned().collect())
+      .unwrap_or_default();
+    names.sort();
+    names
+  }
+
+  fn get(&self, column_family: &str, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    Ok(self.read_column_family(column_family)?.get(key).cloned())
+  }
+
+  fn write(&self, batch: StorageBatch, _sync: bool) -> Result<(), String> {
+    let mut column_families = self.column_families.write().map_err(|err| err.to_string())?;
+    // Batch is validated first, so it is applied entirely or not at all
+    for op in batch.ops.iter() {
+      let column_family = match op {
+        BatchOp::Put(cf, _, _) | BatchOp::Delete(cf, _) | BatchOp::DeleteRange(cf, _, _) => cf
+      };
+      if !column_families.contains_key(column_family) {
+        return Err(format!("There is no column family as: {}", column_family));
+      }
+    }
+    for op in batch.ops.into_iter() {
+      let column_family = match op {
+        BatchOp::Put(ref cf, _, _) | BatchOp::Delete(ref cf, _) | BatchOp::DeleteRange(ref cf, _, _) => cf.clone()
+      };
+      // Copies the column family only when an open iterator or snapshot still shares it
+      let entries = match column_families.get_mut(&column_family) {
+        Some(entries) => Arc::make_mut(entries),
+        None => continue
+      };
+      match op {
+        BatchOp::Put(_, key, value) => { entries.insert(key, value); },
+        BatchOp::Delete(_, key) => { entries.remove(&key); },
+        BatchOp::DeleteRange(_, from, to) => {
+          let keys: Vec<Vec<u8>> = entries.range::<[u8], _>((Included(from.as_slice()), Excluded(to.as_slice())))
+            .map(|(key, _)| key.clone())
+            .collect();
+          for key in keys {
+            entries.remove(&key);
+          }
+        }
+      };
+    }
+    Ok(())
+  }
+
+  fn iter<'a>(&'a self, column_family: &str) -> Result<Box<dyn StorageIterator + 'a>, String> {
+    Ok(Box::new(MemoryIterator{column_family: self.read_column_family(column_family)?, current: None}))
+  }
+
+  fn snapshot<'a>(&'a self) -> Box<dyn StorageSnapshot + 'a> {
+    let column_families = self.column_families.read().map(|column_families| column_families.clone()).unwrap_or_default();
+    Box::new(MemorySnapshot{column_families: column_families})
+  }
+
+  fn get_property_int(&self, column_family: &str, name: &str) -> Option<u64> {
+    match name {
+      "rocksdb.estimate-num-keys" => self.read_column_family(column_family).ok().map(|entries| entries.len() as u64),
+      _ => None
+    }
+  }
+}
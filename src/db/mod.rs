@@ -1,2 +1,17 @@
 pub mod cachedb;
-pub mod reader;
\ No newline at end of file
+pub mod storage;
+#[cfg(feature = "rocksdb")]
+pub mod rocks_storage;
+pub mod memory_storage;
+pub mod reader;
+pub mod key_codec;
+pub mod key_schema;
+pub mod value_codec;
+pub mod indexes;
+pub mod aggregations;
+pub mod schema_version;
+pub mod compaction;
+pub mod backup;
+pub mod retention;
+pub mod stats;
+pub mod export;
\ No newline at end of file
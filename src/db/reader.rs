@@ -1,10 +1,13 @@
-use rocksdb::{SeekKey, DBVector};
 use db::cachedb::CacheDB;
+use db::key_schema::{format_key, name_key, event_key, split_qualified_name, KeySchema, KeyValue};
+use db::value_codec::decode_value;
 use std::str::from_utf8;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Event {
   pub key: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub fields: Option<serde_json::Value>,
   pub params: serde_json::Value
 }
 
@@ -14,82 +17,140 @@ pub struct Filter {
   pub id: String
 }
 
-pub fn get_prefix_from_query(query: String, prefix_size: usize) -> Vec<u8> {
-  let prefix: Vec<u8> = if query.clone().len() >= prefix_size {
-    Vec::from(query.clone()[..prefix_size].to_string())
-  } else {
-    let mut vec_query = Vec::from(query.clone());
-    let extend_query_size = query.clone().len() - prefix_size;
-    vec_query.extend(&vec![b'-'; extend_query_size]);
-    vec_query
-  };
-  return prefix;
+/// Builds event from raw key and value. Key is parsed into structured fields by the schema of column family,
+/// value is decoded from its stored format into json.
+fn to_event(column_family: &str, key: &[u8], value: &[u8]) -> Result<Event, String> {
+  let fields = KeySchema::for_column_family(column_family).ok()
+    .and_then(|schema| schema.decode_to_json(key).ok());
+  let params = decode_value(value)?;
+  Ok(Event{key: format_key(key), fields: fields, params: params})
+}
+
+/// Returns first `prefix_size` bytes of the query. Shorter query is a prefix itself.
+pub fn get_prefix_from_query(query: &[u8], prefix_size: usize) -> &[u8] {
+  &query[..prefix_size.min(query.len())]
 }
 
-pub fn get_events_by_prefix(db: CacheDB, column_family: String, query: String, size: i32, is_forward: bool, prefix_size: usize) -> Result<Vec<Event>, String> {
-  let prefix = get_prefix_from_query(query.clone(), prefix_size);
-  let query_vec_u8 = query.clone().into_bytes();
-  match db.db.cf_handle(&column_family) {
-    Some(cf_handle) => {
+pub fn get_events_by_prefix(db: CacheDB, column_family: String, query: Vec<u8>, size: i32, is_forward: bool, prefix_size: usize) -> Result<Vec<Event>, String> {
+  let prefix = get_prefix_from_query(&query, prefix_size).to_vec();
+  match db.storage.iter(&column_family) {
+    Ok(mut iter) => {
       let mut return_msg: Vec<Event> = Vec::new();
-      let mut iter = db.db.iter_cf(cf_handle);
       // You can iterate db in both directions
       if is_forward == true {
-        iter.seek(SeekKey::Key(query.clone().as_bytes()));
+        iter.seek(&query);
       } else {
-        iter.seek_for_prev(SeekKey::Key(query.clone().as_bytes()));
+        iter.seek_for_prev(&query);
+      };
+      let mut key_count = 0;
+      while iter.valid() && size > key_count && iter.key().starts_with(&prefix) && iter.key() >= query.as_slice() {
+        key_count = key_count + 1;
+        return_msg.push(to_event(&column_family, iter.key(), iter.value())?);
+        iter.next();
       };
-      let valid = iter.valid();
-      if valid == true {
-        let mut key_count = 0;
-        let iter_key_string: String = from_utf8(iter.key()).expect(&format!("Cannot change {:?} into string", iter.key())).to_string();
-        let mut key_prefix: Vec<u8> = get_prefix_from_query(iter_key_string, prefix_size);
-
-        let mut key: Vec<u8> = Vec::from(iter.key());
-        while iter.valid() && size > key_count && key_prefix == prefix && key >= query_vec_u8 {
-          key_count = key_count + 1;
-          match iter.kv() {
-            Some((k, v)) => {
-              let _k = from_utf8(&k).expect(&format!("Cannot change {:?} into string", k)).to_string();
-              let _v = from_utf8(&v).expect(&format!("Cannot change {:?} into string", v));
-              let event = Event{key: _k, params: serde_json::from_str(_v).map_err(|_| {String::from("Cannot decode database content to json")})?};
-              return_msg.push(event);
-            },
-            None => {()}
-          };
-          iter.next();
-          if (iter.valid() == true) {
-            key_prefix = get_prefix_from_query(from_utf8(iter.key()).expect(&format!("Cannot change {:?} into string", iter.key())).to_string(), prefix_size);
-            key = Vec::from(iter.key());
-          }
-        };
-      }
       return Ok(return_msg);
     },
-    None => {
+    Err(_) => {
       return Err(String::from("Cannot parse your query."));
     }
   };
 }
 
-pub fn get_by_key(db: CacheDB, column_family: String, query: String) -> Result<String, String> {
-  match db.db.cf_handle(&column_family) {
-    Some(cf_handle) => {
-      let get_result: Option<DBVector> = db.db.get_cf(cf_handle, query.as_bytes())
-        .expect(&format!("Cannot get such key: {} from cf: {}", query, column_family));
-      match get_result {
-        Some(db_v) => {
-          let db_vector_str = db_v.to_utf8().expect(&format!("Cannot convert vector value to utf8"));
-          let db_vector_as_vec_string = db_vector_str.to_string();
-          Ok(db_vector_as_vec_string)
-        },
-        None => {
-          Err(format!("There is no such key as {}", query))
+/// Returns events from secondary index. Query is an index key or its prefix, see `db::indexes::index_query`.
+/// Entries are followed while they have the same event, index and value.
+pub fn get_events_by_index(db: CacheDB, query: Vec<u8>, size: i32) -> Result<Vec<Event>, String> {
+  let prefix = get_prefix_from_query(&query, KeySchema::indexes().prefix_size(3)).to_vec();
+  let mut return_msg: Vec<Event> = Vec::new();
+  let mut iter = db.storage.iter("indexes")?;
+  iter.seek(&query);
+  let mut key_count = 0;
+  while iter.valid() && size > key_count && iter.key().starts_with(&prefix) {
+    key_count = key_count + 1;
+    // Index entry points at the primary key of the event
+    let event_key = iter.value().to_vec();
+    if db.storage.get("events", &event_key)?.is_none() {
+      // Entry left by an interrupted removal is skipped, so it does not break the whole page
+      warn!("Index entry {} points at missing event {}", format_key(iter.key()), format_key(&event_key));
+    } else {
+      return_msg.push(get_event_by_key_bytes(db.clone(), String::from("events"), event_key)?);
+    }
+    iter.next();
+  };
+  Ok(return_msg)
+}
+
+/// Follows pointer entries ("addresses", "timeline") to events. Pointer key has "block", "log" and "event"
+/// components and its json value is merged into event fields. Entries not accepted by `is_matching(contract, event)`
+/// are skipped. Second value is the key from which next page starts.
+fn get_events_by_pointers<F>(db: CacheDB, column_family: &str, query: Vec<u8>, prefix_size: usize, size: i32, is_matching: F) -> Result<(Vec<Event>, Option<Vec<u8>>), String>
+  where F: Fn(&str, &str) -> bool {
+  let schema = KeySchema::for_column_family(column_family)?;
+  let prefix = get_prefix_from_query(&query, prefix_size).to_vec();
+  let mut return_msg: Vec<Event> = Vec::new();
+  let mut iter = db.storage.iter(column_family)?;
+  iter.seek(&query);
+  while iter.valid() && iter.key().starts_with(&prefix) {
+    if return_msg.len() as i32 >= size {
+      return Ok((return_msg, Some(iter.key().to_vec())));
+    }
+    let entry: serde_json::Value = from_utf8(iter.value()).ok()
+      .and_then(|value| serde_json::from_str(value).ok())
+      .ok_or(format!("Cannot decode {} entry {}", column_family, format_key(iter.key())))?;
+    let pointer = schema.decode_to_map(iter.key())?;
+    match (pointer.get("block"), pointer.get("log"), pointer.get("event")) {
+      (Some(KeyValue::U64(block_number)), Some(KeyValue::U32(log_index)), Some(KeyValue::Name(event_name))) => {
+        if is_matching(entry["contract"].as_str().unwrap_or(""), event_name.as_str()) {
+          let mut event = get_event_by_key_bytes(db.clone(), String::from("events"), event_key(event_name, *block_number, *log_index)?)?;
+          if let (Some(serde_json::Value::Object(fields)), serde_json::Value::Object(entry_fields)) = (event.fields.as_mut(), entry) {
+            fields.extend(entry_fields);
+          }
+          return_msg.push(event);
         }
-      }
-    },
-    None => {Err(String::from("Cannot parse your query."))}
+      },
+      _ => return Err(format!("Cannot decode {} entry {}", column_family, format_key(iter.key())))
+    };
+    iter.next();
+  };
+  Ok((return_msg, None))
+}
+
+/// Event filter of pointer queries takes event name with or without its contract, ex. "Transfer" or "Token.Transfer"
+fn is_event_matching(event_name: &Option<String>, entry_event_name: &str) -> bool {
+  event_name.as_ref().map_or(true, |e| e == entry_event_name || e == split_qualified_name(entry_event_name).1)
+}
+
+/// Returns events touching an address, ordered by block and log across all contracts and events.
+/// Query is an address key or its prefix, see `db::indexes::address_query`.
+pub fn get_events_by_address(db: CacheDB, query: Vec<u8>, size: i32, contract: Option<String>, event_name: Option<String>) -> Result<(Vec<Event>, Option<Vec<u8>>), String> {
+  get_events_by_pointers(db, "addresses", query, KeySchema::addresses().prefix_size(1), size, |entry_contract, entry_event_name| {
+    contract.as_ref().map_or(true, |c| c == entry_contract) && is_event_matching(&event_name, entry_event_name)
+  })
+}
+
+/// Returns events of a contract in chain order. Query is a timeline key or its prefix, see `db::indexes::timeline_query`.
+pub fn get_events_by_timeline(db: CacheDB, query: Vec<u8>, size: i32, event_name: Option<String>) -> Result<(Vec<Event>, Option<Vec<u8>>), String> {
+  get_events_by_pointers(db, "timeline", query, KeySchema::timeline().prefix_size(1), size, |_, entry_event_name| {
+    is_event_matching(&event_name, entry_event_name)
+  })
+}
+
+pub fn get_by_key(db: CacheDB, column_family: String, query: String) -> Result<String, String> {
+  get_by_key_bytes(db, column_family, query.into_bytes())
+}
+
+/// Returns raw stored value
+pub fn get_value_bytes(db: CacheDB, column_family: &str, query: &[u8]) -> Result<Vec<u8>, String> {
+  if !db.storage.has_column_family(column_family) {
+    return Err(String::from("Cannot parse your query."));
   }
+  let get_result: Option<Vec<u8>> = db.storage.get(column_family, query)
+    .map_err(|err| format!("Cannot get such key: {} from cf: {}. Error: {}", format_key(query), column_family, err))?;
+  get_result.ok_or(format!("There is no such key as {}", format_key(query)))
+}
+
+pub fn get_by_key_bytes(db: CacheDB, column_family: String, query: Vec<u8>) -> Result<String, String> {
+  let db_v = get_value_bytes(db, &column_family, &query)?;
+  String::from_utf8(db_v).map_err(|_| format!("Cannot convert vector value to utf8"))
 }
 
 pub fn get_by_key_with_default(db: CacheDB, column_family: String, query: String, default: String) -> String {
@@ -104,21 +165,18 @@ pub fn get_by_key_with_default(db: CacheDB, column_family: String, query: String
 }
 
 pub fn get_event_by_key(db: CacheDB,  column_family: String, query: String) -> Result<Event, String> {
-  let key_from_db : Result<String, String> = get_by_key(db, column_family, query.clone());
-  match key_from_db {
-    Ok(param) => {
-      let event = Event{key: query, params: serde_json::from_str(&param).map_err(|_| {String::from("Cannot decode database content to json")})?};
-      Ok(event)
-      },
-    Err(error) => {
-      Err(error)
-    }
-  }
+  let value = get_value_bytes(db, &column_family, query.as_bytes())?;
+  Ok(Event{key: query, fields: None, params: decode_value(&value)?})
+}
+
+pub fn get_event_by_key_bytes(db: CacheDB, column_family: String, query: Vec<u8>) -> Result<Event, String> {
+  let value = get_value_bytes(db, &column_family, &query)?;
+  to_event(&column_family, &query, &value)
 }
 
 pub fn get_filter_by_name(db: CacheDB, name: String) -> Result<Filter, String> {
   let column_family = String::from("filters");
-  let key_from_db : Result<String, String> = get_by_key(db, column_family, name.clone());
+  let key_from_db : Result<String, String> = get_by_key_bytes(db, column_family, name_key(&name)?);
   match key_from_db {
     Ok(param) => {
       let filter = Filter{name: name, id: param};
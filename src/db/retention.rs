@@ -0,0 +1,207 @@
+/// # Module Retention
+/// Pruning of events older than retention policy of their event. Events are removed by range from "events"
+/// column family together with their index, address and timeline entries. Boundary of pruned data is kept
+/// in "stats" column family under "pruned:<event>" key, so queries can tell pruned data from missing one.
+/// Pruning only reads the database. Its batches are written by `write`, ex. through RocksWriteActor, so they are
+/// serialized with ingestion and reindex.
+use chrono::prelude::*;
+use actors::rocks_write_actor::{MsgContentType, MsgType, WriteOp};
+use config::structs::contractconfig::RetentionConfig;
+use db::cachedb::CacheDB;
+use db::indexes::EventIndexes;
+use db::key_schema::{decode_event_key, event_key, name_key, prefix_upper_bound, KeySchema, KeyValue};
+
+pub const PRUNED_PREFIX: &'static str = "pruned:";
+const PRUNING_BATCH_SIZE: usize = 1000;
+
+/// Retention policy of a single event with everything needed to remove its entries
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+  pub event_name: String,                 // Qualified name, ex. "Token.Transfer"
+  pub event_indexes: EventIndexes,
+  pub retention: RetentionConfig
+}
+
+fn parse_position(position: &serde_json::Value) -> Option<(u64, u32)> {
+  let values = KeySchema::events().parse_values(vec![
+    String::new(),
+    position["block"].as_str()?.to_string(),
+    position["log"].as_str()?.to_string()
+  ]).ok()?;
+  match values.as_slice() {
+    [_, KeyValue::U64(block_number), KeyValue::U32(log_index)] => Some((*block_number, *log_index)),
+    _ => None
+  }
+}
+
+/// Returns (block, log) before which events of the event were pruned
+pub fn get_pruned_before(db: &CacheDB, event_name: &str) -> Result<Option<(u64, u32)>, String> {
+  let key = format!("{}{}", PRUNED_PREFIX, event_name);
+  match db.storage.get("stats", key.as_bytes())? {
+    Some(value) => {
+      let boundary: serde_json::Value = serde_json::from_slice(&value)
+        .map_err(|_| format!("Cannot decode pruned boundary of {}", event_name))?;
+      Ok(parse_position(&boundary))
+    },
+    None => Ok(None)
+  }
+}
+
+/// Message for queries starting before pruned boundary of the event
+pub fn get_pruned_msg(db: &CacheDB, event_name: &str, from: (u64, u32)) -> Option<String> {
+  match get_pruned_before(db, event_name) {
+    Ok(Some((block_number, log_index))) if from < (block_number, log_index) => {
+      Some(format!("Data of {} before block 0x{:x} log 0x{:x} has been pruned", event_name, block_number, log_index))
+    },
+    _ => None
+  }
+}
+
+/// Returns block of the event cursor
+fn get_cursor_block(db: &CacheDB, event_name: &str) -> Result<Option<u64>, String> {
+  match db.storage.get("aggregations", &name_key(event_name)?)? {
+    Some(value) => {
+      let cursor: serde_json::Value = serde_json::from_slice(&value)
+        .map_err(|_| format!("Cannot decode cursor of {}", event_name))?;
+      let block_number = cursor["last_block"].as_str()
+        .and_then(|last_block| last_block.split('-').next())
+        .map(|block| block.to_string());
+      match block_number {
+        Some(block) => KeySchema::events().parse_values(vec![String::new(), block, String::from("0")])
+          .map(|values| match values.get(1) { Some(KeyValue::U64(block_number)) => Some(*block_number), _ => None }),
+        None => Ok(None)
+      }
+    },
+    None => Ok(None)
+  }
+}
+
+/// Returns position of the `count`-th newest event. Older events are over the count limit.
+fn get_count_boundary(db: &CacheDB, event_name: &str, count: u64) -> Result<Option<(u64, u32)>, String> {
+  let prefix = name_key(event_name)?;
+  let mut iter = db.storage.iter("events")?;
+  match prefix_upper_bound(&prefix) {
+    Some(upper_bound) => {
+      iter.seek_for_prev(&upper_bound);
+      if iter.valid() && iter.key() == upper_bound.as_slice() {
+        iter.prev();
+      }
+    },
+    None => iter.seek_to_last()
+  };
+  let mut seen: u64 = 0;
+  while iter.valid() && iter.key().starts_with(&prefix) {
+    seen = seen + 1;
+    if seen == count {
+      let (_, block_number, log_index) = decode_event_key(iter.key())?;
+      return Ok(Some((block_number, log_index)));
+    }
+    iter.prev();
+  }
+  Ok(None)
+}
+
+/// Returns the first block mined at or after `timestamp`, searching blocks from `first_block` up to `last_block`
+pub fn find_first_block_after<F>(timestamp: u64, first_block: u64, last_block: u64, get_timestamp: F) -> Result<Option<u64>, String>
+  where F: Fn(u64) -> Result<u64, String> {
+  if get_timestamp(last_block)? < timestamp {
+    return Ok(None);
+  }
+  let (mut low, mut high) = (first_block.min(last_block), last_block);
+  while low < high {
+    let middle = low + (high - low) / 2;
+    if get_timestamp(middle)? < timestamp {
+      low = middle + 1;
+    } else {
+      high = middle;
+    }
+  }
+  Ok(Some(low))
+}
+
+/// Returns (block, log) before which events break the retention policy. None when nothing is to be pruned.
+/// Block timestamps are needed only for `maxAgeSecs`. Boundary never moves back, so blocks before the
+/// pruned one are not searched and every run reads only timestamps of blocks indexed since the last one.
+pub fn get_prune_boundary<F>(db: &CacheDB, policy: &RetentionPolicy, get_timestamp: Option<F>) -> Result<Option<(u64, u32)>, String>
+  where F: Fn(u64) -> Result<u64, String> {
+  let mut boundaries: Vec<(u64, u32)> = Vec::new();
+  let cursor_block = get_cursor_block(db, &policy.event_name)?;
+  let pruned_block = get_pruned_before(db, &policy.event_name)?.map(|(block_number, _)| block_number).unwrap_or(0);
+  if let (Some(max_blocks), Some(cursor_block)) = (policy.retention.max_blocks, cursor_block) {
+    if cursor_block + 1 > max_blocks {
+      boundaries.push((cursor_block + 1 - max_blocks, 0));
+    }
+  }
+  if let (Some(max_age_secs), Some(cursor_block)) = (policy.retention.max_age_secs, cursor_block) {
+    match get_timestamp {
+      Some(get_block_timestamp) => {
+        let oldest_timestamp = (Utc::now().timestamp() as u64).saturating_sub(max_age_secs);
+        match find_first_block_after(oldest_timestamp, pruned_block, cursor_block, get_block_timestamp)? {
+          Some(block_number) => boundaries.push((block_number, 0)),
+          // Every indexed block is too old
+          None => boundaries.push((cursor_block + 1, 0))
+        };
+      },
+      None => warn!("Retention by age of {} needs block timestamps from the node", policy.event_name)
+    };
+  }
+  if let Some(max_count) = policy.retention.max_count {
+    if let Some(boundary) = get_count_boundary(db, &policy.event_name, max_count.max(1))? {
+      boundaries.push(boundary);
+    }
+  }
+  Ok(boundaries.into_iter().max())
+}
+
+/// Removes events before (block, log) with all their entries. Returns number of removed events.
+/// Range is split into batches of PRUNING_BATCH_SIZE events. Every batch removes its events with a single range delete,
+/// while their index, address and timeline entries are found by the writer from stored events.
+pub fn prune_events<W>(db: &CacheDB, policy: &RetentionPolicy, before: (u64, u32), write: W) -> Result<usize, String>
+  where W: Fn(Vec<WriteOp>) -> Result<String, String> {
+  let (before_block, before_log) = before;
+  if let Some(pruned_before) = get_pruned_before(db, &policy.event_name)? {
+    if before <= pruned_before {
+      return Ok(0);
+    }
+  }
+  let from_key = event_key(&policy.event_name, 0, 0)?;
+  let to_key = event_key(&policy.event_name, before_block, before_log)?;
+  // Boundary is written first, so data is never missing without being reported as pruned
+  let boundary = json!({
+    "block": format!("0x{:x}", before_block),
+    "log": format!("0x{:x}", before_log),
+    "ts": Utc::now().timestamp_millis()
+  });
+  let boundary_key = format!("{}{}", PRUNED_PREFIX, policy.event_name).into_bytes();
+  write(vec![WriteOp::Put(MsgType::Stat, boundary_key, MsgContentType::PureString(boundary.to_string()))])?;
+  let mut pruned: usize = 0;
+  let mut batch_ends: Vec<Vec<u8>> = Vec::new();
+  {
+    let mut iter = db.storage.iter("events")?;
+    iter.seek(&from_key);
+    while iter.valid() && iter.key() < to_key.as_slice() {
+      if pruned > 0 && pruned % PRUNING_BATCH_SIZE == 0 {
+        batch_ends.push(iter.key().to_vec());
+      }
+      pruned = pruned + 1;
+      iter.next();
+    }
+  }
+  batch_ends.push(to_key);
+  let mut batch_start = from_key;
+  for batch_end in batch_ends {
+    write(vec![WriteOp::PruneEvents(batch_start, batch_end.clone(), policy.event_indexes.clone())])?;
+    batch_start = batch_end;
+  }
+  info!("{} events of {} before block 0x{:x} log 0x{:x} have been pruned", pruned, policy.event_name, before_block, before_log);
+  Ok(pruned)
+}
+
+/// Applies retention policy of an event. Returns number of removed events.
+pub fn apply_retention<F, W>(db: &CacheDB, policy: &RetentionPolicy, get_timestamp: Option<F>, write: W) -> Result<usize, String>
+  where F: Fn(u64) -> Result<u64, String>, W: Fn(Vec<WriteOp>) -> Result<String, String> {
+  match get_prune_boundary(db, policy, get_timestamp)? {
+    Some(before) => prune_events(db, policy, before, write),
+    None => Ok(0)
+  }
+}
@@ -0,0 +1,209 @@
+/// # Module RocksStorage
+/// Storage backed by RocksDB. Column families are created by `CacheDB::create_with_config`.
+/// `ReadOnlyRocksStorage` reads a database written by another process (`CacheDB::open_read_only`).
+use rocksdb::{DB, DBIterator, SeekKey, Snapshot, ReadOptions, Writable, WriteBatch, WriteOptions};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use db::storage::{BatchOp, Storage, StorageBatch, StorageIterator, StorageSnapshot};
+
+pub struct RocksStorage {
+  pub db: Arc<DB>
+}
+
+pub struct RocksIterator<D: Deref<Target = DB>> {
+  iter: DBIterator<D>
+}
+
+impl<D: Deref<Target = DB>> StorageIterator for RocksIterator<D> {
+  fn seek(&mut self, key: &[u8]) {
+    self.iter.seek(SeekKey::Key(key));
+  }
+
+  fn seek_for_prev(&mut self, key: &[u8]) {
+    self.iter.seek_for_prev(SeekKey::Key(key));
+  }
+
+  fn seek_to_first(&mut self) {
+    self.iter.seek(SeekKey::Start);
+  }
+
+  fn seek_to_last(&mut self) {
+    self.iter.seek(SeekKey::End);
+  }
+
+  fn valid(&self) -> bool {
+    self.iter.valid()
+  }
+
+  fn next(&mut self) {
+    self.iter.next();
+  }
+
+  fn prev(&mut self) {
+    self.iter.prev();
+  }
+
+  fn key(&self) -> &[u8] {
+    self.iter.key()
+  }
+
+  fn value(&self) -> &[u8] {
+    self.iter.value()
+  }
+}
+
+pub struct RocksSnapshot<'a> {
+  db: &'a DB,
+  snapshot: Snapshot<&'a DB>
+}
+
+impl<'a> StorageSnapshot for RocksSnapshot<'a> {
+  fn get(&self, column_family: &str, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    let cf_handle = self.db.cf_handle(column_family).ok_or(format!("There is no column family as: {}", column_family))?;
+    Ok(self.snapshot.get_cf(cf_handle, key)?.map(|value| value.to_vec()))
+  }
+
+  fn iter<'b>(&'b self, column_family: &str) -> Result<Box<dyn StorageIterator + 'b>, String> {
+    let cf_handle = self.db.cf_handle(column_family).ok_or(format!("There is no column family as: {}", column_family))?;
+    Ok(Box::new(RocksIterator{iter: self.snapshot.iter_opt_cf(cf_handle, ReadOptions::new())}))
+  }
+}
+
+impl RocksStorage {
+  pub fn new(db: DB) -> RocksStorage {
+    RocksStorage{db: Arc::new(db)}
+  }
+}
+
+impl Storage for RocksStorage {
+  fn column_families(&self) -> Vec<String> {
+    self.db.cf_names().into_iter().filter(|cf| *cf != "default").map(|cf| cf.to_string()).collect()
+  }
+
+  fn get(&self, column_family: &str, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    let cf_handle = self.db.cf_handle(column_family).ok_or(format!("There is no column family as: {}", column_family))?;
+    Ok(self.db.get_cf(cf_handle, key)?.map(|value| value.to_vec()))
+  }
+
+  fn write(&self, batch: StorageBatch, sync: bool) -> Result<(), String> {
+    let write_batch = WriteBatch::new();
+    for op in batch.ops.iter() {
+      let column_family = match op {
+        BatchOp::Put(cf, _, _) | BatchOp::Delete(cf, _) | BatchOp::DeleteRange(cf, _, _) => cf
+      };
+      let cf_handle = self.db.cf_handle(column_family).ok_or(format!("There is no column family as: {}", column_family))?;
+      match op {
+        BatchOp::Put(_, key, value) => write_batch.put_cf(cf_handle, key, value)?,
+        BatchOp::Delete(_, key) => write_batch.delete_cf(cf_handle, key)?,
+        BatchOp::DeleteRange(_, from, to) => write_batch.delete_range_cf(cf_handle, from, to)?
+      };
+    }
+    let mut write_options = WriteOptions::new();
+    write_options.set_sync(sync);
+    self.db.write_opt(&write_batch, &write_options)
+  }
+
+  fn iter<'a>(&'a self, column_family: &str) -> Result<Box<dyn StorageIterator + 'a>, String> {
+    let cf_handle = self.db.cf_handle(column_family).ok_or(format!("There is no column family as: {}", column_family))?;
+    Ok(Box::new(RocksIterator{iter: self.db.iter_cf(cf_handle)}))
+  }
+
+  fn snapshot<'a>(&'a self) -> Box<dyn StorageSnapshot + 'a> {
+    Box::new(RocksSnapshot{db: &self.db, snapshot: self.db.snapshot()})
+  }
+
+  fn get_property_int(&self, column_family: &str, name: &str) -> Option<u64> {
+    let cf_handle = self.db.cf_handle(column_family)?;
+    self.db.get_property_int_cf(cf_handle, name)
+  }
+
+  fn compact_range(&self, column_family: &str, from: Option<&[u8]>, to: Option<&[u8]>) -> Result<(), String> {
+    let cf_handle = self.db.cf_handle(column_family).ok_or(format!("There is no column family as: {}", column_family))?;
+    self.db.compact_range_cf(cf_handle, from, to);
+    Ok(())
+  }
+
+  fn create_checkpoint(&self, path: &Path) -> Result<(), String> {
+    let mut checkpointer = self.db.new_checkpointer()?;
+    checkpointer.create_at(path, None, 0)
+  }
+}
+
+/// Read-only storage of a database written by another process. RocksDB shows a read-only instance
+/// as it was when it was opened, so `catch_up` opens it again. Readers keep the instance they have started with.
+pub struct ReadOnlyRocksStorage {
+  open: Box<dyn Fn() -> Result<DB, String> + Send + Sync>,
+  db: RwLock<Arc<DB>>
+}
+
+/// Snapshot of a read-only instance. The instance never changes, so it is the snapshot itself.
+pub struct ReadOnlySnapshot {
+  db: Arc<DB>
+}
+
+impl StorageSnapshot for ReadOnlySnapshot {
+  fn get(&self, column_family: &str, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    let cf_handle = self.db.cf_handle(column_family).ok_or(format!("There is no column family as: {}", column_family))?;
+    Ok(self.db.get_cf(cf_handle, key)?.map(|value| value.to_vec()))
+  }
+
+  fn iter<'b>(&'b self, column_family: &str) -> Result<Box<dyn StorageIterator + 'b>, String> {
+    let cf_handle = self.db.cf_handle(column_family).ok_or(format!("There is no column family as: {}", column_family))?;
+    Ok(Box::new(RocksIterator{iter: self.db.iter_cf(cf_handle)}))
+  }
+}
+
+impl ReadOnlyRocksStorage {
+  pub fn new<F>(open: F) -> Result<ReadOnlyRocksStorage, String> where F: Fn() -> Result<DB, String> + Send + Sync + 'static {
+    let db = open()?;
+    Ok(ReadOnlyRocksStorage{open: Box::new(open), db: RwLock::new(Arc::new(db))})
+  }
+
+  fn current(&self) -> Arc<DB> {
+    self.db.read().unwrap().clone()
+  }
+}
+
+impl Storage for ReadOnlyRocksStorage {
+  fn column_families(&self) -> Vec<String> {
+    self.current().cf_names().into_iter().filter(|cf| *cf != "default").map(|cf| cf.to_string()).collect()
+  }
+
+  fn get(&self, column_family: &str, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    let db = self.current();
+    let cf_handle = db.cf_handle(column_family).ok_or(format!("There is no column family as: {}", column_family))?;
+    Ok(db.get_cf(cf_handle, key)?.map(|value| value.to_vec()))
+  }
+
+  fn write(&self, _batch: StorageBatch, _sync: bool) -> Result<(), String> {
+    Err(String::from("Database is opened read-only"))
+  }
+
+  fn iter<'a>(&'a self, column_family: &str) -> Result<Box<dyn StorageIterator + 'a>, String> {
+    // Iterator owns the instance, so catch up never closes it under a reader
+    let db = self.current();
+    let cf_handle = db.cf_handle(column_family).ok_or(format!("There is no column family as: {}", column_family))?;
+    Ok(Box::new(RocksIterator{iter: DBIterator::new_cf(db.clone(), cf_handle, ReadOptions::new())}))
+  }
+
+  fn snapshot<'a>(&'a self) -> Box<dyn StorageSnapshot + 'a> {
+    Box::new(ReadOnlySnapshot{db: self.current()})
+  }
+
+  fn get_property_int(&self, column_family: &str, name: &str) -> Option<u64> {
+    let db = self.current();
+    let cf_handle = db.cf_handle(column_family)?;
+    db.get_property_int_cf(cf_handle, name)
+  }
+
+  fn compact_range(&self, _column_family: &str, _from: Option<&[u8]>, _to: Option<&[u8]>) -> Result<(), String> {
+    Err(String::from("Database is opened read-only"))
+  }
+
+  fn catch_up(&self) -> Result<(), String> {
+    let db = (self.open)()?;
+    *self.db.write().unwrap() = Arc::new(db);
+    Ok(())
+  }
+}
@@ -0,0 +1,75 @@
+/// # Module Schema version
+/// Layout version of the database and metadata of the build which opened it, stored in "settings" column family.
+///
+/// Versions:
+///  1 - keys stored as padded hex strings
+///  2 - binary order-preserving keys (`db::key_schema`)
+///  3 - event names in keys qualified by contract ("Token.Transfer")
+use chrono::prelude::*;
+use db::cachedb::CacheDB;
+use db::storage::StorageBatch;
+
+pub const SCHEMA_VERSION: u32 = 3;
+pub const SCHEMA_VERSION_KEY: &'static str = "schema_version";
+pub const BUILD_KEY: &'static str = "build";
+
+/// Returns stored schema version. None when database has no version yet.
+pub fn get_schema_version(db: &CacheDB) -> Result<Option<u32>, String> {
+  match db.storage.get("settings", SCHEMA_VERSION_KEY.as_bytes())? {
+    Some(value) => {
+      let version_str = String::from_utf8(value).map_err(|_| String::from("Cannot convert schema version to utf8"))?;
+      version_str.parse::<u32>()
+        .map(Some)
+        .map_err(|err| format!("Cannot parse schema version {}. Error: {}", version_str, err))
+    },
+    None => Ok(None)
+  }
+}
+
+/// Returns stored schema version. Empty database without version gets the current one,
+/// database with events and without version was created before versioning (version 1).
+pub fn detect_schema_version(db: &CacheDB) -> Result<u32, String> {
+  if let Some(version) = get_schema_version(db)? {
+    return Ok(version);
+  }
+  let mut iter = db.storage.iter("events")?;
+  iter.seek_to_first();
+  if iter.valid() {
+    Ok(1)
+  } else {
+    Ok(SCHEMA_VERSION)
+  }
+}
+
+/// Adds schema version and build metadata into the batch
+pub fn put_schema_version(batch: &mut StorageBatch, version: u32) {
+  let build = json!({
+    "version": env!("CARGO_PKG_VERSION"),
+    "schemaVersion": version,
+    "ts": Utc::now().timestamp_millis()
+  });
+  batch.put("settings", SCHEMA_VERSION_KEY.as_bytes(), version.to_string().as_bytes());
+  batch.put("settings", BUILD_KEY.as_bytes(), build.to_string().as_bytes());
+}
+
+/// Checks if database can be used by this build without storing anything. Used by read-only instances.
+pub fn verify_schema_version(db: &CacheDB) -> Result<u32, String> {
+  let version = detect_schema_version(db)?;
+  if version > SCHEMA_VERSION {
+    return Err(format!("Database schema version {} is newer than version {} supported by this build. Please upgrade cacherz", version, SCHEMA_VERSION));
+  }
+  if version < SCHEMA_VERSION {
+    return Err(format!("Database schema version {} is older than version {}. Please stop the service and run `cacherz migrate`", version, SCHEMA_VERSION));
+  }
+  Ok(version)
+}
+
+/// Checks if database can be used by this build. Newer databases are refused,
+/// older ones have to be migrated with `cacherz migrate` first. Stores version and build metadata.
+pub fn check_schema_version(db: &CacheDB) -> Result<u32, String> {
+  let version = verify_schema_version(db)?;
+  let mut batch = StorageBatch::new();
+  put_schema_version(&mut batch, version);
+  db.storage.write(batch, false)?;
+  Ok(version)
+}
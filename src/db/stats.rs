@@ -0,0 +1,207 @@
+/// # Module Stats
+/// Ingestion statistics of events. Eth actors collect counters of every poll into `StatsDelta`, which is
+/// written together with events, so counters never drift from stored data. Statistics of an event live in
+/// "stats" column family under "ingest:<contract>.<event>" key. Contract statistics are summed from its events.
+use chrono::prelude::*;
+use std::collections::BTreeMap;
+use serde_json;
+use db::cachedb::CacheDB;
+use db::storage::{Storage, StorageBatch};
+use db::key_schema::qualified_name;
+
+pub const INGEST_STATS_PREFIX: &'static str = "ingest:";
+const DAILY_HISTORY_DAYS: usize = 30;
+
+/// Counters of a single poll of an event
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatsDelta {
+  pub events: u64,
+  pub removed_events: u64,                // Logs removed by chain reorganization
+  pub blocks: Vec<u64>,                   // Blocks of written events
+  pub decode_failures: u64,
+  pub transform_failures: u64,            // Logs written unchanged because their transform script failed
+  pub aggregation_failures: u64,          // Aggregations skipped for written or removed events
+  pub rpc_calls: u64,
+  pub rpc_errors: u64,
+  pub latency_ms: Option<u64>             // Time of fetching and decoding logs of the poll
+}
+
+impl StatsDelta {
+  pub fn add_rpc_call<T>(&mut self, result: &Result<T, String>) {
+    self.rpc_calls = self.rpc_calls + 1;
+    if result.is_err() {
+      self.rpc_errors = self.rpc_errors + 1;
+    }
+  }
+
+  /// Adds counters of another delta, ex. of the same event written twice in a batch
+  pub fn add(&mut self, other: &StatsDelta) {
+    self.events = self.events + other.events;
+    self.removed_events = self.removed_events + other.removed_events;
+    self.blocks.extend(other.blocks.iter().cloned());
+    self.decode_failures = self.decode_failures + other.decode_failures;
+    self.transform_failures = self.transform_failures + other.transform_failures;
+    self.aggregation_failures = self.aggregation_failures + other.aggregation_failures;
+    self.rpc_calls = self.rpc_calls + other.rpc_calls;
+    self.rpc_errors = self.rpc_errors + other.rpc_errors;
+    if other.latency_ms.is_some() {
+      self.latency_ms = other.latency_ms;
+    }
+  }
+
+  /// Poll without events, failures or rpc errors. Such delta does not need to be written on its own.
+  pub fn is_idle(&self) -> bool {
+    self.events == 0 && self.removed_events == 0 && self.blocks.is_empty() && self.decode_failures == 0
+      && self.transform_failures == 0 && self.aggregation_failures == 0 && self.rpc_errors == 0
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestStats {
+  pub contract: String,
+  pub event: String,
+  pub total_events: u64,
+  pub removed_events: u64,
+  pub blocks_with_events: u64,
+  pub decode_failures: u64,
+  #[serde(default)]
+  pub transform_failures: u64,
+  #[serde(default)]
+  pub aggregation_failures: u64,
+  pub rpc_calls: u64,
+  pub rpc_errors: u64,
+  pub last_block: Option<u64>,
+  pub last_ingest_latency_ms: Option<u64>,
+  pub last_ingest_at: Option<i64>,                  // Timestamp in milliseconds
+  #[serde(default)]
+  pub daily_events: BTreeMap<String, u64>           // Events written per day (UTC, "YYYY-MM-DD") of the last 30 days
+}
+
+impl IngestStats {
+  pub fn apply(&mut self, delta: &StatsDelta, now: DateTime<Utc>) {
+    self.total_events = self.total_events + delta.events;
+    self.removed_events = self.removed_events + delta.removed_events;
+    self.decode_failures = self.decode_failures + delta.decode_failures;
+    self.transform_failures = self.transform_failures + delta.transform_failures;
+    self.aggregation_failures = self.aggregation_failures + delta.aggregation_failures;
+    self.rpc_calls = self.rpc_calls + delta.rpc_calls;
+    self.rpc_errors = self.rpc_errors + delta.rpc_errors;
+    // Blocks come in chain order, so only blocks after the last one are new
+    let mut blocks = delta.blocks.clone();
+    blocks.sort();
+    blocks.dedup();
+    for block_number in blocks {
+      if self.last_block.map_or(true, |last_block| block_number > last_block) {
+        self.blocks_with_events = self.blocks_with_events + 1;
+        self.last_block = Some(block_number);
+      }
+    }
+    if delta.latency_ms.is_some() {
+      self.last_ingest_latency_ms = delta.latency_ms;
+    }
+    if delta.events > 0 {
+      self.last_ingest_at = Some(now.timestamp_millis());
+      *self.daily_events.entry(now.format("%Y-%m-%d").to_string()).or_insert(0) += delta.events;
+      while self.daily_events.len() > DAILY_HISTORY_DAYS {
+        let oldest_day = self.daily_events.keys().next().cloned().unwrap_or_default();
+        self.daily_events.remove(&oldest_day);
+      }
+    }
+  }
+
+  pub fn events_per_block(&self) -> f64 {
+    if self.blocks_with_events == 0 {
+      0.0
+    } else {
+      self.total_events as f64 / self.blocks_with_events as f64
+    }
+  }
+
+  fn to_json(&self) -> serde_json::Value {
+    let mut stats_json = serde_json::to_value(self).unwrap_or(json!({}));
+    stats_json["eventsPerBlock"] = json!(self.events_per_block());
+    stats_json
+  }
+}
+
+fn stats_key(contract: &str, event_name: &str) -> String {
+  format!("{}{}", INGEST_STATS_PREFIX, qualified_name(contract, event_name))
+}
+
+pub fn get_ingest_stats(storage: &dyn Storage, contract: &str, event_name: &str) -> Result<Option<IngestStats>, String> {
+  match storage.get("stats", stats_key(contract, event_name).as_bytes())? {
+    Some(value) => serde_json::from_slice(&value)
+      .map(Some)
+      .map_err(|err| format!("Cannot decode stats of {}. Error: {}", qualified_name(contract, event_name), err)),
+    None => Ok(None)
+  }
+}
+
+/// Adds delta of a poll into the batch. Batches are written by a single write actor, so read and update do not race.
+pub fn add_stats_delta(storage: &dyn Storage, batch: &mut StorageBatch, contract: &str, event_name: &str, delta: &StatsDelta) -> Result<(), String> {
+  let mut stats = get_ingest_stats(storage, contract, event_name)?.unwrap_or_default();
+  stats.contract = contract.to_string();
+  stats.event = event_name.to_string();
+  stats.apply(delta, Utc::now());
+  let value = serde_json::to_string(&stats).map_err(|err| err.to_string())?;
+  batch.put("stats", stats_key(contract, event_name).as_bytes(), value.as_bytes());
+  Ok(())
+}
+
+pub fn get_all_ingest_stats(db: &CacheDB) -> Result<Vec<IngestStats>, String> {
+  let mut all_stats = Vec::new();
+  let mut iter = db.storage.iter("stats")?;
+  iter.seek(INGEST_STATS_PREFIX.as_bytes());
+  while iter.valid() && iter.key().starts_with(INGEST_STATS_PREFIX.as_bytes()) {
+    match serde_json::from_slice::<IngestStats>(iter.value()) {
+      Ok(stats) => all_stats.push(stats),
+      Err(err) => warn!("Cannot decode stats {:?}. Error: {}", iter.key(), err)
+    };
+    iter.next();
+  }
+  Ok(all_stats)
+}
+
+/// Returns stats of events grouped by contract. Counters of a contract are sums of its events.
+pub fn get_stats_report(db: &CacheDB, contract: Option<&str>, event_name: Option<&str>) -> Result<serde_json::Value, String> {
+  let mut contracts: BTreeMap<String, Vec<IngestStats>> = BTreeMap::new();
+  for stats in get_all_ingest_stats(db)? {
+    if contract.map_or(true, |c| c == stats.contract) && event_name.map_or(true, |e| e == stats.event) {
+      contracts.entry(stats.contract.clone()).or_insert_with(Vec::new).push(stats);
+    }
+  }
+  let report: Vec<serde_json::Value> = contracts.into_iter().map(|(contract_name, events)| {
+    let mut total = IngestStats{contract: contract_name.clone(), ..IngestStats::default()};
+    for stats in events.iter() {
+      total.total_events = total.total_events + stats.total_events;
+      total.removed_events = total.removed_events + stats.removed_events;
+      total.blocks_with_events = total.blocks_with_events + stats.blocks_with_events;
+      total.decode_failures = total.decode_failures + stats.decode_failures;
+      total.transform_failures = total.transform_failures + stats.transform_failures;
+      total.aggregation_failures = total.aggregation_failures + stats.aggregation_failures;
+      total.rpc_calls = total.rpc_calls + stats.rpc_calls;
+      total.rpc_errors = total.rpc_errors + stats.rpc_errors;
+      total.last_block = total.last_block.max(stats.last_block);
+      total.last_ingest_at = total.last_ingest_at.max(stats.last_ingest_at);
+      for (day, count) in stats.daily_events.iter() {
+        *total.daily_events.entry(day.clone()).or_insert(0) += *count;
+      }
+    }
+    json!({
+      "contract": contract_name,
+      "totalEvents": total.total_events,
+      "removedEvents": total.removed_events,
+      "decodeFailures": total.decode_failures,
+      "transformFailures": total.transform_failures,
+      "aggregationFailures": total.aggregation_failures,
+      "rpcCalls": total.rpc_calls,
+      "rpcErrors": total.rpc_errors,
+      "lastBlock": total.last_block,
+      "lastIngestAt": total.last_ingest_at,
+      "dailyEvents": total.daily_events,
+      "events": events.iter().map(|stats| stats.to_json()).collect::<Vec<serde_json::Value>>()
+    })
+  }).collect();
+  Ok(json!(report))
+}
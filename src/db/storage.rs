@@ -0,0 +1,128 @@
+/// # Module Storage
+/// Key-value storage with column families used by the whole cache. `RocksStorage` keeps data in RocksDB,
+/// `MemoryStorage` in memory, so library users and tests can run without native RocksDB.
+use std::path::Path;
+
+/// Single operation of a batch
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchOp {
+  Put(String, Vec<u8>, Vec<u8>),
+  Delete(String, Vec<u8>),
+  DeleteRange(String, Vec<u8>, Vec<u8>)   // Removes keys from the first (inclusive) to the second (exclusive)
+}
+
+/// Operations applied atomically by `Storage::write`
+#[derive(Debug, Clone, Default)]
+pub struct StorageBatch {
+  pub ops: Vec<BatchOp>
+}
+
+impl StorageBatch {
+  pub fn new() -> StorageBatch {
+    StorageBatch{ops: Vec::new()}
+  }
+
+  pub fn put(&mut self, column_family: &str, key: &[u8], value: &[u8]) {
+    self.ops.push(BatchOp::Put(column_family.to_string(), key.to_vec(), value.to_vec()));
+  }
+
+  pub fn delete(&mut self, column_family: &str, key: &[u8]) {
+    self.ops.push(BatchOp::Delete(column_family.to_string(), key.to_vec()));
+  }
+
+  pub fn delete_range(&mut self, column_family: &str, from: &[u8], to: &[u8]) {
+    self.ops.push(BatchOp::DeleteRange(column_family.to_string(), from.to_vec(), to.to_vec()));
+  }
+
+  pub fn len(&self) -> usize {
+    self.ops.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.ops.is_empty()
+  }
+}
+
+/// Cursor over keys of a column family in byte order. Iterator sees data as it was when it was created.
+pub trait StorageIterator {
+  /// Moves to the first key at or after `key`
+  fn seek(&mut self, key: &[u8]);
+  /// Moves to the last key at or before `key`
+  fn seek_for_prev(&mut self, key: &[u8]);
+  fn seek_to_first(&mut self);
+  fn seek_to_last(&mut self);
+  fn valid(&self) -> bool;
+  fn next(&mut self);
+  fn prev(&mut self);
+  /// Current key. Iterator has to be valid.
+  fn key(&self) -> &[u8];
+  /// Current value. Iterator has to be valid.
+  fn value(&self) -> &[u8];
+}
+
+/// Consistent read-only view of the whole storage
+pub trait StorageSnapshot {
+  fn get(&self, column_family: &str, key: &[u8]) -> Result<Option<Vec<u8>>, String>;
+  fn iter<'a>(&'a self, column_family: &str) -> Result<Box<dyn StorageIterator + 'a>, String>;
+}
+
+pub trait Storage: Send + Sync {
+  fn column_families(&self) -> Vec<String>;
+
+  fn get(&self, column_family: &str, key: &[u8]) -> Result<Option<Vec<u8>>, String>;
+
+  /// Applies batch atomically. `sync` makes it durable before return.
+  fn write(&self, batch: StorageBatch, sync: bool) -> Result<(), String>;
+
+  fn iter<'a>(&'a self, column_family: &str) -> Result<Box<dyn StorageIterator + 'a>, String>;
+
+  fn snapshot<'a>(&'a self) -> Box<dyn StorageSnapshot + 'a>;
+
+  fn has_column_family(&self, column_family: &str) -> bool {
+    self.column_families().iter().any(|cf| cf.as_str() == column_family)
+  }
+
+  fn put(&self, column_family: &str, key: &[u8], value: &[u8]) -> Result<(), String> {
+    let mut batch = StorageBatch::new();
+    batch.put(column_family, key, value);
+    self.write(batch, false)
+  }
+
+  fn delete(&self, column_family: &str, key: &[u8]) -> Result<(), String> {
+    let mut batch = StorageBatch::new();
+    batch.delete(column_family, key);
+    self.write(batch, false)
+  }
+
+  /// Returns entries which keys start with `prefix`, at most `limit` of them
+  fn scan_prefix(&self, column_family: &str, prefix: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>, String> {
+    let mut iter = self.iter(column_family)?;
+    iter.seek(prefix);
+    let mut entries = Vec::new();
+    while iter.valid() && iter.key().starts_with(prefix) && entries.len() < limit {
+      entries.push((iter.key().to_vec(), iter.value().to_vec()));
+      iter.next();
+    }
+    Ok(entries)
+  }
+
+  /// Integer property of a column family (ex. "rocksdb.total-sst-files-size"). None when backend has no such property.
+  fn get_property_int(&self, _column_family: &str, _name: &str) -> Option<u64> {
+    None
+  }
+
+  /// Compacts key range of a column family. Backends without compaction do nothing.
+  fn compact_range(&self, _column_family: &str, _from: Option<&[u8]>, _to: Option<&[u8]>) -> Result<(), String> {
+    Ok(())
+  }
+
+  /// Makes writes of another process visible. Only storages opened read-only need it.
+  fn catch_up(&self) -> Result<(), String> {
+    Ok(())
+  }
+
+  /// Writes consistent copy of the storage into a directory which does not exist yet
+  fn create_checkpoint(&self, _path: &Path) -> Result<(), String> {
+    Err(String::from("Storage does not support checkpoints"))
+  }
+}
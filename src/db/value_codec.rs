@@ -0,0 +1,225 @@
+/// # Module Value codec
+/// Encoding of event values. Json values are stored as text. Binary values start with `BINARY_TAG`
+/// and a schema id followed by the payload, so both formats can live in one database and every
+/// value is decoded by its own header. Json never starts with `BINARY_TAG`.
+///
+/// Schemas:
+///  1 - string map: varint count, then varint length and bytes of every key and value, ordered by key
+///  2 - hex map: like string map, but every value starts with a tag. Lowercase hex values (uints, addresses, bytes
+///      as formatted by ethabi) are stored as bytes, so they take half of their text. Transfer with two addresses
+///      and a uint256 takes 71 bytes, against 125 bytes of json and 115 bytes of schema 1.
+use std::collections::{BTreeMap, HashMap};
+use std::str::from_utf8;
+use config::structs::storageconfig::ValueFormat;
+use db::storage::{Storage, StorageBatch};
+
+pub const BINARY_TAG: u8 = 0xcb;
+pub const STRING_MAP_SCHEMA: u8 = 1;
+pub const HEX_MAP_SCHEMA: u8 = 2;
+const TEXT_VALUE: u8 = 0;
+const HEX_VALUE: u8 = 1;
+const ODD_HEX_VALUE: u8 = 2;      // Hex of odd length, stored with a leading zero nibble
+pub const VALUE_FORMAT_KEY: &'static str = "value_format";
+
+pub fn format_name(format: ValueFormat) -> &'static str {
+  match format {
+    ValueFormat::Json => "json",
+    ValueFormat::Binary => "binary"
+  }
+}
+
+pub fn parse_format(name: &str) -> Result<ValueFormat, String> {
+  match name {
+    "json" => Ok(ValueFormat::Json),
+    "binary" => Ok(ValueFormat::Binary),
+    _ => Err(format!("Unknown value format {}. Expected json or binary", name))
+  }
+}
+
+pub fn is_binary(value: &[u8]) -> bool {
+  value.first() == Some(&BINARY_TAG)
+}
+
+/// Checks if value is stored in the format with its current schema
+pub fn is_encoded_in(value: &[u8], format: ValueFormat) -> bool {
+  match format {
+    ValueFormat::Json => !is_binary(value),
+    ValueFormat::Binary => is_binary(value) && value.get(1) == Some(&HEX_MAP_SCHEMA)
+  }
+}
+
+fn encode_varint(mut value: u64, bytes: &mut Vec<u8>) {
+  while value >= 0x80 {
+    bytes.push((value as u8) | 0x80);
+    value = value >> 7;
+  }
+  bytes.push(value as u8);
+}
+
+fn decode_varint(bytes: &[u8], position: &mut usize) -> Result<u64, String> {
+  let mut value: u64 = 0;
+  let mut shift = 0;
+  loop {
+    let byte = *bytes.get(*position).ok_or(String::from("Binary value is truncated"))?;
+    *position = *position + 1;
+    if shift > 63 {
+      return Err(String::from("Binary value has too long varint"));
+    }
+    value = value | (((byte & 0x7f) as u64) << shift);
+    if byte & 0x80 == 0 {
+      return Ok(value);
+    }
+    shift = shift + 7;
+  }
+}
+
+fn decode_bytes<'a>(bytes: &'a [u8], position: &mut usize) -> Result<&'a [u8], String> {
+  let length = decode_varint(bytes, position)? as usize;
+  let end = position.checked_add(length).filter(|end| *end <= bytes.len()).ok_or(String::from("Binary value is truncated"))?;
+  let decoded = &bytes[*position..end];
+  *position = end;
+  Ok(decoded)
+}
+
+fn decode_string<'a>(bytes: &'a [u8], position: &mut usize) -> Result<&'a str, String> {
+  from_utf8(decode_bytes(bytes, position)?).map_err(|err| format!("Binary value has invalid string. Error: {}", err))
+}
+
+fn is_lowercase_hex(value: &str) -> bool {
+  !value.is_empty() && value.bytes().all(|byte| (byte >= b'0' && byte <= b'9') || (byte >= b'a' && byte <= b'f'))
+}
+
+fn hex_digit(nibble: u8) -> char {
+  (if nibble < 10 { b'0' + nibble } else { b'a' + nibble - 10 }) as char
+}
+
+/// Tag and bytes of a single value of hex map
+fn encode_tagged_value(value: &str, bytes: &mut Vec<u8>) {
+  if !is_lowercase_hex(value) {
+    bytes.push(TEXT_VALUE);
+    encode_varint(value.len() as u64, bytes);
+    bytes.extend_from_slice(value.as_bytes());
+    return;
+  }
+  let digits: Vec<u8> = value.bytes().map(|byte| if byte <= b'9' { byte - b'0' } else { byte - b'a' + 10 }).collect();
+  let is_odd = digits.len() % 2 == 1;
+  bytes.push(if is_odd { ODD_HEX_VALUE } else { HEX_VALUE });
+  let padded: Vec<u8> = if is_odd { [0u8].iter().chain(digits.iter()).cloned().collect() } else { digits };
+  encode_varint((padded.len() / 2) as u64, bytes);
+  for pair in padded.chunks(2) {
+    bytes.push((pair[0] << 4) | pair[1]);
+  }
+}
+
+fn decode_tagged_value(bytes: &[u8], position: &mut usize) -> Result<String, String> {
+  let tag = *bytes.get(*position).ok_or(String::from("Binary value is truncated"))?;
+  *position = *position + 1;
+  match tag {
+    TEXT_VALUE => decode_string(bytes, position).map(|value| value.to_string()),
+    HEX_VALUE | ODD_HEX_VALUE => {
+      let mut value = String::new();
+      for byte in decode_bytes(bytes, position)? {
+        value.push(hex_digit(byte >> 4));
+        value.push(hex_digit(byte & 0x0f));
+      }
+      if tag == ODD_HEX_VALUE {
+        value.remove(0);
+      }
+      Ok(value)
+    },
+    _ => Err(format!("Unknown binary value tag {}", tag))
+  }
+}
+
+/// Encodes event params in a given format
+pub fn encode_params(params: &HashMap<String, String>, format: ValueFormat) -> Result<Vec<u8>, String> {
+  match format {
+    ValueFormat::Json => serde_json::to_vec(params)
+      .map_err(|err| format!("Cannot convert json: {:?} to string. Error: {}", params, err)),
+    ValueFormat::Binary => {
+      let ordered: BTreeMap<&String, &String> = params.iter().collect();
+      let mut bytes = vec![BINARY_TAG, HEX_MAP_SCHEMA];
+      encode_varint(ordered.len() as u64, &mut bytes);
+      for (key, value) in ordered {
+        encode_varint(key.len() as u64, &mut bytes);
+        bytes.extend_from_slice(key.as_bytes());
+        encode_tagged_value(value, &mut bytes);
+      }
+      Ok(bytes)
+    }
+  }
+}
+
+/// Decodes event params stored in any format
+pub fn decode_params(value: &[u8]) -> Result<HashMap<String, String>, String> {
+  if !is_binary(value) {
+    return serde_json::from_slice(value).map_err(|err| format!("Cannot decode stored event params. Error: {}", err));
+  }
+  let schema = *value.get(1).ok_or(String::from("Binary value is truncated"))?;
+  if schema != STRING_MAP_SCHEMA && schema != HEX_MAP_SCHEMA {
+    return Err(format!("Unknown binary value schema {}", schema));
+  }
+  let mut position = 2;
+  let count = decode_varint(value, &mut position)?;
+  let mut params = HashMap::new();
+  for _ in 0..count {
+    let key = decode_string(value, &mut position)?.to_string();
+    let param = if schema == HEX_MAP_SCHEMA {
+      decode_tagged_value(value, &mut position)?
+    } else {
+      decode_string(value, &mut position)?.to_string()
+    };
+    params.insert(key, param);
+  }
+  if position != value.len() {
+    return Err(String::from("Binary value has trailing bytes"));
+  }
+  Ok(params)
+}
+
+/// Decodes any stored value into json. Binary event params become a json object of strings.
+pub fn decode_value(value: &[u8]) -> Result<serde_json::Value, String> {
+  if is_binary(value) {
+    let params = decode_params(value)?;
+    return serde_json::to_value(params).map_err(|err| err.to_string());
+  }
+  serde_json::from_slice(value).map_err(|_| String::from("Cannot decode database content to json"))
+}
+
+/// Returns format of new event values. Databases without stored format keep json.
+pub fn get_value_format(storage: &dyn Storage) -> Result<ValueFormat, String> {
+  match storage.get("settings", VALUE_FORMAT_KEY.as_bytes())? {
+    Some(value) => parse_format(from_utf8(&value).map_err(|_| String::from("Cannot convert value format to utf8"))?),
+    None => Ok(ValueFormat::Json)
+  }
+}
+
+pub fn is_value_format_stored(storage: &dyn Storage) -> Result<bool, String> {
+  Ok(storage.get("settings", VALUE_FORMAT_KEY.as_bytes())?.is_some())
+}
+
+pub fn put_value_format(batch: &mut StorageBatch, format: ValueFormat) {
+  batch.put("settings", VALUE_FORMAT_KEY.as_bytes(), format_name(format).as_bytes());
+}
+
+/// Stores configured format in a new database. Databases with events and without format were written
+/// before value formats existed, so they hold json. Stored format wins, as values are already written in it.
+pub fn check_value_format(storage: &dyn Storage, configured: ValueFormat) -> Result<ValueFormat, String> {
+  if !is_value_format_stored(storage)? {
+    let mut iter = storage.iter("events")?;
+    iter.seek_to_first();
+    let format = if iter.valid() { ValueFormat::Json } else { configured };
+    let mut batch = StorageBatch::new();
+    put_value_format(&mut batch, format);
+    storage.write(batch, true)?;
+    if format == configured {
+      return Ok(format);
+    }
+  }
+  let stored = get_value_format(storage)?;
+  if stored != configured {
+    warn!("Database stores events as {}, but {} is configured. Run `cacherz migrate --valueFormat {}` to convert them",
+      format_name(stored), format_name(configured), format_name(configured));
+  }
+  Ok(stored)
+}
@@ -0,0 +1,173 @@
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use reqwest;
+use serde_json;
+use serde_json::Value;
+use std::sync::mpsc::Receiver;
+use eth::ipc;
+
+/// Environment variables with ethereum node credentials
+const ENV_ETH_USER: &'static str = "CACHERZ_ETH_USER";
+const ENV_ETH_PASSWORD: &'static str = "CACHERZ_ETH_PASSWORD";
+const ENV_ETH_BEARER_TOKEN: &'static str = "CACHERZ_ETH_BEARER_TOKEN";
+
+/// Credentials file content. Every field is optional.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointCredentials {
+  pub user: Option<String>,
+  pub password: Option<String>,
+  pub bearer_token: Option<String>,
+  #[serde(default)]
+  pub headers: Vec<String>
+}
+
+/// Json-rpc endpoint of ethereum node
+#[derive(Clone, Default)]
+pub struct Endpoint {
+  pub url: String,                                  // Full url with scheme, path and query
+  pub headers: Vec<(String, String)>,               // Custom headers added to every request
+  pub basic_auth: Option<(String, Option<String>)>, // User and password
+  pub bearer_token: Option<String>,                 // Token send as "Authorization: Bearer"
+  pub ca_bundle_path: Option<String>,               // Path to pem file with additional root certificates
+  client: Option<reqwest::Client>                   // Built once and shared by clones, so requests reuse connections
+}
+
+/// Credentials are never printed, because actors settings are logged
+impl fmt::Debug for Endpoint {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Endpoint")
+      .field("url", &self.url)
+      .field("headers", &self.headers.iter().map(|(name, _)| name.clone()).collect::<Vec<String>>())
+      .field("basic_auth", &self.basic_auth.is_some())
+      .field("bearer_token", &self.bearer_token.is_some())
+      .field("ca_bundle_path", &self.ca_bundle_path)
+      .finish()
+  }
+}
+
+impl Endpoint {
+  pub fn from_url(url: String) -> Endpoint {
+    let mut endpoint = Endpoint{url: url, ..Default::default()};
+    endpoint.client = endpoint.build_client().ok();
+    endpoint
+  }
+
+  pub fn from_host_and_port(host: String, port: String) -> Endpoint {
+    Endpoint::from_url(format!("http://{}:{}", host, port))
+  }
+
+  /// Adds header given as "Name: value"
+  pub fn with_header(mut self, header: &str) -> Result<Endpoint, String> {
+    let mut header_parts = header.splitn(2, ':');
+    match (header_parts.next(), header_parts.next()) {
+      (Some(name), Some(value)) => {
+        self.headers.push((name.trim().to_string(), value.trim().to_string()));
+        Ok(self)
+      },
+      _ => Err(format!("Header {} should have \"Name: value\" format", header))
+    }
+  }
+
+  /// Adds root certificates from pem file. Bundle is read here once, not on every request.
+  pub fn with_ca_bundle(mut self, ca_bundle_path: Option<String>) -> Result<Endpoint, String> {
+    self.ca_bundle_path = ca_bundle_path;
+    self.client = Some(self.build_client()?);
+    Ok(self)
+  }
+
+  /// Reads credentials from CACHERZ_ETH_USER, CACHERZ_ETH_PASSWORD and CACHERZ_ETH_BEARER_TOKEN
+  pub fn with_credentials_from_env(mut self) -> Endpoint {
+    if let Ok(user) = env::var(ENV_ETH_USER) {
+      self.basic_auth = Some((user, env::var(ENV_ETH_PASSWORD).ok()));
+    }
+    if let Ok(token) = env::var(ENV_ETH_BEARER_TOKEN) {
+      self.bearer_token = Some(token);
+    }
+    self
+  }
+
+  /// Reads credentials from json file. Values from file override values from env.
+  pub fn with_credentials_from_file(mut self, file_path: String) -> Result<Endpoint, String> {
+    let mut contents = String::new();
+    File::open(Path::new(&file_path))
+      .and_then(|mut file_handler| file_handler.read_to_string(&mut contents))
+      .map_err(|err| format!("Can not read credentials file {}. Error: {}", file_path, err))?;
+    let credentials: EndpointCredentials = serde_json::from_str(&contents)
+      .map_err(|err| format!("Can not parse credentials file {}. Error: {}", file_path, err))?;
+    if let Some(user) = credentials.user {
+      self.basic_auth = Some((user, credentials.password));
+    }
+    if let Some(token) = credentials.bearer_token {
+      self.bearer_token = Some(token);
+    }
+    for header in credentials.headers {
+      self = self.with_header(&header)?;
+    }
+    Ok(self)
+  }
+
+  fn build_client(&self) -> Result<reqwest::Client, String> {
+    let mut client_builder = reqwest::Client::builder();
+    if let Some(ca_bundle_path) = self.ca_bundle_path.clone() {
+      let mut ca_bundle = Vec::new();
+      File::open(Path::new(&ca_bundle_path))
+        .and_then(|mut file_handler| file_handler.read_to_end(&mut ca_bundle))
+        .map_err(|err| format!("Can not read CA bundle {}. Error: {}", ca_bundle_path, err))?;
+      let certificate = reqwest::Certificate::from_pem(&ca_bundle)
+        .map_err(|err| format!("Can not parse CA bundle {}. Error: {}", ca_bundle_path, err))?;
+      client_builder = client_builder.add_root_certificate(certificate);
+    }
+    client_builder.build().map_err(|err| err.to_string())
+  }
+
+  pub fn is_ipc(&self) -> bool {
+    ipc::is_ipc_url(&self.url)
+  }
+
+  /// Subscribes for notifications (eth_subscribe). Only ipc endpoints support subscriptions.
+  pub fn subscribe(&self, id: i64, params: Value) -> Result<(String, Receiver<Value>), String> {
+    if self.is_ipc() {
+      ipc::subscribe(&ipc::get_socket_path(&self.url), id, params)
+    } else {
+      Err(format!("Endpoint {} does not support subscriptions. Use ipc:// endpoint", self.url))
+    }
+  }
+
+  /// Sends json-rpc request and returns response body
+  pub fn post(&self, json: &Value) -> Result<String, String> {
+    if self.is_ipc() {
+      return ipc::post(&ipc::get_socket_path(&self.url), json);
+    }
+    let client = match self.client {
+      Some(ref client) => client.clone(),
+      None => self.build_client()?
+    };
+    let mut request = client.post(&self.url).json(json);
+    for (name, value) in self.headers.clone() {
+      request = request.header(name.as_str(), value.as_str());
+    }
+    if let Some((user, password)) = self.basic_auth.clone() {
+      request = request.basic_auth(user, password);
+    }
+    if let Some(token) = self.bearer_token.clone() {
+      request = request.header("Authorization", format!("Bearer {}", token).as_str());
+    }
+    match request.send() {
+      Ok(mut result) => {
+        let mut buf = String::new();
+        result
+          .read_to_string(&mut buf)
+          .map_err(|err| format!("Failed to read response. Error: {}", err))?;
+        Ok(buf)
+      },
+      Err(error) => {
+        error!("Error: {:?}", error);
+        Err(error.to_string())
+      }
+    }
+  }
+}
@@ -1,71 +1,67 @@
-use std::io::Read;
+use std::collections::HashMap;
 use serde_json;
-use reqwest;
+use eth::endpoint::Endpoint;
 use eth::structs::eventchanges::EventChanges;
+use eth::structs::eventresult::EventResult;
+use futures::sync::mpsc::{unbounded, UnboundedReceiver};
+use std::thread;
 
-pub fn create_new_filter(eth_addr: String, eth_port: String, eth_event_hex: String, id: i64, from_block: String) -> Result<String, String> {
-  let client = reqwest::Client::new();
-  let eth_address: String = format!("http://{}:{}", eth_addr, eth_port);
+pub fn create_new_filter(endpoint: Endpoint, eth_event_hex: String, id: i64, from_block: String, to_block: Option<String>, address: Option<String>) -> Result<String, String> {
+    let mut filter = json!({
+      "fromBlock": from_block,
+      "topics": [format!("0x{}", eth_event_hex)]
+    });
+    if let Some(_to_block) = to_block {
+      filter["toBlock"] = json!(_to_block);
+    }
+    if let Some(_address) = address {
+      filter["address"] = json!(_address);
+    }
     let json = json!({
       "jsonrpc": "2.0",
       "method": "eth_newFilter",
       "id": id,
-      "params": [{
-          "fromBlock": from_block,
-          "topics": [format!("0x{}", eth_event_hex)]
-      }
-      ]
+      "params": [filter]
     });
-    let filter_result = client.post(&eth_address).json(&json).send();
+    let filter_result = endpoint.post(&json);
     match filter_result {
-      Ok(mut result) => {
-        let mut buf = String::new();
-        result
-          .read_to_string(&mut buf)
-          .expect("Failed to read response");
-        let filter_id: String =
-          get_filter_id(buf.clone()).expect(&format!("Can not receive id from {}", buf));
+      Ok(buf) => {
+        let filter_id: String = get_filter_id(buf.clone())?;
         Ok(filter_id)
       }
       Err(error) => {
         error!("Error: {:?}", error);
-        Err(error.to_string())
+        Err(error)
       },
     }
 }
 
 fn get_filter_id(filter_str: String) -> Result<String, String> {
-  let filter_json: serde_json::Value =
-    serde_json::from_str(&filter_str).expect("Can not convert string into json");
-  let filter_json_result = filter_json["result"].as_str().expect(&format!(
+  let filter_json: serde_json::Value = serde_json::from_str(&filter_str)
+    .map_err(|err| format!("Can not convert {} into json. Error: {}", filter_str, err))?;
+  let filter_json_result = filter_json["result"].as_str().ok_or(format!(
     "Can not convert result: {:?} into string",
     filter_json
-  ));
+  ))?;
   return Ok(filter_json_result.to_string());
 }
 
-pub fn get_new_events(eth_addr: String, eth_port: String, filter_id: String, id: i64) -> Result<EventChanges, String> {
-  let client = reqwest::Client::new();
-  let eth_address: String = format!("http://{}:{}", eth_addr, eth_port);
+pub fn get_new_events(endpoint: Endpoint, filter_id: String, id: i64) -> Result<EventChanges, String> {
   let json = json!({
     "jsonrpc": "2.0",
     "method": "eth_getFilterChanges",
     "id": id,
     "params": [filter_id]
   });
-  let filter_result = client.post(&eth_address).json(&json).send();
+  let filter_result = endpoint.post(&json);
   match filter_result {
-    Ok(mut result) => {
-      let mut buf = String::new();
-      result
-        .read_to_string(&mut buf)
-        .expect("Failed to read response");
+    Ok(buf) => {
       let event_result: EventChanges = convert_event(buf.clone())?;
       Ok(event_result)
     }
     Err(error) => {
       error!("Error: {:?}", error);
-      Err(error.to_string())
+      Err(error)
       },
   }
 }
@@ -76,4 +72,161 @@ fn convert_event(event_string: String) -> Result<EventChanges, String> {
     Ok(e_r) => Ok(e_r),
     Err(error) => Err(error.to_string()),
   }
-}
\ No newline at end of file
+}
+
+pub fn get_logs(endpoint: Endpoint, eth_event_hex: String, id: i64, from_block: String, to_block: String, address: Option<String>) -> Result<EventChanges, String> {
+  let mut filter = json!({
+    "fromBlock": from_block,
+    "toBlock": to_block,
+    "topics": [format!("0x{}", eth_event_hex)]
+  });
+  if let Some(_address) = address {
+    filter["address"] = json!(_address);
+  }
+  let json = json!({
+    "jsonrpc": "2.0",
+    "method": "eth_getLogs",
+    "id": id,
+    "params": [filter]
+  });
+  let logs_result = endpoint.post(&json);
+  match logs_result {
+    Ok(buf) => convert_event(buf),
+    Err(error) => {
+      error!("Error: {:?}", error);
+      Err(error)
+    },
+  }
+}
+
+pub fn get_block_number(endpoint: Endpoint, id: i64) -> Result<u64, String> {
+  let json = json!({
+    "jsonrpc": "2.0",
+    "method": "eth_blockNumber",
+    "id": id,
+    "params": []
+  });
+  let block_number_result = endpoint.post(&json);
+  match block_number_result {
+    Ok(buf) => {
+      let block_number_json: serde_json::Value = serde_json::from_str(&buf)
+        .map_err(|err| format!("Can not convert {} into json. Error: {}", buf, err))?;
+      let block_number_hex = block_number_json["result"].as_str()
+        .ok_or(format!("There is no block number in response: {}", buf))?;
+      parse_hex_u64(block_number_hex)
+    }
+    Err(error) => {
+      error!("Error: {:?}", error);
+      Err(error)
+    },
+  }
+}
+
+/// Returns chain id of the node. Nodes without `eth_chainId` are asked for `net_version`.
+pub fn get_chain_id(endpoint: Endpoint, id: i64) -> Result<u64, String> {
+  let json = json!({
+    "jsonrpc": "2.0",
+    "method": "eth_chainId",
+    "id": id,
+    "params": []
+  });
+  let buf = endpoint.post(&json)?;
+  let chain_id_json: serde_json::Value = serde_json::from_str(&buf)
+    .map_err(|err| format!("Can not convert {} into json. Error: {}", buf, err))?;
+  if let Some(chain_id_hex) = chain_id_json["result"].as_str() {
+    return parse_hex_u64(chain_id_hex);
+  }
+  let net_version_json = json!({
+    "jsonrpc": "2.0",
+    "method": "net_version",
+    "id": id,
+    "params": []
+  });
+  let net_version_buf = endpoint.post(&net_version_json)?;
+  let net_version: serde_json::Value = serde_json::from_str(&net_version_buf)
+    .map_err(|err| format!("Can not convert {} into json. Error: {}", net_version_buf, err))?;
+  net_version["result"].as_str()
+    .ok_or(format!("There is no chain id in responses: {} {}", buf, net_version_buf))
+    .and_then(|version| version.parse::<u64>().map_err(|err| format!("Can not parse {} into number. Error: {}", version, err)))
+}
+
+/// Returns timestamp of a block in seconds
+pub fn get_block_timestamp(endpoint: Endpoint, block_number: u64, id: i64) -> Result<u64, String> {
+  let json = json!({
+    "jsonrpc": "2.0",
+    "method": "eth_getBlockByNumber",
+    "id": id,
+    "params": [format!("0x{:x}", block_number), false]
+  });
+  let buf = endpoint.post(&json)?;
+  let block_json: serde_json::Value = serde_json::from_str(&buf)
+    .map_err(|err| format!("Can not convert {} into json. Error: {}", buf, err))?;
+  block_json["result"]["timestamp"].as_str()
+    .ok_or(format!("There is no block timestamp in response: {}", buf))
+    .and_then(parse_hex_u64)
+}
+
+/// Number of transactions whose senders are fetched by one batch request
+pub const TRANSACTION_SENDERS_BATCH_SIZE: usize = 100;
+
+/// Returns senders of many transactions using one json-rpc batch request. Transactions whose sender
+/// cannot be read are left out of the result.
+pub fn get_transaction_senders(endpoint: Endpoint, transaction_hashes: &[String], id: i64) -> Result<HashMap<String, String>, String> {
+  if transaction_hashes.is_empty() {
+    return Ok(HashMap::new());
+  }
+  let requests: Vec<serde_json::Value> = transaction_hashes.iter().enumerate().map(|(position, transaction_hash)| json!({
+    "jsonrpc": "2.0",
+    "method": "eth_getTransactionByHash",
+    "id": id + position as i64,
+    "params": [transaction_hash]
+  })).collect();
+  let buf = endpoint.post(&serde_json::Value::Array(requests))?;
+  let responses: Vec<serde_json::Value> = serde_json::from_str(&buf)
+    .map_err(|err| format!("Can not convert {} into json batch. Error: {}", buf, err))?;
+  let mut senders: HashMap<String, String> = HashMap::new();
+  for response in responses {
+    // Responses of a batch can come in any order, so they are matched by id
+    let transaction_hash = response["id"].as_i64()
+      .and_then(|response_id| transaction_hashes.get((response_id - id) as usize));
+    match (transaction_hash, response["result"]["from"].as_str()) {
+      (Some(transaction_hash), Some(sender)) => { senders.insert(transaction_hash.clone(), sender.to_string()); },
+      _ => warn!("There is no transaction sender in response: {}", response)
+    };
+  }
+  Ok(senders)
+}
+
+/// Subscribes for new logs of the event. Works only with ipc endpoints.
+/// Logs come as a stream, so actors can handle them as messages. Subscription ends when the stream is dropped.
+pub fn subscribe_logs(endpoint: Endpoint, eth_event_hex: String, id: i64, address: Option<String>) -> Result<(String, UnboundedReceiver<EventResult>), String> {
+  let mut filter = json!({
+    "topics": [format!("0x{}", eth_event_hex)]
+  });
+  if let Some(_address) = address {
+    filter["address"] = json!(_address);
+  }
+  let (subscription_id, notifications) = endpoint.subscribe(id, json!(["logs", filter]))?;
+  let (sender, receiver) = unbounded::<EventResult>();
+  let _subscription_id = subscription_id.clone();
+  thread::spawn(move || {
+    for notification in notifications {
+      match serde_json::from_value::<EventResult>(notification) {
+        Ok(event_result) => {
+          if sender.unbounded_send(event_result).is_err() {
+            break;
+          }
+        },
+        Err(err) => error!("Can not convert log from subscription {}. Error: {}", _subscription_id, err)
+      }
+    }
+  });
+  Ok((subscription_id, receiver))
+}
+
+/// Parses ethereum quantity (ex. "0x1a") into u64
+pub fn parse_hex_u64(hex: &str) -> Result<u64, String> {
+  let trimmed_hex = hex.trim_start_matches("0x");
+  u64::from_str_radix(trimmed_hex, 16)
+    .map_err(|err| format!("Can not parse {} into number. Error: {}", hex, err))
+}
@@ -0,0 +1,110 @@
+/// # Module Ipc
+/// Json-rpc over unix domain socket (ex. geth.ipc). Every request opens its own connection,
+/// subscription keeps its connection open in a reader thread.
+use serde_json;
+use serde_json::Value;
+use std::sync::mpsc::Receiver;
+#[cfg(unix)]
+use std::sync::mpsc::channel;
+#[cfg(unix)]
+use std::time::Duration;
+#[cfg(unix)]
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::thread;
+
+const IPC_SCHEME: &'static str = "ipc://";
+#[cfg(unix)]
+const IPC_READ_TIMEOUT_SECS: u64 = 30;
+
+pub fn is_ipc_url(url: &str) -> bool {
+  url.starts_with(IPC_SCHEME)
+}
+
+/// Returns socket path from "ipc:///data/geth.ipc" url
+pub fn get_socket_path(url: &str) -> String {
+  url.trim_start_matches(IPC_SCHEME).to_string()
+}
+
+#[cfg(unix)]
+fn connect(socket_path: &str, json: &Value) -> Result<UnixStream, String> {
+  let mut stream = UnixStream::connect(socket_path)
+    .map_err(|err| format!("Can not connect to ipc socket {}. Error: {}", socket_path, err))?;
+  let request = serde_json::to_vec(json)
+    .map_err(|err| format!("Can not convert {:?} into bytes. Error: {}", json, err))?;
+  stream.write_all(&request)
+    .and_then(|_| stream.flush())
+    .map_err(|err| format!("Can not write to ipc socket {}. Error: {}", socket_path, err))?;
+  Ok(stream)
+}
+
+/// Sends json-rpc request and returns response body
+#[cfg(unix)]
+pub fn post(socket_path: &str, json: &Value) -> Result<String, String> {
+  let stream = connect(socket_path, json)?;
+  stream.set_read_timeout(Some(Duration::new(IPC_READ_TIMEOUT_SECS, 0)))
+    .map_err(|err| err.to_string())?;
+  // Node does not close connection after response, so only first json value is read
+  let mut responses = serde_json::Deserializer::from_reader(stream).into_iter::<Value>();
+  match responses.next() {
+    Some(Ok(response)) => Ok(response.to_string()),
+    Some(Err(err)) => Err(format!("Can not read response from ipc socket {}. Error: {}", socket_path, err)),
+    None => Err(format!("Ipc socket {} has been closed without response", socket_path))
+  }
+}
+
+/// Sends eth_subscribe request. Notifications are passed to returned receiver
+/// until the socket is closed or receiver is dropped.
+#[cfg(unix)]
+pub fn subscribe(socket_path: &str, id: i64, params: Value) -> Result<(String, Receiver<Value>), String> {
+  let json = json!({
+    "jsonrpc": "2.0",
+    "method": "eth_subscribe",
+    "id": id,
+    "params": params
+  });
+  let stream = connect(socket_path, &json)?;
+  let mut messages = serde_json::Deserializer::from_reader(stream).into_iter::<Value>();
+  let subscription_id: String = match messages.next() {
+    Some(Ok(response)) => response["result"].as_str()
+      .map(|result| result.to_string())
+      .ok_or(format!("Can not get subscription id from {}", response))?,
+    Some(Err(err)) => return Err(format!("Can not read response from ipc socket {}. Error: {}", socket_path, err)),
+    None => return Err(format!("Ipc socket {} has been closed without response", socket_path))
+  };
+  let (sender, receiver) = channel::<Value>();
+  let _subscription_id = subscription_id.clone();
+  let _socket_path = socket_path.to_string();
+  thread::spawn(move || {
+    for message in messages {
+      match message {
+        Ok(notification) => {
+          if notification["method"] != "eth_subscription" || notification["params"]["subscription"] != _subscription_id.as_str() {
+            continue;
+          }
+          if sender.send(notification["params"]["result"].clone()).is_err() {
+            info!("Subscription {} receiver has been dropped", _subscription_id);
+            break;
+          }
+        },
+        Err(err) => {
+          error!("Subscription {} on ipc socket {} has been closed. Error: {}", _subscription_id, _socket_path, err);
+          break;
+        }
+      }
+    }
+  });
+  Ok((subscription_id, receiver))
+}
+
+#[cfg(not(unix))]
+pub fn post(socket_path: &str, _json: &Value) -> Result<String, String> {
+  Err(format!("Ipc transport ({}) is supported only on unix systems", socket_path))
+}
+
+#[cfg(not(unix))]
+pub fn subscribe(socket_path: &str, _id: i64, _params: Value) -> Result<(String, Receiver<Value>), String> {
+  Err(format!("Ipc transport ({}) is supported only on unix systems", socket_path))
+}
@@ -1,3 +1,5 @@
 pub mod eth_contract_loader;
 pub mod eth_json_rpc;
+pub mod endpoint;
+pub mod ipc;
 pub mod structs;
\ No newline at end of file
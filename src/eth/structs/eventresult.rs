@@ -12,6 +12,8 @@ pub struct EventResult {
   pub address: String,
   pub data: String,
   pub topics: Vec<String>,
+  #[serde(default)]
+  pub removed: bool,          // True when log was removed by chain reorganization
 }
 
 impl EventResult {
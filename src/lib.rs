@@ -1,4 +1,5 @@
 extern crate reqwest;
+#[cfg(feature = "rocksdb")]
 extern crate rocksdb;
 // extern crate sha3;
 extern crate serde;
@@ -6,11 +7,16 @@ extern crate chrono;
 // extern crate hex;
 extern crate ethabi;
 extern crate futures;
+extern crate bytes;
 
 pub mod eth;
 pub mod actors;
 pub mod db;
 pub mod web;
+pub mod config;
+pub mod commands;
+#[cfg(feature = "mock")]
+pub mod mock;
 
 #[macro_use]
 extern crate serde_json;
@@ -25,7 +31,9 @@ extern crate rustc_hex;
 extern crate log;
 extern crate env_logger;
 extern crate actix_lua;
+extern crate rlua;
 #[macro_use]
 extern crate lazy_static;
 extern crate actix_web;
-extern crate docopt;
\ No newline at end of file
+extern crate docopt;
+extern crate parquet;
\ No newline at end of file
@@ -2,22 +2,53 @@
 extern crate lib;
 extern crate reqwest;
 extern crate ethabi;
-extern crate log;
+#[macro_use] extern crate log;
 extern crate docopt;
+extern crate env_logger;
 
 use docopt::Docopt;
 
-use lib::eth::eth_contract_loader;
+use lib::eth::{eth_contract_loader, eth_json_rpc};
+use lib::eth::endpoint::Endpoint;
+use lib::config::config_loader;
+use lib::config::structs::cacherzconfig::CacherzConfig;
+use lib::config::structs::contractconfig::ContractConfig;
+use lib::config::structs::storageconfig::StorageConfig;
+use std::path::Path;
 use lib::actors::main_actor::MainActor;
 use lib::actors::eth_actor::EthActor;
 use std::collections::HashMap;
 use lib::actors::structs::settings::Settings;
+use lib::commands::{reindex, migrate, migrate_values};
+use lib::commands::import::{self, ImportSummary, ImportTarget, IMPORT_BATCH_SIZE};
+use lib::db::cachedb::CacheDB;
+use lib::db::indexes::EventIndexes;
+use lib::db::schema_version::{check_schema_version, verify_schema_version};
+use lib::db::compaction::{self, CompactionStatus};
+use lib::db::backup::{self, BackupManifest};
+use lib::db::value_codec;
+use lib::db::aggregations::check_aggregations;
+use lib::db::key_schema::qualified_name;
+use lib::actors::lua_transform::check_script;
+use lib::db::export::{self, ExportSource};
+use lib::web::lua_query::QueryScripts;
+use lib::web::admin::AdminConfig;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
 
 const USAGE: &'static str = "
   Cacherz.
 
   Usage:
-  cacherz --ethHost <ethHost> --ethPort <ethPort> --webHost <webHost> --webPort <webPort> --abiFilePath <abiFilePath> --rocksdbPath <rocksdbPath>
+  cacherz (--ethHost <ethHost> --ethPort <ethPort> | --ethUrl <ethUrl>) [--ethHeader <ethHeader>]... --webHost <webHost> --webPort <webPort> (--abiFilePath <abiFilePath> | --configFilePath <configFilePath>) --rocksdbPath <rocksdbPath> [options]
+  cacherz reindex (--ethHost <ethHost> --ethPort <ethPort> | --ethUrl <ethUrl>) [--ethHeader <ethHeader>]... (--abiFilePath <abiFilePath> | --configFilePath <configFilePath>) --rocksdbPath <rocksdbPath> --event <event> --from <from> --to <to> [options]
+  cacherz migrate --rocksdbPath <rocksdbPath> [--abiFilePath <abiFilePath> | --configFilePath <configFilePath>] [--valueFormat <valueFormat>] [--prefixSize <prefixSize>]
+  cacherz backup --rocksdbPath <rocksdbPath> --backupPath <backupPath> [--configFilePath <configFilePath>] [--prefixSize <prefixSize>]
+  cacherz restore --backupPath <backupPath> --rocksdbPath <rocksdbPath> [--configFilePath <configFilePath>] [--prefixSize <prefixSize>]
+  cacherz serve --webHost <webHost> --webPort <webPort> --rocksdbPath <rocksdbPath> [options]
+  cacherz export --rocksdbPath <rocksdbPath> (--abiFilePath <abiFilePath> | --configFilePath <configFilePath>) [--contract <contract>] [--event <event>] --format <format> [--output <output>] [--from <from>] [--to <to>] [--prefixSize <prefixSize>]
+  cacherz import (--abiFilePath <abiFilePath> | --configFilePath <configFilePath>) --rocksdbPath <rocksdbPath> [--input <input>] [options]
+  cacherz compact --rocksdbPath <rocksdbPath> --columnFamily <columnFamily> [--fromKey <fromKey>] [--toKey <toKey>] [--configFilePath <configFilePath>] [--prefixSize <prefixSize>]
   cacherz --version
 
   Options:
@@ -25,22 +56,386 @@ const USAGE: &'static str = "
   --version     Show version.
   --ethHost=<ethHost>     Host of the ethereum node.
   --ethPort=<ethPort>     Port of the ethereum node.
+  --ethUrl=<ethUrl>       Full url of the ethereum node json-rpc endpoint (ex. https://node.example.com/v3/key).
+  --ethHeader=<ethHeader>     Header added to every json-rpc request, given as \"Name: value\".
+  --ethCredentialsFile=<ethCredentialsFile>     Json file with user, password, bearerToken and headers of the ethereum node.
+  --ethCaBundle=<ethCaBundle>     Pem file with additional root certificates of the ethereum node.
   --webHost=<webHost>     Host of the web service.
   --webPort=<webPort>     Port of the web service.
   --abiFilePath=<abiFilePath>     Full path to abi file.
   --rocksdbPath=<rocksdbPath>     Full path to rocksdb main folder.
   --prefixSize=<prefixSize>       Size of a prefix
+  --configFilePath=<configFilePath>     Full path to json config file with contracts definitions.
+  --startBlock=<startBlock>       Block from which events are indexed.
+  --endBlock=<endBlock>           Block after which events are not indexed.
+  --contract=<contract>           Name of the contract to reindex or export.
+  --event=<event>                 Name of the event to reindex or export.
+  --from=<from>                   First block of reindexed or exported range.
+  --to=<to>                       Last block of reindexed or exported range.
+  --syncWrites                    Fsync every batch of events before polling for next one.
+  --indexTxSender                 Add transaction sender of every log into address index.
+  --globalTimeline                Keep timeline of events across all contracts.
+  --columnFamily=<columnFamily>   Name of the column family to compact.
+  --fromKey=<fromKey>             First key of compacted range as hex.
+  --toKey=<toKey>                 Last key of compacted range as hex.
+  --backupPath=<backupPath>       Directory of the backup. It must not exist when backup is created.
+  --adminHost=<adminHost>         Host of the admin api. 127.0.0.1 by default.
+  --adminPort=<adminPort>         Port of the admin api (compaction and backups). Needs token in CACHERZ_ADMIN_TOKEN.
+  --backupDir=<backupDir>         Directory in which admin api creates backups.
+  --valueFormat=<valueFormat>     Converts stored events into json or binary format after migration.
+  --format=<format>               Format of exported events: csv, ndjson or parquet.
+  --output=<output>               File of exported events. Events are written to stdout by default.
+  --input=<input>                 NDJSON file of imported events. Events are read from stdin by default.
+  --catchUpSecs=<catchUpSecs>     Interval in which serve-only instance reopens the database to see new writes. 5 s by default.
+  --queryScriptsPath=<queryScriptsPath>     Directory of lua query scripts served at /q/<name>.
+  --queryTimeoutMs=<queryTimeoutMs>         Time budget of a single query script run. 1000 ms by default.
+  --queryMaxRows=<queryMaxRows>             Number of rows a single query script run can read. 10000 by default.
+  --queryMemoryLimitKb=<queryMemoryLimitKb> Memory of a single query script run. 16384 KB by default.
 ";
 
+const COLUMN_FAMILIES: [&'static str; 9] = ["events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline"];
+
 #[derive(Debug,Deserialize)]
 struct Args {
   flag_ethHost: String,
   flag_ethPort: String,
+  flag_ethUrl: Option<String>,
+  flag_ethHeader: Vec<String>,
+  flag_ethCredentialsFile: Option<String>,
+  flag_ethCaBundle: Option<String>,
   flag_webHost: String,
   flag_webPort: String,
-  flag_abiFilePath: String,
+  flag_abiFilePath: Option<String>,
   flag_rocksdbPath: String,
-  flag_prefixSize: Option<usize>
+  flag_prefixSize: Option<usize>,
+  flag_configFilePath: Option<String>,
+  flag_startBlock: Option<u64>,
+  flag_endBlock: Option<u64>,
+  flag_contract: Option<String>,
+  flag_event: Option<String>,
+  flag_from: Option<u64>,
+  flag_to: Option<u64>,
+  flag_syncWrites: bool,
+  flag_indexTxSender: bool,
+  flag_globalTimeline: bool,
+  flag_columnFamily: Option<String>,
+  flag_fromKey: Option<String>,
+  flag_toKey: Option<String>,
+  flag_backupPath: Option<String>,
+  flag_adminHost: Option<String>,
+  flag_adminPort: Option<String>,
+  flag_backupDir: Option<String>,
+  flag_valueFormat: Option<String>,
+  flag_format: Option<String>,
+  flag_output: Option<String>,
+  flag_input: Option<String>,
+  flag_catchUpSecs: Option<u64>,
+  flag_queryScriptsPath: Option<String>,
+  flag_queryTimeoutMs: Option<u64>,
+  flag_queryMaxRows: Option<usize>,
+  flag_queryMemoryLimitKb: Option<u64>,
+  cmd_reindex: bool,
+  cmd_migrate: bool,
+  cmd_compact: bool,
+  cmd_backup: bool,
+  cmd_restore: bool,
+  cmd_serve: bool,
+  cmd_export: bool,
+  cmd_import: bool
+}
+
+/// Builds ethereum node endpoint from url or host and port. Credentials are read from env and credentials file.
+fn get_endpoint(args: &Args) -> Result<Endpoint, String> {
+  let mut endpoint = match args.flag_ethUrl.clone() {
+    Some(url) => Endpoint::from_url(url),
+    None => Endpoint::from_host_and_port(args.flag_ethHost.clone(), args.flag_ethPort.clone())
+  };
+  endpoint = endpoint
+    .with_credentials_from_env()
+    .with_ca_bundle(args.flag_ethCaBundle.clone())?;
+  if let Some(credentials_file_path) = args.flag_ethCredentialsFile.clone() {
+    endpoint = endpoint.with_credentials_from_file(credentials_file_path)?;
+  }
+  for header in args.flag_ethHeader.iter() {
+    endpoint = endpoint.with_header(header)?;
+  }
+  Ok(endpoint)
+}
+
+/// Builds contracts configuration either from config file or from single abi file flags
+fn get_contracts_config(args: &Args) -> Result<CacherzConfig, String> {
+  match (args.flag_configFilePath.clone(), args.flag_abiFilePath.clone()) {
+    (Some(config_file_path), _) => config_loader::get_config(config_file_path),
+    (None, Some(abi_file_path)) => {
+      let contract_name = Path::new(&abi_file_path).file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("default")
+        .to_string();
+      Ok(CacherzConfig{global_timeline: args.flag_globalTimeline, storage: StorageConfig::default(), contracts: vec![ContractConfig{
+        name: contract_name,
+        abi_file_path: abi_file_path,
+        address: None,
+        start_block: args.flag_startBlock,
+        end_block: args.flag_endBlock,
+        index_tx_sender: args.flag_indexTxSender,
+        retention: None,
+        events: HashMap::new()
+      }]})
+    },
+    (None, None) => Err(String::from("There is no abiFilePath or configFilePath"))
+  }
+}
+
+/// Storage options of config file. Commands opening the database for writing use them, so column families
+/// are opened with the options they were created with.
+fn get_storage_config(args: &Args) -> Result<StorageConfig, String> {
+  match args.flag_configFilePath.clone() {
+    Some(config_file_path) => Ok(config_loader::get_config(config_file_path)?.storage),
+    None => Ok(StorageConfig::default())
+  }
+}
+
+/// Contract of every configured event name, used to namespace keys stored before contracts were part of them.
+/// Event of more contracts is given to the first one, events of other contracts have to be reindexed.
+fn get_event_contracts(args: &Args) -> Result<HashMap<String, String>, String> {
+  let mut contracts: HashMap<String, String> = HashMap::new();
+  if args.flag_configFilePath.is_none() && args.flag_abiFilePath.is_none() {
+    return Ok(contracts);
+  }
+  for contract_config in get_contracts_config(args)?.contracts.iter() {
+    let eth_contract = eth_contract_loader::get_abi(contract_config.abi_file_path.clone())?;
+    // Qualified names are written into keys, so they have to fit before anything is migrated
+    let contract_events: Vec<ethabi::Event> = eth_contract.events.values().cloned().collect();
+    contract_config.validate_names(&contract_events)?;
+    for event_name in eth_contract.events.keys() {
+      match contracts.get(event_name).cloned() {
+        Some(contract) => warn!("Event {} is in contracts {} and {}. Stored events are given to {}, events of {} have to be reindexed",
+          event_name, contract, contract_config.name, contract, contract_config.name),
+        None => { contracts.insert(event_name.clone(), contract_config.name.clone()); }
+      };
+    }
+  }
+  Ok(contracts)
+}
+
+/// Events of every contract in config which can be exported
+fn get_export_sources(contracts_config: &CacherzConfig) -> Result<Vec<ExportSource>, String> {
+  let mut export_sources: Vec<ExportSource> = Vec::new();
+  for contract_config in contracts_config.contracts.iter() {
+    let eth_contract = eth_contract_loader::get_abi(contract_config.abi_file_path.clone())?;
+    for event in eth_contract.events.values() {
+      export_sources.push(ExportSource{contract: contract_config.name.clone(), event: event.clone(),
+        index_tx_sender: contract_config.index_tx_sender});
+    }
+  }
+  Ok(export_sources)
+}
+
+/// Location and budgets of lua query scripts when they are enabled
+fn get_query_scripts(args: &Args) -> Option<QueryScripts> {
+  args.flag_queryScriptsPath.clone().map(|query_scripts_path| {
+    QueryScripts::new(query_scripts_path)
+      .with_timeout_ms(args.flag_queryTimeoutMs)
+      .with_max_rows(args.flag_queryMaxRows)
+      .with_memory_limit_kb(args.flag_queryMemoryLimitKb)
+  })
+}
+
+/// Admin api when it is enabled by its port
+fn get_admin_config(args: &Args) -> Result<Option<AdminConfig>, String> {
+  match args.flag_adminPort.clone() {
+    Some(admin_port) => {
+      let admin_host = args.flag_adminHost.clone().unwrap_or(String::from("127.0.0.1"));
+      AdminConfig::new(admin_host, admin_port, args.flag_backupDir.clone()).map(Some)
+    },
+    None => Ok(None)
+  }
+}
+
+/// Serves web api of a database written by another cacherz process on the same host.
+/// Database is opened read-only and reopened every `--catchUpSecs` to see new writes.
+fn run_serve(args: &Args) -> Result<(), String> {
+  // Storage tuning (ex. prefix extractors) has to match the primary
+  let (storage_config, export_sources) = match args.flag_configFilePath.clone() {
+    Some(config_file_path) => {
+      let contracts_config = config_loader::get_config(config_file_path)?;
+      let export_sources = get_export_sources(&contracts_config)?;
+      (contracts_config.storage, export_sources)
+    },
+    None => (StorageConfig::default(), Vec::new())
+  };
+  let mut settings: HashMap<String, Settings> = HashMap::new();
+  settings.insert("serve_only".to_string(), Settings::Bool(true));
+  settings.insert("catch_up_secs".to_string(), Settings::U64(args.flag_catchUpSecs.unwrap_or(5)));
+  settings.insert("webPort".to_string(), Settings::PureString(args.flag_webPort.clone()));
+  settings.insert("webHost".to_string(), Settings::PureString(args.flag_webHost.clone()));
+  settings.insert("db_path".to_string(), Settings::PureString(args.flag_rocksdbPath.clone()));
+  settings.insert("prefix".to_string(), Settings::USize(args.flag_prefixSize.unwrap_or(30)));
+  settings.insert("storage".to_string(), Settings::Storage(storage_config));
+  settings.insert("export_sources".to_string(), Settings::ExportSources(export_sources));
+  if let Some(query_scripts) = get_query_scripts(args) {
+    settings.insert("query_scripts".to_string(), Settings::QueryScripts(query_scripts));
+  }
+  if let Some(admin_config) = get_admin_config(args)? {
+    settings.insert("admin".to_string(), Settings::Admin(admin_config));
+  }
+  let m_actor: MainActor = MainActor{system_name: "EventServer".to_string(), eth_actors: Vec::new(), write_actor: None, read_actor: None, settings: Some(settings), addr: None, db: None};
+  m_actor.run();
+  Ok(())
+}
+
+/// Rebuilds a block range of a single event. Should be run when cacherz service is stopped.
+fn run_reindex(args: &Args) -> Result<usize, String> {
+  let contracts_config = get_contracts_config(args)?;
+  let contract_config = match args.flag_contract.clone() {
+    Some(contract_name) => contracts_config.contracts.into_iter()
+      .find(|contract| contract.name == contract_name)
+      .ok_or(format!("There is no contract {} in config", contract_name))?,
+    None => contracts_config.contracts.into_iter().next()
+      .ok_or(String::from("There is no contract in config"))?
+  };
+  let event_name = args.flag_event.clone().ok_or(String::from("There is no event to reindex"))?;
+  let eth_contract = eth_contract_loader::get_abi(contract_config.abi_file_path.clone())?;
+  let event = eth_contract.events.get(&event_name).cloned()
+    .ok_or(format!("There is no event {} in contract {}", event_name, contract_config.name))?;
+  // Reindex writes decoded logs, so logs of a transformed event would be stored without their transform
+  if contract_config.get_transform(&event_name).is_some() {
+    return Err(format!("Event {} of contract {} has a transform script and cannot be reindexed", event_name, contract_config.name));
+  }
+  let from_block = args.flag_from.ok_or(String::from("There is no from block"))?;
+  let to_block = args.flag_to.ok_or(String::from("There is no to block"))?;
+  let db = CacheDB::create_with_config(args.flag_rocksdbPath.clone(), COLUMN_FAMILIES.to_vec(), args.flag_prefixSize.unwrap_or(30), contracts_config.storage.clone())?;
+  check_schema_version(&db)?;
+  let endpoint = get_endpoint(args)?;
+  let event_indexes = EventIndexes::for_event(contract_config.name.clone(), &event,
+    contract_config.get_indexes(&event_name), contract_config.index_tx_sender)
+    .with_global_timeline(contracts_config.global_timeline)
+    .with_aggregations(contract_config.get_aggregations(&event_name));
+  // Aggregations changed in config are rebuilt first, so reindexed events are subtracted from what was added
+  check_aggregations(&db, &qualified_name(&contract_config.name, &event_name), &event_indexes.aggregations)?;
+  reindex::reindex(db, event, endpoint, contract_config.address.clone(), event_indexes, from_block, to_block)
+}
+
+/// Writes events of an event or a contract into file or stdout. Database is opened read-only, so it can run next to cacherz service.
+fn run_export(args: &Args) -> Result<u64, String> {
+  let contracts_config = get_contracts_config(args)?;
+  let export_sources = get_export_sources(&contracts_config)?;
+  let sources = export::select_sources(&export_sources, args.flag_contract.as_ref().map(|contract| contract.as_str()), args.flag_event.as_ref().map(|event| event.as_str()))?;
+  let format = export::parse_export_format(&args.flag_format.clone().ok_or(String::from("There is no export format"))?)?;
+  let db = CacheDB::open_read_only(args.flag_rocksdbPath.clone(), args.flag_prefixSize.unwrap_or(30), contracts_config.storage.clone())?;
+  verify_schema_version(&db)?;
+  match args.flag_output.clone() {
+    Some(output_path) => {
+      let file = File::create(&output_path).map_err(|err| format!("Can not create {}. Error: {}", output_path, err))?;
+      export::export_events(&db, &sources, args.flag_from, args.flag_to, format, BufWriter::new(file))
+    },
+    None => {
+      let stdout = io::stdout();
+      let exported = export::export_events(&db, &sources, args.flag_from, args.flag_to, format, BufWriter::new(stdout.lock()))?;
+      io::stdout().flush().map_err(|err| err.to_string())?;
+      Ok(exported)
+    }
+  }
+}
+
+/// Loads events from NDJSON dump with indexes and aggregations. Should be run when cacherz service is stopped.
+fn run_import(args: &Args) -> Result<ImportSummary, String> {
+  let contracts_config = get_contracts_config(args)?;
+  let db = CacheDB::create_with_config(args.flag_rocksdbPath.clone(), COLUMN_FAMILIES.to_vec(), args.flag_prefixSize.unwrap_or(30), contracts_config.storage.clone())?;
+  check_schema_version(&db)?;
+  let mut targets: Vec<ImportTarget> = Vec::new();
+  for contract_config in contracts_config.contracts.iter() {
+    let eth_contract = eth_contract_loader::get_abi(contract_config.abi_file_path.clone())?;
+    for (event_name, event) in eth_contract.events.iter() {
+      let event_indexes = EventIndexes::for_event(contract_config.name.clone(), event,
+        contract_config.get_indexes(event_name), contract_config.index_tx_sender)
+        .with_global_timeline(contracts_config.global_timeline)
+        .with_aggregations(contract_config.get_aggregations(event_name));
+      // Aggregations changed in config are rebuilt first, so imported events are added to what is stored
+      check_aggregations(&db, &qualified_name(&contract_config.name, event_name), &event_indexes.aggregations)?;
+      targets.push(ImportTarget{contract: contract_config.name.clone(), event: event.clone(), event_indexes: event_indexes});
+    }
+  }
+  match args.flag_input.clone() {
+    Some(input_path) => {
+      let file = File::open(&input_path).map_err(|err| format!("Can not open {}. Error: {}", input_path, err))?;
+      import::import(&db, &targets, BufReader::new(file), IMPORT_BATCH_SIZE)
+    },
+    None => {
+      let stdin = io::stdin();
+      import::import(&db, &targets, stdin.lock(), IMPORT_BATCH_SIZE)
+    }
+  }
+}
+
+/// Compacts a column family or its key range. Should be run when cacherz service is stopped.
+fn run_compact(args: &Args) -> Result<CompactionStatus, String> {
+  let storage_config = get_storage_config(args)?;
+  let column_family = args.flag_columnFamily.clone().ok_or(String::from("There is no column family to compact"))?;
+  let from = compaction::parse_key_bound(args.flag_fromKey.as_ref())?;
+  let to = compaction::parse_key_bound(args.flag_toKey.as_ref())?;
+  let db = CacheDB::create_with_config(args.flag_rocksdbPath.clone(), COLUMN_FAMILIES.to_vec(), args.flag_prefixSize.unwrap_or(30), storage_config)?;
+  check_schema_version(&db)?;
+  compaction::compact(&db, &column_family, from.as_ref().map(|key| key.as_slice()), to.as_ref().map(|key| key.as_slice()), "manual")
+}
+
+/// Creates backup of a database. Running service is backed up with `/admin/backup` endpoint of admin api instead.
+fn run_backup(args: &Args) -> Result<BackupManifest, String> {
+  let backup_path = args.flag_backupPath.clone().ok_or(String::from("There is no backup path"))?;
+  let db = CacheDB::create_with_config(args.flag_rocksdbPath.clone(), COLUMN_FAMILIES.to_vec(), args.flag_prefixSize.unwrap_or(30), get_storage_config(args)?)?;
+  check_schema_version(&db)?;
+  backup::create_backup(&db, &backup_path)
+}
+
+fn run_restore(args: &Args) -> Result<BackupManifest, String> {
+  let backup_path = args.flag_backupPath.clone().ok_or(String::from("There is no backup path"))?;
+  backup::restore_backup(&backup_path, &args.flag_rocksdbPath, COLUMN_FAMILIES.to_vec(), args.flag_prefixSize.unwrap_or(30), get_storage_config(args)?)
+}
+
+/// Validates configuration of every contract and creates actors of their events
+fn get_eth_actors(contracts_config: &CacherzConfig, eth_actor_settings: &HashMap<String, Settings>, head_block: &Result<u64, String>) -> Result<Vec<EthActor>, String> {
+  let mut eth_actors : Vec<EthActor> = Vec::new();
+  let global_timeline = contracts_config.global_timeline;
+  for contract_config in contracts_config.contracts.iter() {
+    let eth_contract = eth_contract_loader::get_abi(contract_config.abi_file_path.clone())
+      .map_err(|err| format!("Can not get abi from: {}. Error: {}", contract_config.abi_file_path, err))?;
+    let event_names: Vec<String> = eth_contract.events.keys().cloned().collect();
+    match *head_block {
+      Ok(head) => contract_config.validate_block_ranges(event_names, head)?,
+      Err(ref err) => warn!("Cannot validate block ranges of contract {}. Error: {}", contract_config.name, err)
+    };
+    let contract_events: Vec<ethabi::Event> = eth_contract.events.values().cloned().collect();
+    contract_config.validate_names(&contract_events)?;
+    contract_config.validate_indexes(&contract_events)?;
+    contract_config.validate_aggregations(&contract_events)?;
+    for event in eth_contract.events {
+      let (start_block, end_block) = contract_config.get_block_range(&event.0);
+      let mut event_settings = eth_actor_settings.clone();
+      event_settings.insert("contract".to_string(), Settings::PureString(contract_config.name.clone()));
+      event_settings.insert("start_block".to_string(), Settings::U64(start_block));
+      if let Some(end) = end_block {
+        event_settings.insert("end_block".to_string(), Settings::U64(end));
+      }
+      if let Some(address) = contract_config.address.clone() {
+        event_settings.insert("contract_address".to_string(), Settings::PureString(address));
+      }
+      let event_indexes = EventIndexes::for_event(contract_config.name.clone(), &event.1,
+        contract_config.get_indexes(&event.0), contract_config.index_tx_sender)
+        .with_global_timeline(global_timeline)
+        .with_aggregations(contract_config.get_aggregations(&event.0));
+      event_settings.insert("event_indexes".to_string(), Settings::EventIndexes(event_indexes));
+      if let Some(retention) = contract_config.get_retention(&event.0) {
+        event_settings.insert("retention".to_string(), Settings::Retention(retention));
+      }
+      if let Some(transform) = contract_config.get_transform(&event.0) {
+        // Broken script stops startup. Later changes are reloaded only when the new script loads.
+        check_script(&transform)?;
+        event_settings.insert("transform".to_string(), Settings::Transform(transform));
+      }
+      eth_actors.push(EthActor::create_new(event.1, event_settings));
+    }
+  }
+  Ok(eth_actors)
 }
 
 fn main() {
@@ -48,24 +443,122 @@ fn main() {
   let args: Args = Docopt::new(USAGE)
     .and_then(|d| d.deserialize())
     .unwrap_or_else(|e| e.exit());
-  let mut eth_actor_settings: HashMap<String, Settings> = HashMap::new();
-  eth_actor_settings.insert("host".to_string(), Settings::PureString(args.flag_ethHost.clone()));
-  eth_actor_settings.insert("port".to_string(), Settings::PureString(args.flag_ethPort.clone()));
-  let file_path = String::from(args.flag_abiFilePath);
-  let eth_contract = eth_contract_loader::get_abi(file_path.clone())
-    .expect(&format!("Can not get abi from: {}", file_path));
-  let mut eth_actors : Vec<EthActor> = Vec::new();
-  for event in eth_contract.events {
-    eth_actors.push(EthActor::create_new(event.1, eth_actor_settings.clone()));
+  if args.cmd_reindex {
+    match run_reindex(&args) {
+      Ok(reindexed) => info!("Reindex has finished. {} events have been written", reindexed),
+      Err(err) => {
+        error!("Reindex has failed. Error: {}", err);
+        std::process::exit(1);
+      }
+    };
+    return;
+  }
+  if args.cmd_migrate {
+    let migrate_result = get_storage_config(&args)
+      .and_then(|storage_config| CacheDB::create_with_config(args.flag_rocksdbPath.clone(), COLUMN_FAMILIES.to_vec(), args.flag_prefixSize.unwrap_or(30), storage_config))
+      .and_then(|db| get_event_contracts(&args).and_then(|contracts| migrate::migrate(db.clone(), contracts)).map(|version| (db, version)))
+      .and_then(|(db, version)| match args.flag_valueFormat.clone() {
+        Some(value_format) => value_codec::parse_format(&value_format)
+          .and_then(|format| migrate_values::convert_values(&db, format))
+          .map(|converted| {
+            info!("{} event values have been converted into {}", converted, value_format);
+            version
+          }),
+        None => Ok(version)
+      });
+    match migrate_result {
+      Ok(version) => info!("Migration has finished. Database schema version is {}", version),
+      Err(err) => {
+        error!("Migration has failed. Error: {}", err);
+        std::process::exit(1);
+      }
+    };
+    return;
+  }
+  if args.cmd_backup || args.cmd_restore {
+    let (command, result) = if args.cmd_backup { ("Backup", run_backup(&args)) } else { ("Restore", run_restore(&args)) };
+    match result {
+      Ok(manifest) => info!("{} has finished. Schema version: {}, chain id: {:?}, cursors: {:?}", command, manifest.schema_version, manifest.chain_id, manifest.cursors),
+      Err(err) => {
+        error!("{} has failed. Error: {}", command, err);
+        std::process::exit(1);
+      }
+    };
+    return;
+  }
+  if args.cmd_serve {
+    if let Err(err) = run_serve(&args) {
+      error!("Serve-only instance has failed. Error: {}", err);
+      std::process::exit(1);
+    }
+    return;
+  }
+  if args.cmd_export {
+    match run_export(&args) {
+      Ok(exported) => info!("Export has finished. {} events have been written", exported),
+      Err(err) => {
+        error!("Export has failed. Error: {}", err);
+        std::process::exit(1);
+      }
+    };
+    return;
+  }
+  if args.cmd_import {
+    match run_import(&args) {
+      Ok(summary) => info!("Import has finished. {} events have been written. Cursors: {:?}", summary.events, summary.cursors),
+      Err(err) => {
+        error!("Import has failed. Error: {}", err);
+        std::process::exit(1);
+      }
+    };
+    return;
   }
+  if args.cmd_compact {
+    match run_compact(&args) {
+      Ok(compaction_status) => info!("Compaction has finished. Stats before: {:?}, after: {:?}", compaction_status.before, compaction_status.after),
+      Err(err) => {
+        error!("Compaction has failed. Error: {}", err);
+        std::process::exit(1);
+      }
+    };
+    return;
+  }
+  let endpoint = match get_endpoint(&args) {
+    Ok(endpoint) => endpoint,
+    Err(err) => {
+      error!("Can not get ethereum node endpoint. Error: {}", err);
+      std::process::exit(1);
+    }
+  };
+  let mut eth_actor_settings: HashMap<String, Settings> = HashMap::new();
+  eth_actor_settings.insert("endpoint".to_string(), Settings::Endpoint(endpoint.clone()));
+  eth_actor_settings.insert("sync_writes".to_string(), Settings::Bool(args.flag_syncWrites));
+  let contracts_config = match get_contracts_config(&args) {
+    Ok(contracts_config) => contracts_config,
+    Err(err) => {
+      error!("Can not get contracts configuration. Error: {}", err);
+      std::process::exit(1);
+    }
+  };
+  let head_block = eth_json_rpc::get_block_number(endpoint.clone(), 0);
+  let chain_id = eth_json_rpc::get_chain_id(endpoint.clone(), 0);
+  let storage_config = contracts_config.storage.clone();
+  let (eth_actors, export_sources) = match get_eth_actors(&contracts_config, &eth_actor_settings, &head_block)
+    .and_then(|eth_actors| get_export_sources(&contracts_config).map(|export_sources| (eth_actors, export_sources))) {
+    Ok(actors_and_sources) => actors_and_sources,
+    Err(err) => {
+      error!("Configuration of contracts is not valid. Error: {}", err);
+      std::process::exit(1);
+    }
+  };
   let mut settings: HashMap<String, Settings> = HashMap::new();
 
-  let settings_column_families: Vec<&'static str> = vec!("events", "aggregations", "stats", "settings", "filters", "log");
+  let settings_column_families: Vec<&'static str> = COLUMN_FAMILIES.to_vec();
   let settings_host: String = args.flag_ethHost.clone();
   let settings_port: String = args.flag_ethPort.clone();
   let settings_webPort: String = args.flag_webPort.clone();
   let settings_webHost: String = args.flag_webHost.clone();
-  let settings_db_path: String = args.flag_rocksdbPath;
+  let settings_db_path: String = args.flag_rocksdbPath.clone();
   let settings_prefix: Option<usize> = args.flag_prefixSize;
 
   let prefix = match settings_prefix {
@@ -76,10 +569,28 @@ fn main() {
   settings.insert("column_families".to_string(), Settings::VecStr(settings_column_families));
   settings.insert("host".to_string(), Settings::PureString(settings_host));
   settings.insert("port".to_string(), Settings::PureString(settings_port));
+  settings.insert("endpoint".to_string(), Settings::Endpoint(endpoint));
   settings.insert("webPort".to_string(), Settings::PureString(settings_webPort));
   settings.insert("webHost".to_string(), Settings::PureString(settings_webHost));
   settings.insert("db_path".to_string(), Settings::PureString(settings_db_path));
   settings.insert("prefix".to_string(), prefix);
+  settings.insert("storage".to_string(), Settings::Storage(storage_config));
+  settings.insert("export_sources".to_string(), Settings::ExportSources(export_sources));
+  if let Some(query_scripts) = get_query_scripts(&args) {
+    settings.insert("query_scripts".to_string(), Settings::QueryScripts(query_scripts));
+  }
+  match get_admin_config(&args) {
+    Ok(Some(admin_config)) => { settings.insert("admin".to_string(), Settings::Admin(admin_config)); },
+    Ok(None) => (),
+    Err(err) => {
+      error!("Admin api cannot be started. Error: {}", err);
+      std::process::exit(1);
+    }
+  };
+  match chain_id {
+    Ok(node_chain_id) => { settings.insert("chain_id".to_string(), Settings::U64(node_chain_id)); },
+    Err(err) => warn!("Cannot get chain id of the node. Database is not checked against it. Error: {}", err)
+  };
   let m_actor: MainActor = MainActor{system_name: "EventStreamer".to_string(), eth_actors: eth_actors, write_actor: None, read_actor: None, settings: Some(settings), addr: None, db: None};
   m_actor.run();
 }
\ No newline at end of file
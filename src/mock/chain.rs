@@ -0,0 +1,335 @@
+/// # Module Chain
+/// Scripted chain served by mock ethereum node.
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use tiny_keccak::keccak256;
+use eth::eth_json_rpc::parse_hex_u64;
+
+/// Log emitted in a mock block. Block number, hashes and indexes are assigned by the chain.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MockLog {
+  pub address: String,
+  pub topics: Vec<String>,
+  pub data: String
+}
+
+#[derive(Debug, Clone)]
+pub struct MockBlock {
+  pub number: u64,
+  pub hash: String,
+  pub parent_hash: String,
+  pub timestamp: u64,
+  pub logs: Vec<MockLog>
+}
+
+/// Single step of the script. One step is executed per mock node tick.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum MockStep {
+  Block {
+    #[serde(default)]
+    logs: Vec<MockLog>
+  },
+  Reorg {
+    depth: u64,
+    blocks: Vec<Vec<MockLog>>
+  },
+  ExpireFilters,
+  Error {
+    method: String,
+    count: u32,
+    code: i64,
+    message: String
+  }
+}
+
+/// Script of the chain read by mock node binary
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MockScript {
+  #[serde(default)]
+  pub genesis_timestamp: u64,
+  #[serde(default)]
+  pub block_time: u64,              // Seconds between blocks timestamps and between steps
+  #[serde(default)]
+  pub chain_id: u64,                // Returned by eth_chainId, 1337 when not set
+  #[serde(default)]
+  pub blocks: Vec<Vec<MockLog>>,    // Blocks mined on start
+  #[serde(default)]
+  pub steps: Vec<MockStep>          // Steps executed one by one
+}
+
+#[derive(Debug, Clone)]
+struct MockFilter {
+  from_block: u64,
+  to_block: Option<u64>,
+  address: Option<String>,
+  topics: Vec<Value>,
+  next_block: u64,            // First block not delivered yet by eth_getFilterChanges
+  pending_removed: Vec<Value> // Delivered logs removed by reorg
+}
+
+#[derive(Debug, Clone)]
+struct MockError {
+  method: String,
+  count: u32,
+  code: i64,
+  message: String
+}
+
+#[derive(Debug, Clone)]
+pub struct MockChain {
+  pub blocks: Vec<MockBlock>,
+  steps: VecDeque<MockStep>,
+  filters: HashMap<String, MockFilter>,
+  errors: Vec<MockError>,
+  next_filter_id: u64,
+  reorg_count: u64,
+  genesis_timestamp: u64,
+  block_time: u64,
+  chain_id: u64
+}
+
+fn mock_hash(seed: String) -> String {
+  let hash = keccak256(seed.as_bytes());
+  let hex: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+  format!("0x{}", hex)
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+  json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+fn result_response(id: Value, result: Value) -> Value {
+  json!({"jsonrpc": "2.0", "id": id, "result": result})
+}
+
+impl MockChain {
+  /// Creates chain with genesis block only
+  pub fn new() -> MockChain {
+    MockChain::from_script(MockScript::default())
+  }
+
+  pub fn from_script(script: MockScript) -> MockChain {
+    let mut chain = MockChain{blocks: Vec::new(), steps: script.steps.into_iter().collect(), filters: HashMap::new(),
+      errors: Vec::new(), next_filter_id: 1, reorg_count: 0, genesis_timestamp: script.genesis_timestamp,
+      block_time: if script.block_time == 0 { 15 } else { script.block_time },
+      chain_id: if script.chain_id == 0 { 1337 } else { script.chain_id }};
+    chain.mine_block(Vec::new());
+    for logs in script.blocks {
+      chain.mine_block(logs);
+    }
+    chain
+  }
+
+  pub fn head(&self) -> u64 {
+    (self.blocks.len() as u64) - 1
+  }
+
+  pub fn mine_block(&mut self, logs: Vec<MockLog>) -> u64 {
+    let number = self.blocks.len() as u64;
+    let parent_hash = match self.blocks.last() {
+      Some(parent) => parent.hash.clone(),
+      None => format!("0x{}", "0".repeat(64))
+    };
+    let hash = mock_hash(format!("block-{}-{}", number, self.reorg_count));
+    let timestamp = self.genesis_timestamp + number * self.block_time;
+    self.blocks.push(MockBlock{number: number, hash: hash, parent_hash: parent_hash, timestamp: timestamp, logs: logs});
+    number
+  }
+
+  /// Replaces last `depth` blocks with new ones. Logs already delivered by filters are reported as removed.
+  pub fn reorg(&mut self, depth: u64, blocks: Vec<Vec<MockLog>>) {
+    let fork_block = if depth > self.head() { 1 } else { self.head() + 1 - depth };
+    let removed_blocks: Vec<MockBlock> = self.blocks.split_off(fork_block as usize);
+    for filter in self.filters.values_mut() {
+      let next_block = filter.next_block;
+      for block in removed_blocks.iter().filter(|block| block.number < next_block) {
+        for (log_index, log) in block.logs.iter().enumerate() {
+          if Self::matches_filter(filter, block.number, log) {
+            filter.pending_removed.push(Self::log_to_json(block, log_index, log, true));
+          }
+        }
+      }
+      if filter.next_block > fork_block {
+        filter.next_block = fork_block;
+      }
+    }
+    self.reorg_count = self.reorg_count + 1;
+    for logs in blocks {
+      self.mine_block(logs);
+    }
+  }
+
+  /// Drops all filters, so eth_getFilterChanges returns "filter not found"
+  pub fn expire_filters(&mut self) {
+    self.filters.clear();
+  }
+
+  /// Next `count` calls of the method return given json-rpc error
+  pub fn inject_error(&mut self, method: String, count: u32, code: i64, message: String) {
+    self.errors.push(MockError{method: method, count: count, code: code, message: message});
+  }
+
+  /// Executes next scripted step. Returns false when there are no more steps.
+  pub fn step(&mut self) -> bool {
+    match self.steps.pop_front() {
+      Some(MockStep::Block{logs}) => { self.mine_block(logs); },
+      Some(MockStep::Reorg{depth, blocks}) => self.reorg(depth, blocks),
+      Some(MockStep::ExpireFilters) => self.expire_filters(),
+      Some(MockStep::Error{method, count, code, message}) => self.inject_error(method, count, code, message),
+      None => return false
+    };
+    true
+  }
+
+  fn log_to_json(block: &MockBlock, log_index: usize, log: &MockLog, removed: bool) -> Value {
+    json!({
+      "address": log.address,
+      "topics": log.topics,
+      "data": log.data,
+      "blockNumber": format!("0x{:x}", block.number),
+      "blockHash": block.hash,
+      "transactionHash": mock_hash(format!("tx-{}-{}", block.hash, log_index)),
+      "transactionIndex": format!("0x{:x}", log_index),
+      "logIndex": format!("0x{:x}", log_index),
+      "removed": removed
+    })
+  }
+
+  fn matches_filter(filter: &MockFilter, block_number: u64, log: &MockLog) -> bool {
+    if block_number < filter.from_block {
+      return false;
+    }
+    if let Some(to_block) = filter.to_block {
+      if block_number > to_block {
+        return false;
+      }
+    }
+    if let Some(address) = filter.address.clone() {
+      if address.to_lowercase() != log.address.to_lowercase() {
+        return false;
+      }
+    }
+    filter.topics.iter().enumerate().all(|(index, topic)| {
+      match topic.as_str() {
+        Some(topic_str) => log.topics.get(index).map(|log_topic| log_topic.to_lowercase() == topic_str.to_lowercase()).unwrap_or(false),
+        None => true
+      }
+    })
+  }
+
+  fn parse_block_tag(&self, tag: &Value, default: u64) -> Result<u64, String> {
+    match tag.as_str() {
+      Some("latest") | Some("pending") => Ok(self.head()),
+      Some("earliest") => Ok(0),
+      Some(hex) => parse_hex_u64(hex),
+      None => Ok(default)
+    }
+  }
+
+  fn parse_filter(&self, params: &Value) -> Result<MockFilter, String> {
+    let filter_params = &params[0];
+    let from_block = self.parse_block_tag(&filter_params["fromBlock"], self.head())?;
+    let to_block = match filter_params["toBlock"].as_str() {
+      Some(_) => Some(self.parse_block_tag(&filter_params["toBlock"], self.head())?),
+      None => None
+    };
+    let topics = filter_params["topics"].as_array().cloned().unwrap_or(Vec::new());
+    Ok(MockFilter{from_block: from_block, to_block: to_block, address: filter_params["address"].as_str().map(|a| a.to_string()),
+      topics: topics, next_block: from_block, pending_removed: Vec::new()})
+  }
+
+  fn get_logs(&self, filter: &MockFilter, from_block: u64) -> Vec<Value> {
+    self.blocks.iter()
+      .filter(|block| block.number >= from_block)
+      .flat_map(|block| {
+        block.logs.iter().enumerate()
+          .filter(|(_, log)| Self::matches_filter(filter, block.number, log))
+          .map(|(log_index, log)| Self::log_to_json(block, log_index, log, false))
+          .collect::<Vec<Value>>()
+      })
+      .collect::<Vec<Value>>()
+  }
+
+  fn block_to_json(&self, block_number: u64) -> Value {
+    match self.blocks.get(block_number as usize) {
+      Some(block) => json!({
+        "number": format!("0x{:x}", block.number),
+        "hash": block.hash,
+        "parentHash": block.parent_hash,
+        "timestamp": format!("0x{:x}", block.timestamp),
+        "transactions": []
+      }),
+      None => Value::Null
+    }
+  }
+
+  fn take_error(&mut self, method: &str) -> Option<(i64, String)> {
+    match self.errors.iter_mut().find(|error| error.method == method && error.count > 0) {
+      Some(error) => {
+        error.count = error.count - 1;
+        Some((error.code, error.message.clone()))
+      },
+      None => None
+    }
+  }
+
+  /// Handles json-rpc request (single or batch) and returns response
+  pub fn handle(&mut self, request: &Value) -> Value {
+    if let Some(requests) = request.as_array() {
+      return Value::Array(requests.iter().map(|r| self.handle(r)).collect());
+    }
+    let id = request["id"].clone();
+    let method = request["method"].as_str().unwrap_or("").to_string();
+    let params = request["params"].clone();
+    if let Some((code, message)) = self.take_error(&method) {
+      return error_response(id, code, message);
+    }
+    let result: Result<Value, (i64, String)> = match method.as_ref() {
+      "eth_blockNumber" => Ok(json!(format!("0x{:x}", self.head()))),
+      "eth_chainId" => Ok(json!(format!("0x{:x}", self.chain_id))),
+      "eth_getBlockByNumber" => self.parse_block_tag(&params[0], self.head())
+        .map(|block_number| self.block_to_json(block_number))
+        .map_err(|err| (-32602, err)),
+      "eth_getLogs" => self.parse_filter(&params)
+        .map(|filter| Value::Array(self.get_logs(&filter, filter.from_block)))
+        .map_err(|err| (-32602, err)),
+      "eth_newFilter" => match self.parse_filter(&params) {
+        Ok(filter) => {
+          let filter_id = format!("0x{:x}", self.next_filter_id);
+          self.next_filter_id = self.next_filter_id + 1;
+          self.filters.insert(filter_id.clone(), filter);
+          Ok(json!(filter_id))
+        },
+        Err(err) => Err((-32602, err))
+      },
+      "eth_getFilterChanges" => {
+        let filter_id = params[0].as_str().unwrap_or("").to_string();
+        match self.filters.get(&filter_id).cloned() {
+          Some(filter) => {
+            let mut changes: Vec<Value> = filter.pending_removed.clone();
+            changes.extend(self.get_logs(&filter, filter.next_block));
+            let head = self.head();
+            if let Some(_filter) = self.filters.get_mut(&filter_id) {
+              _filter.pending_removed.clear();
+              _filter.next_block = head + 1;
+            }
+            Ok(Value::Array(changes))
+          },
+          None => Err((-32000, String::from("filter not found")))
+        }
+      },
+      "eth_uninstallFilter" => {
+        let filter_id = params[0].as_str().unwrap_or("").to_string();
+        Ok(json!(self.filters.remove(&filter_id).is_some()))
+      },
+      _ => Err((-32601, format!("the method {} does not exist/is not available", method)))
+    };
+    match result {
+      Ok(value) => result_response(id, value),
+      Err((code, message)) => error_response(id, code, message)
+    }
+  }
+}
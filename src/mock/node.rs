@@ -0,0 +1,107 @@
+/// # Module Node
+/// Minimal http json-rpc server serving a scripted `MockChain`.
+use serde_json;
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use mock::chain::MockChain;
+use eth::endpoint::Endpoint;
+
+#[derive(Clone)]
+pub struct MockNode {
+  pub host: String,
+  pub port: u16,                      // Bound port. Useful when node was started on port 0
+  pub chain: Arc<Mutex<MockChain>>    // Chain shared with connection threads
+}
+
+impl MockNode {
+  /// Starts node in a background thread. Port 0 binds a free port.
+  pub fn start(host: &str, port: u16, chain: MockChain) -> Result<MockNode, String> {
+    let listener = TcpListener::bind((host, port))
+      .map_err(|err| format!("Can not bind mock node to {}:{}. Error: {}", host, port, err))?;
+    let bound_port = listener.local_addr().map_err(|err| err.to_string())?.port();
+    let chain = Arc::new(Mutex::new(chain));
+    let _chain = chain.clone();
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        match stream {
+          Ok(tcp_stream) => {
+            let connection_chain = _chain.clone();
+            thread::spawn(move || {
+              if let Err(err) = handle_connection(tcp_stream, connection_chain) {
+                warn!("Mock node connection error: {}", err);
+              }
+            });
+          },
+          Err(err) => error!("Mock node cannot accept connection. Error: {}", err)
+        }
+      }
+    });
+    info!("Mock node is listening on {}:{}", host, bound_port);
+    Ok(MockNode{host: host.to_string(), port: bound_port, chain: chain})
+  }
+
+  pub fn url(&self) -> String {
+    format!("http://{}:{}", self.host, self.port)
+  }
+
+  pub fn endpoint(&self) -> Endpoint {
+    Endpoint::from_url(self.url())
+  }
+
+  /// Runs given function on the chain, ex. to mine a block or inject an error from a test
+  pub fn with_chain<T, F: FnOnce(&mut MockChain) -> T>(&self, f: F) -> T {
+    let mut chain = self.chain.lock().expect("Mock chain lock is poisoned");
+    f(&mut chain)
+  }
+
+  /// Executes scripted steps every `step_interval` until there are no more steps
+  pub fn run_steps(&self, step_interval: Duration) {
+    loop {
+      thread::sleep(step_interval);
+      let has_more_steps = self.with_chain(|chain| chain.step());
+      if !has_more_steps {
+        info!("Mock node has executed all scripted steps");
+        break;
+      }
+    }
+  }
+}
+
+fn handle_connection(stream: TcpStream, chain: Arc<Mutex<MockChain>>) -> Result<(), String> {
+  let mut reader = BufReader::new(stream.try_clone().map_err(|err| err.to_string())?);
+  let mut content_length: usize = 0;
+  let mut request_line = String::new();
+  reader.read_line(&mut request_line).map_err(|err| err.to_string())?;
+  loop {
+    let mut header_line = String::new();
+    reader.read_line(&mut header_line).map_err(|err| err.to_string())?;
+    let header = header_line.trim();
+    if header.is_empty() {
+      break;
+    }
+    let mut header_parts = header.splitn(2, ':');
+    if let (Some(name), Some(value)) = (header_parts.next(), header_parts.next()) {
+      if name.trim().to_lowercase() == "content-length" {
+        content_length = value.trim().parse().map_err(|_| format!("Wrong content length: {}", value))?;
+      }
+    }
+  }
+  let mut body = vec![0; content_length];
+  reader.read_exact(&mut body).map_err(|err| err.to_string())?;
+  let response: Value = match serde_json::from_slice::<Value>(&body) {
+    Ok(request) => {
+      let mut _chain = chain.lock().map_err(|_| String::from("Mock chain lock is poisoned"))?;
+      _chain.handle(&request)
+    },
+    Err(err) => json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32700, "message": format!("Parse error: {}", err)}})
+  };
+  let response_body = response.to_string();
+  let mut writer = stream;
+  write!(writer, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", response_body.len(), response_body)
+    .and_then(|_| writer.flush())
+    .map_err(|err| err.to_string())
+}
@@ -0,0 +1,64 @@
+#[macro_use] extern crate serde_derive;
+extern crate lib;
+extern crate serde_json;
+#[macro_use] extern crate log;
+extern crate docopt;
+extern crate env_logger;
+
+use docopt::Docopt;
+use std::fs::File;
+use std::io::Read;
+use std::time::Duration;
+use lib::mock::chain::{MockChain, MockScript};
+use lib::mock::node::MockNode;
+
+const USAGE: &'static str = "
+  Cacherz mock ethereum node.
+
+  Usage:
+  cacherz-mock-node --host <host> --port <port> [--scriptPath <scriptPath>]
+  cacherz-mock-node --version
+
+  Options:
+  -h --help     Show this screen.
+  --version     Show version.
+  --host=<host>     Host of the mock node.
+  --port=<port>     Port of the mock node.
+  --scriptPath=<scriptPath>     Full path to json chain script.
+";
+
+#[derive(Debug,Deserialize)]
+struct Args {
+  flag_host: String,
+  flag_port: u16,
+  flag_scriptPath: Option<String>
+}
+
+fn read_script(file_path: String) -> Result<MockScript, String> {
+  let mut contents = String::new();
+  File::open(&file_path)
+    .and_then(|mut file_handler| file_handler.read_to_string(&mut contents))
+    .map_err(|err| format!("Can not read script {}. Error: {}", file_path, err))?;
+  serde_json::from_str::<MockScript>(&contents)
+    .map_err(|err| format!("Can not parse script {}. Error: {}", file_path, err))
+}
+
+fn main() {
+  env_logger::init();
+  let args: Args = Docopt::new(USAGE)
+    .and_then(|d| d.deserialize())
+    .unwrap_or_else(|e| e.exit());
+  let script = match args.flag_scriptPath {
+    Some(script_path) => read_script(script_path).expect("Can not load chain script"),
+    None => MockScript::default()
+  };
+  let step_interval = Duration::new(if script.block_time == 0 { 1 } else { script.block_time }, 0);
+  let node = MockNode::start(&args.flag_host, args.flag_port, MockChain::from_script(script))
+    .expect("Can not start mock node");
+  info!("Mock node is available at {}", node.url());
+  node.run_steps(step_interval);
+  // Node keeps serving the final chain state
+  loop {
+    std::thread::sleep(Duration::new(60, 0));
+  }
+}
@@ -0,0 +1,157 @@
+/// # Module Admin
+/// Admin api (compaction and backups) served on its own listener, apart from the public web api.
+/// Every request needs "Authorization: Bearer <token>" with the token from CACHERZ_ADMIN_TOKEN.
+/// Backups are written only into the configured backup directory.
+use actix::Addr;
+use actix_web::{http, middleware, server, App, HttpRequest, HttpResponse, Error, Query};
+use actors::compaction_actor::{CompactionActor, MsgCompact};
+use db::backup::create_backup;
+use db::cachedb::CacheDB;
+use db::compaction::{get_compaction_report, parse_key_bound};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::path::Path;
+use serde_json;
+
+/// Environment variable with the token of admin api
+pub const ENV_ADMIN_TOKEN: &'static str = "CACHERZ_ADMIN_TOKEN";
+
+/// Listener of admin api
+#[derive(Clone)]
+pub struct AdminConfig {
+  pub host: String,
+  pub port: String,
+  pub token: String,
+  pub backup_dir: Option<String>    // Backups are refused when not set
+}
+
+/// Token is never printed, because actors settings are logged
+impl fmt::Debug for AdminConfig {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("AdminConfig")
+      .field("host", &self.host)
+      .field("port", &self.port)
+      .field("backup_dir", &self.backup_dir)
+      .finish()
+  }
+}
+
+impl AdminConfig {
+  /// Admin api is enabled by its port and needs a token from CACHERZ_ADMIN_TOKEN
+  pub fn new(host: String, port: String, backup_dir: Option<String>) -> Result<AdminConfig, String> {
+    match env::var(ENV_ADMIN_TOKEN) {
+      Ok(ref token) if !token.is_empty() => Ok(AdminConfig{host: host, port: port, token: token.clone(), backup_dir: backup_dir}),
+      _ => Err(format!("Admin api needs a token in {}", ENV_ADMIN_TOKEN))
+    }
+  }
+}
+
+struct AdminActor {
+  db: CacheDB,
+  compaction_actor: Option<Addr<CompactionActor>>,   // None in serve-only instance
+  config: AdminConfig
+}
+
+fn is_authorized(req: &HttpRequest<AdminActor>) -> bool {
+  let expected = format!("Bearer {}", req.state().config.token);
+  match req.headers().get(http::header::AUTHORIZATION).and_then(|value| value.to_str().ok()) {
+    Some(authorization) => authorization.as_bytes().len() == expected.as_bytes().len()
+      // Compared without early exit, so response time does not tell how much of the token matched
+      && authorization.bytes().zip(expected.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0,
+    None => false
+  }
+}
+
+fn json_response(result: serde_json::Value) -> Result<HttpResponse, Error> {
+  Ok(HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&result).unwrap()))
+}
+
+fn unauthorized() -> Result<HttpResponse, Error> {
+  Ok(HttpResponse::Unauthorized().content_type("application/json").body(json!({"status": "error", "msg": "Unauthorized"}).to_string()))
+}
+
+/// Backup name is a single plain directory name inside backup directory
+fn get_backup_path(backup_dir: &Option<String>, name: &str) -> Result<String, String> {
+  let backup_dir = backup_dir.clone().ok_or(String::from("Backups are not enabled. Start cacherz with --backupDir"))?;
+  let is_plain_name = !name.is_empty() && !name.starts_with('.')
+    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+  if !is_plain_name {
+    return Err(format!("Backup name {} should contain only letters, digits, '-', '_' and '.'", name));
+  }
+  Path::new(&backup_dir).join(name).to_str()
+    .map(|backup_path| backup_path.to_string())
+    .ok_or(format!("Cannot build backup path of {}", name))
+}
+
+/// Starts compaction of a column family or its key range. Params: column_family, from, to (keys as hex).
+fn start_compaction((req, query_string): (HttpRequest<AdminActor>, Query<HashMap<String, String>>)) -> Result<HttpResponse, Error> {
+  if !is_authorized(&req) {
+    return unauthorized();
+  }
+  let state = req.state();
+  let compaction_msg = query_string.get("column_family")
+    .ok_or(String::from("There is no requested param column_family"))
+    .and_then(|column_family| if state.db.storage.has_column_family(column_family) {
+        Ok(column_family.to_string())
+      } else {
+        Err(format!("There is no column family as: {}", column_family))
+      })
+    .and_then(|column_family| parse_key_bound(query_string.get("from")).map(|from| (column_family, from)))
+    .and_then(|(column_family, from)| parse_key_bound(query_string.get("to")).map(|to| MsgCompact{column_family: column_family, from: from, to: to}));
+  let result = match (compaction_msg, state.compaction_actor.as_ref()) {
+    (Ok(msg), Some(compaction_actor)) => {
+      let msg_text = format!("Compaction of {} has been started", msg.column_family);
+      compaction_actor.do_send(msg);
+      json!({"status": "ok", "msg": msg_text})
+    },
+    (Ok(_), None) => json!({"status": "error", "msg": "Compaction is not available in serve-only instance"}),
+    (Err(error_msg), _) => json!({"status": "error", "msg": error_msg})
+  };
+  json_response(result)
+}
+
+/// Returns stats of a column family and status of its last compaction. Params: column_family.
+fn get_compaction((req, query_string): (HttpRequest<AdminActor>, Query<HashMap<String, String>>)) -> Result<HttpResponse, Error> {
+  if !is_authorized(&req) {
+    return unauthorized();
+  }
+  let report = query_string.get("column_family")
+    .ok_or(String::from("There is no requested param column_family"))
+    .and_then(|column_family| get_compaction_report(&req.state().db, column_family));
+  let result = match report {
+    Ok(compaction_report) => json!({"status": "ok", "data": compaction_report}),
+    Err(error_msg) => json!({"status": "error", "msg": error_msg})
+  };
+  json_response(result)
+}
+
+/// Creates checkpoint of the database with manifest. Params: name (directory in backup directory which does not exist yet).
+fn start_backup((req, query_string): (HttpRequest<AdminActor>, Query<HashMap<String, String>>)) -> Result<HttpResponse, Error> {
+  if !is_authorized(&req) {
+    return unauthorized();
+  }
+  let state = req.state();
+  let manifest = query_string.get("name")
+    .ok_or(String::from("There is no requested param name"))
+    .and_then(|name| get_backup_path(&state.config.backup_dir, name))
+    .and_then(|backup_path| create_backup(&state.db, &backup_path));
+  let result = match manifest {
+    Ok(backup_manifest) => json!({"status": "ok", "data": backup_manifest}),
+    Err(error_msg) => json!({"status": "error", "msg": error_msg})
+  };
+  json_response(result)
+}
+
+pub fn run(config: AdminConfig, db: CacheDB, compaction_actor: Option<Addr<CompactionActor>>) {
+  let address = format!("{}:{}", config.host, config.port);
+  server::new(move || {
+        App::with_state(AdminActor{db: db.clone(), compaction_actor: compaction_actor.clone(), config: config.clone()})
+            .middleware(middleware::Logger::default())
+            .resource("/admin/compact", |r| r.method(http::Method::POST).with(start_compaction))
+            .resource("/admin/compaction", |r| r.method(http::Method::GET).with(get_compaction))
+            .resource("/admin/backup", |r| r.method(http::Method::POST).with(start_backup))
+    }).bind(address)
+        .unwrap()
+        .start();
+}
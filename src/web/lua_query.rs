@@ -0,0 +1,301 @@
+/// # Module Lua query
+/// Custom read endpoints written in Lua. Script `<name>.lua` from the query scripts directory is served
+/// at `/q/<name>`. It defines `query(params)`, gets query string as `params` and returns a table sent back as json.
+/// Scripts see the database only through read functions of the `db` table:
+/// * `db.get(column_family, key)` - value of a key or nil
+/// * `db.scan(column_family, prefix, limit)` - list of `{key = ..., value = ...}` of keys starting with prefix
+/// * `db.key(column_family, ...)` - key built from components of the column family key schema
+/// Keys are "0x" prefixed hex, values are decoded from json (events also from binary format).
+/// Every run has its own Lua state with time, memory and read rows budgets. Runs are served by query actors
+/// on threads of their own, so a bad script cannot block web workers.
+use actix::{Actor, Addr, Handler, Message, SyncArbiter, SyncContext};
+use rlua::{Debug as LuaDebug, Error as LuaError, HookTriggers, Lua, Table, Value as LuaValue, Variadic};
+use serde_json::{self, Map, Number, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use db::cachedb::CacheDB;
+use db::key_codec::{decode_hex, encode_hex};
+use db::key_schema::KeySchema;
+use db::value_codec::decode_value;
+
+const HOOK_INSTRUCTIONS: u32 = 1000;        // Time budget is checked every N lua instructions
+const MAX_JSON_DEPTH: usize = 32;
+const QUERY_THREADS: usize = 4;             // Number of scripts run at the same time, others wait for a free thread
+
+/// Removes functions which reach outside of the database, load other code or bypass the memory budget
+const SANDBOX: &'static str = r#"
+io = nil
+package = nil
+require = nil
+dofile = nil
+loadfile = nil
+load = nil
+collectgarbage = nil
+string.rep = nil
+os = {time = os.time, clock = os.clock, date = os.date}
+"#;
+
+/// Location and budgets of query scripts
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryScripts {
+  pub path: String,         // Directory with <name>.lua scripts
+  pub timeout_ms: u64,      // Time budget of a single run
+  pub max_rows: usize,      // Number of rows a single run can read
+  pub memory_limit_kb: u64  // Memory of the Lua state of a single run
+}
+
+impl QueryScripts {
+  pub fn new(path: String) -> QueryScripts {
+    QueryScripts{path: path, timeout_ms: 1000, max_rows: 10000, memory_limit_kb: 16384}
+  }
+
+  pub fn with_timeout_ms(mut self, timeout_ms: Option<u64>) -> QueryScripts {
+    if let Some(timeout_ms) = timeout_ms {
+      self.timeout_ms = timeout_ms;
+    }
+    self
+  }
+
+  pub fn with_max_rows(mut self, max_rows: Option<usize>) -> QueryScripts {
+    if let Some(max_rows) = max_rows {
+      self.max_rows = max_rows;
+    }
+    self
+  }
+
+  pub fn with_memory_limit_kb(mut self, memory_limit_kb: Option<u64>) -> QueryScripts {
+    if let Some(memory_limit_kb) = memory_limit_kb {
+      self.memory_limit_kb = memory_limit_kb;
+    }
+    self
+  }
+}
+
+fn to_lua_error(err: String) -> LuaError {
+  LuaError::RuntimeError(err)
+}
+
+/// Errors of db functions reach the script wrapped in callback errors, which only print the traceback
+fn error_message(err: &LuaError) -> String {
+  match *err {
+    LuaError::CallbackError{ref cause, ..} => error_message(cause),
+    ref err => err.to_string()
+  }
+}
+
+/// Script names are plain, so they never point outside of the scripts directory
+fn read_script(scripts: &QueryScripts, script_name: &str) -> Result<String, String> {
+  if script_name.is_empty() || !script_name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+    return Err(format!("Query script name {} can have only letters, digits, '_' and '-'", script_name));
+  }
+  let script_path = Path::new(&scripts.path).join(format!("{}.lua", script_name));
+  fs::read_to_string(&script_path).map_err(|_| format!("There is no query script {}", script_name))
+}
+
+fn json_to_lua<'lua>(lua: &'lua Lua, value: &Value) -> Result<LuaValue<'lua>, LuaError> {
+  Ok(match *value {
+    Value::Null => LuaValue::Nil,
+    Value::Bool(boolean) => LuaValue::Boolean(boolean),
+    Value::Number(ref number) => match number.as_i64() {
+      Some(integer) => LuaValue::Integer(integer),
+      None => LuaValue::Number(number.as_f64().unwrap_or(0.0))
+    },
+    Value::String(ref string) => LuaValue::String(lua.create_string(string)?),
+    Value::Array(ref items) => {
+      let table = lua.create_table()?;
+      for (position, item) in items.iter().enumerate() {
+        table.set(position + 1, json_to_lua(lua, item)?)?;
+      }
+      LuaValue::Table(table)
+    },
+    Value::Object(ref fields) => {
+      let table = lua.create_table()?;
+      for (name, field) in fields.iter() {
+        table.set(name.as_str(), json_to_lua(lua, field)?)?;
+      }
+      LuaValue::Table(table)
+    }
+  })
+}
+
+/// Lua tables with keys 1..n become arrays, other tables become objects
+fn lua_to_json<'lua>(value: LuaValue<'lua>, depth: usize) -> Result<Value, String> {
+  if depth > MAX_JSON_DEPTH {
+    return Err(format!("Query result is nested deeper than {} levels", MAX_JSON_DEPTH));
+  }
+  match value {
+    LuaValue::Nil => Ok(Value::Null),
+    LuaValue::Boolean(boolean) => Ok(Value::Bool(boolean)),
+    LuaValue::Integer(integer) => Ok(json!(integer)),
+    LuaValue::Number(number) => Ok(Number::from_f64(number).map(Value::Number).unwrap_or(Value::Null)),
+    LuaValue::String(string) => string.to_str().map(|string| json!(string)).map_err(|err| err.to_string()),
+    LuaValue::Table(table) => table_to_json(table, depth),
+    other => Err(format!("Query result cannot contain {:?}", other))
+  }
+}
+
+fn table_to_json<'lua>(table: Table<'lua>, depth: usize) -> Result<Value, String> {
+  let mut entries: Vec<(LuaValue<'lua>, LuaValue<'lua>)> = Vec::new();
+  for pair in table.pairs::<LuaValue, LuaValue>() {
+    entries.push(pair.map_err(|err| err.to_string())?);
+  }
+  let is_array = !entries.is_empty() && entries.iter().all(|&(ref key, _)| match *key {
+    LuaValue::Integer(position) => position >= 1 && position as usize <= entries.len(),
+    _ => false
+  });
+  if is_array {
+    let mut items: Vec<Value> = vec![Value::Null; entries.len()];
+    for (key, value) in entries {
+      if let LuaValue::Integer(position) = key {
+        items[position as usize - 1] = lua_to_json(value, depth + 1)?;
+      }
+    }
+    return Ok(Value::Array(items));
+  }
+  let mut fields = Map::new();
+  for (key, value) in entries {
+    let name = match key {
+      LuaValue::String(name) => name.to_str().map_err(|err| err.to_string())?.to_string(),
+      LuaValue::Integer(position) => position.to_string(),
+      other => return Err(format!("Query result cannot have key {:?}", other))
+    };
+    fields.insert(name, lua_to_json(value, depth + 1)?);
+  }
+  Ok(Value::Object(fields))
+}
+
+/// Values of events may be binary, other values are json. Anything else is returned as a string.
+fn decode_row(value: &[u8]) -> Value {
+  decode_value(value).unwrap_or_else(|_| json!(String::from_utf8_lossy(value).to_string()))
+}
+
+/// Takes rows from the budget of the run
+fn take_rows(rows_left: &AtomicUsize, count: usize, max_rows: usize) -> Result<(), LuaError> {
+  let left = rows_left.load(Ordering::SeqCst);
+  if count > left {
+    return Err(to_lua_error(format!("Query has exceeded its budget of {} rows", max_rows)));
+  }
+  rows_left.store(left - count, Ordering::SeqCst);
+  Ok(())
+}
+
+fn register_db(lua: &Lua, db: &CacheDB, scripts: &QueryScripts) -> Result<(), LuaError> {
+  let rows_left = Arc::new(AtomicUsize::new(scripts.max_rows));
+  let max_rows = scripts.max_rows;
+  let db_table = lua.create_table()?;
+
+  let get_db = db.clone();
+  let get_rows_left = rows_left.clone();
+  db_table.set("get", lua.create_function(move |lua, (column_family, key): (String, String)| {
+    if !get_db.storage.has_column_family(&column_family) {
+      return Err(to_lua_error(format!("There is no column family as: {}", column_family)));
+    }
+    take_rows(&get_rows_left, 1, max_rows)?;
+    let key = decode_hex(&key).map_err(to_lua_error)?;
+    match get_db.storage.get(&column_family, &key).map_err(to_lua_error)? {
+      Some(value) => json_to_lua(lua, &decode_row(&value)),
+      None => Ok(LuaValue::Nil)
+    }
+  })?)?;
+
+  let scan_db = db.clone();
+  let scan_rows_left = rows_left.clone();
+  db_table.set("scan", lua.create_function(move |lua, (column_family, prefix, limit): (String, String, Option<usize>)| {
+    if !scan_db.storage.has_column_family(&column_family) {
+      return Err(to_lua_error(format!("There is no column family as: {}", column_family)));
+    }
+    let prefix = decode_hex(&prefix).map_err(to_lua_error)?;
+    // Scan never reads more than is left in the budget
+    let limit = limit.unwrap_or(100).min(scan_rows_left.load(Ordering::SeqCst) + 1);
+    let entries = scan_db.storage.scan_prefix(&column_family, &prefix, limit).map_err(to_lua_error)?;
+    take_rows(&scan_rows_left, entries.len(), max_rows)?;
+    let rows = lua.create_table()?;
+    for (position, (key, value)) in entries.into_iter().enumerate() {
+      let row = lua.create_table()?;
+      row.set("key", encode_hex(&key))?;
+      row.set("value", json_to_lua(lua, &decode_row(&value))?)?;
+      rows.set(position + 1, row)?;
+    }
+    Ok(rows)
+  })?)?;
+
+  db_table.set("key", lua.create_function(|_, (column_family, values): (String, Variadic<String>)| {
+    let schema = KeySchema::for_column_family(&column_family).map_err(to_lua_error)?;
+    let key_values = schema.parse_values(values.to_vec()).map_err(to_lua_error)?;
+    schema.encode(&key_values).map(|key| encode_hex(&key)).map_err(to_lua_error)
+  })?)?;
+
+  lua.globals().set("db", db_table)
+}
+
+/// Runs `query(params)` of a script and returns its result as json
+pub fn run_query(db: &CacheDB, scripts: &QueryScripts, script_name: &str, params: &HashMap<String, String>) -> Result<Value, String> {
+  let script = read_script(scripts, script_name)?;
+  let lua = Lua::new();
+  lua.set_memory_limit(Some(scripts.memory_limit_kb as usize * 1024));
+  let deadline = Instant::now() + Duration::from_millis(scripts.timeout_ms);
+  let timeout_ms = scripts.timeout_ms;
+  let timed_out = Arc::new(Mutex::new(false));
+  let hook_timed_out = timed_out.clone();
+  lua.set_hook(HookTriggers{every_nth_instruction: Some(HOOK_INSTRUCTIONS), ..HookTriggers::default()}, move |_: &LuaDebug| {
+    if Instant::now() > deadline {
+      *hook_timed_out.lock().unwrap() = true;
+      return Err(to_lua_error(format!("Query has exceeded its time budget of {} ms", timeout_ms)));
+    }
+    Ok(())
+  });
+  let result = lua.exec::<_, ()>(SANDBOX, Some("sandbox"))
+    .and_then(|_| register_db(&lua, db, scripts))
+    .and_then(|_| lua.exec::<_, ()>(&script, Some(script_name)))
+    .and_then(|_| lua.globals().get::<_, ::rlua::Function>("query"))
+    .and_then(|query| {
+      let params_table = lua.create_table()?;
+      for (name, value) in params.iter() {
+        params_table.set(name.as_str(), value.as_str())?;
+      }
+      query.call::<_, LuaValue>(params_table)
+    });
+  match result {
+    Ok(value) => lua_to_json(value, 0),
+    Err(_) if *timed_out.lock().unwrap() => Err(format!("Query {} has exceeded its time budget of {} ms", script_name, timeout_ms)),
+    Err(LuaError::MemoryError(_)) => Err(format!("Query {} has exceeded its memory budget of {} KB", script_name, scripts.memory_limit_kb)),
+    Err(err) => Err(format!("Query {} has failed. Error: {}", script_name, error_message(&err)))
+  }
+}
+
+/// Runs query scripts on threads of a sync arbiter
+pub struct QueryActor {
+  db: CacheDB,
+  scripts: QueryScripts
+}
+
+impl Actor for QueryActor {
+  type Context = SyncContext<Self>;
+}
+
+/// Run of a query script with the query string as params
+pub struct RunQuery {
+  pub script_name: String,
+  pub params: HashMap<String, String>
+}
+
+impl Message for RunQuery {
+  type Result = Result<Value, String>;
+}
+
+impl Handler<RunQuery> for QueryActor {
+  type Result = Result<Value, String>;
+
+  fn handle(&mut self, msg: RunQuery, _: &mut Self::Context) -> Result<Value, String> {
+    run_query(&self.db, &self.scripts, &msg.script_name, &msg.params)
+  }
+}
+
+/// Starts query actors. At most `QUERY_THREADS` scripts run at the same time.
+pub fn start_query_actors(db: CacheDB, scripts: QueryScripts) -> Addr<QueryActor> {
+  SyncArbiter::start(QUERY_THREADS, move || QueryActor{db: db.clone(), scripts: scripts.clone()})
+}
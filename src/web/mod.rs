@@ -1 +1,3 @@
-pub mod web;
\ No newline at end of file
+pub mod web;
+pub mod lua_query;
+pub mod admin;
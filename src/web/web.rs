@@ -1,15 +1,31 @@
+use actix::{Actor, Addr, Handler, Message, SyncArbiter, SyncContext};
 use actix_web::{
-    http, middleware, server, App, HttpResponse, State, Error, Query
+    error, http, middleware, server, App, AsyncResponder, FutureResponse, HttpResponse, State, Error, Query, Path
 };
+use db::retention::get_pruned_msg;
+use db::stats::get_stats_report;
+use db::aggregations::{get_aggregate, get_aggregates};
 use db::cachedb::CacheDB;
-use db::reader::{get_event_by_key, get_events_by_prefix, Event};
+use db::reader::{get_event_by_key_bytes, get_events_by_prefix, get_events_by_index, get_events_by_address, get_events_by_timeline, Event};
+use db::key_schema::{name_key, qualified_name, split_qualified_name, KeySchema, KeyValue};
+use db::indexes::{index_query, address_query, timeline_query, GLOBAL_TIMELINE};
+use db::export::{content_type, export_events, parse_export_format, select_sources, ExportFormat, ExportSource};
+use web::lua_query::{start_query_actors, QueryActor, QueryScripts, RunQuery};
+use bytes::Bytes;
+use futures::{future, Future, Sink, Stream};
+use futures::sync::mpsc;
 use std::collections::HashMap;
-use std::str::from_utf8;
+use std::io::{self, Write};
 use serde_json;
 
+const EXPORT_CHUNK_SIZE: usize = 64 * 1024;
+const EXPORT_THREADS: usize = 2;        // Number of exports streamed at the same time, others wait for a free thread
+
 struct WebActor {
     db: CacheDB,
-    prefix: usize
+    query_actor: Option<Addr<QueryActor>>,   // None when query scripts are not enabled
+    export_sources: Vec<ExportSource>,
+    export_actor: Addr<ExportActor>
 }
 #[derive(Serialize, Deserialize)]
 struct JsonWebResponse {
@@ -18,6 +34,15 @@ struct JsonWebResponse {
   msg: Option<String>
 }
 
+/// Response with a page of events. `next` holds params of the next page.
+#[derive(Serialize, Deserialize)]
+struct JsonWebPageResponse {
+  status: String,
+  data: Vec<Event>,
+  msg: Option<String>,
+  next: Option<serde_json::Value>
+}
+
 enum ContentType {
   JsonApplication
 }
@@ -27,22 +52,63 @@ enum QueryStringType {
   PureString(String)
 } 
 
-fn parse_string_by_prefix(string_key: String, prefix_size: usize) -> String {
-  if string_key.clone().len() >= prefix_size {
-    from_utf8(string_key[..prefix_size].as_bytes()).unwrap().to_string()
-  } else {
-    let mut vec_query = Vec::from(string_key.clone());
-    let extend_query_size = prefix_size - string_key.clone().len();
-    vec_query.extend(&vec![b'-'; extend_query_size]);
-    from_utf8(&vec_query).unwrap().to_string()
+/// Builds key from query params named after schema components. First component is always passed as `key`.
+/// Missing trailing components give a prefix of the key.
+fn build_query(schema: &KeySchema, query_string: &HashMap<String, String>) -> Result<Vec<u8>, String> {
+  let mut raw_values: Vec<String> = Vec::new();
+  for (component_index, component) in schema.components.iter().enumerate() {
+    let param_name = if component_index == 0 { "key" } else { component.field() };
+    match query_string.get(param_name) {
+      Some(raw_value) => raw_values.push(raw_value.to_string()),
+      None => break
+    };
+  }
+  if raw_values.is_empty() {
+    return Err(String::from("There is no requested param key"));
+  }
+  let values = schema.parse_values(raw_values)?;
+  schema.encode(&values)
+}
+
+/// Builds secondary index query. `value` holds comma separated values of indexed arguments.
+/// Optional `block` and `log` params give the position from which events are returned.
+fn build_index_query(index_name: &str, query_string: &HashMap<String, String>) -> Result<Vec<u8>, String> {
+  let event_name = query_string.get("key").ok_or(String::from("There is no requested param key"))?;
+  let values: Vec<String> = query_string.get("value")
+    .ok_or(String::from("There is no requested param value"))?
+    .split(',')
+    .map(|value| value.to_string())
+    .collect();
+  let from = parse_from_position(query_string)?;
+  index_query(event_name, index_name, &values, from)
+}
+
+/// Parses optional `block` and `log` params into position from which events are returned
+fn parse_from_position(query_string: &HashMap<String, String>) -> Result<Option<(u64, u32)>, String> {
+  match query_string.get("block") {
+    Some(block_nr) => {
+      let log_nr = query_string.get("log").cloned().unwrap_or(String::from("0x0"));
+      match KeySchema::events().parse_values(vec![String::new(), block_nr.to_string(), log_nr])?.as_slice() {
+        [_, KeyValue::U64(block_number), KeyValue::U32(log_index)] => Ok(Some((*block_number, *log_index))),
+        _ => Err(format!("Cannot parse block {} and log", block_nr))
+      }
+    },
+    None => Ok(None)
   }
 }
 
-fn build_query(key: String, block_nr: String, log_nr: String, prefix_size: usize) -> String {
-  let parsed_key: String = parse_string_by_prefix(key, prefix_size);
-  let parsed_blocknr: String = parse_string_by_prefix(block_nr, 15);
-  let parsed_lognr: String = parse_string_by_prefix(log_nr, 15);
-  format!("{}{}{}", parsed_key, parsed_blocknr, parsed_lognr)
+/// Tells if events requested from `block` and `log` params were pruned. Needs event name.
+fn get_query_pruned_msg(db: &CacheDB, event_name: Option<&String>, query_string: &HashMap<String, String>) -> Option<String> {
+  let from = parse_from_position(query_string).ok()?.unwrap_or((0, 0));
+  event_name.and_then(|name| get_pruned_msg(db, name, from))
+}
+
+/// Qualified name of `event` param. Event is qualified by `contract` unless it names its contract itself.
+fn get_qualified_event(contract: Option<&str>, event_name: Option<&String>) -> Option<String> {
+  event_name.map(|event_name| match (contract, split_qualified_name(event_name)) {
+    (Some(contract), (None, _)) => qualified_name(contract, event_name),
+    _ => event_name.clone()
+  })
 }
 
 fn parse_query_string(query: Query<HashMap<String, String>>, query_name: &str, query_string_type: QueryStringType) -> Result<QueryStringType, String> {
@@ -76,8 +142,8 @@ fn get_last_event_from_db((state, query_string): (State<WebActor>, Query<HashMap
     Some(e_n) => e_n.to_string(),
     None => return Ok(HttpResponse::Ok().content_type("json/application").body("There is no requested param event_name".to_string()))
   };
-  let event_name_prefixed: String = parse_string_by_prefix(event_name, state.prefix);
-  let last_event_block_log = get_event_by_key(state.db.clone(), String::from("aggregations"), event_name_prefixed);
+  let last_event_block_log = name_key(&event_name)
+    .and_then(|event_name_key| get_event_by_key_bytes(state.db.clone(), String::from("aggregations"), event_name_key));
   match last_event_block_log {
     Ok(last_event) => {
       Ok(HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&JsonWebResponse{status: String::from("ok"), data: vec![last_event], msg: Some(String::from(""))}).unwrap()))
@@ -93,17 +159,9 @@ fn get_events((state, query_string): (State<WebActor>, Query<HashMap<String, Str
     Some(cf) => cf.to_string(),
     None => return Ok(HttpResponse::Ok().content_type("json/application").body("There is no requested param column_family".to_string()))
   };
-  let key: String = match query_string.get("key") {
-    Some(q) => q.to_string(),
-    None => return Ok(HttpResponse::Ok().content_type("json/application").body(err_msg("There is no requested param key".to_string())))
-  };
-  let block_nr: String = match query_string.get("block") {
-    Some(q) => q.to_string(),
-    None => return Ok(HttpResponse::Ok().content_type("json/application").body(err_msg("There is no requested param block".to_string())))
-  };
-  let log_nr: String = match query_string.get("log") {
-    Some(q) => q.to_string(),
-    None => return Ok(HttpResponse::Ok().content_type("json/application").body(err_msg("There is no requested param log".to_string())))
+  let schema: KeySchema = match KeySchema::for_column_family(&column_family) {
+    Ok(s) => s,
+    Err(error_msg) => return Ok(HttpResponse::Ok().content_type("json/application").body(err_msg(error_msg)))
   };
   let size: i32 = match query_string.get("size") {
     Some(s) => s.parse().unwrap(),
@@ -113,17 +171,30 @@ fn get_events((state, query_string): (State<WebActor>, Query<HashMap<String, Str
     Some(m) => m.to_string(),
     None => return Ok(HttpResponse::Ok().content_type("json/application").body(err_msg("There is no requested param method".to_string())))
   };
-  let query: String = build_query(key, block_nr, log_nr, state.prefix);
+  // Pruned boundary is checked for the event given explicitly by `event` (and `contract`) params
+  let pruned_event = get_qualified_event(query_string.get("contract").map(|contract| contract.as_str()), query_string.get("event"));
+  if let Some(index_name) = query_string.get("index") {
+    let result: JsonWebResponse = match build_index_query(index_name, &query_string).and_then(|query| get_events_by_index(state.db.clone(), query, size)) {
+      Ok(event_results) => JsonWebResponse{status: String::from("ok"), data: event_results, msg: get_query_pruned_msg(&state.db, pruned_event.as_ref(), &query_string)},
+      Err(error_msg) => JsonWebResponse{status: String::from("error"), data: vec![], msg: Some(error_msg)}
+    };
+    return Ok(HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&result).unwrap()));
+  }
+  let query: Vec<u8> = match build_query(&schema, &query_string) {
+    Ok(q) => q,
+    Err(error_msg) => return Ok(HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&JsonWebResponse{status: String::from("error"), data: vec![], msg: Some(error_msg)}).unwrap()))
+  };
   let result: JsonWebResponse = match method.as_ref() {
       "prefix" => {
-        let get_result: Result<Vec<Event>, String> = get_events_by_prefix(state.db.clone(), column_family, query, size, true, state.prefix);
+        let pruned_msg = if column_family == "events" { get_query_pruned_msg(&state.db, pruned_event.as_ref(), &query_string) } else { None };
+        let get_result: Result<Vec<Event>, String> = get_events_by_prefix(state.db.clone(), column_family, query, size, true, schema.prefix_size(1));
         match get_result {
-          Ok(event_results) => JsonWebResponse{status: String::from("ok"), data: event_results, msg: None},
+          Ok(event_results) => JsonWebResponse{status: String::from("ok"), data: event_results, msg: pruned_msg},
           Err(error_msg) => JsonWebResponse{status: String::from("error"), data: vec![], msg: Some(error_msg)}
         }
       },
       "key" => {
-        let get_result: Result<Event, String> = get_event_by_key(state.db.clone(), column_family, query);
+        let get_result: Result<Event, String> = get_event_by_key_bytes(state.db.clone(), column_family, query);
         match get_result {
           Ok(event_result) => JsonWebResponse{status: String::from("ok"), data: vec![event_result], msg: None},
           Err(error_msg) => JsonWebResponse{status: String::from("error"), data: vec![], msg: Some(error_msg)}
@@ -137,13 +208,236 @@ fn get_events((state, query_string): (State<WebActor>, Query<HashMap<String, Str
   Ok(HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&result_hm).unwrap()))
 }
 
-pub fn run(host: String, port: String, db: CacheDB, prefix: usize) {
+/// Parses optional `size` param of paged endpoints
+fn parse_page_size(query_string: &HashMap<String, String>) -> Result<i32, String> {
+  match query_string.get("size") {
+    Some(size) => size.parse().map_err(|_| format!("Can not parse param: {} into integer", size)),
+    None => Ok(100)
+  }
+}
+
+/// Builds page response. Key of the next page entry is turned into `block` and `log` params.
+fn page_response(schema: KeySchema, page_result: Result<(Vec<Event>, Option<Vec<u8>>), String>, msg: Option<String>) -> HttpResponse {
+  let result = match page_result {
+    Ok((event_results, next_key)) => {
+      let next = match next_key.map(|key| schema.decode_to_json(&key)) {
+        Some(Ok(next_fields)) => Some(json!({"block": next_fields["block"], "log": next_fields["log"]})),
+        _ => None
+      };
+      JsonWebPageResponse{status: String::from("ok"), data: event_results, msg: msg, next: next}
+    },
+    Err(error_msg) => JsonWebPageResponse{status: String::from("error"), data: vec![], msg: Some(error_msg), next: None}
+  };
+  HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&result).unwrap())
+}
+
+/// Returns events touching an address across all contracts and events. Params: size, block, log, contract, event.
+fn get_address_events((state, path, query_string): (State<WebActor>, Path<(String,)>, Query<HashMap<String, String>>)) -> Result<HttpResponse, Error> {
+  let address = path.0.clone();
+  let page_result = parse_page_size(&query_string)
+    .and_then(|size| parse_from_position(&query_string).map(|from| (size, from)))
+    .and_then(|(size, from)| address_query(&address, from).map(|query| (size, query)))
+    .and_then(|(size, query)| get_events_by_address(state.db.clone(), query, size, query_string.get("contract").cloned(), query_string.get("event").cloned()));
+  let event_name = get_qualified_event(query_string.get("contract").map(|contract| contract.as_str()), query_string.get("event"));
+  Ok(page_response(KeySchema::addresses(), page_result, get_query_pruned_msg(&state.db, event_name.as_ref(), &query_string)))
+}
+
+fn get_timeline(db: CacheDB, contract: &str, query_string: &HashMap<String, String>) -> HttpResponse {
+  let page_result = parse_page_size(query_string)
+    .and_then(|size| parse_from_position(query_string).map(|from| (size, from)))
+    .and_then(|(size, from)| timeline_query(contract, from).map(|query| (size, query)))
+    .and_then(|(size, query)| get_events_by_timeline(db.clone(), query, size, query_string.get("event").cloned()));
+  let timeline_contract = if contract == GLOBAL_TIMELINE { None } else { Some(contract) };
+  let event_name = get_qualified_event(timeline_contract, query_string.get("event"));
+  page_response(KeySchema::timeline(), page_result, get_query_pruned_msg(&db, event_name.as_ref(), query_string))
+}
+
+/// Returns events of a contract in chain order. Params: size, block, log, event.
+fn get_contract_timeline((state, path, query_string): (State<WebActor>, Path<(String,)>, Query<HashMap<String, String>>)) -> Result<HttpResponse, Error> {
+  Ok(get_timeline(state.db.clone(), &path.0, &query_string))
+}
+
+/// Returns events of all contracts in chain order. Needs `--globalTimeline`.
+fn get_global_timeline((state, query_string): (State<WebActor>, Query<HashMap<String, String>>)) -> Result<HttpResponse, Error> {
+  Ok(get_timeline(state.db.clone(), GLOBAL_TIMELINE, &query_string))
+}
+
+/// Returns groups of an event aggregation. Params: value (group values, comma separated in group by order)
+/// returns a single group, otherwise groups are paged with size and after (`next` of the previous page).
+fn get_aggregation((state, path, query_string): (State<WebActor>, Path<(String, String)>, Query<HashMap<String, String>>)) -> Result<HttpResponse, Error> {
+  let (event_name, aggregation_name) = (path.0.clone(), path.1.clone());
+  let result = match query_string.get("value") {
+    Some(value) => {
+      let group_values: Vec<String> = value.split(',').map(|group_value| group_value.to_string()).collect();
+      match get_aggregate(&state.db, &event_name, &aggregation_name, &group_values) {
+        Ok(group) => json!({"status": "ok", "data": group.into_iter().collect::<Vec<serde_json::Value>>()}),
+        Err(error_msg) => json!({"status": "error", "msg": error_msg})
+      }
+    },
+    None => {
+      let page_result = parse_page_size(&query_string)
+        .and_then(|size| get_aggregates(&state.db, &event_name, &aggregation_name, query_string.get("after").map(|after| after.as_str()), size.max(1) as usize));
+      match page_result {
+        Ok((groups, next)) => json!({"status": "ok", "data": groups, "next": next}),
+        Err(error_msg) => json!({"status": "error", "msg": error_msg})
+      }
+    }
+  };
+  Ok(HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&result).unwrap()))
+}
+
+/// Returns ingestion stats grouped by contract. Params: contract and event (both optional).
+fn get_stats((state, query_string): (State<WebActor>, Query<HashMap<String, String>>)) -> Result<HttpResponse, Error> {
+  let contract = query_string.get("contract").map(|contract| contract.as_str());
+  let event_name = query_string.get("event").map(|event_name| event_name.as_str());
+  let result = match get_stats_report(&state.db, contract, event_name) {
+    Ok(stats_report) => json!({"status": "ok", "data": stats_report}),
+    Err(error_msg) => json!({"status": "error", "msg": error_msg})
+  };
+  Ok(HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&result).unwrap()))
+}
+
+fn query_response(query_result: Result<serde_json::Value, String>) -> Result<HttpResponse, Error> {
+  let result = match query_result {
+    Ok(data) => json!({"status": "ok", "data": data}),
+    Err(error_msg) => json!({"status": "error", "msg": error_msg})
+  };
+  Ok(HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&result).unwrap()))
+}
+
+/// Runs lua query script on a query actor. Query string is passed to the script as params.
+fn get_lua_query((state, script_name, query_string): (State<WebActor>, Path<String>, Query<HashMap<String, String>>)) -> FutureResponse<HttpResponse> {
+  let query_actor = match state.query_actor {
+    Some(ref query_actor) => query_actor.clone(),
+    None => return Box::new(future::result(query_response(Err(String::from("Query scripts are not enabled. Start cacherz with --queryScriptsPath")))))
+  };
+  query_actor.send(RunQuery{script_name: script_name.into_inner(), params: query_string.into_inner()})
+    .then(|query_result| query_response(query_result
+      .map_err(|err| format!("Query has not been run. Error: {}", err))
+      .and_then(|query_result| query_result)))
+    .responder()
+}
+
+/// Sends written bytes to the response in chunks. Writing blocks while the client is behind
+/// and fails when the client has disconnected, which stops the export.
+struct ChunkWriter {
+  sender: mpsc::Sender<Result<Bytes, String>>,
+  buffer: Vec<u8>
+}
+
+impl Write for ChunkWriter {
+  fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+    self.buffer.extend_from_slice(data);
+    if self.buffer.len() >= EXPORT_CHUNK_SIZE {
+      self.flush()?;
+    }
+    Ok(data.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    if self.buffer.is_empty() {
+      return Ok(());
+    }
+    let chunk = Bytes::from(self.buffer.split_off(0));
+    let sender = self.sender.clone();
+    sender.send(Ok(chunk)).wait()
+      .map(|_| ())
+      .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Client has disconnected"))
+  }
+}
+
+/// Export streamed into a response. `description` tells the request in logs.
+struct RunExport {
+  sources: Vec<ExportSource>,
+  from_block: Option<u64>,
+  to_block: Option<u64>,
+  format: ExportFormat,
+  sender: mpsc::Sender<Result<Bytes, String>>,
+  description: String
+}
+
+impl Message for RunExport {
+  type Result = ();
+}
+
+/// Streams exports on threads of a sync arbiter, so their number is bounded and web workers are never blocked
+struct ExportActor {
+  db: CacheDB
+}
+
+impl Actor for ExportActor {
+  type Context = SyncContext<Self>;
+}
+
+impl Handler<RunExport> for ExportActor {
+  type Result = ();
+
+  /// Export failing after its first chunk cannot change the response status. It ends with an error line
+  /// and the response is broken off, so clients never take a truncated file for a whole one.
+  fn handle(&mut self, msg: RunExport, _: &mut Self::Context) {
+    let out = ChunkWriter{sender: msg.sender.clone(), buffer: Vec::with_capacity(EXPORT_CHUNK_SIZE)};
+    if let Err(err) = export_events(&self.db, &msg.sources, msg.from_block, msg.to_block, msg.format, out) {
+      error!("Export of {} has failed. Error: {}", msg.description, err);
+      let error_line = format!("\n{}\n", json!({"status": "error", "msg": format!("Export has failed. Error: {}", err)}));
+      let _ = msg.sender.send(Ok(Bytes::from(error_line)))
+        .and_then(|sender| sender.send(Err(err)))
+        .wait();
+    }
+  }
+}
+
+/// Streams events as csv, ndjson or parquet. Params: event or contract, format, from and to (blocks, both optional).
+/// Errors found before the first row are returned as json, later ones end the stream with an error.
+fn get_export((state, query_string): (State<WebActor>, Query<HashMap<String, String>>)) -> Result<HttpResponse, Error> {
+  let contract = query_string.get("contract").map(|contract| contract.as_str());
+  let event_name = query_string.get("event").map(|event_name| event_name.as_str());
+  let block_bound = |param_name: &str| -> Result<Option<u64>, String> {
+    match query_string.get(param_name) {
+      Some(raw_value) => raw_value.parse::<u64>().map(Some).map_err(|err| format!("Can not parse {} into block number. Error: {}", param_name, err)),
+      None => Ok(None)
+    }
+  };
+  let export_params = query_string.get("format")
+    .ok_or(String::from("There is no requested param format"))
+    .and_then(|format_name| parse_export_format(format_name))
+    .and_then(|format| select_sources(&state.export_sources, contract, event_name).map(|sources| (format, sources)))
+    .and_then(|(format, sources)| block_bound("from").and_then(|from| block_bound("to").map(|to| (format, sources, from, to))));
+  let (format, sources, from_block, to_block) = match export_params {
+    Ok(export_params) => export_params,
+    Err(error_msg) => {
+      let result = json!({"status": "error", "msg": error_msg});
+      return Ok(HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&result).unwrap()));
+    }
+  };
+  let description = format!("contract: {:?}, event: {:?}, format: {:?}, from: {:?}, to: {:?}",
+    contract, event_name, query_string.get("format"), from_block, to_block);
+  let (sender, receiver) = mpsc::channel::<Result<Bytes, String>>(4);
+  state.export_actor.do_send(RunExport{sources: sources, from_block: from_block, to_block: to_block, format: format, sender: sender, description: description});
+  Ok(HttpResponse::Ok()
+    .content_type(content_type(format))
+    .streaming(receiver
+      .map_err(|_| error::ErrorInternalServerError("Export has failed"))
+      .and_then(|chunk| chunk.map_err(error::ErrorInternalServerError))))
+}
+
+pub fn run(host: String, port: String, db: CacheDB, query_scripts: Option<QueryScripts>, export_sources: Vec<ExportSource>) {
+  let query_actor = query_scripts.map(|query_scripts| start_query_actors(db.clone(), query_scripts));
+  let export_db = db.clone();
+  let export_actor = SyncArbiter::start(EXPORT_THREADS, move || ExportActor{db: export_db.clone()});
   server::new(move || {
-        App::with_state(WebActor{db: db.clone(), prefix: prefix})
+        App::with_state(WebActor{db: db.clone(), query_actor: query_actor.clone(), export_sources: export_sources.clone(),
+          export_actor: export_actor.clone()})
             // enable logger
             .middleware(middleware::Logger::default())
             .resource("/get_events/", |r| r.method(http::Method::GET).with(get_events))
             .resource("/last_event/", |r| r.method(http::Method::GET).with(get_last_event_from_db))
+            .resource("/address/{address}/events", |r| r.method(http::Method::GET).with(get_address_events))
+            .resource("/timeline/", |r| r.method(http::Method::GET).with(get_global_timeline))
+            .resource("/timeline/{contract}", |r| r.method(http::Method::GET).with(get_contract_timeline))
+            .resource("/aggregations/{event}/{aggregation}", |r| r.method(http::Method::GET).with(get_aggregation))
+            .resource("/stats/", |r| r.method(http::Method::GET).with(get_stats))
+            .resource("/q/{script}", |r| r.method(http::Method::GET).with(get_lua_query))
+            .resource("/export/", |r| r.method(http::Method::GET).with(get_export))
     }).bind(format!("{}:{}", host, port))
         .unwrap()
         .start();
@@ -0,0 +1,197 @@
+extern crate lib;
+#[macro_use]
+extern crate serde_json;
+use std::collections::HashMap;
+use lib::actors::rocks_write_actor::{write_batch, WriteOp};
+use lib::config::structs::contractconfig::{AggregationConfig, AggregationKind};
+use lib::db::aggregations::{check_aggregations, get_aggregate, get_aggregates};
+use lib::db::cachedb::CacheDB;
+use lib::db::indexes::{EventBatch, EventIndexes, LogTransaction};
+use lib::db::key_schema::event_key;
+use lib::db::stats::{get_ingest_stats, StatsDelta};
+
+const COLUMN_FAMILIES: [&str; 9] = ["events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline"];
+
+fn transfer_params(from: &str, to: &str, value: &str) -> HashMap<String, String> {
+  let mut params = HashMap::new();
+  params.insert(String::from("from"), from.to_string());
+  params.insert(String::from("to"), to.to_string());
+  params.insert(String::from("value"), value.to_string());
+  params
+}
+
+fn aggregation(name: &str, kind: AggregationKind, arg: Option<&str>, group_by: Vec<&str>) -> AggregationConfig {
+  AggregationConfig{
+    name: name.to_string(),
+    kind: kind,
+    arg: arg.map(|arg| arg.to_string()),
+    group_by: group_by.into_iter().map(|arg| arg.to_string()).collect()
+  }
+}
+
+fn transfer_aggregations() -> Vec<AggregationConfig> {
+  vec![
+    aggregation("transfers", AggregationKind::Count, None, Vec::new()),
+    aggregation("received", AggregationKind::Sum, Some("value"), vec!["to"]),
+    aggregation("smallest", AggregationKind::Min, Some("value"), vec!["to"]),
+    aggregation("largest", AggregationKind::Max, Some("value"), vec!["to"]),
+    aggregation("senders", AggregationKind::Distinct, Some("from"), vec!["to"])
+  ]
+}
+
+fn write<F>(db: &CacheDB, build: F) where F: FnOnce(&mut EventBatch) {
+  let mut event_batch = EventBatch::new(&*db.storage);
+  build(&mut event_batch);
+  db.storage.write(event_batch.into_batch(), false).unwrap();
+}
+
+fn group(db: &CacheDB, aggregation_name: &str, values: Vec<&str>) -> serde_json::Value {
+  let values: Vec<String> = values.into_iter().map(|value| value.to_string()).collect();
+  get_aggregate(db, "Transfer", aggregation_name, &values).unwrap().unwrap_or(json!(null))
+}
+
+#[test]
+fn test_incremental_aggregations() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  let indexes = EventIndexes{aggregations: transfer_aggregations(), ..EventIndexes::default()};
+
+  println!("Test events are aggregated per group with 256 bit numbers...");
+  write(&db, |batch| {
+    batch.put_event(&event_key("Transfer", 10, 0).unwrap(), transfer_params("aa", "bb", "ffffffffffffffffffffffffffffffff"), &LogTransaction::default(), &indexes).unwrap();
+    batch.put_event(&event_key("Transfer", 10, 1).unwrap(), transfer_params("cc", "bb", "ffffffffffffffffffffffffffffffff"), &LogTransaction::default(), &indexes).unwrap();
+    batch.put_event(&event_key("Transfer", 11, 0).unwrap(), transfer_params("aa", "bb", "a"), &LogTransaction::default(), &indexes).unwrap();
+    batch.put_event(&event_key("Transfer", 12, 0).unwrap(), transfer_params("aa", "dd", "5"), &LogTransaction::default(), &indexes).unwrap();
+  });
+  assert_eq!(group(&db, "transfers", vec![])["count"], json!(4));
+  assert_eq!(group(&db, "received", vec!["0xBB"])["sum"], json!("680564733841876926926749214863536422920"));
+  assert_eq!(group(&db, "received", vec!["dd"])["group"], json!({"to": "dd"}));
+  assert_eq!(group(&db, "smallest", vec!["bb"])["min"], json!("10"));
+  assert_eq!(group(&db, "largest", vec!["bb"])["max"], json!("340282366920938463463374607431768211455"));
+  assert_eq!(group(&db, "senders", vec!["bb"])["distinct"], json!(2));
+
+  println!("Test overwritten event is counted once...");
+  write(&db, |batch| {
+    batch.put_event(&event_key("Transfer", 12, 0).unwrap(), transfer_params("aa", "dd", "7"), &LogTransaction::default(), &indexes).unwrap();
+  });
+  assert_eq!(group(&db, "transfers", vec![])["count"], json!(4));
+  assert_eq!(group(&db, "received", vec!["dd"])["sum"], json!("7"));
+  assert_eq!(group(&db, "smallest", vec!["dd"])["min"], json!("7"));
+
+  println!("Test reorg rollback subtracts removed events...");
+  write(&db, |batch| {
+    batch.put_event(&event_key("Transfer", 13, 0).unwrap(), transfer_params("ee", "bb", "1"), &LogTransaction::default(), &indexes).unwrap();
+    batch.delete_event(&event_key("Transfer", 13, 0).unwrap(), &indexes).unwrap();
+    batch.delete_event(&event_key("Transfer", 11, 0).unwrap(), &indexes).unwrap();
+    batch.delete_event(&event_key("Transfer", 10, 1).unwrap(), &indexes).unwrap();
+  });
+  assert_eq!(group(&db, "transfers", vec![])["count"], json!(2));
+  assert_eq!(group(&db, "received", vec!["bb"])["sum"], json!("340282366920938463463374607431768211455"));
+  assert_eq!(group(&db, "smallest", vec!["bb"])["min"], json!("340282366920938463463374607431768211455"));
+  assert_eq!(group(&db, "senders", vec!["bb"])["distinct"], json!(1));
+
+  println!("Test empty groups are removed...");
+  write(&db, |batch| {
+    batch.delete_event(&event_key("Transfer", 12, 0).unwrap(), &indexes).unwrap();
+  });
+  assert_eq!(group(&db, "received", vec!["dd"]), json!(null));
+
+  println!("Test events pruned by range stay counted...");
+  write(&db, |batch| {
+    batch.delete_event_entries(&event_key("Transfer", 10, 0).unwrap(), &indexes).unwrap();
+  });
+  assert_eq!(group(&db, "transfers", vec![])["count"], json!(1));
+}
+
+#[test]
+fn test_bad_event_in_batch() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  let indexes = EventIndexes{aggregations: transfer_aggregations(), ..EventIndexes::default()};
+  let put_event = |block_number: u64, params: HashMap<String, String>| {
+    WriteOp::PutEvent(event_key("Token.Transfer", block_number, 0).unwrap(), params, LogTransaction::default(), indexes.clone())
+  };
+
+  println!("Test aggregations failing for an event are skipped and the rest of the batch is written...");
+  let ops = vec![
+    put_event(10, transfer_params("aa", "bb", "5")),
+    put_event(11, transfer_params("cc", "bb", "not a number")),
+    put_event(12, transfer_params("dd", "bb", "3")),
+    WriteOp::UpdateStats(String::from("Token"), String::from("Transfer"), StatsDelta{events: 3, blocks: vec![10, 11, 12], ..StatsDelta::default()})
+  ];
+  assert!(write_batch(&*db.storage, ops, false).is_ok());
+  for block_number in 10..13 {
+    assert!(db.storage.get("events", &event_key("Token.Transfer", block_number, 0).unwrap()).unwrap().is_some());
+  }
+  let group = |aggregation_name: &str, values: Vec<&str>| {
+    let values: Vec<String> = values.into_iter().map(|value| value.to_string()).collect();
+    get_aggregate(&db, "Token.Transfer", aggregation_name, &values).unwrap().unwrap_or(json!(null))
+  };
+  assert_eq!(group("transfers", vec![])["count"], json!(3));
+  assert_eq!(group("received", vec!["bb"])["sum"], json!("8"));
+  assert_eq!(group("received", vec!["bb"])["count"], json!(2));
+  assert_eq!(group("senders", vec!["bb"])["distinct"], json!(3));
+
+  println!("Test skipped aggregations are counted in stats of the event...");
+  let stats = get_ingest_stats(&*db.storage, "Token", "Transfer").unwrap().unwrap();
+  assert_eq!(stats.total_events, 3);
+  assert_eq!(stats.aggregation_failures, 3);
+
+  println!("Test removed bad event is skipped the same way...");
+  assert!(write_batch(&*db.storage, vec![WriteOp::DeleteEvent(event_key("Token.Transfer", 11, 0).unwrap(), indexes.clone())], false).is_ok());
+  assert_eq!(group("transfers", vec![])["count"], json!(2));
+  assert_eq!(group("received", vec!["bb"])["sum"], json!("8"));
+  assert_eq!(get_ingest_stats(&*db.storage, "Token", "Transfer").unwrap().unwrap().aggregation_failures, 6);
+}
+
+#[test]
+fn test_aggregation_pages() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  let indexes = EventIndexes{aggregations: transfer_aggregations(), ..EventIndexes::default()};
+  write(&db, |batch| {
+    for log_index in 0..5 {
+      let to = format!("{:02x}", log_index);
+      batch.put_event(&event_key("Transfer", 10, log_index).unwrap(), transfer_params("aa", &to, "1"), &LogTransaction::default(), &indexes).unwrap();
+    }
+  });
+
+  println!("Test groups are paged...");
+  let (first_page, next) = get_aggregates(&db, "Transfer", "largest", None, 3).unwrap();
+  assert_eq!(first_page.len(), 3);
+  assert!(next.is_some());
+  let (second_page, next) = get_aggregates(&db, "Transfer", "largest", next.as_ref().map(|next| next.as_str()), 3).unwrap();
+  assert_eq!(second_page.len(), 2);
+  assert!(next.is_none());
+  let mut receivers: Vec<serde_json::Value> = first_page.iter().chain(second_page.iter()).map(|group| group["group"]["to"].clone()).collect();
+  receivers.sort_by_key(|to| to.to_string());
+  assert_eq!(receivers, vec![json!("00"), json!("01"), json!("02"), json!("03"), json!("04")]);
+  assert!(second_page.iter().all(|group| group["max"] == json!("1")));
+}
+
+#[test]
+fn test_rebuild_aggregations() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  write(&db, |batch| {
+    batch.put_event(&event_key("Transfer", 10, 0).unwrap(), transfer_params("aa", "bb", "2"), &LogTransaction::default(), &EventIndexes::default()).unwrap();
+    batch.put_event(&event_key("Transfer", 11, 0).unwrap(), transfer_params("cc", "bb", "3"), &LogTransaction::default(), &EventIndexes::default()).unwrap();
+  });
+
+  println!("Test new aggregation is built from stored events...");
+  let mut aggregations = vec![aggregation("received", AggregationKind::Sum, Some("value"), vec!["to"])];
+  check_aggregations(&db, "Transfer", &aggregations).unwrap();
+  assert_eq!(group(&db, "received", vec!["bb"])["sum"], json!("5"));
+
+  println!("Test built aggregation is not built again...");
+  let indexes = EventIndexes{aggregations: aggregations.clone(), ..EventIndexes::default()};
+  write(&db, |batch| {
+    batch.put_event(&event_key("Transfer", 12, 0).unwrap(), transfer_params("aa", "bb", "4"), &LogTransaction::default(), &indexes).unwrap();
+  });
+  check_aggregations(&db, "Transfer", &aggregations).unwrap();
+  assert_eq!(group(&db, "received", vec!["bb"])["sum"], json!("9"));
+  assert_eq!(group(&db, "received", vec!["bb"])["count"], json!(3));
+
+  println!("Test changed aggregation is rebuilt...");
+  aggregations[0].group_by = vec![String::from("from")];
+  check_aggregations(&db, "Transfer", &aggregations).unwrap();
+  assert_eq!(group(&db, "received", vec!["bb"]), json!(null));
+  assert_eq!(group(&db, "received", vec!["aa"])["sum"], json!("6"));
+  assert_eq!(group(&db, "received", vec!["cc"])["sum"], json!("3"));
+}
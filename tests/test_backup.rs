@@ -0,0 +1,81 @@
+extern crate lib;
+extern crate tempdir;
+use lib::db::backup::{create_backup, restore_backup, read_manifest, check_chain_id, get_chain_id};
+use lib::config::structs::storageconfig::StorageConfig;
+use lib::db::cachedb::CacheDB;
+use lib::db::key_schema::{event_key, name_key};
+use lib::db::reader::get_by_key_bytes;
+use lib::db::schema_version::{check_schema_version, SCHEMA_VERSION};
+use tempdir::TempDir;
+
+const COLUMN_FAMILIES: [&'static str; 9] = ["events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline"];
+
+fn put_event(db: &CacheDB, block_number: u64) {
+  db.storage.put("events", &event_key("Transfer", block_number, 0).unwrap(), b"{\"value\": \"1\"}").unwrap();
+  let cursor = format!("{{\"last_block\": \"0x{:x}-0x0\", \"ts\": 0}}", block_number);
+  db.storage.put("aggregations", &name_key("Transfer").unwrap(), cursor.as_bytes()).unwrap();
+}
+
+#[test]
+fn test_chain_id() {
+  let path = TempDir::new("_rust_rocksdb_chain_id").expect("");
+  let db = CacheDB::create(path.path().to_str().unwrap().to_string(), COLUMN_FAMILIES.to_vec(), 30).unwrap();
+  assert_eq!(get_chain_id(&db).unwrap(), None);
+  check_chain_id(&db, 1).unwrap();
+  assert_eq!(get_chain_id(&db).unwrap(), Some(1));
+  assert!(check_chain_id(&db, 1).is_ok());
+  assert!(check_chain_id(&db, 3).is_err());
+}
+
+#[test]
+fn test_backup_and_restore() {
+  let source_path = TempDir::new("_rust_rocksdb_backup_source").expect("");
+  let backups_path = TempDir::new("_rust_rocksdb_backups").expect("");
+  let backup_path = backups_path.path().join("backup").to_str().unwrap().to_string();
+  let restored_path = TempDir::new("_rust_rocksdb_restored").expect("");
+  let restored_path_str = restored_path.path().to_str().unwrap().to_string();
+
+  let db = CacheDB::create(source_path.path().to_str().unwrap().to_string(), COLUMN_FAMILIES.to_vec(), 30).unwrap();
+  check_schema_version(&db).unwrap();
+  check_chain_id(&db, 1337).unwrap();
+  put_event(&db, 10);
+
+  println!("Test backup of a running database...");
+  let manifest = create_backup(&db, &backup_path).unwrap();
+  assert_eq!(manifest.schema_version, SCHEMA_VERSION);
+  assert_eq!(manifest.chain_id, Some(1337));
+  assert_eq!(manifest.cursors.get("Transfer"), Some(&String::from("0xa-0x0")));
+  assert_eq!(read_manifest(&backup_path).unwrap(), manifest);
+  assert!(create_backup(&db, &backup_path).is_err());
+  put_event(&db, 11);
+
+  println!("Test restore of the backup...");
+  let restored_manifest = restore_backup(&backup_path, &restored_path_str, COLUMN_FAMILIES.to_vec(), 30, StorageConfig::default()).unwrap();
+  assert_eq!(restored_manifest, manifest);
+  let restored_db = CacheDB::create(restored_path_str.clone(), COLUMN_FAMILIES.to_vec(), 30).unwrap();
+  assert!(get_by_key_bytes(restored_db.clone(), String::from("events"), event_key("Transfer", 10, 0).unwrap()).is_ok());
+  assert!(get_by_key_bytes(restored_db.clone(), String::from("events"), event_key("Transfer", 11, 0).unwrap()).is_err());
+  assert_eq!(check_schema_version(&restored_db).unwrap(), SCHEMA_VERSION);
+  assert!(check_chain_id(&restored_db, 1).is_err());
+
+  println!("Test restore refuses non-empty database path...");
+  assert!(restore_backup(&backup_path, &restored_path_str, COLUMN_FAMILIES.to_vec(), 30, StorageConfig::default()).is_err());
+}
+
+#[test]
+fn test_restore_skips_lock_and_logs() {
+  let source_path = TempDir::new("_rust_rocksdb_backup_logs_source").expect("");
+  let backups_path = TempDir::new("_rust_rocksdb_backup_logs").expect("");
+  let backup_path = backups_path.path().join("backup").to_str().unwrap().to_string();
+  let restored_path = TempDir::new("_rust_rocksdb_backup_logs_restored").expect("");
+
+  let db = CacheDB::create(source_path.path().to_str().unwrap().to_string(), COLUMN_FAMILIES.to_vec(), 30).unwrap();
+  check_schema_version(&db).unwrap();
+  put_event(&db, 10);
+  create_backup(&db, &backup_path).unwrap();
+  std::fs::write(std::path::Path::new(&backup_path).join("LOG.old.1"), "info log of the source").unwrap();
+
+  println!("Test lock and info logs of the backup are not restored...");
+  restore_backup(&backup_path, restored_path.path().to_str().unwrap(), COLUMN_FAMILIES.to_vec(), 30, StorageConfig::default()).unwrap();
+  assert!(!restored_path.path().join("LOG.old.1").exists());
+}
@@ -0,0 +1,53 @@
+extern crate lib;
+extern crate tempdir;
+extern crate serde_json;
+use lib::config::structs::storageconfig::StorageConfig;
+use lib::db::cachedb::CacheDB;
+use lib::db::compaction::{compact, get_compaction_status, get_compaction_report};
+use lib::db::key_schema::event_key;
+use tempdir::TempDir;
+
+const COLUMN_FAMILIES: [&'static str; 9] = ["events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline"];
+
+#[test]
+fn test_compaction_triggers() {
+  let storage_config: StorageConfig = serde_json::from_str(r#"{
+    "columnFamilies": {
+      "events": {"disableAutoCompactions": true},
+      "indexes": {"disableAutoCompactions": true, "compactionIntervalSecs": 600},
+      "timeline": {"compactionTriggerMb": 64}
+    }
+  }"#).unwrap();
+  assert_eq!(storage_config.get_compaction_triggers("events"), Some((Some(3600), Some(1024))));
+  assert_eq!(storage_config.get_compaction_triggers("indexes"), Some((Some(600), None)));
+  assert_eq!(storage_config.get_compaction_triggers("timeline"), Some((None, Some(64))));
+  assert_eq!(storage_config.get_compaction_triggers("addresses"), None);
+}
+
+#[test]
+fn test_compact() {
+  let path = TempDir::new("_rust_rocksdb_compaction").expect("");
+  let storage_config: StorageConfig = serde_json::from_str(r#"{"columnFamilies": {"events": {"disableAutoCompactions": true}}}"#).unwrap();
+  let db = CacheDB::create_with_config(path.path().to_str().unwrap().to_string(), COLUMN_FAMILIES.to_vec(), 30, storage_config).unwrap();
+  for block_number in 0..100 {
+    db.storage.put("events", &event_key("Transfer", block_number, 0).unwrap(), b"{\"value\": \"1\"}").unwrap();
+  }
+  assert!(get_compaction_status(&db, "events").unwrap().is_none());
+
+  println!("Test compaction of the whole column family...");
+  let compaction_status = compact(&db, "events", None, None, "manual").unwrap();
+  assert_eq!(compaction_status.status, "done");
+  assert!(compaction_status.finished_at.is_some());
+  assert!(compaction_status.after.unwrap().total_sst_files_size > 0);
+  assert_eq!(get_compaction_status(&db, "events").unwrap().unwrap().status, "done");
+
+  println!("Test compaction of a key range...");
+  let from = event_key("Transfer", 10, 0).unwrap();
+  let to = event_key("Transfer", 20, 0).unwrap();
+  let compaction_status = compact(&db, "events", Some(&from), Some(&to), "manual").unwrap();
+  assert_eq!(compaction_status.from, Some(lib::db::key_codec::encode_hex(&from)));
+  let report = get_compaction_report(&db, "events").unwrap();
+  assert_eq!(report["lastCompaction"]["status"], "done");
+  assert!(report["stats"]["totalSstFilesSize"].as_u64().unwrap() > 0);
+  assert!(compact(&db, "unknown", None, None, "manual").is_err());
+}
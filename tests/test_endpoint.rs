@@ -0,0 +1,22 @@
+extern crate lib;
+extern crate tempdir;
+use std::fs::File;
+use std::io::Write;
+use lib::eth::endpoint::Endpoint;
+use tempdir::TempDir;
+
+#[test]
+fn test_ca_bundle() {
+  let path = TempDir::new("_cacherz_ca_bundle").expect("");
+
+  println!("Test CA bundle is read when endpoint is built, not on request...");
+  let missing_path = path.path().join("missing.pem").to_str().unwrap().to_string();
+  assert!(Endpoint::from_url(String::from("https://localhost:8545")).with_ca_bundle(Some(missing_path)).is_err());
+  let invalid_path = path.path().join("invalid.pem");
+  File::create(&invalid_path).unwrap().write_all(b"not a certificate").unwrap();
+  assert!(Endpoint::from_url(String::from("https://localhost:8545")).with_ca_bundle(Some(invalid_path.to_str().unwrap().to_string())).is_err());
+
+  println!("Test endpoint without CA bundle keeps default roots...");
+  let endpoint = Endpoint::from_url(String::from("https://localhost:8545")).with_ca_bundle(None).unwrap();
+  assert_eq!(endpoint.ca_bundle_path, None);
+}
@@ -0,0 +1,147 @@
+extern crate lib;
+extern crate ethabi;
+extern crate parquet;
+extern crate tempdir;
+#[macro_use]
+extern crate serde_json;
+use ethabi::{Event, EventParam, ParamType};
+use serde_json::Value;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::{Field, RowAccessor};
+use std::fs::{self, File};
+use tempdir::TempDir;
+use lib::db::cachedb::CacheDB;
+use lib::db::key_schema::{event_key, KeySchema, KeyValue};
+use lib::db::export::{export_events, select_sources, ExportFormat, ExportSource};
+
+const COLUMN_FAMILIES: [&str; 9] = ["events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline"];
+
+fn transfer_source() -> ExportSource {
+  ExportSource{contract: String::from("Token"), event: Event{
+    name: String::from("Transfer"),
+    inputs: vec![
+      EventParam{name: String::from("from"), kind: ParamType::Address, indexed: true},
+      EventParam{name: String::from("value"), kind: ParamType::Uint(256), indexed: false},
+      EventParam{name: String::from("nonce"), kind: ParamType::Uint(64), indexed: false},
+      EventParam{name: String::from("delta"), kind: ParamType::Int(32), indexed: false},
+      EventParam{name: String::from("memo"), kind: ParamType::String, indexed: false}
+    ],
+    anonymous: false
+  }, index_tx_sender: false}
+}
+
+fn approval_source() -> ExportSource {
+  ExportSource{contract: String::from("Token"), event: Event{
+    name: String::from("Approval"),
+    inputs: vec![
+      EventParam{name: String::from("spender"), kind: ParamType::Address, indexed: true},
+      EventParam{name: String::from("approved"), kind: ParamType::Bool, indexed: false},
+      EventParam{name: String::from("block"), kind: ParamType::Uint(64), indexed: false}
+    ],
+    anonymous: false
+  }, index_tx_sender: false}
+}
+
+fn export_to_vec(db: &CacheDB, sources: &[ExportSource], from: Option<u64>, to: Option<u64>, format: ExportFormat) -> (u64, Vec<u8>) {
+  let mut out: Vec<u8> = Vec::new();
+  let rows = export_events(db, sources, from, to, format, &mut out).unwrap();
+  (rows, out)
+}
+
+#[test]
+fn test_export_events() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  for block_number in 1..4 {
+    let value = json!({
+      "from": "00000000000000000000000000000000000000aa",
+      "value": "de0b6b3a7640000",
+      "nonce": format!("{:x}", block_number),
+      "delta": "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+      "memo": format!("hello, \"{}\"", block_number)
+    }).to_string();
+    db.storage.put("events", &event_key("Token.Transfer", block_number, 0).unwrap(), value.as_bytes()).unwrap();
+  }
+  let timeline_key = KeySchema::timeline().encode(&[KeyValue::Name(String::from("Token")), KeyValue::U64(1), KeyValue::U32(0),
+    KeyValue::Name(String::from("Token.Transfer"))]).unwrap();
+  let timeline_entry = json!({"contract": "Token", "transactionHash": "0xabc", "transactionIndex": "0x2"}).to_string();
+  db.storage.put("timeline", &timeline_key, timeline_entry.as_bytes()).unwrap();
+  let approval = json!({"spender": "00000000000000000000000000000000000000bb", "approved": "true", "block": "7"}).to_string();
+  db.storage.put("events", &event_key("Token.Approval", 2, 1).unwrap(), approval.as_bytes()).unwrap();
+  let sources = vec![transfer_source(), approval_source()];
+
+  println!("Test event or contract has to be selected...");
+  assert!(select_sources(&sources, None, None).is_err());
+  assert!(select_sources(&sources, Some("Other"), None).is_err());
+  let transfers = select_sources(&sources, None, Some("Transfer")).unwrap();
+  assert_eq!(transfers, vec![transfer_source()]);
+  assert_eq!(select_sources(&sources, Some("Token"), None).unwrap(), vec![approval_source(), transfer_source()]);
+
+  println!("Test csv has typed columns and quoted fields...");
+  let (rows, csv) = export_to_vec(&db, &transfers, None, None, ExportFormat::Csv);
+  assert_eq!(rows, 3);
+  let csv = String::from_utf8(csv).unwrap();
+  let lines: Vec<&str> = csv.lines().collect();
+  assert_eq!(lines[0], "contract,event,block,log,transactionHash,transactionIndex,arg.from,arg.value,arg.nonce,arg.delta,arg.memo");
+  assert_eq!(lines[1], "Token,Transfer,1,0,0xabc,0x2,0x00000000000000000000000000000000000000aa,1000000000000000000,1,-1,\"hello, \"\"1\"\"\"");
+  assert_eq!(lines[2], "Token,Transfer,2,0,,,0x00000000000000000000000000000000000000aa,1000000000000000000,2,-1,\"hello, \"\"2\"\"\"");
+  assert_eq!(lines.len(), 4);
+
+  println!("Test ndjson of a block range...");
+  let (rows, ndjson) = export_to_vec(&db, &transfers, Some(2), Some(2), ExportFormat::Ndjson);
+  assert_eq!(rows, 1);
+  let row: Value = serde_json::from_slice(&ndjson[..ndjson.len() - 1]).unwrap();
+  assert_eq!(row, json!({"contract": "Token", "event": "Transfer", "block": 2, "log": 0,
+    "arg.from": "0x00000000000000000000000000000000000000aa", "arg.value": "1000000000000000000", "arg.nonce": 2, "arg.delta": -1, "arg.memo": "hello, \"2\""}));
+
+  println!("Test contract export leaves out arguments of other events and keeps arguments named as metadata...");
+  let (rows, ndjson) = export_to_vec(&db, &select_sources(&sources, Some("Token"), None).unwrap(), None, None, ExportFormat::Ndjson);
+  assert_eq!(rows, 4);
+  let first_row: Value = serde_json::from_str(String::from_utf8(ndjson).unwrap().lines().next().unwrap()).unwrap();
+  assert_eq!(first_row, json!({"contract": "Token", "event": "Approval", "block": 2, "log": 1,
+    "arg.spender": "0x00000000000000000000000000000000000000bb", "arg.approved": true, "arg.block": 7}));
+
+  println!("Test parquet file has magic and footer...");
+  let (rows, parquet) = export_to_vec(&db, &transfers, None, None, ExportFormat::Parquet);
+  assert_eq!(rows, 3);
+  assert_eq!(&parquet[..4], b"PAR1");
+  assert_eq!(&parquet[parquet.len() - 4..], b"PAR1");
+  let footer_size = parquet[parquet.len() - 8..parquet.len() - 4].iter().rev().fold(0usize, |size, byte| (size << 8) | *byte as usize);
+  assert!(footer_size > 0 && footer_size + 12 < parquet.len());
+
+  println!("Test parquet file is read back with typed columns...");
+  let dir = TempDir::new("test_export_parquet").unwrap();
+  let parquet_path = dir.path().join("transfers.parquet");
+  fs::write(&parquet_path, &parquet).unwrap();
+  let reader = SerializedFileReader::new(File::open(&parquet_path).unwrap()).unwrap();
+  let file_metadata = reader.metadata().file_metadata();
+  assert_eq!(file_metadata.num_rows(), 3);
+  let column_names: Vec<String> = (0..file_metadata.schema_descr().num_columns())
+    .map(|position| file_metadata.schema_descr().column(position).name().to_string())
+    .collect();
+  assert_eq!(column_names, vec!["contract", "event", "block", "log", "transactionHash", "transactionIndex", "arg.from", "arg.value", "arg.nonce", "arg.delta", "arg.memo"]);
+  let read_rows: Vec<_> = reader.get_row_iter(None).unwrap().collect();
+  assert_eq!(read_rows.len(), 3);
+  let row = &read_rows[1];
+  assert_eq!(row.get_string(0).unwrap(), "Token");
+  assert_eq!(row.get_string(1).unwrap(), "Transfer");
+  assert_eq!(row.get_ulong(2).unwrap(), 2);
+  assert_eq!(row.get_ulong(3).unwrap(), 0);
+  assert_eq!(row.get_string(6).unwrap(), "0x00000000000000000000000000000000000000aa");
+  assert_eq!(row.get_string(7).unwrap(), "1000000000000000000");
+  assert_eq!(row.get_ulong(8).unwrap(), 2);
+  assert_eq!(row.get_long(9).unwrap(), -1);
+  assert_eq!(row.get_string(10).unwrap(), "hello, \"2\"");
+  assert_eq!(read_rows[0].get_string(4).unwrap(), "0xabc");
+  assert_eq!(read_rows[0].get_string(5).unwrap(), "0x2");
+
+  println!("Test parquet arguments of other events are null...");
+  let (_, contract_parquet) = export_to_vec(&db, &select_sources(&sources, Some("Token"), None).unwrap(), None, None, ExportFormat::Parquet);
+  let contract_path = dir.path().join("token.parquet");
+  fs::write(&contract_path, &contract_parquet).unwrap();
+  let reader = SerializedFileReader::new(File::open(&contract_path).unwrap()).unwrap();
+  let first_row = reader.get_row_iter(None).unwrap().next().unwrap();
+  let fields: Vec<(String, Field)> = first_row.get_column_iter().map(|(name, field)| (name.clone(), field.clone())).collect();
+  assert_eq!(fields[1], (String::from("event"), Field::Str(String::from("Approval"))));
+  assert_eq!(fields[7], (String::from("arg.approved"), Field::Bool(true)));
+  assert!(fields.iter().any(|&(ref name, ref field)| name == "arg.memo" && *field == Field::Null));
+}
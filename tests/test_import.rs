@@ -0,0 +1,208 @@
+extern crate lib;
+extern crate ethabi;
+#[macro_use]
+extern crate serde_json;
+use ethabi::{Event, EventParam, ParamType};
+use serde_json::Value;
+use lib::commands::import::{import, stored_param, ImportTarget};
+use lib::db::cachedb::CacheDB;
+use lib::db::export::{export_events, ExportFormat, ExportSource};
+use lib::db::indexes::EventIndexes;
+use lib::db::key_schema::{event_key, name_key, KeySchema, KeyValue};
+use lib::db::stats::get_ingest_stats;
+use lib::db::value_codec::decode_params;
+
+const COLUMN_FAMILIES: [&str; 9] = ["events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline"];
+
+fn transfer_event() -> Event {
+  Event{
+    name: String::from("Transfer"),
+    inputs: vec![
+      EventParam{name: String::from("from"), kind: ParamType::Address, indexed: true},
+      EventParam{name: String::from("value"), kind: ParamType::Uint(256), indexed: false},
+      EventParam{name: String::from("delta"), kind: ParamType::Int(32), indexed: false}
+    ],
+    anonymous: false
+  }
+}
+
+fn transfer_target(index_tx_sender: bool) -> ImportTarget {
+  let event = transfer_event();
+  let event_indexes = EventIndexes::for_event(String::from("Token"), &event, Vec::new(), index_tx_sender);
+  ImportTarget{contract: String::from("Token"), event: event, event_indexes: event_indexes}
+}
+
+fn total_events(db: &CacheDB) -> u64 {
+  get_ingest_stats(&*db.storage, "Token", "Transfer").unwrap().unwrap().total_events
+}
+
+fn get_timeline_entry(db: &CacheDB, block_number: u64, log_index: u32) -> Value {
+  let timeline_key = KeySchema::timeline().encode(&[KeyValue::Name(String::from("Token")), KeyValue::U64(block_number), KeyValue::U32(log_index),
+    KeyValue::Name(String::from("Token.Transfer"))]).unwrap();
+  serde_json::from_slice(&db.storage.get("timeline", &timeline_key).unwrap().unwrap()).unwrap()
+}
+
+fn count_entries(db: &CacheDB, column_family: &str) -> usize {
+  let mut iter = db.storage.iter(column_family).unwrap();
+  iter.seek_to_first();
+  let mut count = 0;
+  while iter.valid() {
+    count = count + 1;
+    iter.next();
+  }
+  count
+}
+
+fn get_cursor(db: &CacheDB) -> String {
+  let cursor: Value = serde_json::from_slice(&db.storage.get("aggregations", &name_key("Token.Transfer").unwrap()).unwrap().unwrap()).unwrap();
+  cursor["last_block"].as_str().unwrap().to_string()
+}
+
+#[test]
+fn test_import_events() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  let targets = vec![transfer_target(false)];
+  let dump = vec![
+    json!({"contract": "Token", "event": "Transfer", "block": 5, "log": 1,
+      "from": "0x00000000000000000000000000000000000000AA", "value": "1000000000000000000", "delta": -1}).to_string(),
+    json!({"key": "Transfer-0x7-0x0", "fields": {"event": "Transfer", "block": "0x7", "log": "0x0"},
+      "params": {"from": "00000000000000000000000000000000000000bb", "value": "10", "delta": "2"}}).to_string(),
+    json!({"key": "Token.Transfer-0x6-0x2", "params": {"from": "00000000000000000000000000000000000000cc", "value": "1", "delta": "0"}}).to_string()
+  ].join("\n");
+
+  println!("Test export and web records are written with stored params...");
+  let summary = import(&db, &targets, dump.as_bytes(), 2).unwrap();
+  assert_eq!(summary.events, 3);
+  let params = decode_params(&db.storage.get("events", &event_key("Token.Transfer", 5, 1).unwrap()).unwrap().unwrap()).unwrap();
+  assert_eq!(params["from"], "00000000000000000000000000000000000000aa");
+  assert_eq!(params["value"], "de0b6b3a7640000");
+  assert_eq!(params["delta"], "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+  let params = decode_params(&db.storage.get("events", &event_key("Token.Transfer", 7, 0).unwrap()).unwrap().unwrap()).unwrap();
+  assert_eq!(params["value"], "10");
+  assert!(db.storage.get("events", &event_key("Token.Transfer", 6, 2).unwrap()).unwrap().is_some());
+  assert_eq!(count_entries(&db, "addresses"), 3);
+  assert_eq!(count_entries(&db, "timeline"), 3);
+
+  println!("Test imported events are counted once in stats...");
+  assert_eq!(total_events(&db), 3);
+  import(&db, &targets, dump.as_bytes(), 2).unwrap();
+  assert_eq!(total_events(&db), 3);
+
+  println!("Test cursor is set to the last imported event and never moves back...");
+  assert_eq!(get_cursor(&db), "0x7-0x0");
+  assert_eq!(summary.cursors["Token.Transfer"], "0x7-0x0");
+  let older = json!({"event": "Transfer", "block": 3, "log": 0, "from": "0x00000000000000000000000000000000000000aa", "value": 1, "delta": 1}).to_string();
+  import(&db, &targets, older.as_bytes(), 2).unwrap();
+  assert_eq!(get_cursor(&db), "0x7-0x0");
+
+  println!("Test records not matching the abi are refused with their line...");
+  let invalid_records = vec![
+    json!({"event": "Approval", "block": 8, "log": 0}),
+    json!({"event": "Transfer", "block": 8, "log": 0, "from": "0xaa", "value": 1, "delta": 1}),
+    json!({"event": "Transfer", "block": 8, "log": 0, "from": "0x00000000000000000000000000000000000000aa", "value": 1}),
+    json!({"event": "Transfer", "block": 8, "log": 0, "from": "0x00000000000000000000000000000000000000aa", "value": 1, "delta": 1, "memo": "x"}),
+    json!({"event": "Transfer", "block": 8, "log": 0, "from": "0x00000000000000000000000000000000000000aa", "value": -1, "delta": 1}),
+    json!({"event": "Transfer", "block": 8, "log": 0, "from": "0x00000000000000000000000000000000000000aa", "value": 1, "delta": 2147483648u64}),
+    json!({"event": "Transfer", "block": 8, "log": 0, "from": "0x00000000000000000000000000000000000000aa", "value": 1, "delta": "-2147483649"}),
+    json!({"event": "Transfer", "block": 8, "log": 0, "from": "0x00000000000000000000000000000000000000aa", "value": 1,
+      "delta": format!("0x1{}", "0".repeat(50))})
+  ];
+  for invalid_record in invalid_records {
+    let invalid_dump = format!("\n{}", invalid_record);
+    assert!(import(&db, &targets, invalid_dump.as_bytes(), 2).unwrap_err().contains("line 2"));
+  }
+  assert!(db.storage.get("events", &event_key("Token.Transfer", 8, 0).unwrap()).unwrap().is_none());
+
+  println!("Test exported events are imported unchanged...");
+  let mut exported: Vec<u8> = Vec::new();
+  export_events(&db, &[ExportSource{contract: String::from("Token"), event: transfer_event(), index_tx_sender: false}], None, None, ExportFormat::Ndjson, &mut exported).unwrap();
+  let copy_db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  assert_eq!(import(&copy_db, &targets, exported.as_slice(), 2).unwrap().events, 4);
+  for &(block_number, log_index) in [(3, 0), (5, 1), (6, 2), (7, 0)].iter() {
+    let key = event_key("Token.Transfer", block_number, log_index).unwrap();
+    let original = decode_params(&db.storage.get("events", &key).unwrap().unwrap()).unwrap();
+    let copy = decode_params(&copy_db.storage.get("events", &key).unwrap().unwrap()).unwrap();
+    assert_eq!(original, copy);
+  }
+}
+
+#[test]
+fn test_stored_param_width() {
+  println!("Test numbers have to fit into their abi type...");
+  assert_eq!(stored_param(&ParamType::Uint(8), &json!(255)).unwrap(), "ff");
+  assert!(stored_param(&ParamType::Uint(8), &json!(300)).is_err());
+  assert!(stored_param(&ParamType::Uint(8), &json!("0x100")).is_err());
+  assert_eq!(stored_param(&ParamType::Int(32), &json!("-2147483648")).unwrap(), format!("{}80000000", "f".repeat(56)));
+  assert!(stored_param(&ParamType::Int(32), &json!(format!("{}", 1u64 << 40))).is_err());
+  assert!(stored_param(&ParamType::Int(32), &json!(format!("0x1{}", "0".repeat(50)))).is_err());
+  assert_eq!(stored_param(&ParamType::Int(8), &json!(format!("0x{}80", "f".repeat(62)))).unwrap(), format!("{}80", "f".repeat(62)));
+  assert!(stored_param(&ParamType::Int(8), &json!(128)).is_err());
+  assert!(stored_param(&ParamType::Int(256), &json!(format!("-1{}", "0".repeat(77)))).is_err());
+}
+
+#[test]
+fn test_import_round_trip_with_tx_sender() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  let targets = vec![transfer_target(true)];
+  let dump = vec![
+    json!({"event": "Transfer", "block": 5, "log": 1, "transactionHash": "0xabc", "transactionIndex": "0x3",
+      "txSender": "0x00000000000000000000000000000000000000dd", "from": "0x00000000000000000000000000000000000000aa", "value": 1, "delta": -1}).to_string(),
+    json!({"event": "Transfer", "block": 6, "log": 0, "transactionHash": "0xdef", "transactionIndex": "0x0",
+      "from": "0x00000000000000000000000000000000000000bb", "value": 2, "delta": 2}).to_string()
+  ].join("\n");
+  import(&db, &targets, dump.as_bytes(), 10).unwrap();
+
+  println!("Test export has transaction and sender of imported events...");
+  let mut exported: Vec<u8> = Vec::new();
+  let sources = [ExportSource{contract: String::from("Token"), event: transfer_event(), index_tx_sender: true}];
+  assert_eq!(export_events(&db, &sources, None, None, ExportFormat::Ndjson, &mut exported).unwrap(), 2);
+  let first_row: Value = serde_json::from_str(String::from_utf8(exported.clone()).unwrap().lines().next().unwrap()).unwrap();
+  assert_eq!(first_row["transactionHash"], "0xabc");
+  assert_eq!(first_row["transactionIndex"], "0x3");
+  assert_eq!(first_row["txSender"], "0x00000000000000000000000000000000000000dd");
+
+  println!("Test export is imported back with transactions, senders and address entries...");
+  let copy_db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  assert_eq!(import(&copy_db, &targets, exported.as_slice(), 10).unwrap().events, 2);
+  for &(block_number, log_index) in [(5, 1), (6, 0)].iter() {
+    let key = event_key("Token.Transfer", block_number, log_index).unwrap();
+    let original = decode_params(&db.storage.get("events", &key).unwrap().unwrap()).unwrap();
+    let copy = decode_params(&copy_db.storage.get("events", &key).unwrap().unwrap()).unwrap();
+    assert_eq!(original, copy);
+    assert_eq!(get_timeline_entry(&db, block_number, log_index), get_timeline_entry(&copy_db, block_number, log_index));
+  }
+  assert_eq!(count_entries(&copy_db, "addresses"), 3);
+  assert_eq!(total_events(&copy_db), 2);
+}
+
+#[test]
+fn test_import_round_trip_of_arguments_named_as_metadata() {
+  let event = Event{
+    name: String::from("Moved"),
+    inputs: vec![
+      EventParam{name: String::from("block"), kind: ParamType::Uint(64), indexed: false},
+      EventParam{name: String::from("event"), kind: ParamType::String, indexed: false}
+    ],
+    anonymous: false
+  };
+  let event_indexes = EventIndexes::for_event(String::from("Token"), &event, Vec::new(), false);
+  let targets = vec![ImportTarget{contract: String::from("Token"), event: event.clone(), event_indexes: event_indexes}];
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  let dump = json!({"event": "Moved", "block": 5, "log": 0, "arg.block": 9, "arg.event": "moved"}).to_string();
+  import(&db, &targets, dump.as_bytes(), 10).unwrap();
+  let key = event_key("Token.Moved", 5, 0).unwrap();
+  let params = decode_params(&db.storage.get("events", &key).unwrap().unwrap()).unwrap();
+  assert_eq!(params["block"], "9");
+  assert_eq!(params["event"], "moved");
+
+  println!("Test arguments named as metadata are exported and imported back...");
+  let mut exported: Vec<u8> = Vec::new();
+  let sources = [ExportSource{contract: String::from("Token"), event: event, index_tx_sender: false}];
+  assert_eq!(export_events(&db, &sources, None, None, ExportFormat::Ndjson, &mut exported).unwrap(), 1);
+  let row: Value = serde_json::from_slice(&exported[..exported.len() - 1]).unwrap();
+  assert_eq!(row["block"], 5);
+  assert_eq!(row["arg.block"], 9);
+  let copy_db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  assert_eq!(import(&copy_db, &targets, exported.as_slice(), 10).unwrap().events, 1);
+  assert_eq!(decode_params(&copy_db.storage.get("events", &key).unwrap().unwrap()).unwrap(), params);
+}
@@ -0,0 +1,236 @@
+extern crate lib;
+extern crate tempdir;
+use std::collections::HashMap;
+use lib::config::structs::contractconfig::IndexConfig;
+use lib::db::cachedb::CacheDB;
+use lib::db::indexes::{EventBatch, EventIndexes, LogTransaction, index_query, address_query, timeline_query, GLOBAL_TIMELINE};
+use lib::db::key_schema::{event_key, qualified_name};
+use lib::db::reader::{get_events_by_index, get_events_by_address, get_events_by_timeline};
+use tempdir::TempDir;
+
+fn transfer_params(from: &str, to: &str, value: &str) -> HashMap<String, String> {
+  let mut params = HashMap::new();
+  params.insert(String::from("from"), from.to_string());
+  params.insert(String::from("to"), to.to_string());
+  params.insert(String::from("value"), value.to_string());
+  params
+}
+
+fn address(byte: u8) -> String {
+  format!("{:040x}", byte)
+}
+
+fn address_page(db: &CacheDB, address: &str, from: Option<(u64, u32)>, size: i32, event_name: Option<&str>) -> (Vec<String>, Option<Vec<u8>>) {
+  let query = address_query(address, from).unwrap();
+  let (events, next) = get_events_by_address(db.clone(), query, size, None, event_name.map(|name| name.to_string())).unwrap();
+  (events.into_iter().map(|event| event.key).collect(), next)
+}
+
+fn write<F>(db: &CacheDB, build: F) where F: FnOnce(&mut EventBatch) {
+  let mut event_batch = EventBatch::new(&*db.storage);
+  build(&mut event_batch);
+  db.storage.write(event_batch.into_batch(), false).unwrap();
+}
+
+fn query_blocks(db: &CacheDB, index_name: &str, values: Vec<&str>, from: Option<(u64, u32)>) -> Vec<String> {
+  let values: Vec<String> = values.into_iter().map(|value| value.to_string()).collect();
+  let query = index_query("Transfer", index_name, &values, from).unwrap();
+  get_events_by_index(db.clone(), query, 100).unwrap()
+    .into_iter()
+    .map(|event| event.key)
+    .collect()
+}
+
+#[test]
+fn test_secondary_indexes() {
+  let path = TempDir::new("_rust_rocksdb_indexes").expect("");
+  let column_families = vec!("events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline");
+  let db = CacheDB::create(path.path().to_str().unwrap().to_string(), column_families, 30).unwrap();
+  let indexes = EventIndexes{
+    contract: String::from("Token"),
+    indexes: vec![
+      IndexConfig{name: String::from("from"), args: vec![String::from("from")]},
+      IndexConfig{name: String::from("from_to"), args: vec![String::from("from"), String::from("to")]}
+    ],
+    address_args: Vec::new(),
+    index_tx_sender: false,
+    global_timeline: false,
+    aggregations: Vec::new()
+  };
+
+  println!("Test index entries are ordered by block and log...");
+  write(&db, |batch| {
+    batch.put_event(&event_key("Transfer", 12, 0).unwrap(), transfer_params("aa", "bb", "1"), &LogTransaction::default(), &indexes).unwrap();
+    batch.put_event(&event_key("Transfer", 10, 1).unwrap(), transfer_params("aa", "cc", "2"), &LogTransaction::default(), &indexes).unwrap();
+    batch.put_event(&event_key("Transfer", 11, 0).unwrap(), transfer_params("dd", "bb", "3"), &LogTransaction::default(), &indexes).unwrap();
+  });
+  assert_eq!(query_blocks(&db, "from", vec!["0xAA"], None), vec!["Transfer-0xa-0x1", "Transfer-0xc-0x0"]);
+  assert_eq!(query_blocks(&db, "from", vec!["aa"], Some((11, 0))), vec!["Transfer-0xc-0x0"]);
+  assert_eq!(query_blocks(&db, "from_to", vec!["aa", "bb"], None), vec!["Transfer-0xc-0x0"]);
+  assert!(query_blocks(&db, "from", vec!["bb"], None).is_empty());
+
+  println!("Test overwritten event moves its index entries...");
+  write(&db, |batch| {
+    batch.put_event(&event_key("Transfer", 12, 0).unwrap(), transfer_params("dd", "bb", "1"), &LogTransaction::default(), &indexes).unwrap();
+  });
+  assert_eq!(query_blocks(&db, "from", vec!["aa"], None), vec!["Transfer-0xa-0x1"]);
+  assert_eq!(query_blocks(&db, "from", vec!["dd"], None), vec!["Transfer-0xb-0x0", "Transfer-0xc-0x0"]);
+
+  println!("Test reorg rollback removes index entries...");
+  write(&db, |batch| {
+    batch.put_event(&event_key("Transfer", 13, 0).unwrap(), transfer_params("aa", "bb", "4"), &LogTransaction::default(), &indexes).unwrap();
+    batch.delete_event(&event_key("Transfer", 13, 0).unwrap(), &indexes).unwrap();
+    batch.delete_event(&event_key("Transfer", 10, 1).unwrap(), &indexes).unwrap();
+  });
+  assert!(query_blocks(&db, "from", vec!["aa"], None).is_empty());
+  assert!(query_blocks(&db, "from_to", vec!["aa", "bb"], None).is_empty());
+  assert_eq!(query_blocks(&db, "from_to", vec!["dd", "bb"], None), vec!["Transfer-0xb-0x0", "Transfer-0xc-0x0"]);
+}
+
+#[test]
+fn test_address_index() {
+  let path = TempDir::new("_rust_rocksdb_addresses").expect("");
+  let column_families = vec!("events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline");
+  let db = CacheDB::create(path.path().to_str().unwrap().to_string(), column_families, 30).unwrap();
+  let transfer_indexes = EventIndexes{
+    contract: String::from("Token"),
+    indexes: Vec::new(),
+    address_args: vec![String::from("from"), String::from("to")],
+    index_tx_sender: false,
+    global_timeline: false,
+    aggregations: Vec::new()
+  };
+  let approval_indexes = EventIndexes{contract: String::from("Exchange"), ..transfer_indexes.clone()};
+
+  println!("Test address entries are ordered by block and log across events...");
+  write(&db, |batch| {
+    batch.put_event(&event_key("Transfer", 12, 0).unwrap(), transfer_params(&address(1), &address(2), "1"), &LogTransaction::default(), &transfer_indexes).unwrap();
+    batch.put_event(&event_key("Approval", 11, 3).unwrap(), transfer_params(&address(2), &address(1), "2"), &LogTransaction::default(), &approval_indexes).unwrap();
+    batch.put_event(&event_key("Transfer", 10, 1).unwrap(), transfer_params(&address(1), &address(1), "3"), &LogTransaction::default(), &transfer_indexes).unwrap();
+    batch.put_event(&event_key("Transfer", 13, 0).unwrap(), transfer_params(&address(2), &address(3), "4"), &LogTransaction::default(), &transfer_indexes).unwrap();
+  });
+  let (all_events, next) = address_page(&db, &format!("0x{}", address(1)), None, 10, None);
+  assert_eq!(all_events, vec!["Transfer-0xa-0x1", "Approval-0xb-0x3", "Transfer-0xc-0x0"]);
+  assert!(next.is_none());
+
+  println!("Test pagination and filters...");
+  let (first_page, next) = address_page(&db, &address(1), None, 2, None);
+  assert_eq!(first_page, vec!["Transfer-0xa-0x1", "Approval-0xb-0x3"]);
+  assert!(next.is_some());
+  let (second_page, _) = address_page(&db, &address(1), Some((11, 4)), 2, None);
+  assert_eq!(second_page, vec!["Transfer-0xc-0x0"]);
+  assert_eq!(address_page(&db, &address(1), None, 10, Some("Approval")).0, vec!["Approval-0xb-0x3"]);
+  let query = address_query(&address(1), None).unwrap();
+  let (exchange_events, _) = get_events_by_address(db.clone(), query, 10, Some(String::from("Exchange")), None).unwrap();
+  assert_eq!(exchange_events.len(), 1);
+  assert_eq!(exchange_events[0].fields.as_ref().unwrap()["contract"], "Exchange");
+
+  println!("Test reorg rollback removes address entries...");
+  write(&db, |batch| {
+    batch.delete_event(&event_key("Transfer", 12, 0).unwrap(), &transfer_indexes).unwrap();
+  });
+  assert_eq!(address_page(&db, &address(1), None, 10, None).0, vec!["Transfer-0xa-0x1", "Approval-0xb-0x3"]);
+  assert_eq!(address_page(&db, &address(2), None, 10, None).0, vec!["Approval-0xb-0x3", "Transfer-0xd-0x0"]);
+  assert!(address_query("0x1234", None).is_err());
+}
+
+fn timeline_page(db: &CacheDB, contract: &str, from: Option<(u64, u32)>, size: i32) -> (Vec<String>, Option<Vec<u8>>) {
+  let query = timeline_query(contract, from).unwrap();
+  let (events, next) = get_events_by_timeline(db.clone(), query, size, None).unwrap();
+  (events.into_iter().map(|event| event.key).collect(), next)
+}
+
+#[test]
+fn test_timeline() {
+  let path = TempDir::new("_rust_rocksdb_timeline").expect("");
+  let column_families = vec!("events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline");
+  let db = CacheDB::create(path.path().to_str().unwrap().to_string(), column_families, 30).unwrap();
+  let token_indexes = EventIndexes::default().with_global_timeline(true);
+  let token_indexes = EventIndexes{contract: String::from("Token"), ..token_indexes};
+  let exchange_indexes = EventIndexes{contract: String::from("Exchange"), ..token_indexes.clone()};
+  let transaction = LogTransaction{hash: String::from("0xab"), index: String::from("0x2")};
+
+  println!("Test timeline merges events of a contract in chain order...");
+  write(&db, |batch| {
+    batch.put_event(&event_key("Transfer", 12, 4).unwrap(), transfer_params("aa", "bb", "1"), &transaction, &token_indexes).unwrap();
+    batch.put_event(&event_key("Approval", 12, 1).unwrap(), transfer_params("aa", "bb", "2"), &transaction, &token_indexes).unwrap();
+    batch.put_event(&event_key("Transfer", 11, 7).unwrap(), transfer_params("aa", "bb", "3"), &transaction, &token_indexes).unwrap();
+    batch.put_event(&event_key("Trade", 12, 2).unwrap(), transfer_params("aa", "bb", "4"), &transaction, &exchange_indexes).unwrap();
+  });
+  let (token_events, next) = timeline_page(&db, "Token", None, 10);
+  assert_eq!(token_events, vec!["Transfer-0xb-0x7", "Approval-0xc-0x1", "Transfer-0xc-0x4"]);
+  assert!(next.is_none());
+  assert_eq!(timeline_page(&db, GLOBAL_TIMELINE, None, 10).0,
+    vec!["Transfer-0xb-0x7", "Approval-0xc-0x1", "Trade-0xc-0x2", "Transfer-0xc-0x4"]);
+
+  println!("Test timeline pages and transaction fields...");
+  let (first_page, next) = timeline_page(&db, "Token", None, 1);
+  assert_eq!(first_page, vec!["Transfer-0xb-0x7"]);
+  assert!(next.is_some());
+  assert_eq!(timeline_page(&db, "Token", Some((12, 0)), 10).0, vec!["Approval-0xc-0x1", "Transfer-0xc-0x4"]);
+  let query = timeline_query("Exchange", None).unwrap();
+  let (exchange_events, _) = get_events_by_timeline(db.clone(), query, 10, None).unwrap();
+  let fields = exchange_events[0].fields.as_ref().unwrap();
+  assert_eq!(fields["transactionHash"], "0xab");
+  assert_eq!(fields["transactionIndex"], "0x2");
+  assert_eq!(fields["contract"], "Exchange");
+
+  println!("Test reorg rollback removes timeline entries...");
+  write(&db, |batch| {
+    batch.delete_event(&event_key("Approval", 12, 1).unwrap(), &token_indexes).unwrap();
+  });
+  assert_eq!(timeline_page(&db, "Token", None, 10).0, vec!["Transfer-0xb-0x7", "Transfer-0xc-0x4"]);
+  assert_eq!(timeline_page(&db, GLOBAL_TIMELINE, None, 10).0, vec!["Transfer-0xb-0x7", "Trade-0xc-0x2", "Transfer-0xc-0x4"]);
+}
+
+#[test]
+fn test_same_event_in_contracts() {
+  let path = TempDir::new("_rust_rocksdb_qualified_names").expect("");
+  let column_families = vec!("events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline");
+  let db = CacheDB::create(path.path().to_str().unwrap().to_string(), column_families, 30).unwrap();
+  let token_indexes = EventIndexes{
+    contract: String::from("Token"),
+    indexes: vec![IndexConfig{name: String::from("from"), args: vec![String::from("from")]}],
+    address_args: vec![String::from("from")],
+    index_tx_sender: false,
+    global_timeline: false,
+    aggregations: Vec::new()
+  };
+  let exchange_indexes = EventIndexes{contract: String::from("Exchange"), ..token_indexes.clone()};
+  write(&db, |batch| {
+    batch.put_event(&event_key(&qualified_name("Token", "Transfer"), 10, 0).unwrap(), transfer_params(&address(1), &address(2), "1"), &LogTransaction::default(), &token_indexes).unwrap();
+    batch.put_event(&event_key(&qualified_name("Exchange", "Transfer"), 10, 0).unwrap(), transfer_params(&address(1), &address(3), "2"), &LogTransaction::default(), &exchange_indexes).unwrap();
+  });
+
+  println!("Test events with the same name and position in different contracts are kept apart...");
+  let query = index_query("Token.Transfer", "from", &[address(1)], None).unwrap();
+  let token_events = get_events_by_index(db.clone(), query, 10).unwrap();
+  assert_eq!(token_events.len(), 1);
+  assert_eq!(token_events[0].key, "Token.Transfer-0xa-0x0");
+  assert_eq!(address_page(&db, &address(1), None, 10, Some("Transfer")).0, vec!["Exchange.Transfer-0xa-0x0", "Token.Transfer-0xa-0x0"]);
+  assert_eq!(address_page(&db, &address(1), None, 10, Some("Exchange.Transfer")).0, vec!["Exchange.Transfer-0xa-0x0"]);
+}
+
+#[test]
+fn test_dangling_index_entry() {
+  let path = TempDir::new("_rust_rocksdb_dangling_index").expect("");
+  // Column families list from before indexes existed still gets index column families
+  let column_families = vec!("events", "aggregations", "stats", "settings", "filters", "log");
+  let db = CacheDB::create(path.path().to_str().unwrap().to_string(), column_families, 30).unwrap();
+  let indexes = EventIndexes{
+    contract: String::from("Token"),
+    indexes: vec![IndexConfig{name: String::from("from"), args: vec![String::from("from")]}],
+    address_args: Vec::new(),
+    index_tx_sender: false,
+    global_timeline: false,
+    aggregations: Vec::new()
+  };
+  write(&db, |batch| {
+    batch.put_event(&event_key("Transfer", 10, 0).unwrap(), transfer_params("aa", "bb", "1"), &LogTransaction::default(), &indexes).unwrap();
+    batch.put_event(&event_key("Transfer", 11, 0).unwrap(), transfer_params("aa", "cc", "2"), &LogTransaction::default(), &indexes).unwrap();
+  });
+
+  println!("Test index entry of missing event is skipped...");
+  db.storage.delete("events", &event_key("Transfer", 10, 0).unwrap()).unwrap();
+  assert_eq!(query_blocks(&db, "from", vec!["aa"], None), vec!["Transfer-0xb-0x0"]);
+}
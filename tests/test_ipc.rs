@@ -0,0 +1,106 @@
+#![cfg(unix)]
+extern crate lib;
+extern crate futures;
+extern crate tempdir;
+#[macro_use]
+extern crate serde_json;
+use std::io::Write;
+use std::os::unix::net::UnixListener;
+use std::thread;
+use futures::Stream;
+use serde_json::Value;
+use lib::eth::eth_json_rpc;
+use lib::eth::endpoint::Endpoint;
+use tempdir::TempDir;
+
+/// Answers every request with messages of `respond` and keeps connections open, as nodes do
+fn start_ipc_node<F>(socket_path: &str, respond: F) where F: Fn(&Value) -> Vec<Value> + Send + 'static {
+  let listener = UnixListener::bind(socket_path).unwrap();
+  thread::spawn(move || {
+    let mut open_streams = Vec::new();
+    for stream in listener.incoming() {
+      let mut stream = match stream {
+        Ok(stream) => stream,
+        Err(_) => break
+      };
+      let request: Value = match serde_json::Deserializer::from_reader(stream.try_clone().unwrap()).into_iter::<Value>().next() {
+        Some(Ok(request)) => request,
+        _ => continue
+      };
+      for message in respond(&request) {
+        stream.write_all(message.to_string().as_bytes()).unwrap();
+      }
+      stream.flush().unwrap();
+      open_streams.push(stream);
+    }
+  });
+}
+
+#[test]
+fn test_ipc_endpoint() {
+  let path = TempDir::new("_cacherz_ipc").expect("");
+  let socket_path = path.path().join("node.ipc").to_str().unwrap().to_string();
+  start_ipc_node(&socket_path, |request| vec![json!({"jsonrpc": "2.0", "id": request["id"], "result": "0x1a"})]);
+
+  println!("Test json-rpc requests are sent over unix socket...");
+  let endpoint = Endpoint::from_url(format!("ipc://{}", socket_path));
+  assert!(endpoint.is_ipc());
+  assert_eq!(eth_json_rpc::get_block_number(endpoint.clone(), 1).unwrap(), 26);
+  assert_eq!(eth_json_rpc::get_block_number(endpoint, 2).unwrap(), 26);
+
+  println!("Test missing socket is an error...");
+  let missing_endpoint = Endpoint::from_url(format!("ipc://{}", path.path().join("missing.ipc").to_str().unwrap()));
+  assert!(eth_json_rpc::get_block_number(missing_endpoint, 3).is_err());
+}
+
+#[test]
+fn test_transaction_senders_batch() {
+  let path = TempDir::new("_cacherz_ipc_senders").expect("");
+  let socket_path = path.path().join("node.ipc").to_str().unwrap().to_string();
+  // Responses come in reverse order and the last transaction is unknown to the node
+  start_ipc_node(&socket_path, |request| {
+    let responses: Vec<Value> = request.as_array().unwrap().iter().rev().map(|single_request| {
+      match single_request["params"][0].as_str().unwrap() {
+        "0x01" => json!({"jsonrpc": "2.0", "id": single_request["id"], "result": {"from": "0xaa"}}),
+        "0x02" => json!({"jsonrpc": "2.0", "id": single_request["id"], "result": {"from": "0xbb"}}),
+        _ => json!({"jsonrpc": "2.0", "id": single_request["id"], "result": null})
+      }
+    }).collect();
+    vec![Value::Array(responses)]
+  });
+
+  println!("Test senders of many transactions are fetched by one request...");
+  let endpoint = Endpoint::from_url(format!("ipc://{}", socket_path));
+  let hashes = vec![String::from("0x01"), String::from("0x02"), String::from("0x03")];
+  let senders = eth_json_rpc::get_transaction_senders(endpoint, &hashes, 10).unwrap();
+  assert_eq!(senders.len(), 2);
+  assert_eq!(senders["0x01"], "0xaa");
+  assert_eq!(senders["0x02"], "0xbb");
+}
+
+#[test]
+fn test_ipc_subscription() {
+  let path = TempDir::new("_cacherz_ipc_subscription").expect("");
+  let socket_path = path.path().join("node.ipc").to_str().unwrap().to_string();
+  // Subscription id is followed by a notification of another subscription and one of its own
+  start_ipc_node(&socket_path, |request| {
+    let log = json!({"logIndex": "0x0", "blockNumber": "0x1a", "blockHash": "0xb", "transactionHash": "0xabc",
+      "transactionIndex": "0x0", "address": "0xa", "data": "0x", "topics": ["0x1"]});
+    vec![
+      json!({"jsonrpc": "2.0", "id": request["id"], "result": "0x5"}),
+      json!({"jsonrpc": "2.0", "method": "eth_subscription", "params": {"subscription": "0x6", "result": {}}}),
+      json!({"jsonrpc": "2.0", "method": "eth_subscription", "params": {"subscription": "0x5", "result": log}})
+    ]
+  });
+
+  println!("Test logs of a subscription come as a stream...");
+  let endpoint = Endpoint::from_url(format!("ipc://{}", socket_path));
+  let (subscription_id, logs) = eth_json_rpc::subscribe_logs(endpoint, String::from("1"), 1, None).unwrap();
+  assert_eq!(subscription_id, "0x5");
+  let log = logs.wait().next().unwrap().unwrap();
+  assert_eq!(log.blockNumber, "0x1a");
+  assert_eq!(log.transactionHash, "0xabc");
+
+  println!("Test http endpoints do not support subscriptions...");
+  assert!(eth_json_rpc::subscribe_logs(Endpoint::from_url(String::from("http://localhost:8545")), String::from("1"), 2, None).is_err());
+}
@@ -0,0 +1,87 @@
+extern crate lib;
+#[macro_use]
+extern crate serde_json;
+use lib::db::key_schema::{event_key, event_key_from_hex, decode_event_key, name_key, format_key, prefix_upper_bound, KeySchema, KeyValue};
+use lib::commands::migrate_keys::{convert_legacy_event_key, convert_legacy_name_key};
+
+#[test]
+fn test_event_key_order() {
+  println!("Test numeric order of blocks and logs...");
+  let key_block_9 = event_key_from_hex("Transfer", "0x9", "0x0").unwrap();
+  let key_block_16 = event_key_from_hex("Transfer", "0x10", "0x0").unwrap();
+  assert!(key_block_9 < key_block_16);
+  let key_log_9 = event_key("Transfer", 16, 9).unwrap();
+  let key_log_16 = event_key("Transfer", 16, 16).unwrap();
+  assert!(key_log_9 < key_log_16);
+  assert!(key_block_16 < key_log_9);
+
+  println!("Test names do not overlap...");
+  let key_trade = event_key("Trade", u64::max_value(), 0).unwrap();
+  let key_trades = event_key("Trades", 0, 0).unwrap();
+  assert!(key_trade < key_trades);
+  assert!(key_trade.starts_with(&name_key("Trade").unwrap()));
+  assert!(!key_trades.starts_with(&name_key("Trade").unwrap()));
+}
+
+#[test]
+fn test_event_key_roundtrip() {
+  let key = event_key("Transfer", 4100000, 3).unwrap();
+  assert_eq!(decode_event_key(&key).unwrap(), (String::from("Transfer"), 4100000, 3));
+  assert_eq!(format_key(&key), "Transfer-0x3e8fa0-0x3");
+  assert_eq!(format_key(&name_key("Transfer").unwrap()), "Transfer");
+  assert!(name_key("ThisEventNameIsLongerThanThirtyBytes").is_err());
+}
+
+#[test]
+fn test_key_schema() {
+  let schema = KeySchema::for_column_family("events").unwrap();
+  assert_eq!(schema.key_size(), 42);
+  assert_eq!(schema.prefix_size(1), 30);
+  assert!(KeySchema::for_column_family("unknown").is_err());
+
+  println!("Test parsing hex and decimal values...");
+  let values = schema.parse_values(vec![String::from("Transfer"), String::from("0x1a"), String::from("3")]).unwrap();
+  assert_eq!(values, vec![KeyValue::Name(String::from("Transfer")), KeyValue::U64(26), KeyValue::U32(3)]);
+  let key = schema.encode(&values).unwrap();
+  assert_eq!(key, event_key("Transfer", 26, 3).unwrap());
+  assert_eq!(schema.decode(&key).unwrap(), values);
+  assert_eq!(schema.decode_to_json(&key).unwrap(), json!({"event": "Transfer", "block": "0x1a", "log": "0x3"}));
+  assert!(schema.decode(&key[..30]).is_err());
+  assert!(schema.parse_values(vec![String::from("Transfer"), String::from("0x1"), String::from("0x100000000")]).is_err());
+
+  println!("Test prefix range covers all keys of the event...");
+  let (start, end) = schema.prefix_range(&[KeyValue::Name(String::from("Transfer"))]).unwrap();
+  let end = end.unwrap();
+  assert!(start <= event_key("Transfer", 0, 0).unwrap());
+  assert!(event_key("Transfer", u64::max_value(), u32::max_value()).unwrap() < end);
+  assert!(end <= event_key("Transfers", 0, 0).unwrap());
+  assert_eq!(prefix_upper_bound(&[0x01, 0xff]), Some(vec![0x02]));
+  assert_eq!(prefix_upper_bound(&[0xff, 0xff]), None);
+}
+
+#[test]
+fn test_legacy_keys_conversion() {
+  let legacy_event_key = "Transfer----------------------0x1a-----------0x3-------";
+  let converted = convert_legacy_event_key(&[], legacy_event_key.as_bytes()).unwrap().unwrap();
+  assert_eq!(converted, event_key("Transfer", 26, 3).unwrap());
+  assert!(convert_legacy_event_key(&[], &converted).is_none());
+
+  let legacy_cursor_key = "Transfer----------------------";
+  let converted_cursor = convert_legacy_name_key(&[], legacy_cursor_key.as_bytes()).unwrap().unwrap();
+  assert_eq!(converted_cursor, name_key("Transfer").unwrap());
+  assert!(convert_legacy_name_key(&[], &converted_cursor).is_none());
+}
+
+#[test]
+fn test_legacy_keys_padding() {
+  let event_names = vec![String::from("Transfer-")];
+  let legacy_event_key = "Transfer----------------------0x1a-----------0x3-------";
+  assert_eq!(convert_legacy_event_key(&event_names, legacy_event_key.as_bytes()).unwrap().unwrap(), event_key("Transfer-", 26, 3).unwrap());
+  assert_eq!(convert_legacy_event_key(&[], legacy_event_key.as_bytes()).unwrap().unwrap(), event_key("Transfer", 26, 3).unwrap());
+
+  let legacy_cursor_key = "Transfer----------------------";
+  assert_eq!(convert_legacy_name_key(&event_names, legacy_cursor_key.as_bytes()).unwrap().unwrap(), name_key("Transfer-").unwrap());
+
+  println!("Test padding is never followed by a value...");
+  assert!(convert_legacy_event_key(&[], b"Transfer----------------------0x1a---------1-0x3-------").is_none());
+}
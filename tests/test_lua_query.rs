@@ -0,0 +1,73 @@
+extern crate lib;
+extern crate tempdir;
+#[macro_use]
+extern crate serde_json;
+use std::collections::HashMap;
+use std::fs;
+use tempdir::TempDir;
+use lib::db::cachedb::CacheDB;
+use lib::db::key_schema::event_key;
+use lib::web::lua_query::{run_query, QueryScripts};
+
+const COLUMN_FAMILIES: [&str; 9] = ["events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline"];
+
+fn write_script(dir: &TempDir, name: &str, script: &str) {
+  fs::write(dir.path().join(format!("{}.lua", name)), script).unwrap();
+}
+
+#[test]
+fn test_lua_queries() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  for block_number in 1..4 {
+    let value = json!({"value": format!("{}", block_number)}).to_string();
+    db.storage.put("events", &event_key("Transfer", block_number, 0).unwrap(), value.as_bytes()).unwrap();
+  }
+  let scripts_dir = TempDir::new("test_lua_queries").unwrap();
+  let scripts = QueryScripts::new(scripts_dir.path().to_str().unwrap().to_string());
+  write_script(&scripts_dir, "transfers", r#"
+function query(params)
+  local rows = db.scan("events", db.key("events", params.event), tonumber(params.size))
+  local values = {}
+  for i, row in ipairs(rows) do
+    values[i] = row.value.value
+  end
+  return {count = #rows, values = values, first = db.get("events", db.key("events", params.event, 1, 0))}
+end
+"#);
+  write_script(&scripts_dir, "endless", "function query(params) while true do end end");
+  write_script(&scripts_dir, "files", "function query(params) return io.open('/etc/passwd') end");
+  write_script(&scripts_dir, "memory", "function query(params) local t = {} for i = 1, 100000000 do t[i] = i end return #t end");
+  write_script(&scripts_dir, "repeat", "function query(params) return string.rep('x', 1000) end");
+  write_script(&scripts_dir, "loader", "function query(params) return load('return 1')() end");
+
+  println!("Test script reads by prefix and key...");
+  let mut params = HashMap::new();
+  params.insert(String::from("event"), String::from("Transfer"));
+  params.insert(String::from("size"), String::from("2"));
+  let result = run_query(&db, &scripts, "transfers", &params).unwrap();
+  assert_eq!(result, json!({"count": 2, "values": ["1", "2"], "first": {"value": "1"}}));
+
+  println!("Test rows budget is enforced...");
+  params.insert(String::from("size"), String::from("3"));
+  let limited_scripts = scripts.clone().with_max_rows(Some(2));
+  assert!(run_query(&db, &limited_scripts, "transfers", &params).unwrap_err().contains("budget of 2 rows"));
+
+  println!("Test time budget stops endless script...");
+  let fast_scripts = scripts.clone().with_timeout_ms(Some(50));
+  assert!(run_query(&db, &fast_scripts, "endless", &HashMap::new()).unwrap_err().contains("time budget"));
+
+  println!("Test scripts have no file access...");
+  assert!(run_query(&db, &scripts, "files", &HashMap::new()).is_err());
+
+  println!("Test memory budget stops growing script...");
+  let small_scripts = scripts.clone().with_timeout_ms(Some(60000)).with_memory_limit_kb(Some(1024));
+  assert!(run_query(&db, &small_scripts, "memory", &HashMap::new()).unwrap_err().contains("memory budget"));
+
+  println!("Test scripts cannot repeat strings or load code...");
+  assert!(run_query(&db, &scripts, "repeat", &HashMap::new()).is_err());
+  assert!(run_query(&db, &scripts, "loader", &HashMap::new()).is_err());
+
+  println!("Test script names cannot leave scripts directory...");
+  assert!(run_query(&db, &scripts, "../transfers", &HashMap::new()).is_err());
+  assert!(run_query(&db, &scripts, "missing", &HashMap::new()).unwrap_err().contains("There is no query script"));
+}
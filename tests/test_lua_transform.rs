@@ -0,0 +1,106 @@
+extern crate lib;
+extern crate actix_lua;
+use std::collections::HashMap;
+use actix_lua::LuaMessage;
+use lib::actors::lua_transform::{batch_message, parse_batch_output, parse_output, records_to_write_ops, TransformRecord};
+use lib::actors::rocks_write_actor::WriteOp;
+use lib::db::cachedb::CacheDB;
+use lib::db::indexes::EventBatch;
+
+const COLUMN_FAMILIES: [&str; 9] = ["events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline"];
+
+fn table(fields: Vec<(&str, LuaMessage)>) -> LuaMessage {
+  LuaMessage::Table(fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect())
+}
+
+fn string(value: &str) -> LuaMessage {
+  LuaMessage::String(value.to_string())
+}
+
+#[test]
+fn test_transform_output() {
+  println!("Test changed params and records are read in order...");
+  let response = table(vec![
+    ("params", table(vec![("to", string("bb")), ("value", LuaMessage::Integer(10))])),
+    ("records", table(vec![
+      ("2", table(vec![("kind", string("increment")), ("key", string("transfers")), ("delta", LuaMessage::Integer(-1))])),
+      ("1", table(vec![("kind", string("put")), ("columnFamily", string("stats")), ("key", string("last")), ("value", string("bb"))]))
+    ]))
+  ]);
+  let output = parse_output(response).unwrap();
+  let mut params = HashMap::new();
+  params.insert(String::from("to"), String::from("bb"));
+  params.insert(String::from("value"), String::from("10"));
+  assert_eq!(output.params, Some(params));
+  assert_eq!(output.records, vec![
+    TransformRecord::Put(String::from("stats"), String::from("last"), String::from("bb")),
+    TransformRecord::Increment(String::from("transfers"), -1)
+  ]);
+
+  println!("Test dropped log has no params...");
+  assert_eq!(parse_output(table(vec![("records", table(vec![]))])).unwrap().params, None);
+
+  println!("Test script error is returned...");
+  assert_eq!(parse_output(table(vec![("error", string("boom"))])), Err(String::from("boom")));
+
+  println!("Test records are namespaced and limited to allowed column families...");
+  let ops = records_to_write_ops("Transfer", vec![
+    TransformRecord::Put(String::from("events"), String::from("k"), String::from("v")),
+    TransformRecord::Increment(String::from("transfers"), 2)
+  ]);
+  assert_eq!(ops.len(), 1);
+  match ops[0] {
+    WriteOp::Increment(ref key, delta) => {
+      assert_eq!(key.as_slice(), &b"lua:Transfer:transfers"[..]);
+      assert_eq!(delta, 2);
+    },
+    ref op => panic!("Unexpected op {:?}", op)
+  };
+}
+
+#[test]
+fn test_transform_batch_output() {
+  println!("Test logs of a poll are sent as a list...");
+  match batch_message(vec![string("a"), string("b")]) {
+    LuaMessage::Table(fields) => {
+      assert_eq!(fields.len(), 2);
+      match (fields.get("1"), fields.get("2")) {
+        (Some(&LuaMessage::String(ref first)), Some(&LuaMessage::String(ref second))) => assert_eq!((first.as_str(), second.as_str()), ("a", "b")),
+        other => panic!("Unexpected logs {:?}", other)
+      };
+    },
+    other => panic!("Unexpected message {:?}", other)
+  };
+
+  println!("Test outputs come in order of the logs with errors of single logs...");
+  let response = table(vec![("outputs", table(vec![
+    ("2", table(vec![("error", string("boom"))])),
+    ("1", table(vec![("params", table(vec![("to", string("bb"))])), ("records", table(vec![]))]))
+  ]))]);
+  let outputs = parse_batch_output(response, 3).unwrap();
+  assert_eq!(outputs.len(), 3);
+  assert_eq!(outputs[0].as_ref().unwrap().params.as_ref().and_then(|params| params.get("to")), Some(&String::from("bb")));
+  assert_eq!(outputs[1], Err(String::from("boom")));
+  assert!(outputs[2].is_err());
+
+  println!("Test error of the whole batch is returned...");
+  assert_eq!(parse_batch_output(table(vec![("error", string("Script has exceeded its memory limit"))]), 1),
+    Err(String::from("Script has exceeded its memory limit")));
+}
+
+#[test]
+fn test_transform_counters() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  println!("Test increments of one batch are summed...");
+  let mut event_batch = EventBatch::new(&*db.storage);
+  event_batch.add_to_counter(b"lua:Transfer:transfers", 3).unwrap();
+  event_batch.add_to_counter(b"lua:Transfer:transfers", 2).unwrap();
+  db.storage.write(event_batch.into_batch(), false).unwrap();
+  assert_eq!(db.storage.get("aggregations", b"lua:Transfer:transfers").unwrap(), Some(b"5".to_vec()));
+
+  println!("Test counter at zero is removed...");
+  let mut event_batch = EventBatch::new(&*db.storage);
+  event_batch.add_to_counter(b"lua:Transfer:transfers", -5).unwrap();
+  db.storage.write(event_batch.into_batch(), false).unwrap();
+  assert_eq!(db.storage.get("aggregations", b"lua:Transfer:transfers").unwrap(), None);
+}
@@ -0,0 +1,129 @@
+extern crate lib;
+#[macro_use]
+extern crate serde_json;
+extern crate tempdir;
+use std::collections::HashMap;
+use lib::commands::migrate::{migrate, MigrationContext, MIGRATION_PROGRESS_KEY};
+use lib::db::cachedb::CacheDB;
+use lib::db::key_codec::encode_hex;
+use lib::db::key_schema::{event_key, name_key, KeySchema, KeyValue};
+use lib::db::reader::{get_by_key, get_by_key_bytes};
+use lib::db::schema_version::{check_schema_version, get_schema_version, SCHEMA_VERSION};
+use tempdir::TempDir;
+
+fn create_db(path: &TempDir) -> CacheDB {
+  let column_families = vec!("events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline");
+  CacheDB::create(path.path().to_str().unwrap().to_string(), column_families, 30).unwrap()
+}
+
+fn contracts() -> HashMap<String, String> {
+  vec![(String::from("Transfer"), String::from("Token"))].into_iter().collect()
+}
+
+fn put(db: &CacheDB, column_family: &str, key: &[u8], value: &str) {
+  db.storage.put(column_family, key, value.as_bytes()).unwrap();
+}
+
+#[test]
+fn test_schema_version() {
+  let path = TempDir::new("_rust_rocksdb_schema_version").expect("");
+  let db = create_db(&path);
+
+  println!("Test empty database gets current version...");
+  assert_eq!(get_schema_version(&db).unwrap(), None);
+  assert_eq!(check_schema_version(&db).unwrap(), SCHEMA_VERSION);
+  assert_eq!(get_schema_version(&db).unwrap(), Some(SCHEMA_VERSION));
+  assert!(get_by_key(db.clone(), String::from("settings"), String::from("build")).unwrap().contains("schemaVersion"));
+
+  println!("Test newer database is refused...");
+  put(&db, "settings", b"schema_version", &(SCHEMA_VERSION + 1).to_string());
+  assert!(check_schema_version(&db).is_err());
+  assert!(migrate(db.clone(), contracts()).is_err());
+}
+
+#[test]
+fn test_migrate_legacy_database() {
+  let path = TempDir::new("_rust_rocksdb_migrate").expect("");
+  let db = create_db(&path);
+  put(&db, "events", b"Transfer----------------------0x1a-----------0x3-------", "{\"value\": \"1\"}");
+  put(&db, "aggregations", b"Transfer----------------------", "{\"last_block\": \"0x1a-0x3\"}");
+
+  println!("Test legacy database is not used before migration...");
+  assert!(check_schema_version(&db).is_err());
+  assert_eq!(migrate(db.clone(), contracts()).unwrap(), SCHEMA_VERSION);
+  assert_eq!(check_schema_version(&db).unwrap(), SCHEMA_VERSION);
+  assert_eq!(get_by_key_bytes(db.clone(), String::from("events"), event_key("Token.Transfer", 26, 3).unwrap()).unwrap(), "{\"value\": \"1\"}");
+  assert_eq!(get_by_key_bytes(db.clone(), String::from("aggregations"), name_key("Token.Transfer").unwrap()).unwrap(), "{\"last_block\": \"0x1a-0x3\"}");
+  assert!(get_by_key(db.clone(), String::from("settings"), String::from(MIGRATION_PROGRESS_KEY)).is_err());
+
+  println!("Test migration is a no-op on current version...");
+  assert_eq!(migrate(db.clone(), contracts()).unwrap(), SCHEMA_VERSION);
+}
+
+#[test]
+fn test_migration_checkpoint() {
+  let path = TempDir::new("_rust_rocksdb_migrate_checkpoint").expect("");
+  let db = create_db(&path);
+  put(&db, "events", b"Transfer----------------------0x1a-----------0x3-------", "{\"value\": \"1\"}");
+  put(&db, "events", b"Transfer----------------------0x1b-----------0x0-------", "{\"value\": \"2\"}");
+
+  println!("Test interrupted migration continues after the last checkpoint...");
+  let legacy_key = b"Transfer----------------------0x1a-----------0x3-------";
+  let progress = json!({"fromVersion": 1, "columnFamilies": {"events": {"lastKey": encode_hex(legacy_key), "done": false}}});
+  put(&db, "settings", MIGRATION_PROGRESS_KEY.as_bytes(), &progress.to_string());
+  let context = MigrationContext::load(db.clone(), 1).unwrap();
+  assert!(!context.is_done("events"));
+  assert_eq!(context.get_last_key("events"), Some(legacy_key.to_vec()));
+  assert_eq!(MigrationContext::load(db.clone(), 2).unwrap().get_last_key("events"), None);
+
+  assert_eq!(migrate(db.clone(), contracts()).unwrap(), SCHEMA_VERSION);
+  // Key up to the checkpoint was already processed, so it is not visited again
+  assert!(get_by_key_bytes(db.clone(), String::from("events"), legacy_key.to_vec()).is_ok());
+  assert_eq!(get_by_key_bytes(db.clone(), String::from("events"), event_key("Token.Transfer", 27, 0).unwrap()).unwrap(), "{\"value\": \"2\"}");
+  assert!(get_by_key(db.clone(), String::from("settings"), String::from(MIGRATION_PROGRESS_KEY)).is_err());
+}
+
+#[test]
+fn test_migrate_names() {
+  let path = TempDir::new("_rust_rocksdb_migrate_names").expect("");
+  let db = create_db(&path);
+  put(&db, "settings", b"schema_version", "2");
+  put(&db, "events", &event_key("Transfer", 26, 3).unwrap(), "{\"value\": \"1\"}");
+  put(&db, "events", &event_key("Approval", 26, 4).unwrap(), "{\"value\": \"2\"}");
+  put(&db, "aggregations", &name_key("Transfer").unwrap(), "{\"last_block\": \"0x1a-0x3\"}");
+  let timeline_schema = KeySchema::timeline();
+  let timeline_key = |event_name: &str| timeline_schema.encode(&[KeyValue::Name(String::from("Token")), KeyValue::U64(26), KeyValue::U32(3),
+    KeyValue::Name(event_name.to_string())]).unwrap();
+  put(&db, "timeline", &timeline_key("Transfer"), "");
+  put(&db, "stats", b"ingest:Transfer", "{}");
+  put(&db, "settings", b"aggregation:Transfer:volume", "{}");
+
+  println!("Test stored events need their contracts...");
+  assert!(migrate(db.clone(), HashMap::new()).is_err());
+
+  println!("Test event names in keys are qualified by contract...");
+  assert_eq!(migrate(db.clone(), contracts()).unwrap(), SCHEMA_VERSION);
+  assert_eq!(get_by_key_bytes(db.clone(), String::from("events"), event_key("Token.Transfer", 26, 3).unwrap()).unwrap(), "{\"value\": \"1\"}");
+  assert!(get_by_key_bytes(db.clone(), String::from("events"), event_key("Transfer", 26, 3).unwrap()).is_err());
+  assert_eq!(get_by_key_bytes(db.clone(), String::from("aggregations"), name_key("Token.Transfer").unwrap()).unwrap(), "{\"last_block\": \"0x1a-0x3\"}");
+  assert!(db.storage.get("timeline", &timeline_key("Token.Transfer")).unwrap().is_some());
+  assert!(db.storage.get("stats", b"ingest:Token.Transfer").unwrap().is_some());
+  assert!(db.storage.get("settings", b"aggregation:Token.Transfer:volume").unwrap().is_some());
+
+  println!("Test events without configured contract are kept...");
+  assert!(db.storage.get("events", &event_key("Approval", 26, 4).unwrap()).unwrap().is_some());
+}
+
+#[test]
+fn test_migrate_names_fails_on_long_names() {
+  let path = TempDir::new("_rust_rocksdb_migrate_long_names").expect("");
+  let db = create_db(&path);
+  put(&db, "settings", b"schema_version", "2");
+  put(&db, "events", &event_key("Transfer", 26, 3).unwrap(), "{\"value\": \"1\"}");
+
+  println!("Test key which cannot be converted fails the step and keeps its version...");
+  let long_contracts: HashMap<String, String> = vec![(String::from("Transfer"), String::from("VeryLongTokenContractName"))].into_iter().collect();
+  assert!(migrate(db.clone(), long_contracts).is_err());
+  assert_eq!(get_schema_version(&db).unwrap(), Some(2));
+  assert!(db.storage.get("events", &event_key("Transfer", 26, 3).unwrap()).unwrap().is_some());
+}
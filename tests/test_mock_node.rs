@@ -0,0 +1,183 @@
+extern crate lib;
+extern crate tempdir;
+extern crate actix;
+extern crate futures;
+extern crate ethabi;
+use std::collections::HashMap;
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::{Duration, Instant};
+use ethabi::{Event, EventParam, ParamType};
+use lib::actors::structs::settings::Settings;
+use lib::actors::main_actor::{MainActor, MsgCreateReadWriteActor};
+use lib::actors::eth_actor::EthActor;
+use lib::db::key_schema::event_key;
+use lib::db::reader::get_events_by_prefix;
+use lib::db::cachedb::CacheDB;
+use lib::eth::eth_json_rpc;
+use lib::mock::chain::{MockChain, MockLog, MockScript};
+use lib::mock::node::MockNode;
+use tempdir::TempDir;
+use actix::{Actor, System, Arbiter};
+use futures::{future, Future};
+
+fn transfer_event() -> Event {
+  Event{
+    name: String::from("Transfer"),
+    inputs: vec![
+      EventParam{name: String::from("from"), kind: ParamType::Address, indexed: true},
+      EventParam{name: String::from("to"), kind: ParamType::Address, indexed: true},
+      EventParam{name: String::from("value"), kind: ParamType::Uint(256), indexed: false}
+    ],
+    anonymous: false
+  }
+}
+
+fn transfer_log(event: &Event, from: u64, to: u64, value: u64) -> MockLog {
+  MockLog{
+    address: String::from("0x00000000000000000000000000000000000000aa"),
+    topics: vec![
+      format!("0x{:x}", event.signature()),
+      format!("0x{:064x}", from),
+      format!("0x{:064x}", to)
+    ],
+    data: format!("0x{:064x}", value)
+  }
+}
+
+fn count_events(db: CacheDB) -> usize {
+  let first_key = event_key("default.Transfer", 0, 0).unwrap();
+  get_events_by_prefix(db, String::from("events"), first_key, 100, true, 30)
+    .map(|events| events.len())
+    .unwrap_or(0)
+}
+
+/// Polls event count until it reaches `expected` or the timeout passes. Returns the last count.
+fn wait_for_events(db: &CacheDB, expected: usize) -> usize {
+  let started = Instant::now();
+  loop {
+    let count = count_events(db.clone());
+    if count == expected || started.elapsed() > Duration::new(30, 0) {
+      return count;
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+}
+
+#[test]
+fn test_mock_node_json_rpc() {
+  let event = transfer_event();
+  let event_hex = format!("{:x}", event.signature());
+  let node = MockNode::start("127.0.0.1", 0, MockChain::new()).unwrap();
+  let endpoint = node.endpoint();
+
+  println!("Test filter changes after new blocks...");
+  let filter_id = eth_json_rpc::create_new_filter(endpoint.clone(), event_hex.clone(), 1, String::from("0x0"), None, None).unwrap();
+  node.with_chain(|chain| {
+    chain.mine_block(vec![transfer_log(&event, 1, 2, 100)]);
+    chain.mine_block(vec![transfer_log(&event, 2, 3, 200)]);
+  });
+  let changes = eth_json_rpc::get_new_events(endpoint.clone(), filter_id.clone(), 1).unwrap();
+  assert_eq!(changes.result.len(), 2);
+  assert_eq!(changes.result[0].blockNumber, "0x1");
+  assert_eq!(changes.result[1].blockNumber, "0x2");
+  let decoded = changes.result[0].clone().decode_hashmap(event.inputs.clone()).unwrap();
+  assert_eq!(decoded.get("value").unwrap(), "64");
+  assert_eq!(eth_json_rpc::get_new_events(endpoint.clone(), filter_id.clone(), 1).unwrap().result.len(), 0);
+
+  println!("Test reorg reports removed logs and new logs...");
+  node.with_chain(|chain| chain.reorg(1, vec![vec![], vec![transfer_log(&event, 3, 4, 300)]]));
+  let changes = eth_json_rpc::get_new_events(endpoint.clone(), filter_id.clone(), 1).unwrap();
+  assert_eq!(changes.result.len(), 2);
+  assert_eq!(changes.result[0].blockNumber, "0x2");
+  assert_eq!(changes.result[1].blockNumber, "0x3");
+
+  println!("Test eth_getLogs, eth_blockNumber and eth_chainId...");
+  let logs = eth_json_rpc::get_logs(endpoint.clone(), event_hex.clone(), 1, String::from("0x0"), String::from("0x3"), None).unwrap();
+  assert_eq!(logs.result.len(), 2);
+  assert_eq!(eth_json_rpc::get_block_number(endpoint.clone(), 1).unwrap(), 3);
+  assert_eq!(eth_json_rpc::get_chain_id(endpoint.clone(), 1).unwrap(), 1337);
+
+  println!("Test error injection...");
+  node.with_chain(|chain| chain.inject_error(String::from("eth_blockNumber"), 1, -32000, String::from("internal error")));
+  assert!(eth_json_rpc::get_block_number(endpoint.clone(), 1).is_err());
+  assert_eq!(eth_json_rpc::get_block_number(endpoint.clone(), 1).unwrap(), 3);
+
+  println!("Test filter expiry...");
+  node.with_chain(|chain| chain.expire_filters());
+  assert!(eth_json_rpc::get_new_events(endpoint.clone(), filter_id, 1).is_err());
+}
+
+#[test]
+fn test_main_actor_with_mock_node() {
+  let path = TempDir::new("_rust_rocksdb_mock_node").expect("");
+  let path_str = path.path().to_str().unwrap();
+  let event = transfer_event();
+  let script = MockScript{blocks: vec![
+    vec![transfer_log(&event, 1, 2, 100)],
+    vec![transfer_log(&event, 2, 3, 200)],
+    vec![transfer_log(&event, 3, 4, 300)]
+  ], ..Default::default()};
+  let node = MockNode::start("127.0.0.1", 0, MockChain::from_script(script)).unwrap();
+
+  let mut eth_settings: HashMap<String, Settings> = HashMap::new();
+  eth_settings.insert("endpoint".to_string(), Settings::Endpoint(node.endpoint()));
+  eth_settings.insert("start_block".to_string(), Settings::U64(0));
+  let eth_actor = EthActor::create_new(event.clone(), eth_settings);
+
+  let mut settings: HashMap<String, Settings> = HashMap::new();
+  let settings_column_families: Vec<&'static str> = vec!("events", "aggregations", "stats", "settings", "filters", "log");
+  settings.insert("column_families".to_string(), Settings::VecStr(settings_column_families));
+  settings.insert("db_path".to_string(), Settings::PureString(path_str.to_string()));
+  settings.insert("prefix".to_string(), Settings::USize(30));
+  let m_actor: MainActor = MainActor{system_name: "TestMockNode".to_string(), eth_actors: vec![eth_actor], write_actor: None, read_actor: None, settings: Some(settings), addr: None, db: None};
+
+  let (result_sender, result_receiver) = channel::<Vec<usize>>();
+  System::run(move || {
+    let system = System::current();
+    let m_actor_addr = m_actor.clone().start();
+    let m_actor_req = m_actor_addr.send(MsgCreateReadWriteActor{});
+    Arbiter::spawn(m_actor_req.then(move |res| {
+      match res {
+        Ok(Some((read_actor_addr, write_actor_addr, Some(db)))) => {
+          m_actor.run_event_actors(write_actor_addr, read_actor_addr, Some(db.clone())).unwrap();
+          thread::spawn(move || {
+            let mut counts: Vec<usize> = Vec::new();
+            counts.push(wait_for_events(&db, 3));
+
+            node.with_chain(|chain| {
+              chain.expire_filters();
+              chain.mine_block(vec![transfer_log(&event, 4, 5, 400)]);
+            });
+            counts.push(wait_for_events(&db, 4));
+
+            node.with_chain(|chain| {
+              chain.inject_error(String::from("eth_getFilterChanges"), 2, -32000, String::from("internal error"));
+              chain.mine_block(vec![transfer_log(&event, 5, 6, 500)]);
+            });
+            counts.push(wait_for_events(&db, 5));
+
+            node.with_chain(|chain| chain.reorg(1, vec![vec![], vec![]]));
+            counts.push(wait_for_events(&db, 4));
+            result_sender.send(counts).unwrap();
+            system.stop();
+          });
+        },
+        _ => {
+          println!("Cannot create read and write actors");
+          system.stop();
+        }
+      };
+      future::result(Ok(()))
+    }));
+  });
+  let counts = result_receiver.recv_timeout(Duration::new(1, 0)).expect("There are no results from pipeline");
+  println!("Test initial sync from mock node...");
+  assert_eq!(counts[0], 3);
+  println!("Test new filter after filter expiry...");
+  assert_eq!(counts[1], 4);
+  println!("Test recovery after json-rpc errors...");
+  assert_eq!(counts[2], 5);
+  println!("Test removing events after reorg...");
+  assert_eq!(counts[3], 4);
+}
@@ -7,7 +7,7 @@ use std::collections::HashMap;
 use serde_json::{Value, Map};
 use lib::actors::structs::settings::Settings;
 use lib::actors::main_actor::MainActor;
-use lib::actors::rocks_write_actor::{MsgContentType, WriteMsg, MsgType};
+use lib::actors::rocks_write_actor::{MsgContentType, WriteMsg, WriteBatchMsg, WriteOp, MsgType};
 use lib::actors::rocks_read_actor as RRA;
 use lib::actors::main_actor::{MsgCreateReadWriteActor, MsgGetDB};
 use tempdir::TempDir;
@@ -115,7 +115,7 @@ fn test_reader_db() {
             let m_actor_req_db = m_actor_addr.clone().send(MsgGetDB{});
             Arbiter::spawn(m_actor_req_db.then(move |res| {
               let unwraped_res_db = res.unwrap();
-              let response_db = get_events_by_prefix(unwraped_res_db.unwrap(), String::from("events"), String::from("Trade----------UserAddr-------0x18-0x12"), 10, true, 15).unwrap();
+              let response_db = get_events_by_prefix(unwraped_res_db.unwrap(), String::from("events"), String::from("Trade----------UserAddr-------0x18-0x12").into_bytes(), 10, true, 15).unwrap();
               assert_eq!(response_db.len(), 2);
               future::result(Ok(()))
             }));
@@ -134,4 +134,54 @@ fn test_reader_db() {
       future::result(Ok(()))
     }));
   });
-}
\ No newline at end of file
+}
+#[test]
+fn test_write_batch() {
+  let path = TempDir::new("_rust_rocksdb_write_batch").expect("");
+  let path_str = path.path().to_str().unwrap();
+  let mut settings: HashMap<String, Settings> = HashMap::new();
+  let settings_column_families: Vec<&'static str> = vec!("events", "aggregations", "stats", "settings", "filters", "log");
+  settings.insert("column_families".to_string(), Settings::VecStr(settings_column_families));
+  settings.insert("db_path".to_string(), Settings::PureString(path_str.to_string()));
+  settings.insert("prefix".to_string(), Settings::USize(15));
+
+  let m_actor: MainActor = MainActor{system_name: "TestBatch".to_string(), eth_actors: Vec::new(), write_actor: None, read_actor: None, settings: Some(settings), addr: None, db: None};
+  let m_actor_addr  = m_actor.clone().start();
+  System::run(move || {
+    let m_actor_req = m_actor_addr.clone().send(MsgCreateReadWriteActor{});
+    Arbiter::spawn(m_actor_req.then(move |res| {
+      match res {
+        Ok(Some((_m_r_a, m_w_a, Some(db)))) => {
+          println!("Test batch with events and cursor...");
+          let ops = vec![
+            WriteOp::Put(MsgType::Event, b"Trade----------0x18-0x01".to_vec(), MsgContentType::PureString("{\"test\": 1}".to_string())),
+            WriteOp::Put(MsgType::Event, b"Trade----------0x18-0x02".to_vec(), MsgContentType::PureString("{\"test\": 2}".to_string())),
+            WriteOp::Put(MsgType::Aggregation, b"Trade----------".to_vec(), MsgContentType::PureString("{\"last_block\": \"0x18-0x02\"}".to_string()))
+          ];
+          let write_response = m_w_a.send(WriteBatchMsg{ops: ops, sync: true});
+          let m_w_a_delete = m_w_a.clone();
+          Arbiter::spawn(write_response.then(move |res| {
+            assert_eq!(res.unwrap(), Ok(String::from("Batch of 3 operations has been written")));
+            assert_eq!(get_by_key(db.clone(), String::from("events"), String::from("Trade----------0x18-0x02")).unwrap(), "{\"test\": 2}");
+            assert_eq!(get_by_key(db.clone(), String::from("aggregations"), String::from("Trade----------")).unwrap(), "{\"last_block\": \"0x18-0x02\"}");
+
+            println!("Test batch with delete...");
+            let delete_response = m_w_a_delete.send(WriteBatchMsg{ops: vec![WriteOp::Delete(MsgType::Event, b"Trade----------0x18-0x02".to_vec())], sync: false});
+            Arbiter::spawn(delete_response.then(move |res| {
+              assert_eq!(res.unwrap(), Ok(String::from("Batch of 1 operations has been written")));
+              assert!(get_by_key(db.clone(), String::from("events"), String::from("Trade----------0x18-0x02")).is_err());
+              System::current().stop();
+              future::result(Ok(()))
+            }));
+            future::result(Ok(()))
+          }));
+        },
+        _ => {
+          println!("Cannot create read and write actors");
+          assert_eq!(true, false);
+        }
+      };
+      future::result(Ok(()))
+    }));
+  });
+}
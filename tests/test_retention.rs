@@ -0,0 +1,110 @@
+extern crate lib;
+extern crate tempdir;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use lib::actors::rocks_write_actor::write_batch;
+use lib::config::structs::contractconfig::{IndexConfig, RetentionConfig};
+use lib::db::cachedb::CacheDB;
+use lib::db::indexes::{EventBatch, EventIndexes, LogTransaction, index_query, timeline_query};
+use lib::db::key_schema::{event_key, name_key};
+use lib::db::reader::{get_events_by_index, get_events_by_prefix, get_events_by_timeline};
+use lib::db::retention::{apply_retention, find_first_block_after, get_prune_boundary, get_pruned_before, get_pruned_msg, RetentionPolicy};
+use tempdir::TempDir;
+
+type GetTimestamp = fn(u64) -> Result<u64, String>;
+
+fn transfer_indexes() -> EventIndexes {
+  EventIndexes{
+    contract: String::from("Token"),
+    indexes: vec![IndexConfig{name: String::from("from"), args: vec![String::from("from")]}],
+    address_args: Vec::new(),
+    index_tx_sender: false,
+    global_timeline: false,
+    aggregations: Vec::new()
+  }
+}
+
+fn policy(retention: RetentionConfig) -> RetentionPolicy {
+  RetentionPolicy{event_name: String::from("Transfer"), event_indexes: transfer_indexes(), retention: retention}
+}
+
+/// Writes one Transfer per block from `from` to `to` and moves cursor to the last one
+fn write_transfers(db: &CacheDB, from: u64, to: u64) {
+  let mut event_batch = EventBatch::new(&*db.storage);
+  for block_number in from..(to + 1) {
+    let mut params = HashMap::new();
+    params.insert(String::from("from"), String::from("aa"));
+    event_batch.put_event(&event_key("Transfer", block_number, 0).unwrap(), params, &LogTransaction::default(), &transfer_indexes()).unwrap();
+  }
+  let cursor = format!("{{\"last_block\": \"0x{:x}-0x0\", \"ts\": 0}}", to);
+  event_batch.batch().put("aggregations", &name_key("Transfer").unwrap(), cursor.as_bytes());
+  db.storage.write(event_batch.into_batch(), false).unwrap();
+}
+
+fn event_count(db: &CacheDB) -> usize {
+  get_events_by_prefix(db.clone(), String::from("events"), name_key("Transfer").unwrap(), 1000, true, 30).unwrap().len()
+}
+
+#[test]
+fn test_find_first_block_after() {
+  let timestamps = |block_number: u64| -> Result<u64, String> { Ok(1000 + block_number * 15) };
+  assert_eq!(find_first_block_after(1000, 0, 100, timestamps).unwrap(), Some(0));
+  assert_eq!(find_first_block_after(1150, 0, 100, timestamps).unwrap(), Some(10));
+  assert_eq!(find_first_block_after(1151, 0, 100, timestamps).unwrap(), Some(11));
+  assert_eq!(find_first_block_after(5000, 0, 100, timestamps).unwrap(), None);
+}
+
+#[test]
+fn test_find_first_block_after_pruned() {
+  let requested: RefCell<Vec<u64>> = RefCell::new(Vec::new());
+  let timestamps = |block_number: u64| -> Result<u64, String> {
+    requested.borrow_mut().push(block_number);
+    Ok(1000 + block_number * 15)
+  };
+
+  println!("Test search starts from the pruned boundary...");
+  assert_eq!(find_first_block_after(1151, 90, 100, &timestamps).unwrap(), Some(90));
+  assert_eq!(find_first_block_after(2400, 90, 100, &timestamps).unwrap(), Some(94));
+  assert!(requested.borrow().iter().all(|block_number| *block_number >= 90));
+}
+
+#[test]
+fn test_retention() {
+  let path = TempDir::new("_rust_rocksdb_retention").expect("");
+  let column_families = vec!("events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline");
+  let db = CacheDB::create(path.path().to_str().unwrap().to_string(), column_families, 30).unwrap();
+  write_transfers(&db, 1, 20);
+
+  println!("Test boundaries of retention policies...");
+  let by_blocks = policy(RetentionConfig{max_blocks: Some(5), max_age_secs: None, max_count: None});
+  assert_eq!(get_prune_boundary(&db, &by_blocks, None::<GetTimestamp>).unwrap(), Some((16, 0)));
+  let by_count = policy(RetentionConfig{max_blocks: None, max_age_secs: None, max_count: Some(3)});
+  assert_eq!(get_prune_boundary(&db, &by_count, None::<GetTimestamp>).unwrap(), Some((18, 0)));
+  let both = policy(RetentionConfig{max_blocks: Some(10), max_age_secs: None, max_count: Some(15)});
+  assert_eq!(get_prune_boundary(&db, &both, None::<GetTimestamp>).unwrap(), Some((11, 0)));
+  let nothing = policy(RetentionConfig{max_blocks: Some(100), max_age_secs: None, max_count: Some(100)});
+  assert_eq!(get_prune_boundary(&db, &nothing, None::<GetTimestamp>).unwrap(), None);
+
+  println!("Test pruning removes events with their entries...");
+  assert_eq!(apply_retention(&db, &by_blocks, None::<GetTimestamp>, |ops| write_batch(&*db.storage, ops, false)).unwrap(), 15);
+  assert_eq!(event_count(&db), 5);
+  let index_keys: Vec<String> = get_events_by_index(db.clone(), index_query("Transfer", "from", &[String::from("aa")], None).unwrap(), 100).unwrap()
+    .into_iter().map(|event| event.key).collect();
+  assert_eq!(index_keys.first(), Some(&String::from("Transfer-0x10-0x0")));
+  assert_eq!(index_keys.len(), 5);
+  let (timeline, _) = get_events_by_timeline(db.clone(), timeline_query("Token", None).unwrap(), 100, None).unwrap();
+  assert_eq!(timeline.len(), 5);
+
+  println!("Test pruned boundary is reported...");
+  assert_eq!(get_pruned_before(&db, "Transfer").unwrap(), Some((16, 0)));
+  assert!(get_pruned_msg(&db, "Transfer", (0, 0)).unwrap().contains("0x10"));
+  assert!(get_pruned_msg(&db, "Transfer", (16, 0)).is_none());
+  assert!(get_pruned_msg(&db, "Approval", (0, 0)).is_none());
+
+  println!("Test pruning is not repeated below the boundary...");
+  assert_eq!(apply_retention(&db, &by_blocks, None::<GetTimestamp>, |ops| write_batch(&*db.storage, ops, false)).unwrap(), 0);
+  write_transfers(&db, 21, 22);
+  assert_eq!(apply_retention(&db, &by_blocks, None::<GetTimestamp>, |ops| write_batch(&*db.storage, ops, false)).unwrap(), 2);
+  assert_eq!(get_pruned_before(&db, "Transfer").unwrap(), Some((18, 0)));
+  assert_eq!(event_count(&db), 5);
+}
@@ -0,0 +1,96 @@
+extern crate lib;
+extern crate chrono;
+#[macro_use]
+extern crate serde_json;
+use chrono::prelude::*;
+use lib::db::cachedb::CacheDB;
+use lib::db::stats::{add_stats_delta, get_ingest_stats, get_stats_report, IngestStats, StatsDelta};
+use lib::db::storage::StorageBatch;
+
+const COLUMN_FAMILIES: [&str; 9] = ["events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline"];
+
+fn poll(events: u64, blocks: Vec<u64>) -> StatsDelta {
+  StatsDelta{events: events, blocks: blocks, rpc_calls: 1, latency_ms: Some(5), ..StatsDelta::default()}
+}
+
+fn write_delta(db: &CacheDB, contract: &str, event_name: &str, delta: StatsDelta) {
+  let mut batch = StorageBatch::new();
+  add_stats_delta(&*db.storage, &mut batch, contract, event_name, &delta).unwrap();
+  db.storage.write(batch, false).unwrap();
+}
+
+#[test]
+fn test_apply_delta() {
+  println!("Test counters are added and blocks are counted once...");
+  let mut stats = IngestStats::default();
+  let day = Utc.ymd(2019, 3, 1).and_hms(12, 0, 0);
+  stats.apply(&poll(3, vec![10, 10, 11]), day);
+  stats.apply(&poll(2, vec![11, 12]), day);
+  assert_eq!(stats.total_events, 5);
+  assert_eq!(stats.blocks_with_events, 3);
+  assert_eq!(stats.last_block, Some(12));
+  assert_eq!(stats.rpc_calls, 2);
+  assert_eq!(stats.events_per_block(), 5.0 / 3.0);
+  assert_eq!(stats.last_ingest_at, Some(day.timestamp_millis()));
+
+  println!("Test failed polls keep last ingestion...");
+  stats.apply(&StatsDelta{rpc_calls: 1, rpc_errors: 1, ..StatsDelta::default()}, day + chrono::Duration::days(1));
+  assert_eq!(stats.rpc_errors, 1);
+  assert_eq!(stats.last_ingest_at, Some(day.timestamp_millis()));
+  assert_eq!(stats.last_ingest_latency_ms, Some(5));
+
+  println!("Test only last days are kept...");
+  for days in 1..40 {
+    stats.apply(&poll(1, vec![12 + days]), day + chrono::Duration::days(days as i64));
+  }
+  assert_eq!(stats.daily_events.len(), 30);
+  assert_eq!(stats.daily_events.keys().next().unwrap(), "2019-03-11");
+  assert_eq!(stats.daily_events.get("2019-04-09"), Some(&1));
+}
+
+#[test]
+fn test_stats_report() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  write_delta(&db, "Token", "Transfer", poll(4, vec![1, 2]));
+  write_delta(&db, "Token", "Transfer", StatsDelta{decode_failures: 1, removed_events: 1, ..poll(2, vec![3])});
+  write_delta(&db, "Token", "Approval", poll(1, vec![2]));
+  write_delta(&db, "Exchange", "Trade", StatsDelta{rpc_calls: 2, rpc_errors: 2, ..StatsDelta::default()});
+  write_delta(&db, "Exchange", "Transfer", poll(1, vec![5]));
+
+  println!("Test stats of event are stored...");
+  let transfer_stats = get_ingest_stats(&*db.storage, "Token", "Transfer").unwrap().unwrap();
+  assert_eq!(transfer_stats.contract, "Token");
+  assert_eq!(transfer_stats.total_events, 6);
+  assert_eq!(transfer_stats.decode_failures, 1);
+  assert_eq!(transfer_stats.removed_events, 1);
+  assert_eq!(transfer_stats.blocks_with_events, 3);
+  assert_eq!(get_ingest_stats(&*db.storage, "Token", "Unknown").unwrap(), None);
+
+  println!("Test events with the same name in different contracts are kept apart...");
+  assert_eq!(get_ingest_stats(&*db.storage, "Exchange", "Transfer").unwrap().unwrap().total_events, 1);
+
+  println!("Test report sums events of contracts...");
+  let report = get_stats_report(&db, None, None).unwrap();
+  assert_eq!(report.as_array().unwrap().len(), 2);
+  assert_eq!(report[0]["contract"], json!("Exchange"));
+  assert_eq!(report[0]["rpcErrors"], json!(2));
+  assert_eq!(report[1]["totalEvents"], json!(7));
+  assert_eq!(report[1]["lastBlock"], json!(3));
+  assert_eq!(report[1]["events"].as_array().unwrap().len(), 2);
+
+  println!("Test report is filtered...");
+  let report = get_stats_report(&db, Some("Token"), Some("Transfer")).unwrap();
+  assert_eq!(report.as_array().unwrap().len(), 1);
+  assert_eq!(report[0]["totalEvents"], json!(6));
+  assert_eq!(report[0]["events"][0]["eventsPerBlock"], json!(2.0));
+  assert_eq!(get_stats_report(&db, Some("Unknown"), None).unwrap(), json!([]));
+}
+
+#[test]
+fn test_idle_delta() {
+  println!("Test polls without events or failures are idle...");
+  assert!(StatsDelta{rpc_calls: 3, latency_ms: Some(5), ..StatsDelta::default()}.is_idle());
+  assert!(!poll(1, vec![1]).is_idle());
+  assert!(!StatsDelta{removed_events: 1, ..StatsDelta::default()}.is_idle());
+  assert!(!StatsDelta{rpc_calls: 1, rpc_errors: 1, ..StatsDelta::default()}.is_idle());
+}
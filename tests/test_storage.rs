@@ -0,0 +1,166 @@
+extern crate lib;
+extern crate tempdir;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use lib::actors::rocks_write_actor::write_batch;
+use lib::config::structs::contractconfig::{IndexConfig, RetentionConfig};
+use lib::config::structs::storageconfig::StorageConfig;
+use lib::db::cachedb::CacheDB;
+use lib::db::indexes::{EventBatch, EventIndexes, LogTransaction, index_query};
+use lib::db::key_schema::{event_key, name_key};
+use lib::db::reader::{get_events_by_index, get_events_by_prefix};
+use lib::db::retention::{prune_events, RetentionPolicy};
+use lib::db::storage::{Storage, StorageBatch};
+use tempdir::TempDir;
+
+const COLUMN_FAMILIES: [&str; 9] = ["events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline"];
+
+fn keys(storage: &dyn Storage, column_family: &str, forward: bool) -> Vec<Vec<u8>> {
+  let mut iter = storage.iter(column_family).unwrap();
+  let mut keys = Vec::new();
+  if forward { iter.seek_to_first(); } else { iter.seek_to_last(); }
+  while iter.valid() {
+    keys.push(iter.key().to_vec());
+    if forward { iter.next(); } else { iter.prev(); }
+  }
+  keys
+}
+
+/// Checks behaviour which every storage has to share
+fn check_storage(storage: &dyn Storage) {
+  println!("Test get, put and delete...");
+  storage.put("events", b"b", b"2").unwrap();
+  assert_eq!(storage.get("events", b"b").unwrap(), Some(b"2".to_vec()));
+  assert_eq!(storage.get("events", b"a").unwrap(), None);
+  storage.delete("events", b"b").unwrap();
+  assert_eq!(storage.get("events", b"b").unwrap(), None);
+  assert!(storage.get("missing", b"b").is_err());
+  assert!(storage.put("missing", b"b", b"2").is_err());
+
+  println!("Test batch is applied atomically...");
+  let mut batch = StorageBatch::new();
+  batch.put("events", b"ab", b"1");
+  batch.put("events", b"aa", b"2");
+  batch.put("events", b"b", b"3");
+  batch.put("events", b"ac", b"4");
+  batch.put("stats", b"aa", b"5");
+  storage.write(batch, true).unwrap();
+  let mut failing_batch = StorageBatch::new();
+  failing_batch.put("events", b"c", b"6");
+  failing_batch.put("missing", b"c", b"6");
+  assert!(storage.write(failing_batch, false).is_err());
+  assert_eq!(storage.get("events", b"c").unwrap(), None);
+
+  println!("Test ordered iteration in both directions...");
+  assert_eq!(keys(storage, "events", true), vec![b"aa".to_vec(), b"ab".to_vec(), b"ac".to_vec(), b"b".to_vec()]);
+  assert_eq!(keys(storage, "events", false), vec![b"b".to_vec(), b"ac".to_vec(), b"ab".to_vec(), b"aa".to_vec()]);
+  let mut iter = storage.iter("events").unwrap();
+  iter.seek(b"aba");
+  assert_eq!(iter.key(), b"ac");
+  iter.seek_for_prev(b"aba");
+  assert_eq!(iter.key(), b"ab");
+  iter.seek_for_prev(b"a");
+  assert!(!iter.valid());
+  iter.seek(b"c");
+  assert!(!iter.valid());
+
+  println!("Test prefix scan...");
+  let entries = storage.scan_prefix("events", b"a", 2).unwrap();
+  assert_eq!(entries, vec![(b"aa".to_vec(), b"2".to_vec()), (b"ab".to_vec(), b"1".to_vec())]);
+  assert_eq!(storage.scan_prefix("events", b"b", 10).unwrap().len(), 1);
+
+  println!("Test snapshot and iterator do not see later writes...");
+  let snapshot = storage.snapshot();
+  let mut iter = storage.iter("events").unwrap();
+  let mut batch = StorageBatch::new();
+  batch.delete_range("events", b"ab", b"b");
+  batch.put("events", b"a", b"7");
+  storage.write(batch, false).unwrap();
+  assert_eq!(keys(storage, "events", true), vec![b"a".to_vec(), b"aa".to_vec(), b"b".to_vec()]);
+  assert_eq!(snapshot.get("events", b"ab").unwrap(), Some(b"1".to_vec()));
+  assert_eq!(snapshot.get("events", b"a").unwrap(), None);
+  let mut snapshot_iter = snapshot.iter("events").unwrap();
+  snapshot_iter.seek_to_first();
+  assert_eq!(snapshot_iter.key(), b"aa");
+  iter.seek(b"ab");
+  assert_eq!(iter.key(), b"ab");
+}
+
+#[test]
+fn test_memory_storage() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  check_storage(&*db.storage);
+  assert_eq!(db.storage.column_families().len(), COLUMN_FAMILIES.len());
+}
+
+#[test]
+fn test_rocks_storage() {
+  let path = TempDir::new("_rust_rocksdb_storage").expect("");
+  let db = CacheDB::create(path.path().to_str().unwrap().to_string(), COLUMN_FAMILIES.to_vec(), 30).unwrap();
+  check_storage(&*db.storage);
+  assert!(db.storage.has_column_family("timeline"));
+}
+
+#[test]
+fn test_read_only_rocks_storage() {
+  let path = TempDir::new("_rust_rocksdb_read_only").expect("");
+  let db_path = path.path().to_str().unwrap().to_string();
+  let db = CacheDB::create(db_path.clone(), COLUMN_FAMILIES.to_vec(), 30).unwrap();
+  db.storage.put("events", b"a", b"1").unwrap();
+  let read_only_db = CacheDB::open_read_only(db_path, 30, StorageConfig::default()).unwrap();
+
+  println!("Test read-only instance reads and refuses writes...");
+  assert_eq!(read_only_db.storage.get("events", b"a").unwrap(), Some(b"1".to_vec()));
+  assert!(read_only_db.storage.put("events", b"b", b"2").is_err());
+  assert!(read_only_db.storage.has_column_family("timeline"));
+
+  println!("Test writes of the primary are visible after catch up...");
+  db.storage.put("events", b"b", b"2").unwrap();
+  let mut iter = read_only_db.storage.iter("events").unwrap();
+  read_only_db.storage.catch_up().unwrap();
+  assert_eq!(read_only_db.storage.get("events", b"b").unwrap(), Some(b"2".to_vec()));
+  assert_eq!(keys(&*read_only_db.storage, "events", true), vec![b"a".to_vec(), b"b".to_vec()]);
+  iter.seek_to_first();
+  assert_eq!(iter.key(), b"a");
+}
+
+#[test]
+fn test_pipeline_in_memory() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  let indexes = EventIndexes{
+    contract: String::from("Token"),
+    indexes: vec![IndexConfig{name: String::from("from"), args: vec![String::from("from")]}],
+    address_args: Vec::new(),
+    index_tx_sender: false,
+    global_timeline: false,
+    aggregations: Vec::new()
+  };
+
+  println!("Test events and indexes are written without RocksDB...");
+  let mut event_batch = EventBatch::new(&*db.storage);
+  for block_number in 1..11 {
+    let mut params = HashMap::new();
+    params.insert(String::from("from"), if block_number % 2 == 0 { String::from("aa") } else { String::from("bb") });
+    event_batch.put_event(&event_key("Transfer", block_number, 0).unwrap(), params, &LogTransaction::default(), &indexes).unwrap();
+  }
+  db.storage.write(event_batch.into_batch(), false).unwrap();
+  let events = get_events_by_prefix(db.clone(), String::from("events"), name_key("Transfer").unwrap(), 100, true, 30).unwrap();
+  assert_eq!(events.len(), 10);
+  assert_eq!(events[0].key, "Transfer-0x1-0x0");
+  let query = index_query("Transfer", "from", &[String::from("aa")], None).unwrap();
+  assert_eq!(get_events_by_index(db.clone(), query.clone(), 100).unwrap().len(), 5);
+
+  println!("Test pruning removes events and index entries...");
+  let policy = RetentionPolicy{event_name: String::from("Transfer"), event_indexes: indexes.clone(), retention: RetentionConfig::default()};
+  let written_batches = RefCell::new(0);
+  let write = |ops| {
+    *written_batches.borrow_mut() += 1;
+    write_batch(&*db.storage, ops, false)
+  };
+  assert_eq!(prune_events(&db, &policy, (6, 0), &write).unwrap(), 5);
+  // Boundary is written before events are removed
+  assert_eq!(*written_batches.borrow(), 2);
+  let events = get_events_by_prefix(db.clone(), String::from("events"), name_key("Transfer").unwrap(), 100, true, 30).unwrap();
+  assert_eq!(events[0].key, "Transfer-0x6-0x0");
+  assert_eq!(get_events_by_index(db.clone(), query, 100).unwrap().len(), 3);
+}
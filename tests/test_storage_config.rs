@@ -0,0 +1,62 @@
+extern crate lib;
+extern crate tempdir;
+extern crate serde_json;
+use lib::config::structs::storageconfig::{StorageConfig, ColumnFamilyConfig, Compression, CompactionStyle};
+use lib::db::cachedb::CacheDB;
+use lib::db::key_schema::name_key;
+use lib::db::reader::get_by_key_bytes;
+use tempdir::TempDir;
+
+const COLUMN_FAMILIES: [&'static str; 9] = ["events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline"];
+
+#[test]
+fn test_storage_config() {
+  println!("Test defaults of hot and small column families...");
+  let storage_config = StorageConfig::default();
+  let events = storage_config.get_column_family_config("events");
+  let settings = storage_config.get_column_family_config("settings");
+  assert_eq!(events.bottommost_compression, Some(Compression::Zstd));
+  assert_eq!(events.shared_cache, Some(false));
+  assert_eq!(settings.compression, Some(Compression::None));
+  assert_eq!(settings.shared_cache, Some(true));
+  assert!(events.write_buffer_size_mb > settings.write_buffer_size_mb);
+  assert_eq!(storage_config.get_shared_cache_size_mb(), 256);
+
+  println!("Test overrides from config file...");
+  let storage_config: StorageConfig = serde_json::from_str(r#"{
+    "sharedCacheSizeMb": 64,
+    "columnFamilies": {
+      "events": {"compression": "zstd", "maxDictBytes": 0, "compactionStyle": "universal"},
+      "filters": {"sharedCache": false, "blockCacheSizeMb": 8}
+    }
+  }"#).unwrap();
+  let events = storage_config.get_column_family_config("events");
+  assert_eq!(events.compression, Some(Compression::Zstd));
+  assert_eq!(events.max_dict_bytes, Some(0));
+  assert_eq!(events.compaction_style, Some(CompactionStyle::Universal));
+  assert_eq!(events.block_size, ColumnFamilyConfig::defaults_for("events").block_size);
+  let filters = storage_config.get_column_family_config("filters");
+  assert_eq!(filters.shared_cache, Some(false));
+  assert_eq!(filters.block_cache_size_mb, Some(8));
+  assert_eq!(storage_config.get_column_family_config("stats"), ColumnFamilyConfig::defaults_for("stats"));
+  assert_eq!(storage_config.get_shared_cache_size_mb(), 64);
+  assert!(serde_json::from_str::<StorageConfig>(r#"{"columnFamilies": {"events": {"compression": "brotli"}}}"#).is_err());
+}
+
+#[test]
+fn test_create_with_storage_config() {
+  let path = TempDir::new("_rust_rocksdb_storage_config").expect("");
+  let path_str = path.path().to_str().unwrap().to_string();
+  let storage_config: StorageConfig = serde_json::from_str(r#"{
+    "columnFamilies": {"events": {"compression": "zstd", "bottommostCompression": "zstd", "blockSize": 4096, "disableAutoCompactions": true}}
+  }"#).unwrap();
+  let key = name_key("Transfer").unwrap();
+  {
+    let db = CacheDB::create_with_config(path_str.clone(), COLUMN_FAMILIES.to_vec(), 30, storage_config.clone()).unwrap();
+    db.storage.put("events", &key, b"{\"value\": \"1\"}").unwrap();
+  }
+
+  println!("Test database is reopened with changed options...");
+  let db = CacheDB::create(path_str, COLUMN_FAMILIES.to_vec(), 30).unwrap();
+  assert_eq!(get_by_key_bytes(db, String::from("events"), key).unwrap(), "{\"value\": \"1\"}");
+}
@@ -0,0 +1,126 @@
+extern crate lib;
+#[macro_use]
+extern crate serde_json;
+use std::collections::HashMap;
+use lib::commands::migrate_values::convert_values;
+use lib::config::structs::storageconfig::ValueFormat;
+use lib::db::cachedb::CacheDB;
+use lib::db::indexes::{EventBatch, EventIndexes, LogTransaction};
+use lib::db::key_schema::{event_key, name_key};
+use lib::db::reader::{get_event_by_key_bytes, get_events_by_prefix};
+use lib::db::value_codec::{check_value_format, decode_params, decode_value, encode_params, get_value_format, is_binary};
+
+const COLUMN_FAMILIES: [&str; 9] = ["events", "aggregations", "stats", "settings", "filters", "log", "indexes", "addresses", "timeline"];
+
+fn params(value: &str) -> HashMap<String, String> {
+  let mut params = HashMap::new();
+  params.insert(String::from("from"), String::from("0x00000000000000000000000000000000000000aa"));
+  params.insert(String::from("value"), value.to_string());
+  params
+}
+
+fn write_transfers(db: &CacheDB, blocks: Vec<u64>) {
+  let mut event_batch = EventBatch::new(&*db.storage);
+  for block_number in blocks {
+    event_batch.put_event(&event_key("Transfer", block_number, 0).unwrap(), params(&block_number.to_string()), &LogTransaction::default(), &EventIndexes::default()).unwrap();
+  }
+  db.storage.write(event_batch.into_batch(), false).unwrap();
+}
+
+fn stored_value(db: &CacheDB, block_number: u64) -> Vec<u8> {
+  db.storage.get("events", &event_key("Transfer", block_number, 0).unwrap()).unwrap().unwrap()
+}
+
+#[test]
+fn test_value_encoding() {
+  println!("Test binary values decode to the same params as json...");
+  let mut unicode_params = params("1000000000000000000");
+  unicode_params.insert(String::from("memo"), "zażółć ∑ 😀".repeat(20));
+  unicode_params.insert(String::new(), String::new());
+  let json_value = encode_params(&unicode_params, ValueFormat::Json).unwrap();
+  let binary_value = encode_params(&unicode_params, ValueFormat::Binary).unwrap();
+  assert!(!is_binary(&json_value));
+  assert!(is_binary(&binary_value));
+  assert!(binary_value.len() < json_value.len());
+  assert_eq!(decode_params(&json_value).unwrap(), unicode_params);
+  assert_eq!(decode_params(&binary_value).unwrap(), unicode_params);
+  assert_eq!(decode_value(&binary_value).unwrap(), decode_value(&json_value).unwrap());
+  assert_eq!(encode_params(&unicode_params, ValueFormat::Binary).unwrap(), binary_value);
+
+  println!("Test damaged binary values are refused...");
+  assert!(decode_params(&binary_value[..binary_value.len() - 1]).is_err());
+  let mut trailing_value = binary_value.clone();
+  trailing_value.push(0);
+  assert!(decode_params(&trailing_value).is_err());
+  let mut unknown_schema = binary_value.clone();
+  unknown_schema[1] = 200;
+  assert!(decode_params(&unknown_schema).is_err());
+  assert!(decode_params(&[0xcb]).is_err());
+}
+
+#[test]
+fn test_hex_values() {
+  println!("Test hex values are stored as bytes...");
+  let mut transfer_params = HashMap::new();
+  transfer_params.insert(String::from("from"), "a".repeat(40));
+  transfer_params.insert(String::from("to"), "b".repeat(40));
+  transfer_params.insert(String::from("value"), String::from("de0b6b3a7640000"));
+  let json_value = encode_params(&transfer_params, ValueFormat::Json).unwrap();
+  let binary_value = encode_params(&transfer_params, ValueFormat::Binary).unwrap();
+  assert_eq!(json_value.len(), 125);
+  assert_eq!(binary_value.len(), 71);
+  assert_eq!(decode_params(&binary_value).unwrap(), transfer_params);
+
+  println!("Test values which only look like hex keep their text...");
+  for value in vec!["0", "00", "0a1", "ABC", "0xab", "", "true"] {
+    let mut single_param = HashMap::new();
+    single_param.insert(String::from("value"), value.to_string());
+    assert_eq!(decode_params(&encode_params(&single_param, ValueFormat::Binary).unwrap()).unwrap(), single_param);
+  }
+
+  println!("Test values of the previous binary schema are still decoded...");
+  let string_map_value = vec![0xcb, 1, 1, 5, b'v', b'a', b'l', b'u', b'e', 2, b'1', b'0'];
+  assert_eq!(decode_params(&string_map_value).unwrap()["value"], "10");
+}
+
+#[test]
+fn test_binary_events() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+
+  println!("Test configured format is stored in a new database...");
+  assert_eq!(get_value_format(&*db.storage).unwrap(), ValueFormat::Json);
+  assert_eq!(check_value_format(&*db.storage, ValueFormat::Binary).unwrap(), ValueFormat::Binary);
+  assert_eq!(check_value_format(&*db.storage, ValueFormat::Json).unwrap(), ValueFormat::Binary);
+
+  println!("Test binary events are served as json...");
+  write_transfers(&db, vec![1, 2]);
+  assert!(is_binary(&stored_value(&db, 1)));
+  let event = get_event_by_key_bytes(db.clone(), String::from("events"), event_key("Transfer", 1, 0).unwrap()).unwrap();
+  assert_eq!(event.params, json!({"from": "0x00000000000000000000000000000000000000aa", "value": "1"}));
+  let events = get_events_by_prefix(db.clone(), String::from("events"), name_key("Transfer").unwrap(), 10, true, 30).unwrap();
+  assert_eq!(events.iter().map(|event| event.params["value"].clone()).collect::<Vec<_>>(), vec![json!("1"), json!("2")]);
+}
+
+#[test]
+fn test_convert_values() {
+  let db = CacheDB::in_memory(COLUMN_FAMILIES.to_vec());
+  write_transfers(&db, vec![1, 2, 3]);
+  assert!(!is_binary(&stored_value(&db, 1)));
+
+  println!("Test database with events and without format keeps json...");
+  assert_eq!(check_value_format(&*db.storage, ValueFormat::Binary).unwrap(), ValueFormat::Json);
+  assert_eq!(db.storage.get("settings", b"value_format").unwrap(), Some(b"json".to_vec()));
+
+  println!("Test values are converted and conversion can be repeated...");
+  assert_eq!(convert_values(&db, ValueFormat::Binary).unwrap(), 3);
+  assert!(is_binary(&stored_value(&db, 2)));
+  assert_eq!(get_value_format(&*db.storage).unwrap(), ValueFormat::Binary);
+  assert_eq!(convert_values(&db, ValueFormat::Binary).unwrap(), 0);
+  write_transfers(&db, vec![4]);
+  assert!(is_binary(&stored_value(&db, 4)));
+  assert_eq!(decode_params(&stored_value(&db, 3)).unwrap(), params("3"));
+
+  println!("Test values are converted back into json...");
+  assert_eq!(convert_values(&db, ValueFormat::Json).unwrap(), 4);
+  assert_eq!(serde_json::from_slice::<serde_json::Value>(&stored_value(&db, 4)).unwrap()["value"], json!("4"));
+}